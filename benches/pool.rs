@@ -0,0 +1,74 @@
+#![feature(test)]
+
+extern crate test;
+
+#[cfg(test)]
+mod benches {
+    use std::env;
+    use std::fs;
+    use std::io;
+    use test::Bencher;
+    use wain_ast::Module;
+    use wain_exec::{DefaultImporter, InstancePool, Machine, PoolConfig};
+    use wain_syntax_binary::parse;
+    use wain_validate::validate;
+
+    fn unwrap<T, E: std::fmt::Display>(result: Result<T, E>) -> T {
+        match result {
+            Ok(r) => r,
+            Err(e) => panic!("Unexpected error: {}", e),
+        }
+    }
+
+    fn read_example(name: &'static str) -> Vec<u8> {
+        let mut file = env::current_dir().unwrap();
+        file.push("examples");
+        file.push(name);
+        fs::read(file).unwrap()
+    }
+
+    fn importer() -> DefaultImporter<io::Empty, io::Sink> {
+        DefaultImporter::with_stdio(io::empty(), io::sink())
+    }
+
+    fn bench_fresh(b: &mut Bencher, module: &Module<'_>) {
+        b.iter(|| {
+            let machine = unwrap(Machine::instantiate(module, importer()));
+            test::black_box(machine.memory().size());
+        });
+    }
+
+    fn bench_pooled(b: &mut Bencher, module: &Module<'_>) {
+        let mut pool = unwrap(InstancePool::new(module, PoolConfig::default()));
+        b.iter(|| {
+            let machine = unwrap(pool.acquire(importer()));
+            test::black_box(machine.memory().size());
+            pool.release(machine);
+        });
+    }
+
+    macro_rules! bench_instantiation {
+        ($fresh:ident, $pooled:ident, $file:expr) => {
+            #[bench]
+            pub fn $fresh(b: &mut Bencher) {
+                let source = read_example($file);
+                let ast = unwrap(parse(&source));
+                unwrap(validate(&ast));
+                bench_fresh(b, &ast.module);
+            }
+
+            #[bench]
+            pub fn $pooled(b: &mut Bencher) {
+                let source = read_example($file);
+                let ast = unwrap(parse(&source));
+                unwrap(validate(&ast));
+                bench_pooled(b, &ast.module);
+            }
+        };
+    }
+
+    bench_instantiation!(hello_fresh, hello_pooled, "hello/hello.wasm");
+    bench_instantiation!(brainfxxk_fresh, brainfxxk_pooled, "brainfxxk.wasm");
+    bench_instantiation!(mt19937_fresh, mt19937_pooled, "mt19937.wasm");
+    bench_instantiation!(quicksort_fresh, quicksort_pooled, "quicksort.wasm");
+}
@@ -0,0 +1,90 @@
+#[macro_use]
+extern crate wain_exec;
+extern crate wain_syntax_text;
+extern crate wain_validate;
+
+use std::process::exit;
+use wain_exec::{ImportInvokeError, Machine, Memory};
+use wain_syntax_text::parse;
+use wain_validate::validate;
+
+const SOURCE: &str = r#"
+(module
+  (import "env" "print" (func $print (param i32 i32)))
+  (import "env" "sqrt" (func $sqrt (param f64) (result f64)))
+  (memory 1)
+  (data (i32.const 0) "sqrt(2) = ")
+  (func (export "run") (result f64)
+    i32.const 0
+    i32.const 10
+    call $print
+    f64.const 2
+    call $sqrt))
+"#;
+
+struct Env;
+
+// Define host functions imported by the module with Rust signatures. The signatures are checked
+// against the imports on instantiation
+impl_importer! {
+    impl Importer for Env {
+        fn print(
+            &mut self,
+            memory: &mut Memory,
+            addr: i32,
+            len: i32
+        ) -> Result<(), ImportInvokeError> {
+            let start = addr as usize;
+            let bytes = match memory.read_range(start..start + len as usize) {
+                Some(bytes) => bytes,
+                None => {
+                    return Err(ImportInvokeError::Fatal {
+                        message: format!("out of memory range: {}+{}", addr, len),
+                    })
+                }
+            };
+            print!("{}", String::from_utf8_lossy(&bytes));
+            Ok(())
+        }
+
+        fn sqrt(&mut self, f: f64) -> f64 {
+            f.sqrt()
+        }
+    }
+}
+
+fn main() {
+    let tree = match parse(SOURCE) {
+        Ok(tree) => tree,
+        Err(err) => {
+            eprintln!("Parse failed: {}", err);
+            exit(1);
+        }
+    };
+
+    if let Err(err) = validate(&tree) {
+        eprintln!("This .wat file is invalid: {}", err);
+        exit(1);
+    }
+
+    let mut machine = match Machine::instantiate(&tree.module, Env) {
+        Ok(m) => m,
+        Err(err) => {
+            eprintln!("could not instantiate module: {}", err);
+            exit(1);
+        }
+    };
+
+    let run = match machine.get_typed_func::<(), f64>("run") {
+        Ok(f) => f,
+        Err(err) => {
+            eprintln!("could not find function: {}", err);
+            exit(1);
+        }
+    };
+
+    match run.call(&mut machine, ()) {
+        Ok(ret) => println!("{}", ret),
+        Err(trap) => eprintln!("Execution was trapped: {}", trap),
+    }
+}
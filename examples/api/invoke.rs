@@ -76,19 +76,29 @@ fn main() {
         }
     };
 
-    // `int add(int, int)` is exported as `(func (param i32) (result i32))`.
-    // Let's invoke add(10, 32). `Value` is an enum to represent arbitrary value of Wasm. Wasm has
-    // i32, i64, f32, f64 basic types.
-    match machine.invoke("add", &[Value::I32(10), Value::I32(32)]) {
-        Ok(ret) => {
-            // `ret` is type of `Option<Value>` where it contains `Some` value when the invoked
-            // function returned a value. Otherwise it's `None` value.
-            if let Some(Value::I32(i)) = ret {
-                println!("10 + 32 = {}", i);
-            } else {
-                unreachable!();
-            }
+    // `int add(int, int)` is exported as `(func (param i32 i32) (result i32))`.
+    // Get a handle of the function with its signature as Rust types. The signature is checked only
+    // here. Parameters are passed as a tuple and the result is returned as a Rust value.
+    let add = match machine.get_typed_func::<(i32, i32), i32>("add") {
+        Ok(f) => f,
+        Err(err) => {
+            eprintln!("could not get function 'add': {}", err);
+            exit(1);
         }
+    };
+
+    // Let's invoke add(10, 32)
+    match add.call(&mut machine, (10, 32)) {
+        Ok(i) => println!("10 + 32 = {}", i),
+        Err(trap) => eprintln!("Execution was trapped: {}", trap),
+    }
+
+    // `Machine::invoke` is also available to invoke a function with arbitrary values. `Value` is an
+    // enum to represent arbitrary value of Wasm. Wasm has i32, i64, f32, f64 basic types. The
+    // returned value is `Some` when the invoked function returned a value.
+    match machine.invoke("add", &[Value::I32(10), Value::I32(32)]) {
+        Ok(Some(Value::I32(i))) => println!("10 + 32 = {}", i),
+        Ok(_) => unreachable!(),
         Err(trap) => eprintln!("Execution was trapped: {}", trap),
     }
 }
@@ -0,0 +1,12 @@
+#include "util.h"
+
+int calc(int x) {
+    int y = add(x, 1);
+    if (y > 10)
+        __builtin_trap();
+    return twice(y);
+}
+
+void _start(void) {
+    calc(10);
+}
@@ -0,0 +1,55 @@
+; LLVM IR equivalent to 'clang -O1 -g --target=wasm32 -c debug.c' with debug info metadata.
+target datalayout = "e-m:e-p:32:32-p10:8:8-p20:8:8-i64:64-n32:64-S128-ni:1:10:20"
+target triple = "wasm32-unknown-unknown"
+
+define internal i32 @twice(i32 %v) !dbg !30 {
+entry:
+  %r = shl i32 %v, 1, !dbg !31
+  ret i32 %r, !dbg !32
+}
+
+define i32 @calc(i32 %x) !dbg !10 {
+entry:
+  %y = add nsw i32 %x, 1, !dbg !20
+  %cmp = icmp sgt i32 %y, 10, !dbg !22
+  br i1 %cmp, label %trap, label %ret, !dbg !23
+trap:
+  call void @llvm.trap(), !dbg !24
+  unreachable, !dbg !24
+ret:
+  %z = call i32 @twice(i32 %y), !dbg !25
+  ret i32 %z, !dbg !26
+}
+
+define void @_start() !dbg !40 {
+entry:
+  %r = call i32 @calc(i32 10), !dbg !41
+  ret void, !dbg !42
+}
+
+declare void @llvm.trap()
+
+!llvm.dbg.cu = !{!0}
+!llvm.module.flags = !{!2, !3}
+!0 = distinct !DICompileUnit(language: DW_LANG_C99, file: !1, producer: "clang", isOptimized: true, runtimeVersion: 0, emissionKind: FullDebug)
+!1 = !DIFile(filename: "debug.c", directory: "/work/examples/dwarf")
+!2 = !{i32 7, !"Dwarf Version", i32 DWARF_VERSION}
+!3 = !{i32 2, !"Debug Info Version", i32 3}
+!4 = !DIFile(filename: "util.h", directory: "/work/examples/dwarf")
+!10 = distinct !DISubprogram(name: "calc", scope: !1, file: !1, line: 3, type: !11, scopeLine: 3, flags: DIFlagPrototyped, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!11 = !DISubroutineType(types: !12)
+!12 = !{null}
+!13 = distinct !DISubprogram(name: "add", scope: !4, file: !4, line: 1, type: !11, scopeLine: 1, flags: DIFlagPrototyped, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!20 = !DILocation(line: 2, column: 14, scope: !13, inlinedAt: !21)
+!21 = distinct !DILocation(line: 4, column: 13, scope: !10)
+!22 = !DILocation(line: 5, column: 11, scope: !10)
+!23 = !DILocation(line: 5, column: 9, scope: !10)
+!24 = !DILocation(line: 6, column: 9, scope: !10)
+!25 = !DILocation(line: 7, column: 12, scope: !10)
+!26 = !DILocation(line: 7, column: 5, scope: !10)
+!30 = distinct !DISubprogram(name: "twice", scope: !4, file: !4, line: 5, type: !11, scopeLine: 5, flags: DIFlagPrototyped, spFlags: DISPFlagLocalToUnit | DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!31 = !DILocation(line: 6, column: 14, scope: !30)
+!32 = !DILocation(line: 6, column: 5, scope: !30)
+!40 = distinct !DISubprogram(name: "_start", scope: !1, file: !1, line: 10, type: !11, scopeLine: 10, flags: DIFlagPrototyped, spFlags: DISPFlagDefinition | DISPFlagOptimized, unit: !0)
+!41 = !DILocation(line: 11, column: 5, scope: !40)
+!42 = !DILocation(line: 12, column: 1, scope: !40)
@@ -0,0 +1,7 @@
+static inline int add(int a, int b) {
+    return a + b;
+}
+
+static int twice(int v) {
+    return v * 2;
+}
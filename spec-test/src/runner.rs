@@ -9,6 +9,7 @@ use std::io::{self, Write};
 use std::path::Path;
 use std::time;
 use wain_ast as ast;
+use wain_exec::stop::ExecError;
 use wain_exec::{DefaultImporter, Machine, Value};
 use wain_syntax_binary as binary;
 use wain_syntax_text as wat;
@@ -310,9 +311,13 @@ impl<'m, 's> Instances<'m, 's> {
         let (machine, mod_pos) = self.find(invoke.id, invoke.start)?;
 
         let args: Box<[Value]> = invoke.args.iter().map(|c| c.to_value().unwrap()).collect();
-        let ret = machine
-            .invoke(&invoke.name, &args)
-            .map_err(|err| Error::run_error(RunKind::Trapped(*err), self.source, mod_pos))?;
+        let ret = machine.invoke(&invoke.name, &args).map_err(|err| {
+            let trap = match err {
+                ExecError::Trap(trap) => *trap,
+                err => unreachable!("execution is never stopped in spec tests: {}", err),
+            };
+            Error::run_error(RunKind::Trapped(trap), self.source, mod_pos)
+        })?;
 
         Ok(ret)
     }
@@ -138,6 +138,7 @@ pub enum AssertReturn<'source> {
 
 // (assert_trap (invoke {name} {constant}*) {string})
 // (assert_trap (module ...) {string})
+#[allow(clippy::large_enum_variant)]
 pub enum TrapPredicate<'source> {
     Invoke(Invoke<'source>),
     Module(ast::Root<'source, TextSource<'source>>),
@@ -0,0 +1,158 @@
+// Printer of core dump for 'coredump inspect' subcommand
+
+use crate::debugger::hex_lines;
+use std::io::{self, Write};
+use wain_exec::{CoreDump, Value};
+
+// Bytes of each memory region printed as hex dump. Rest of the region is omitted
+const MAX_REGION_BYTES: usize = 256;
+
+fn values(vs: &[Value]) -> String {
+    let vs: Vec<_> = vs.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", vs.join(", "))
+}
+
+fn func(dump: &CoreDump, funcidx: u32) -> String {
+    match dump.func_name(funcidx) {
+        Some(name) => format!("'{}' (function {})", name, funcidx),
+        None => format!("function {}", funcidx),
+    }
+}
+
+pub fn inspect<W: Write>(dump: &CoreDump, mut out: W) -> io::Result<()> {
+    if !dump.reason.is_empty() {
+        writeln!(
+            out,
+            "Trap: {} at byte offset 0x{:x}",
+            dump.reason, dump.offset
+        )?;
+    }
+    if !dump.module.is_empty() {
+        writeln!(out, "Module: {}", dump.module)?;
+    }
+    if !dump.executable.is_empty() {
+        writeln!(out, "Executable: {}", dump.executable)?;
+    }
+
+    writeln!(out, "\nCall stack:")?;
+    for (i, frame) in dump.frames.iter().enumerate() {
+        writeln!(
+            out,
+            "  #{}: {} at code offset 0x{:x}",
+            i,
+            func(dump, frame.funcidx),
+            frame.codeoffset
+        )?;
+        writeln!(out, "      locals: {}", values(&frame.locals))?;
+        writeln!(out, "      stack:  {}", values(&frame.stack))?;
+    }
+
+    if !dump.globals.is_empty() {
+        writeln!(out, "\nGlobals:")?;
+        for (i, g) in dump.globals.iter().enumerate() {
+            let mutable = if g.mutable { " (mut)" } else { "" };
+            writeln!(out, "  {}: {}{}", i, g.value, mutable)?;
+        }
+    }
+
+    if let Some(table) = &dump.table {
+        writeln!(out, "\nTable: {} elements", table.len())?;
+        for (i, elem) in table.iter().enumerate() {
+            if let Some(funcidx) = elem {
+                writeln!(out, "  {}: {}", i, func(dump, *funcidx))?;
+            }
+        }
+    }
+
+    if let Some(memory) = &dump.memory {
+        writeln!(
+            out,
+            "\nMemory: {} pages ({} bytes)",
+            memory.len() / 65536,
+            memory.len()
+        )?;
+        for r in dump.memory_regions() {
+            writeln!(
+                out,
+                "  region 0x{:x}..0x{:x} ({} bytes)",
+                r.start,
+                r.end,
+                r.len()
+            )?;
+            let end = r.end.min(r.start + MAX_REGION_BYTES);
+            for line in hex_lines(r.start, &memory[r.start..end]) {
+                writeln!(out, "    {}", line)?;
+            }
+            if end < r.end {
+                writeln!(out, "    ... {} more bytes", r.end - end)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wain_exec::{DefaultImporter, Machine};
+
+    #[test]
+    fn inspect_trapped_module() {
+        let source = r#"
+(module
+  (memory 1)
+  (table 2 funcref)
+  (global $count (mut i32) (i32.const 0))
+  (elem (i32.const 1) $fail)
+  (func $fail (param i32)
+    i32.const 16
+    i32.const 0x6e696177
+    i32.store
+    unreachable)
+  (func $main (export "_start")
+    i32.const 7
+    global.set $count
+    i32.const 1
+    call $fail))
+"#;
+        let root =
+            wain_syntax_text::parse_with_id_names(source).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine =
+            Machine::instantiate(&root.module, importer).unwrap_or_else(|t| panic!("{}", t));
+        machine.set_coredump(true);
+        let trap = machine.execute().map_err(crate::trap_of).err().unwrap();
+        let dump =
+            CoreDump::decode(&trap.coredump.unwrap().encode()).unwrap_or_else(|e| panic!("{}", e));
+
+        let mut out = vec![];
+        inspect(&dump, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "Trap: reached unreachable code at byte offset 0xc7",
+                "",
+                "Call stack:",
+                "  #0: 'fail' (function 0) at code offset 0x54",
+                "      locals: [1i32]",
+                "      stack:  []",
+                "  #1: 'main' (function 1) at code offset 0x58",
+                "      locals: []",
+                "      stack:  []",
+                "",
+                "Globals:",
+                "  0: 7i32 (mut)",
+                "",
+                "Table: 2 elements",
+                "  1: 'fail' (function 0)",
+                "",
+                "Memory: 1 pages (65536 bytes)",
+                "  region 0x10..0x14 (4 bytes)",
+                "    0x00000010: 77 61 69 6e                                      |wain|",
+            ],
+        );
+    }
+}
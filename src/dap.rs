@@ -0,0 +1,1227 @@
+// Debug Adapter Protocol server for 'wain dap' subcommand. Messages from client are read from STDIN
+// and messages to client are written to STDOUT.
+// https://microsoft.github.io/debug-adapter-protocol/specification
+
+use crate::debugger::{find_func, parse_value};
+use crate::json::Json;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use wain_ast::source::Source;
+use wain_ast::{FuncKind, InsnKind, Instruction, Module, Root};
+use wain_exec::stop::StopReason;
+use wain_exec::{Breakpoint, DebugContext, Debugger, DefaultImporter, Machine, Resume, Run, Value};
+
+// Only one thread runs in the machine
+const THREAD_ID: i64 = 1;
+
+// Variables references of scopes. Locals and operand stack are available only in the innermost frame
+const LOCALS: i64 = 1;
+const OPERANDS: i64 = 2;
+const GLOBALS: i64 = 3;
+
+struct Request {
+    seq: i64,
+    command: String,
+    arguments: Json,
+}
+
+impl Request {
+    fn arg(&self, key: &str) -> Option<&Json> {
+        self.arguments.get(key)
+    }
+}
+
+struct Connection<R, W> {
+    reader: R,
+    writer: W,
+    seq: i64,
+    output: Vec<u8>, // Output from the program which is not sent to client yet
+}
+
+type Shared<R, W> = Arc<Mutex<Connection<R, W>>>;
+
+impl<R: BufRead, W: Write> Connection<R, W> {
+    fn new(reader: R, writer: W) -> Self {
+        Self {
+            reader,
+            writer,
+            seq: 0,
+            output: vec![],
+        }
+    }
+
+    // Read message framed with 'Content-Length' header. None means the connection was closed
+    fn read_request(&mut self) -> io::Result<Option<Request>> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut len = None;
+        loop {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                if len.is_some() {
+                    break;
+                }
+                continue;
+            }
+            if let Some(value) = line.strip_prefix("Content-Length:") {
+                let value = value.trim();
+                len = Some(
+                    value
+                        .parse()
+                        .map_err(|_| invalid(format!("Invalid Content-Length '{}'", value)))?,
+                );
+            }
+        }
+
+        let mut buf = vec![0; len.unwrap()];
+        self.reader.read_exact(&mut buf)?;
+        let text = String::from_utf8(buf).map_err(|e| invalid(e.to_string()))?;
+        let msg = Json::parse(&text).map_err(|e| invalid(format!("Invalid message: {}", e)))?;
+        Ok(Some(Request {
+            seq: msg.get("seq").and_then(Json::as_i64).unwrap_or(0),
+            command: msg
+                .get("command")
+                .and_then(Json::as_str)
+                .unwrap_or("")
+                .to_string(),
+            arguments: msg.get("arguments").cloned().unwrap_or(Json::Null),
+        }))
+    }
+
+    fn send(&mut self, mut fields: Vec<(&str, Json)>) -> io::Result<()> {
+        self.seq += 1;
+        fields.insert(0, ("seq", self.seq.into()));
+        let msg = Json::object(fields).to_string();
+        write!(self.writer, "Content-Length: {}\r\n\r\n{}", msg.len(), msg)?;
+        self.writer.flush()
+    }
+
+    fn respond(&mut self, req: &Request, body: Json) -> io::Result<()> {
+        let mut fields = vec![
+            ("type", "response".into()),
+            ("request_seq", req.seq.into()),
+            ("success", true.into()),
+            ("command", req.command.as_str().into()),
+        ];
+        if body != Json::Null {
+            fields.push(("body", body));
+        }
+        self.send(fields)
+    }
+
+    fn respond_error(&mut self, req: &Request, message: &str) -> io::Result<()> {
+        self.send(vec![
+            ("type", "response".into()),
+            ("request_seq", req.seq.into()),
+            ("success", false.into()),
+            ("command", req.command.as_str().into()),
+            ("message", message.into()),
+        ])
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> io::Result<()> {
+        let mut fields = vec![("type", "event".into()), ("event", event.into())];
+        if body != Json::Null {
+            fields.push(("body", body));
+        }
+        self.send(fields)
+    }
+
+    fn output(&mut self, category: &str, output: String) -> io::Result<()> {
+        let body = Json::object(vec![
+            ("category", category.into()),
+            ("output", output.into()),
+        ]);
+        self.event("output", body)
+    }
+
+    fn flush_output(&mut self) -> io::Result<()> {
+        if self.output.is_empty() {
+            return Ok(());
+        }
+        let output = String::from_utf8_lossy(&self.output).into_owned();
+        self.output.clear();
+        self.output("stdout", output)
+    }
+}
+
+// STDOUT of the program. The output is sent to client as 'output' events line by line
+struct Output<R, W>(Shared<R, W>);
+
+impl<R: BufRead, W: Write> Write for Output<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut conn = self.0.lock().unwrap();
+        conn.output.extend_from_slice(buf);
+        if buf.contains(&b'\n') {
+            conn.flush_output()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.lock().unwrap().flush_output()
+    }
+}
+
+// Mapping from byte offsets in the program to positions in its source
+enum SourceMap {
+    Text {
+        path: String,
+        lines: Vec<usize>, // Start offsets of lines
+        insns: Vec<usize>, // Sorted start offsets of all instructions
+    },
+    #[allow(dead_code)]
+    Binary(Option<wain_dwarf::DebugInfo>),
+}
+
+fn collect_insns(insns: &[Instruction], offsets: &mut Vec<usize>) {
+    for insn in insns {
+        offsets.push(insn.start);
+        match &insn.kind {
+            InsnKind::Block { body, .. } | InsnKind::Loop { body, .. } => {
+                collect_insns(body, offsets)
+            }
+            InsnKind::If {
+                then_body,
+                else_body,
+                ..
+            } => {
+                collect_insns(then_body, offsets);
+                collect_insns(else_body, offsets);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn same_file(a: &str, b: &str) -> bool {
+    a == b
+        || match (fs::canonicalize(a), fs::canonicalize(b)) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => false,
+        }
+}
+
+impl SourceMap {
+    #[allow(dead_code)]
+    fn text(path: &str, source: &str, module: &Module<'_>) -> Self {
+        let mut lines = vec![0];
+        lines.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        let mut insns = vec![];
+        for func in module.funcs.iter() {
+            if let FuncKind::Body { expr, .. } = &func.kind {
+                collect_insns(expr, &mut insns);
+            }
+        }
+        insns.sort_unstable();
+        SourceMap::Text {
+            path: path.to_string(),
+            lines,
+            insns,
+        }
+    }
+
+    // Returns file path, line and column (both are 1-based)
+    fn location(&self, offset: usize) -> Option<(String, i64, i64)> {
+        match self {
+            SourceMap::Text { path, lines, .. } => {
+                let idx = match lines.binary_search(&offset) {
+                    Ok(i) => i,
+                    Err(i) => i - 1,
+                };
+                let col = offset - lines[idx] + 1;
+                Some((path.clone(), idx as i64 + 1, col as i64))
+            }
+            SourceMap::Binary(info) => {
+                let info = info.as_ref()?;
+                let loc = info.location(info.address(offset)?)?;
+                Some((loc.file?.to_string(), loc.line as i64, loc.column as i64))
+            }
+        }
+    }
+
+    // Breakpoint is set to the first instruction at or after the line. Returns the offset of the
+    // instruction and its line
+    fn line_breakpoint(&self, file: &str, line: i64) -> Result<(usize, i64), String> {
+        match self {
+            SourceMap::Text { path, lines, insns } => {
+                if !same_file(file, path) {
+                    return Err(format!("Source '{}' is not the debugged program", file));
+                }
+                if line < 1 || line as usize > lines.len() {
+                    return Err(format!("Line {} is out of the source", line));
+                }
+                let start = lines[line as usize - 1];
+                let idx = match insns.binary_search(&start) {
+                    Ok(i) | Err(i) => i,
+                };
+                match insns.get(idx) {
+                    Some(&offset) => Ok((offset, self.location(offset).unwrap().1)),
+                    None => Err(format!("No instruction at or after line {}", line)),
+                }
+            }
+            SourceMap::Binary(_) => {
+                Err("Line breakpoints are not supported for binary format".to_string())
+            }
+        }
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        ("supportsConfigurationDoneRequest", true.into()),
+        ("supportsFunctionBreakpoints", true.into()),
+        ("supportsSetVariable", true.into()),
+        ("supportsTerminateRequest", true.into()),
+    ])
+}
+
+// Type is shown separately so value does not have type suffix unlike Display of Value
+fn value_text(value: &Value) -> String {
+    match value {
+        Value::I32(i) => i.to_string(),
+        Value::I64(i) => i.to_string(),
+        Value::F32(f) => f.to_string(),
+        Value::F64(f) => f.to_string(),
+    }
+}
+
+fn variable(name: String, value: &Value) -> Json {
+    Json::object(vec![
+        ("name", name.into()),
+        ("value", value_text(value).into()),
+        ("type", value.valtype().to_string().into()),
+        ("variablesReference", 0.into()),
+    ])
+}
+
+fn local_name(module: &Module<'_>, funcidx: u32, localidx: u32) -> String {
+    match module.names.local(funcidx, localidx) {
+        Some(name) => format!("${}", name.0),
+        None => localidx.to_string(),
+    }
+}
+
+fn global_name(module: &Module<'_>, globalidx: u32) -> String {
+    match module.names.global(globalidx) {
+        Some(name) => format!("${}", name.0),
+        None => globalidx.to_string(),
+    }
+}
+
+fn replace_breakpoints(
+    ctx: Option<&mut DebugContext<'_, '_, '_>>,
+    old: &mut Vec<Breakpoint>,
+    new: Vec<Breakpoint>,
+) {
+    if let Some(ctx) = ctx {
+        for bp in old.iter() {
+            ctx.remove_breakpoint(*bp);
+        }
+        for bp in new.iter() {
+            ctx.add_breakpoint(*bp);
+        }
+    }
+    *old = new;
+}
+
+// State of debug session shared by the server and the debugger called from the machine
+struct Session<R, W> {
+    conn: Shared<R, W>,
+    source: SourceMap,
+    stop_on_entry: bool,
+    configured: bool,   // 'configurationDone' request was received
+    disconnected: bool, // 'disconnect' request was received or the connection was closed
+    last_resume: Resume,
+    line_breakpoints: Vec<Breakpoint>,
+    func_breakpoints: Vec<Breakpoint>,
+    next_breakpoint_id: i64,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead, W: Write> Session<R, W> {
+    fn new(conn: Shared<R, W>, source: SourceMap, stop_on_entry: bool) -> Self {
+        Self {
+            conn,
+            source,
+            stop_on_entry,
+            configured: false,
+            disconnected: false,
+            last_resume: Resume::StepIn,
+            line_breakpoints: vec![],
+            func_breakpoints: vec![],
+            next_breakpoint_id: 1,
+            error: None,
+        }
+    }
+
+    fn breakpoint(&mut self, verified: bool, line: Option<i64>, message: Option<String>) -> Json {
+        let mut fields = vec![
+            ("id", self.next_breakpoint_id.into()),
+            ("verified", verified.into()),
+        ];
+        self.next_breakpoint_id += 1;
+        if let Some(line) = line {
+            fields.push(("line", line.into()));
+        }
+        if let Some(message) = message {
+            fields.push(("message", message.into()));
+        }
+        Json::object(fields)
+    }
+
+    fn set_breakpoints(
+        &mut self,
+        req: &Request,
+        ctx: Option<&mut DebugContext<'_, '_, '_>>,
+    ) -> Json {
+        let file = req
+            .arg("source")
+            .and_then(|s| s.get("path"))
+            .and_then(Json::as_str)
+            .unwrap_or("");
+        let lines: Vec<_> = req
+            .arg("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|bp| bp.get("line").and_then(Json::as_i64))
+            .collect();
+
+        let mut bps = vec![];
+        let mut body = vec![];
+        for line in lines {
+            let json = match self.source.line_breakpoint(file, line) {
+                Ok((offset, line)) => {
+                    bps.push(Breakpoint::Offset(offset));
+                    self.breakpoint(true, Some(line), None)
+                }
+                Err(msg) => self.breakpoint(false, Some(line), Some(msg)),
+            };
+            body.push(json);
+        }
+        replace_breakpoints(ctx, &mut self.line_breakpoints, bps);
+        Json::object(vec![("breakpoints", body.into())])
+    }
+
+    fn set_func_breakpoints(
+        &mut self,
+        req: &Request,
+        ctx: Option<&mut DebugContext<'_, '_, '_>>,
+    ) -> Json {
+        let names: Vec<_> = req
+            .arg("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|bp| bp.get("name").and_then(Json::as_str))
+            .collect();
+
+        let mut bps = vec![];
+        let mut body = vec![];
+        for name in names {
+            let funcidx = ctx.as_ref().and_then(|ctx| find_func(ctx.module(), name));
+            let json = match funcidx {
+                Some(idx) => {
+                    bps.push(Breakpoint::Func(idx));
+                    self.breakpoint(true, None, None)
+                }
+                None if ctx.is_none() => {
+                    self.breakpoint(false, None, Some("Program is not running".to_string()))
+                }
+                None => self.breakpoint(false, None, Some(format!("No function '{}'", name))),
+            };
+            body.push(json);
+        }
+        replace_breakpoints(ctx, &mut self.func_breakpoints, bps);
+        Json::object(vec![("breakpoints", body.into())])
+    }
+
+    fn stack_trace(&self, req: &Request, ctx: &DebugContext<'_, '_, '_>) -> Json {
+        let frames = ctx.backtrace();
+        let start = req.arg("startFrame").and_then(Json::as_i64).unwrap_or(0) as usize;
+        let levels = match req.arg("levels").and_then(Json::as_i64) {
+            Some(levels) if levels > 0 => levels as usize,
+            _ => frames.len(),
+        };
+
+        let mut body = vec![];
+        for (i, frame) in frames.iter().enumerate().skip(start).take(levels) {
+            let name = match &frame.name {
+                Some(name) => name.clone(),
+                None => format!("function {}", frame.funcidx),
+            };
+            let mut fields = vec![
+                ("id", (i as i64).into()),
+                ("name", name.into()),
+                (
+                    "instructionPointerReference",
+                    format!("0x{:x}", frame.offset).into(),
+                ),
+            ];
+            match self.source.location(frame.offset) {
+                Some((path, line, column)) => {
+                    let name = match Path::new(&path).file_name() {
+                        Some(name) => name.to_string_lossy().into_owned(),
+                        None => path.clone(),
+                    };
+                    let source = Json::object(vec![("name", name.into()), ("path", path.into())]);
+                    fields.push(("source", source));
+                    fields.push(("line", line.into()));
+                    fields.push(("column", column.into()));
+                }
+                None => {
+                    fields.push(("line", 0.into()));
+                    fields.push(("column", 0.into()));
+                }
+            }
+            body.push(Json::object(fields));
+        }
+
+        Json::object(vec![
+            ("stackFrames", body.into()),
+            ("totalFrames", (frames.len() as i64).into()),
+        ])
+    }
+
+    fn scopes(&self, req: &Request) -> Json {
+        let scope = |name: &str, reference: i64| {
+            Json::object(vec![
+                ("name", name.into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ])
+        };
+        let mut scopes = vec![];
+        if req.arg("frameId").and_then(Json::as_i64) == Some(0) {
+            scopes.push(scope("Locals", LOCALS));
+            scopes.push(scope("Operand Stack", OPERANDS));
+        }
+        scopes.push(scope("Globals", GLOBALS));
+        Json::object(vec![("scopes", scopes.into())])
+    }
+
+    fn variables(&self, req: &Request, ctx: &DebugContext<'_, '_, '_>) -> Result<Json, String> {
+        let module = ctx.module();
+        let vars: Vec<_> = match req.arg("variablesReference").and_then(Json::as_i64) {
+            Some(LOCALS) => {
+                let funcidx = ctx.funcidx();
+                ctx.locals()
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| variable(local_name(module, funcidx, i as u32), v))
+                    .collect()
+            }
+            // Top of stack comes first
+            Some(OPERANDS) => ctx
+                .operands()
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(i, v)| variable(i.to_string(), v))
+                .collect(),
+            Some(GLOBALS) => ctx
+                .globals()
+                .iter()
+                .enumerate()
+                .map(|(i, v)| variable(global_name(module, i as u32), v))
+                .collect(),
+            _ => return Err("Unknown variables reference".to_string()),
+        };
+        Ok(Json::object(vec![("variables", vars.into())]))
+    }
+
+    fn set_variable(
+        &self,
+        req: &Request,
+        ctx: &mut DebugContext<'_, '_, '_>,
+    ) -> Result<Json, String> {
+        let module = ctx.module();
+        let name = req.arg("name").and_then(Json::as_str).unwrap_or("");
+        let value = req.arg("value").and_then(Json::as_str).unwrap_or("").trim();
+        let parse =
+            |ty| parse_value(ty, value).ok_or_else(|| format!("Invalid {} value '{}'", ty, value));
+
+        let value = match req.arg("variablesReference").and_then(Json::as_i64) {
+            Some(LOCALS) => {
+                let funcidx = ctx.funcidx();
+                let idx = (0..ctx.frame().num_locals() as u32)
+                    .find(|i| local_name(module, funcidx, *i) == name)
+                    .ok_or_else(|| format!("No local variable '{}'", name))?;
+                let value = parse(ctx.frame().local_type(idx))?;
+                ctx.set_local(idx, value.clone());
+                value
+            }
+            Some(GLOBALS) => {
+                let idx = (0..module.globals.len() as u32)
+                    .find(|i| global_name(module, *i) == name)
+                    .ok_or_else(|| format!("No global variable '{}'", name))?;
+                let value = parse(module.globals[idx as usize].ty)?;
+                if !ctx.set_global(idx, value.clone()) {
+                    return Err(format!("Global variable '{}' is immutable", name));
+                }
+                value
+            }
+            _ => return Err("Only locals and globals can be modified".to_string()),
+        };
+
+        Ok(Json::object(vec![
+            ("value", value_text(&value).into()),
+            ("type", value.valtype().to_string().into()),
+            ("variablesReference", 0.into()),
+        ]))
+    }
+
+    // Handle one request. Returns how to resume the execution when the request resumes it
+    fn handle(
+        &mut self,
+        req: &Request,
+        ctx: Option<&mut DebugContext<'_, '_, '_>>,
+    ) -> io::Result<Option<Resume>> {
+        let resumed = |resume| {
+            let body = Json::object(vec![("allThreadsContinued", true.into())]);
+            Ok((body, Some(resume)))
+        };
+        let result = match (req.command.as_str(), ctx) {
+            ("setBreakpoints", ctx) => Ok((self.set_breakpoints(req, ctx), None)),
+            ("setFunctionBreakpoints", ctx) => Ok((self.set_func_breakpoints(req, ctx), None)),
+            ("configurationDone", _) => {
+                self.configured = true;
+                Ok((Json::Null, None))
+            }
+            ("threads", _) => {
+                let thread = Json::object(vec![("id", THREAD_ID.into()), ("name", "main".into())]);
+                Ok((Json::object(vec![("threads", vec![thread].into())]), None))
+            }
+            ("terminate", _) => Ok((Json::Null, Some(Resume::Terminate))),
+            ("disconnect", _) => {
+                self.disconnected = true;
+                Ok((Json::Null, Some(Resume::Terminate)))
+            }
+            ("scopes", _) => Ok((self.scopes(req), None)),
+            ("stackTrace", Some(ctx)) => Ok((self.stack_trace(req, ctx), None)),
+            ("variables", Some(ctx)) => self.variables(req, ctx).map(|body| (body, None)),
+            ("setVariable", Some(ctx)) => self.set_variable(req, ctx).map(|body| (body, None)),
+            ("continue", Some(_)) => resumed(Resume::Continue),
+            ("next", Some(_)) => resumed(Resume::StepOver),
+            ("stepIn", Some(_)) => resumed(Resume::StepIn),
+            ("stepOut", Some(_)) => resumed(Resume::StepOut),
+            ("stackTrace", None)
+            | ("variables", None)
+            | ("setVariable", None)
+            | ("continue", None)
+            | ("next", None)
+            | ("stepIn", None)
+            | ("stepOut", None) => Err("Program is not paused".to_string()),
+            (cmd, _) => Err(format!("Unsupported request '{}'", cmd)),
+        };
+
+        let mut conn = self.conn.lock().unwrap();
+        match result {
+            Ok((body, resume)) => {
+                conn.respond(req, body)?;
+                Ok(resume)
+            }
+            Err(msg) => {
+                conn.respond_error(req, &msg)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn next_request(
+        &mut self,
+        ctx: Option<&mut DebugContext<'_, '_, '_>>,
+    ) -> io::Result<Option<Resume>> {
+        let req = self.conn.lock().unwrap().read_request()?;
+        match req {
+            Some(req) => self.handle(&req, ctx),
+            None => {
+                self.disconnected = true;
+                Ok(Some(Resume::Terminate))
+            }
+        }
+    }
+
+    fn pause(&mut self, ctx: &mut DebugContext<'_, '_, '_>) -> io::Result<Resume> {
+        let reason = if self.configured {
+            if self.last_resume == Resume::Continue {
+                "breakpoint"
+            } else {
+                "step"
+            }
+        } else {
+            // Configuration requests are handled at the first instruction since breakpoints need to
+            // be set to the running machine
+            while !self.configured {
+                if let Some(resume) = self.next_request(Some(ctx))? {
+                    return Ok(resume);
+                }
+            }
+            let at_breakpoint = ctx.breakpoints().iter().any(|bp| match bp {
+                Breakpoint::Func(idx) => *idx == ctx.funcidx(),
+                Breakpoint::Offset(offset) => *offset == ctx.instruction().start,
+            });
+            if self.stop_on_entry {
+                "entry"
+            } else if at_breakpoint {
+                "breakpoint"
+            } else {
+                self.last_resume = Resume::Continue;
+                return Ok(Resume::Continue);
+            }
+        };
+
+        {
+            let mut conn = self.conn.lock().unwrap();
+            conn.flush_output()?;
+            let body = Json::object(vec![
+                ("reason", reason.into()),
+                ("threadId", THREAD_ID.into()),
+                ("allThreadsStopped", true.into()),
+            ]);
+            conn.event("stopped", body)?;
+        }
+
+        loop {
+            if let Some(resume) = self.next_request(Some(ctx))? {
+                self.last_resume = resume;
+                return Ok(resume);
+            }
+        }
+    }
+}
+
+// Debugger called from the machine. It handles requests from client while paused
+struct Adapter<R, W>(Arc<Mutex<Session<R, W>>>);
+
+impl<R: BufRead, W: Write> Debugger for Adapter<R, W> {
+    fn pause(&mut self, ctx: &mut DebugContext<'_, '_, '_>) -> Resume {
+        let mut session = self.0.lock().unwrap();
+        match session.pause(ctx) {
+            Ok(resume) => resume,
+            Err(err) => {
+                // Error is reported after the execution is terminated
+                session.error = Some(err);
+                Resume::Terminate
+            }
+        }
+    }
+}
+
+fn fail<R: BufRead, W: Write>(conn: &Shared<R, W>, req: &Request, msg: String) -> io::Result<bool> {
+    conn.lock().unwrap().respond_error(req, &msg)?;
+    Ok(false)
+}
+
+// Run the program with debugger until client disconnects. Returns false when launching the program
+// failed
+fn debug<R, W, S>(
+    conn: &Shared<R, W>,
+    req: &Request,
+    root: &Root<'_, S>,
+    source: SourceMap,
+    stop_on_entry: bool,
+) -> io::Result<bool>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+    S: Source,
+{
+    if let Err(err) = wain_validate::validate(root) {
+        let note = crate::unsupported_features_note(&root.module.target_features);
+        return fail(conn, req, format!("Error on validation: {}{}", err, note));
+    }
+    // STDIN is used for messages from client so the program cannot read it
+    let importer = DefaultImporter::with_stdio(io::empty(), Output(conn.clone()));
+    let mut machine = match Machine::instantiate(&root.module, importer) {
+        Ok(machine) => machine,
+        Err(trap) => return fail(conn, req, format!("Error on instantiation: {}", trap)),
+    };
+
+    {
+        let mut conn = conn.lock().unwrap();
+        conn.respond(req, Json::Null)?;
+        conn.event("initialized", Json::Null)?;
+    }
+
+    let session = Arc::new(Mutex::new(Session::new(
+        conn.clone(),
+        source,
+        stop_on_entry,
+    )));
+    machine.set_resume(Resume::StepIn);
+    machine.set_debugger(Adapter(session.clone()));
+    let result = machine.execute();
+
+    let mut session = session.lock().unwrap();
+    if let Some(err) = session.error.take() {
+        return Err(err);
+    }
+    // Configuration requests were not handled when the program did not execute any instruction
+    while !session.configured && !session.disconnected {
+        session.next_request(None)?;
+    }
+    if session.disconnected {
+        return Ok(true);
+    }
+
+    {
+        let mut conn = conn.lock().unwrap();
+        conn.flush_output()?;
+        let exited = |code: i64| Json::object(vec![("exitCode", code.into())]);
+        match result {
+            Ok(run) => {
+                if let Run::Warning(msg) = run {
+                    conn.output("console", format!("Warning: {}\n", msg))?;
+                }
+                conn.event("exited", exited(0))?;
+            }
+            Err(err) if matches!(err.stop(), Some(StopReason::TerminatedByDebugger)) => {}
+            Err(err) => {
+                conn.output("stderr", format!("Error on running wasm: {}\n", err))?;
+                conn.event("exited", exited(1))?;
+            }
+        }
+        conn.event("terminated", Json::Null)?;
+    }
+
+    while !session.disconnected {
+        session.next_request(None)?;
+    }
+    Ok(true)
+}
+
+#[cfg(feature = "text")]
+fn launch_text<R, W>(
+    conn: &Shared<R, W>,
+    req: &Request,
+    program: &str,
+    stop: bool,
+) -> io::Result<bool>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let text = match fs::read_to_string(program) {
+        Ok(text) => text,
+        Err(err) => return fail(conn, req, format!("Cannot read '{}': {}", program, err)),
+    };
+    let root = match wain_syntax_text::parse_with_id_names(&text) {
+        Ok(root) => root,
+        Err(err) => return fail(conn, req, format!("Error on parsing: {}", err)),
+    };
+    let source = SourceMap::text(program, &text, &root.module);
+    debug(conn, req, &root, source, stop)
+}
+#[cfg(not(feature = "text"))]
+fn launch_text<R, W>(conn: &Shared<R, W>, req: &Request, _: &str, _: bool) -> io::Result<bool>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let msg = "Text format is not supported since 'text' feature is disabled";
+    fail(conn, req, msg.to_string())
+}
+
+#[cfg(feature = "binary")]
+fn launch_binary<R, W>(
+    conn: &Shared<R, W>,
+    req: &Request,
+    program: &str,
+    stop: bool,
+) -> io::Result<bool>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let bin = match fs::read(program) {
+        Ok(bin) => bin,
+        Err(err) => return fail(conn, req, format!("Cannot read '{}': {}", program, err)),
+    };
+    let root = match wain_syntax_binary::parse(&bin) {
+        Ok(root) => root,
+        Err(err) => {
+            let features = wain_syntax_binary::target_features(&bin).unwrap_or_default();
+            let note = crate::unsupported_features_note(&features);
+            return fail(conn, req, format!("Error on parsing: {}{}", err, note));
+        }
+    };
+    let info = wain_dwarf::DebugInfo::from_binary(&bin).ok().flatten();
+    debug(conn, req, &root, SourceMap::Binary(info), stop)
+}
+#[cfg(not(feature = "binary"))]
+fn launch_binary<R, W>(conn: &Shared<R, W>, req: &Request, _: &str, _: bool) -> io::Result<bool>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let msg = "Binary format is not supported since 'binary' feature is disabled";
+    fail(conn, req, msg.to_string())
+}
+
+// Serve a debug session. Program is specified by 'launch' request
+pub fn serve<R, W>(reader: R, writer: W) -> io::Result<()>
+where
+    R: BufRead + Send + 'static,
+    W: Write + Send + 'static,
+{
+    let conn = Arc::new(Mutex::new(Connection::new(reader, writer)));
+    loop {
+        let req = conn.lock().unwrap().read_request()?;
+        let req = match req {
+            Some(req) => req,
+            None => return Ok(()),
+        };
+        match req.command.as_str() {
+            "initialize" => conn.lock().unwrap().respond(&req, capabilities())?,
+            "launch" => {
+                let program = match req.arg("program").and_then(Json::as_str) {
+                    Some(program) => program.to_string(),
+                    None => {
+                        fail(&conn, &req, "'program' argument is required".to_string())?;
+                        continue;
+                    }
+                };
+                let stop = req
+                    .arg("stopOnEntry")
+                    .and_then(Json::as_bool)
+                    .unwrap_or(false);
+                let done = if program.ends_with(".wasm") {
+                    launch_binary(&conn, &req, &program, stop)?
+                } else {
+                    launch_text(&conn, &req, &program, stop)?
+                };
+                if done {
+                    return Ok(());
+                }
+            }
+            "disconnect" => {
+                conn.lock().unwrap().respond(&req, Json::Null)?;
+                return Ok(());
+            }
+            cmd => {
+                let msg = format!("Request '{}' is not available before launch", cmd);
+                conn.lock().unwrap().respond_error(&req, &msg)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const PROGRAM: &str = r#"(module
+  (import "env" "putchar" (func $putchar (param i32) (result i32)))
+  (global $g (mut i32) (i32.const 10))
+  (func $add (param $a i32) (param $b i32) (result i32)
+    local.get $a
+    local.get $b
+    i32.add)
+  (func $start
+    i32.const 1
+    i32.const 2
+    call $add
+    global.set $g
+    i32.const 65
+    call $putchar
+    drop)
+  (start $start))
+"#;
+
+    // Run the server with scripted requests and returns all messages sent from the server
+    fn run_session(requests: &[(&str, Json)]) -> Vec<Json> {
+        let mut input = vec![];
+        for (i, (command, arguments)) in requests.iter().enumerate() {
+            let msg = Json::object(vec![
+                ("seq", (i as i64 + 1).into()),
+                ("type", "request".into()),
+                ("command", (*command).into()),
+                ("arguments", arguments.clone()),
+            ])
+            .to_string();
+            write!(input, "Content-Length: {}\r\n\r\n{}", msg.len(), msg).unwrap();
+        }
+
+        let output = SharedBuf::default();
+        serve(Cursor::new(input), output.clone()).unwrap();
+
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        let mut msgs = vec![];
+        let mut rest = output.as_str();
+        while !rest.is_empty() {
+            let header_end = rest.find("\r\n\r\n").unwrap();
+            let len: usize = rest[..header_end]
+                .strip_prefix("Content-Length: ")
+                .unwrap()
+                .parse()
+                .unwrap();
+            let body = &rest[header_end + 4..header_end + 4 + len];
+            msgs.push(Json::parse(body).unwrap());
+            rest = &rest[header_end + 4 + len..];
+        }
+        msgs
+    }
+
+    fn kind(msg: &Json) -> String {
+        let ty = msg.get("type").and_then(Json::as_str).unwrap();
+        let name = match ty {
+            "event" => msg.get("event"),
+            _ => msg.get("command"),
+        };
+        format!("{} {}", ty, name.and_then(Json::as_str).unwrap())
+    }
+
+    fn body(msgs: &[Json], request_seq: i64) -> &Json {
+        let msg = msgs
+            .iter()
+            .find(|m| m.get("request_seq").and_then(Json::as_i64) == Some(request_seq))
+            .unwrap();
+        assert_eq!(msg.get("success"), Some(&Json::Bool(true)), "{}", msg);
+        msg.get("body").unwrap()
+    }
+
+    fn values(vars: &Json) -> Vec<(String, String)> {
+        vars.get("variables")
+            .and_then(Json::as_array)
+            .unwrap()
+            .iter()
+            .map(|v| {
+                let name = v.get("name").and_then(Json::as_str).unwrap();
+                let value = v.get("value").and_then(Json::as_str).unwrap();
+                (name.to_string(), value.to_string())
+            })
+            .collect()
+    }
+
+    fn write_program(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("wain-dap-test-{}.wat", name));
+        fs::write(&path, PROGRAM).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn obj(fields: Vec<(&str, Json)>) -> Json {
+        Json::object(fields)
+    }
+
+    #[test]
+    fn debug_session() {
+        let path = write_program("session");
+        let source = obj(vec![("path", path.as_str().into())]);
+        let line = |l: i64| obj(vec![("line", l.into())]);
+        let msgs = run_session(&[
+            ("initialize", obj(vec![("adapterID", "wain".into())])),
+            ("launch", obj(vec![("program", path.as_str().into())])),
+            (
+                "setBreakpoints",
+                obj(vec![
+                    ("source", source),
+                    ("breakpoints", vec![line(4), line(100)].into()),
+                ]),
+            ),
+            (
+                "setFunctionBreakpoints",
+                obj(vec![(
+                    "breakpoints",
+                    vec![obj(vec![("name", "nope".into())])].into(),
+                )]),
+            ),
+            ("configurationDone", Json::Null),
+            ("threads", Json::Null),
+            ("stackTrace", obj(vec![("threadId", 1.into())])),
+            ("scopes", obj(vec![("frameId", 0.into())])),
+            (
+                "variables",
+                obj(vec![("variablesReference", LOCALS.into())]),
+            ),
+            (
+                "setVariable",
+                obj(vec![
+                    ("variablesReference", LOCALS.into()),
+                    ("name", "$b".into()),
+                    ("value", "40".into()),
+                ]),
+            ),
+            ("next", obj(vec![("threadId", 1.into())])),
+            (
+                "variables",
+                obj(vec![("variablesReference", OPERANDS.into())]),
+            ),
+            ("stepOut", obj(vec![("threadId", 1.into())])),
+            (
+                "variables",
+                obj(vec![("variablesReference", OPERANDS.into())]),
+            ),
+            ("continue", obj(vec![("threadId", 1.into())])),
+            ("disconnect", Json::Null),
+        ]);
+        fs::remove_file(&path).unwrap();
+
+        let kinds: Vec<_> = msgs.iter().map(kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                "response initialize",
+                "response launch",
+                "event initialized",
+                "response setBreakpoints",
+                "response setFunctionBreakpoints",
+                "response configurationDone",
+                "event stopped",
+                "response threads",
+                "response stackTrace",
+                "response scopes",
+                "response variables",
+                "response setVariable",
+                "response next",
+                "event stopped",
+                "response variables",
+                "response stepOut",
+                "event stopped",
+                "response variables",
+                "response continue",
+                "event output",
+                "event exited",
+                "event terminated",
+                "response disconnect",
+            ],
+        );
+
+        let bps = body(&msgs, 3)
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap();
+        assert_eq!(bps[0].get("verified"), Some(&Json::Bool(true)));
+        assert_eq!(bps[0].get("line").and_then(Json::as_i64), Some(5));
+        assert_eq!(bps[1].get("verified"), Some(&Json::Bool(false)));
+        let bps = body(&msgs, 4)
+            .get("breakpoints")
+            .and_then(Json::as_array)
+            .unwrap();
+        assert_eq!(bps[0].get("verified"), Some(&Json::Bool(false)));
+
+        let stopped: Vec<_> = msgs
+            .iter()
+            .filter(|m| m.get("event").and_then(Json::as_str) == Some("stopped"))
+            .map(|m| {
+                m.get("body")
+                    .unwrap()
+                    .get("reason")
+                    .and_then(Json::as_str)
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(stopped, ["breakpoint", "step", "step"]);
+
+        let frames = body(&msgs, 7)
+            .get("stackFrames")
+            .and_then(Json::as_array)
+            .unwrap();
+        let frames: Vec<_> = frames
+            .iter()
+            .map(|f| {
+                let name = f.get("name").and_then(Json::as_str).unwrap();
+                let line = f.get("line").and_then(Json::as_i64).unwrap();
+                let column = f.get("column").and_then(Json::as_i64).unwrap();
+                let source = f.get("source").unwrap().get("path");
+                assert_eq!(source.and_then(Json::as_str), Some(path.as_str()));
+                (name.to_string(), line, column)
+            })
+            .collect();
+        assert_eq!(
+            frames,
+            [("add".to_string(), 5, 5), ("start".to_string(), 11, 5)]
+        );
+
+        let scopes = body(&msgs, 8)
+            .get("scopes")
+            .and_then(Json::as_array)
+            .unwrap();
+        assert_eq!(scopes.len(), 3);
+
+        let s = |n: &str, v: &str| (n.to_string(), v.to_string());
+        assert_eq!(values(body(&msgs, 9)), [s("$a", "1"), s("$b", "2")]);
+        assert_eq!(values(body(&msgs, 12)), [s("0", "1")]);
+        assert_eq!(values(body(&msgs, 14)), [s("0", "41")]);
+
+        let output = msgs.iter().find(|m| kind(m) == "event output").unwrap();
+        let output = output.get("body").unwrap();
+        assert_eq!(
+            output.get("category").and_then(Json::as_str),
+            Some("stdout")
+        );
+        assert_eq!(output.get("output").and_then(Json::as_str), Some("A"));
+
+        let exited = msgs.iter().find(|m| kind(m) == "event exited").unwrap();
+        let code = exited.get("body").unwrap().get("exitCode");
+        assert_eq!(code.and_then(Json::as_i64), Some(0));
+    }
+
+    #[test]
+    fn launch_error_and_terminate() {
+        let path = write_program("terminate");
+        let msgs = run_session(&[
+            ("initialize", Json::Null),
+            ("launch", obj(vec![("program", "/no/such/file.wat".into())])),
+            (
+                "launch",
+                obj(vec![
+                    ("program", path.as_str().into()),
+                    ("stopOnEntry", true.into()),
+                ]),
+            ),
+            ("configurationDone", Json::Null),
+            ("variables", obj(vec![("variablesReference", 42.into())])),
+            ("terminate", Json::Null),
+            ("continue", obj(vec![("threadId", 1.into())])),
+            ("disconnect", Json::Null),
+        ]);
+        fs::remove_file(&path).unwrap();
+
+        let kinds: Vec<_> = msgs.iter().map(kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                "response initialize",
+                "response launch",
+                "response launch",
+                "event initialized",
+                "response configurationDone",
+                "event stopped",
+                "response variables",
+                "response terminate",
+                "event terminated",
+                "response continue",
+                "response disconnect",
+            ],
+        );
+
+        let success: Vec<_> = msgs
+            .iter()
+            .filter_map(|m| m.get("success").and_then(Json::as_bool))
+            .collect();
+        assert_eq!(success, [true, false, true, true, false, true, false, true],);
+        let reason = msgs[5].get("body").unwrap().get("reason");
+        assert_eq!(reason.and_then(Json::as_str), Some("entry"));
+    }
+}
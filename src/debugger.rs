@@ -0,0 +1,530 @@
+// Interactive command line debugger for 'wain debug' subcommand
+
+use std::io::{self, BufRead, Write};
+use std::process::exit;
+use wain_ast::{InsnKind, Instruction, Module, ValType};
+use wain_exec::trap::{Frame, Result};
+use wain_exec::{Breakpoint, DebugContext, Debugger, DefaultImporter, Machine, Resume, Run, Value};
+
+const HELP: &str = "\
+COMMANDS:
+    run | r                      : Start execution
+    start                        : Start execution and pause at the first instruction
+    break | b {func}             : Set breakpoint at function name or index
+    break | b *{offset}          : Set breakpoint at instruction at byte offset
+    delete | d [{num}]           : Delete breakpoint (all breakpoints without argument)
+    continue | c                 : Continue execution until next breakpoint
+    step | s                     : Step to next instruction stepping into function calls
+    next | n                     : Step to next instruction stepping over function calls
+    finish                       : Run until current function returns
+    info breakpoints | i b       : Show breakpoints
+    info locals | i l            : Show parameters and local variables
+    info stack | i s             : Show values on operand stack
+    info globals | i g           : Show global variables
+    backtrace | bt               : Show call stack
+    memory | x {addr} [{len}]    : Show memory contents at address
+    set local {idx} {value}      : Set value to local variable
+    set global {idx} {value}     : Set value to global variable
+    set memory {addr} {byte}...  : Set bytes to memory at address
+    help | h                     : Show this help
+    quit | q                     : Quit debugger
+
+Empty line repeats the previous command. Numbers can be written in hex with '0x' prefix.";
+
+// Source location of the byte offset such as 'foo.wat:3:5'
+pub type Locate = Box<dyn Fn(usize) -> Option<String> + Send>;
+
+enum Action {
+    Resume(Resume),
+    Prompt,
+}
+
+// Breakpoints before starting execution are held by debugger. After that, they are held by machine
+enum Target<'t, 'a, 'f, 's> {
+    NotStarted(&'t mut Vec<Breakpoint>),
+    Paused(&'t mut DebugContext<'a, 'f, 's>),
+}
+
+impl<'t, 'a, 'f, 's> Target<'t, 'a, 'f, 's> {
+    fn breakpoints(&self) -> &[Breakpoint] {
+        match self {
+            Target::NotStarted(bps) => bps,
+            Target::Paused(ctx) => ctx.breakpoints(),
+        }
+    }
+
+    fn add_breakpoint(&mut self, bp: Breakpoint) -> bool {
+        match self {
+            Target::NotStarted(bps) if bps.contains(&bp) => false,
+            Target::NotStarted(bps) => {
+                bps.push(bp);
+                true
+            }
+            Target::Paused(ctx) => ctx.add_breakpoint(bp),
+        }
+    }
+
+    fn remove_breakpoint(&mut self, bp: Breakpoint) {
+        match self {
+            Target::NotStarted(bps) => bps.retain(|b| *b != bp),
+            Target::Paused(ctx) => {
+                ctx.remove_breakpoint(bp);
+            }
+        }
+    }
+}
+
+fn parse_int(s: &str) -> Option<u64> {
+    if let Some(hex) = s.strip_prefix("0x") {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+pub fn parse_value(ty: ValType, s: &str) -> Option<Value> {
+    let (neg, abs) = match s.strip_prefix('-') {
+        Some(abs) => (true, abs),
+        None => (false, s),
+    };
+    let int = || {
+        let i = parse_int(abs)? as i64;
+        Some(if neg { i.wrapping_neg() } else { i })
+    };
+    match ty {
+        ValType::I32 => int().map(|i| Value::I32(i as i32)),
+        ValType::I64 => int().map(Value::I64),
+        ValType::F32 => s.parse().ok().map(Value::F32),
+        ValType::F64 => s.parse().ok().map(Value::F64),
+    }
+}
+
+// Instruction in text format with its immediates
+pub fn insn_text(kind: &InsnKind) -> String {
+    use InsnKind::*;
+    let name = kind.name();
+    match kind {
+        Br(idx) | BrIf(idx) => format!("{} {}", name, idx),
+        BrTable {
+            labels,
+            default_label,
+        } => {
+            let mut s = name.to_string();
+            for label in labels.iter().chain(Some(default_label)) {
+                s.push_str(&format!(" {}", label));
+            }
+            s
+        }
+        Call(idx) | LocalGet(idx) | LocalSet(idx) | LocalTee(idx) | GlobalGet(idx)
+        | GlobalSet(idx) => format!("{} {}", name, idx),
+        CallIndirect(idx) => format!("{} (type {})", name, idx),
+        I32Load(m) | I64Load(m) | F32Load(m) | F64Load(m) | I32Load8S(m) | I32Load8U(m)
+        | I32Load16S(m) | I32Load16U(m) | I64Load8S(m) | I64Load8U(m) | I64Load16S(m)
+        | I64Load16U(m) | I64Load32S(m) | I64Load32U(m) | I32Store(m) | I64Store(m)
+        | F32Store(m) | F64Store(m) | I32Store8(m) | I32Store16(m) | I64Store8(m)
+        | I64Store16(m) | I64Store32(m) => match m.offset {
+            Some(offset) if offset != 0 => format!("{} offset={}", name, offset),
+            _ => name.to_string(),
+        },
+        I32Const(i) => format!("{} {}", name, i),
+        I64Const(i) => format!("{} {}", name, i),
+        F32Const(f) => format!("{} {}", name, f),
+        F64Const(f) => format!("{} {}", name, f),
+        _ => name.to_string(),
+    }
+}
+
+fn has_insn_at(insns: &[Instruction], offset: usize) -> bool {
+    insns.iter().any(|insn| {
+        insn.start == offset
+            || match &insn.kind {
+                InsnKind::Block { body, .. } | InsnKind::Loop { body, .. } => {
+                    has_insn_at(body, offset)
+                }
+                InsnKind::If {
+                    then_body,
+                    else_body,
+                    ..
+                } => has_insn_at(then_body, offset) || has_insn_at(else_body, offset),
+                _ => false,
+            }
+    })
+}
+
+pub fn func_name(module: &Module<'_>, funcidx: u32) -> String {
+    match module.names.func(funcidx) {
+        Some(name) => format!("'{}' (function {})", name.0, funcidx),
+        None => format!("function {}", funcidx),
+    }
+}
+
+// Lines of hex dump of the bytes starting at the address. Each line shows 16 bytes
+pub fn hex_lines(addr: usize, data: &[u8]) -> impl Iterator<Item = String> + '_ {
+    data.chunks(16).enumerate().map(move |(i, chunk)| {
+        let hex: Vec<_> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        format!(
+            "0x{:08x}: {:<47}  |{}|",
+            addr + i * 16,
+            hex.join(" "),
+            ascii
+        )
+    })
+}
+
+pub fn find_func(module: &Module<'_>, name: &str) -> Option<u32> {
+    if let Some(idx) = parse_int(name) {
+        return if (idx as usize) < module.funcs.len() {
+            Some(idx as u32)
+        } else {
+            None
+        };
+    }
+    let name = name.strip_prefix('$').unwrap_or(name);
+    if let Some((idx, _)) = module.names.funcs.iter().find(|(_, n)| n.0 == name) {
+        return Some(*idx);
+    }
+    module.exports.iter().find_map(|e| match e.kind {
+        wain_ast::ExportKind::Func(idx) if e.name.0 == name => Some(idx),
+        _ => None,
+    })
+}
+
+pub struct Cli {
+    locate: Locate,
+    last_command: String,
+}
+
+impl Cli {
+    pub fn new(locate: Locate) -> Self {
+        Self {
+            locate,
+            last_command: String::new(),
+        }
+    }
+
+    fn prompt(&mut self) -> String {
+        print!("(wain) ");
+        let _ = io::stdout().flush();
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => {
+                println!();
+                exit(0); // EOF
+            }
+            Ok(_) => {}
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            self.last_command.clone()
+        } else {
+            self.last_command = line.to_string();
+            line.to_string()
+        }
+    }
+
+    fn location(&self, offset: usize) -> String {
+        match (self.locate)(offset) {
+            Some(loc) => format!(" ({})", loc),
+            None => String::new(),
+        }
+    }
+
+    fn breakpoint_text(&self, module: &Module<'_>, bp: &Breakpoint) -> String {
+        match bp {
+            Breakpoint::Func(idx) => func_name(module, *idx),
+            Breakpoint::Offset(offset) => {
+                format!("byte offset 0x{:x}{}", offset, self.location(*offset))
+            }
+        }
+    }
+
+    fn frame_text(&self, frame: &Frame) -> String {
+        format!(
+            "{} at byte offset 0x{:x}{}",
+            func_name_of(frame),
+            frame.offset,
+            self.location(frame.offset)
+        )
+    }
+
+    fn print_position(&self, ctx: &DebugContext<'_, '_, '_>) {
+        let insn = ctx.instruction();
+        println!(
+            "Paused in {} at byte offset 0x{:x}{}",
+            func_name(ctx.module(), ctx.funcidx()),
+            insn.start,
+            self.location(insn.start)
+        );
+        println!("  => {}", insn_text(&insn.kind));
+    }
+
+    fn command(
+        &self,
+        line: &str,
+        module: &Module<'_>,
+        mut target: Target<'_, '_, '_, '_>,
+    ) -> std::result::Result<Action, String> {
+        let words: Vec<_> = line.split_whitespace().collect();
+        let running = matches!(target, Target::Paused(_));
+        let resume = |resume| {
+            if running {
+                Ok(Action::Resume(resume))
+            } else {
+                Err("Program is not running. Use 'run' or 'start' command".to_string())
+            }
+        };
+        match words.as_slice() {
+            [] => {}
+            ["run"] | ["r"] | ["start"] if running => {
+                return Err("Program is already running".to_string())
+            }
+            ["run"] | ["r"] => return Ok(Action::Resume(Resume::Continue)),
+            ["start"] => return Ok(Action::Resume(Resume::StepIn)),
+            ["continue"] | ["c"] => return resume(Resume::Continue),
+            ["step"] | ["s"] => return resume(Resume::StepIn),
+            ["next"] | ["n"] => return resume(Resume::StepOver),
+            ["finish"] => return resume(Resume::StepOut),
+            ["break", arg] | ["b", arg] => {
+                let bp = if let Some(offset) = arg.strip_prefix('*') {
+                    let offset = parse_int(offset)
+                        .ok_or_else(|| format!("Invalid byte offset '{}'", offset))?
+                        as usize;
+                    let found = module.funcs.iter().any(|f| match &f.kind {
+                        wain_ast::FuncKind::Body { expr, .. } => has_insn_at(expr, offset),
+                        wain_ast::FuncKind::Import(_) => false,
+                    });
+                    if !found {
+                        return Err(format!("No instruction at byte offset 0x{:x}", offset));
+                    }
+                    Breakpoint::Offset(offset)
+                } else {
+                    let idx =
+                        find_func(module, arg).ok_or_else(|| format!("No function '{}'", arg))?;
+                    Breakpoint::Func(idx)
+                };
+                if !target.add_breakpoint(bp) {
+                    return Err("Breakpoint already exists".to_string());
+                }
+                let num = target.breakpoints().len();
+                println!("Breakpoint #{}: {}", num, self.breakpoint_text(module, &bp));
+            }
+            ["delete"] | ["d"] => {
+                for bp in target.breakpoints().to_vec() {
+                    target.remove_breakpoint(bp);
+                }
+            }
+            ["delete", num] | ["d", num] => {
+                let bp = parse_int(num)
+                    .and_then(|n| target.breakpoints().get((n as usize).checked_sub(1)?))
+                    .copied()
+                    .ok_or_else(|| format!("No breakpoint #{}", num))?;
+                target.remove_breakpoint(bp);
+            }
+            ["info", "breakpoints"] | ["info", "b"] | ["i", "breakpoints"] | ["i", "b"] => {
+                if target.breakpoints().is_empty() {
+                    println!("No breakpoints");
+                }
+                for (i, bp) in target.breakpoints().iter().enumerate() {
+                    println!("#{}: {}", i + 1, self.breakpoint_text(module, bp));
+                }
+            }
+            ["help"] | ["h"] => println!("{}", HELP),
+            ["quit"] | ["q"] => {
+                let _ = io::stdout().flush();
+                exit(0);
+            }
+            words => {
+                if let Target::Paused(ctx) = target {
+                    return self.inspect(words, module, ctx);
+                }
+                return Err(format!(
+                    "Unknown command '{}' or program is not running. Type 'help' to see commands",
+                    line
+                ));
+            }
+        }
+        Ok(Action::Prompt)
+    }
+
+    // Commands available only while program is paused
+    fn inspect(
+        &self,
+        words: &[&str],
+        module: &Module<'_>,
+        ctx: &mut DebugContext<'_, '_, '_>,
+    ) -> std::result::Result<Action, String> {
+        match words {
+            ["info", "locals"] | ["info", "l"] | ["i", "locals"] | ["i", "l"] => {
+                let funcidx = ctx.funcidx();
+                for (i, v) in ctx.locals().iter().enumerate() {
+                    match module.names.local(funcidx, i as u32) {
+                        Some(name) => println!("{} ${}: {}", i, name.0, v),
+                        None => println!("{}: {}", i, v),
+                    }
+                }
+            }
+            ["info", "stack"] | ["info", "s"] | ["i", "stack"] | ["i", "s"] => {
+                let operands = ctx.operands();
+                if operands.is_empty() {
+                    println!("Operand stack is empty");
+                }
+                // Top of stack comes first
+                for (i, v) in operands.iter().rev().enumerate() {
+                    println!("{}: {}", i, v);
+                }
+            }
+            ["info", "globals"] | ["info", "g"] | ["i", "globals"] | ["i", "g"] => {
+                for (i, (v, g)) in ctx.globals().iter().zip(module.globals.iter()).enumerate() {
+                    let name = match module
+                        .names
+                        .globals
+                        .iter()
+                        .find(|(idx, _)| *idx == i as u32)
+                    {
+                        Some((_, name)) => format!(" ${}", name.0),
+                        None => String::new(),
+                    };
+                    let mutable = if g.mutable { " (mut)" } else { "" };
+                    println!("{}{}: {}{}", i, name, v, mutable);
+                }
+            }
+            ["backtrace"] | ["bt"] => {
+                for (i, frame) in ctx.backtrace().iter().enumerate() {
+                    println!("#{}: {}", i, self.frame_text(frame));
+                }
+            }
+            ["memory", addr] | ["x", addr] => self.dump_memory(ctx, addr, "64")?,
+            ["memory", addr, len] | ["x", addr, len] => self.dump_memory(ctx, addr, len)?,
+            ["set", "local", idx, value] => {
+                let idx = parse_int(idx)
+                    .filter(|i| (*i as usize) < ctx.frame().num_locals())
+                    .ok_or_else(|| format!("No local variable {}", idx))?
+                    as u32;
+                let ty = ctx.frame().local_type(idx);
+                let value = parse_value(ty, value)
+                    .ok_or_else(|| format!("Invalid {} value '{}'", ty, value))?;
+                ctx.set_local(idx, value);
+            }
+            ["set", "global", idx, value] => {
+                let ty = parse_int(idx)
+                    .and_then(|i| module.globals.get(i as usize))
+                    .ok_or_else(|| format!("No global variable {}", idx))?
+                    .ty;
+                let value = parse_value(ty, value)
+                    .ok_or_else(|| format!("Invalid {} value '{}'", ty, value))?;
+                ctx.set_global(parse_int(idx).unwrap() as u32, value);
+            }
+            ["set", "memory", addr, bytes @ ..] if !bytes.is_empty() => {
+                let addr =
+                    parse_int(addr).ok_or_else(|| format!("Invalid address '{}'", addr))? as usize;
+                let bytes = bytes
+                    .iter()
+                    .map(|b| {
+                        parse_int(b)
+                            .filter(|b| *b <= 0xff)
+                            .map(|b| b as u8)
+                            .ok_or_else(|| format!("Invalid byte '{}'", b))
+                    })
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                let memory = ctx.memory_mut();
+                if !memory.write_range(addr, &bytes) {
+                    return Err(format!(
+                        "Address range 0x{:x}..0x{:x} is out of memory (size 0x{:x})",
+                        addr,
+                        addr.saturating_add(bytes.len()),
+                        memory.byte_len()
+                    ));
+                }
+            }
+            _ => {
+                return Err(format!(
+                    "Unknown command '{}'. Type 'help' to see commands",
+                    words.join(" ")
+                ))
+            }
+        }
+        Ok(Action::Prompt)
+    }
+
+    fn dump_memory(
+        &self,
+        ctx: &DebugContext<'_, '_, '_>,
+        addr: &str,
+        len: &str,
+    ) -> std::result::Result<(), String> {
+        let addr = parse_int(addr).ok_or_else(|| format!("Invalid address '{}'", addr))? as usize;
+        let len = parse_int(len).ok_or_else(|| format!("Invalid length '{}'", len))? as usize;
+        let memory = ctx.memory();
+        if addr >= memory.byte_len() {
+            return Err(format!(
+                "Address 0x{:x} is out of memory (size 0x{:x})",
+                addr,
+                memory.byte_len()
+            ));
+        }
+        let end = addr.saturating_add(len).min(memory.byte_len());
+        let data = memory.read_range(addr..end).unwrap();
+        for line in hex_lines(addr, &data) {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    // Read commands until execution starts, then execute the module with this debugger
+    pub fn run(mut self, module: &Module<'_>) -> Result<Run> {
+        println!("Type 'help' to see commands");
+        let mut breakpoints = vec![];
+        let resume = loop {
+            let line = self.prompt();
+            match self.command(&line, module, Target::NotStarted(&mut breakpoints)) {
+                Ok(Action::Resume(resume)) => break resume,
+                Ok(Action::Prompt) => {}
+                Err(msg) => println!("{}", msg),
+            }
+        };
+
+        // Note: Stdin and stdout are not locked since the debugger also uses them while paused
+        let importer = DefaultImporter::with_stdio(io::stdin(), io::stdout());
+        let mut machine = Machine::instantiate(module, importer)?;
+        for bp in breakpoints {
+            machine.add_breakpoint(bp);
+        }
+        machine.set_resume(resume);
+        machine.set_debugger(self);
+        let run = machine.execute().map_err(crate::trap_of)?;
+        println!("Program exited");
+        Ok(run)
+    }
+}
+
+fn func_name_of(frame: &Frame) -> String {
+    match &frame.name {
+        Some(name) => format!("'{}' (function {})", name, frame.funcidx),
+        None => format!("function {}", frame.funcidx),
+    }
+}
+
+impl Debugger for Cli {
+    fn pause(&mut self, ctx: &mut DebugContext<'_, '_, '_>) -> Resume {
+        self.print_position(ctx);
+        let module = ctx.module();
+        loop {
+            let line = self.prompt();
+            match self.command(&line, module, Target::Paused(ctx)) {
+                Ok(Action::Resume(resume)) => return resume,
+                Ok(Action::Prompt) => {}
+                Err(msg) => println!("{}", msg),
+            }
+        }
+    }
+}
@@ -0,0 +1,249 @@
+// Printer of imports, exports and sections of module for 'inspect' subcommand
+
+use crate::json::Json;
+use std::io::{self, Write};
+use wain_ast::info::ModuleInfo;
+use wain_ast::{ExternType, Limits, Module};
+
+// Size of section in binary format
+pub struct Section {
+    pub name: String,
+    pub custom: bool,
+    pub offset: usize,
+    pub size: usize,
+}
+
+#[cfg(feature = "binary")]
+pub fn sections(bin: &[u8]) -> wain_syntax_binary::Result<'_, Vec<Section>> {
+    use wain_syntax_binary::SectionKind;
+    let sections = wain_syntax_binary::sections(bin)?;
+    Ok(sections
+        .into_iter()
+        .map(|s| {
+            let (name, custom) = match s.kind {
+                SectionKind::Standard(id) => (id.to_string(), false),
+                SectionKind::Custom(name) => (name.0.into_owned(), true),
+            };
+            Section {
+                name,
+                custom,
+                offset: s.start,
+                size: s.size,
+            }
+        })
+        .collect())
+}
+
+fn limits_json(limit: &Limits) -> Vec<(&'static str, Json)> {
+    match limit {
+        Limits::Range(min, max) => {
+            vec![("min", (*min as i64).into()), ("max", (*max as i64).into())]
+        }
+        Limits::From(min) => vec![("min", (*min as i64).into()), ("max", Json::Null)],
+    }
+}
+
+fn type_json(ty: &ExternType<'_>) -> Json {
+    fn val_types(tys: &[wain_ast::ValType]) -> Json {
+        Json::Array(tys.iter().map(|t| t.as_ref().into()).collect())
+    }
+    let fields = match ty {
+        ExternType::Func(ty) => vec![
+            ("params", val_types(&ty.params)),
+            ("results", val_types(&ty.results)),
+        ],
+        ExternType::Table(ty) => {
+            let mut fields = limits_json(&ty.limit);
+            fields.push(("element", "funcref".into()));
+            fields
+        }
+        ExternType::Memory(ty) => limits_json(&ty.limit),
+        ExternType::Global { ty, mutable } => {
+            vec![("type", ty.as_ref().into()), ("mutable", (*mutable).into())]
+        }
+    };
+    Json::object(fields)
+}
+
+pub fn json(module: &Module<'_>, sections: &[Section], bin_size: usize) -> Json {
+    let info = ModuleInfo::new(module);
+    let imports = info
+        .imports
+        .iter()
+        .map(|i| {
+            Json::object(vec![
+                ("module", i.mod_name.into()),
+                ("name", i.name.into()),
+                ("kind", i.ty.kind().into()),
+                ("type", type_json(&i.ty)),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let exports = info
+        .exports
+        .iter()
+        .map(|e| {
+            Json::object(vec![
+                ("name", e.name.into()),
+                ("kind", e.ty.kind().into()),
+                ("type", type_json(&e.ty)),
+            ])
+        })
+        .collect::<Vec<_>>();
+    let sections = sections
+        .iter()
+        .map(|s| {
+            Json::object(vec![
+                ("name", s.name.as_str().into()),
+                ("custom", s.custom.into()),
+                ("offset", (s.offset as i64).into()),
+                ("size", (s.size as i64).into()),
+            ])
+        })
+        .collect::<Vec<_>>();
+    Json::object(vec![
+        ("imports", imports.into()),
+        ("exports", exports.into()),
+        ("sections", sections.into()),
+        ("size", (bin_size as i64).into()),
+    ])
+}
+
+pub fn inspect<W: Write>(
+    module: &Module<'_>,
+    sections: &[Section],
+    bin_size: usize,
+    mut out: W,
+) -> io::Result<()> {
+    let info = ModuleInfo::new(module);
+
+    writeln!(out, "Imports:")?;
+    for i in info.imports.iter() {
+        writeln!(out, "  {}.{}: {}", i.mod_name, i.name, i.ty)?;
+    }
+
+    writeln!(out, "\nExports:")?;
+    for e in info.exports.iter() {
+        writeln!(out, "  {}: {}", e.name, e.ty)?;
+    }
+
+    if !sections.is_empty() {
+        writeln!(out, "\nSections:")?;
+        for s in sections.iter() {
+            let kind = if s.custom { "custom " } else { "" };
+            writeln!(
+                out,
+                "  {}'{}' at 0x{:x}: {} bytes",
+                kind, s.name, s.offset, s.size
+            )?;
+        }
+        writeln!(out, "  Total: {} bytes", bin_size)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r#"
+(module
+  (import "env" "putchar" (func $putchar (param i32) (result i32)))
+  (import "env" "counter" (global (mut i64)))
+  (import "env" "memory" (memory 1 2))
+  (table 2 funcref)
+  (global $g f32 (f32.const 0))
+  (func $main (export "_start"))
+  (export "memory" (memory 0))
+  (export "table" (table 0))
+  (export "g" (global $g)))
+"#;
+
+    #[test]
+    fn inspect_module() {
+        let root = wain_syntax_text::parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let bin = wain_encode_binary::encode(&root.module);
+        let sections = sections(&bin).unwrap_or_else(|e| panic!("{}", e));
+
+        let mut out = vec![];
+        inspect(&root.module, &sections, bin.len(), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let expected = "\
+Imports:
+  env.putchar: func (param i32) (result i32)
+  env.counter: global (mut i64)
+  env.memory: memory 1 2
+
+Exports:
+  _start: func
+  memory: memory 1 2
+  table: table 2 funcref
+  g: global f32
+
+Sections:
+";
+        assert!(out.starts_with(expected), "{}", out);
+        assert!(out.contains("  'import' at 0x"), "{}", out);
+        assert!(
+            out.ends_with(&format!("  Total: {} bytes\n", bin.len())),
+            "{}",
+            out
+        );
+
+        let json = json(&root.module, &sections, bin.len());
+        let imports = json.get("imports").unwrap().as_array().unwrap();
+        assert_eq!(imports.len(), 3);
+        assert_eq!(imports[0].get("module").unwrap().as_str(), Some("env"));
+        assert_eq!(imports[0].get("kind").unwrap().as_str(), Some("func"));
+        let ty = imports[0].get("type").unwrap();
+        assert_eq!(ty.get("params").unwrap(), &Json::Array(vec!["i32".into()]));
+        let ty = imports[1].get("type").unwrap();
+        assert_eq!(ty.get("mutable").unwrap().as_bool(), Some(true));
+        let ty = imports[2].get("type").unwrap();
+        assert_eq!(ty.get("max").unwrap().as_i64(), Some(2));
+
+        let exports = json.get("exports").unwrap().as_array().unwrap();
+        let names: Vec<_> = exports
+            .iter()
+            .map(|e| e.get("name").unwrap().as_str().unwrap())
+            .collect();
+        assert_eq!(names, &["_start", "memory", "table", "g"]);
+        let ty = exports[2].get("type").unwrap();
+        assert_eq!(ty.get("max"), Some(&Json::Null));
+
+        let sections = json.get("sections").unwrap().as_array().unwrap();
+        let total: i64 = sections
+            .iter()
+            .map(|s| s.get("size").unwrap().as_i64().unwrap())
+            .sum();
+        assert!(total < bin.len() as i64);
+        assert_eq!(json.get("size").unwrap().as_i64(), Some(bin.len() as i64));
+        assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+    }
+
+    #[test]
+    fn imports_in_section_order() {
+        fn names(module: &Module<'_>) -> Vec<String> {
+            let info = ModuleInfo::new(module);
+            info.imports.iter().map(|i| i.name.to_string()).collect()
+        }
+
+        let source = r#"
+(module
+  (import "env" "g" (global i32))
+  (import "env" "m" (memory 1))
+  (import "env" "f" (func)))
+"#;
+        let root = wain_syntax_text::parse(source).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(names(&root.module), ["g", "m", "f"]);
+
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        bin.extend_from_slice(b"\x01\x04\x01\x60\x00\x00"); // Type section
+        bin.extend_from_slice(b"\x02\x12\x02"); // Import section with 2 entries
+        bin.extend_from_slice(b"\x03env\x01g\x03\x7f\x00"); // Global
+        bin.extend_from_slice(b"\x03env\x01f\x00\x00"); // Function
+        let root = wain_syntax_binary::parse(&bin).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(names(&root.module), ["g", "f"]);
+    }
+}
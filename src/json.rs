@@ -0,0 +1,387 @@
+// Minimal JSON parser and serializer for Debug Adapter Protocol messages
+// https://www.json.org/json-en.html
+
+use std::fmt;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>), // Keep order of keys
+}
+
+impl Json {
+    pub fn parse(input: &str) -> Result<Json, String> {
+        let mut parser = Parser { input, pos: 0 };
+        let json = parser.value()?;
+        parser.skip_whitespaces();
+        if parser.pos != input.len() {
+            return Err(parser.error("end of input"));
+        }
+        Ok(json)
+    }
+
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Number(n) if n.fract() == 0.0 => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Json {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Json {
+        Json::String(s)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(i: i64) -> Json {
+        Json::Number(i as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Json {
+        Json::Bool(b)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(v: Vec<Json>) -> Json {
+        Json::Array(v)
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => f.write_str("null"), // JSON cannot represent NaN and infinity
+            Json::String(s) => write_string(f, s),
+            Json::Array(elems) => {
+                f.write_str("[")?;
+                for (i, elem) in elems.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    elem.fmt(f)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, key)?;
+                    f.write_str(":")?;
+                    value.fmt(f)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, expected: &str) -> String {
+        match self.input[self.pos..].chars().next() {
+            Some(c) => format!(
+                "expected {} but got '{}' at offset {} in JSON",
+                expected, c, self.pos
+            ),
+            None => format!("expected {} but reached end of JSON", expected),
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    fn skip_whitespaces(&mut self) {
+        while let Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        self.skip_whitespaces();
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn keyword(&mut self, word: &str, json: Json) -> Result<Json, String> {
+        if self.input[self.pos..].starts_with(word) {
+            self.pos += word.len();
+            Ok(json)
+        } else {
+            Err(self.error(word))
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.skip_whitespaces();
+        match self.peek() {
+            Some(b'n') => self.keyword("null", Json::Null),
+            Some(b't') => self.keyword("true", Json::Bool(true)),
+            Some(b'f') => self.keyword("false", Json::Bool(false)),
+            Some(b'"') => self.string().map(Json::String),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut elems = vec![];
+                if self.eat(b']') {
+                    return Ok(Json::Array(elems));
+                }
+                loop {
+                    elems.push(self.value()?);
+                    if self.eat(b']') {
+                        return Ok(Json::Array(elems));
+                    }
+                    if !self.eat(b',') {
+                        return Err(self.error("',' or ']'"));
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = vec![];
+                if self.eat(b'}') {
+                    return Ok(Json::Object(fields));
+                }
+                loop {
+                    self.skip_whitespaces();
+                    let key = self.string()?;
+                    if !self.eat(b':') {
+                        return Err(self.error("':'"));
+                    }
+                    fields.push((key, self.value()?));
+                    if self.eat(b'}') {
+                        return Ok(Json::Object(fields));
+                    }
+                    if !self.eat(b',') {
+                        return Err(self.error("',' or '}'"));
+                    }
+                }
+            }
+            Some(b'-') | Some(b'0'..=b'9') => self.number(),
+            _ => Err(self.error("JSON value")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        while let Some(b'0'..=b'9') | Some(b'-') | Some(b'+') | Some(b'.') | Some(b'e')
+        | Some(b'E') = self.peek()
+        {
+            self.pos += 1;
+        }
+        match self.input[start..self.pos].parse() {
+            Ok(n) => Ok(Json::Number(n)),
+            Err(_) => {
+                self.pos = start;
+                Err(self.error("number"))
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let hex = self.input.get(self.pos..self.pos + 4);
+        match hex.and_then(|h| u32::from_str_radix(h, 16).ok()) {
+            Some(u) => {
+                self.pos += 4;
+                Ok(u)
+            }
+            None => Err(self.error("4 hex digits")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        if self.peek() != Some(b'"') {
+            return Err(self.error("string"));
+        }
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let c = match self.input[self.pos..].chars().next() {
+                Some(c) => c,
+                None => return Err(self.error("'\"'")),
+            };
+            self.pos += c.len_utf8();
+            match c {
+                '"' => return Ok(s),
+                '\\' => {
+                    let c = match self.peek() {
+                        Some(b) => b,
+                        None => return Err(self.error("escape character")),
+                    };
+                    self.pos += 1;
+                    match c {
+                        b'"' => s.push('"'),
+                        b'\\' => s.push('\\'),
+                        b'/' => s.push('/'),
+                        b'b' => s.push('\u{8}'),
+                        b'f' => s.push('\u{c}'),
+                        b'n' => s.push('\n'),
+                        b'r' => s.push('\r'),
+                        b't' => s.push('\t'),
+                        b'u' => {
+                            let mut u = self.hex4()?;
+                            if (0xd800..0xdc00).contains(&u)
+                                && self.input[self.pos..].starts_with("\\u")
+                            {
+                                // Surrogate pair
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                u = 0x10000
+                                    + ((u - 0xd800) << 10)
+                                    + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            s.push(std::char::from_u32(u).unwrap_or('\u{fffd}'));
+                        }
+                        _ => {
+                            self.pos -= 1;
+                            return Err(self.error("escape character"));
+                        }
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        let json = Json::parse(
+            r#" {"a": [1, -2.5, 1e3, true, false, null], "b": {"c": "d\nあ😀"}, "e": []} "#,
+        )
+        .unwrap();
+        let a = json.get("a").unwrap().as_array().unwrap();
+        assert_eq!(a[0].as_i64(), Some(1));
+        assert_eq!(a[1], Json::Number(-2.5));
+        assert_eq!(a[2].as_i64(), Some(1000));
+        assert_eq!(a[3].as_bool(), Some(true));
+        assert_eq!(a[4].as_bool(), Some(false));
+        assert_eq!(a[5], Json::Null);
+        let c = json.get("b").and_then(|b| b.get("c")).unwrap();
+        assert_eq!(c.as_str(), Some("d\n\u{3042}\u{1f600}"));
+        assert_eq!(json.get("e").unwrap().as_array().unwrap().len(), 0);
+        assert!(json.get("f").is_none());
+    }
+
+    #[test]
+    fn parse_errors() {
+        for input in &[
+            "",
+            "{",
+            "[1,]",
+            r#"{"a" 1}"#,
+            r#""abc"#,
+            "tru",
+            "1 2",
+            r#""\x""#,
+            "-",
+        ] {
+            assert!(Json::parse(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn serialize() {
+        let json = Json::object(vec![
+            ("s", Json::from("a\"b\\c\n\u{1}")),
+            ("i", Json::from(-42)),
+            ("f", Json::Number(0.5)),
+            ("b", Json::from(true)),
+            ("n", Json::Null),
+            ("a", Json::from(vec![Json::from(1), Json::from("x")])),
+            ("o", Json::object(vec![])),
+        ]);
+        let s = json.to_string();
+        assert_eq!(
+            s,
+            r#"{"s":"a\"b\\c\n\u0001","i":-42,"f":0.5,"b":true,"n":null,"a":[1,"x"],"o":{}}"#
+        );
+        assert_eq!(Json::parse(&s).unwrap(), json);
+    }
+}
@@ -1,10 +1,21 @@
 #![forbid(unsafe_code)]
 
+mod coredump;
+mod dap;
+mod debugger;
+mod inspect;
+mod json;
+mod preinit;
+mod trace;
+
 use std::env;
 use std::fs;
 use std::io;
 use std::io::Read;
 use std::process::exit;
+use std::sync::{Arc, Mutex};
+use wain_exec::stop::ExecError;
+use wain_exec::trap::Trap;
 
 #[allow(dead_code)]
 enum InputOption {
@@ -48,18 +59,106 @@ impl InputOption {
     }
 }
 
+enum Command {
+    Run {
+        trace: Option<Vec<String>>, // Functions to trace. Empty means all functions
+        profile: Option<String>,    // File path to output folded stacks
+        coverage: Option<String>,   // File path to output coverage report
+        coredump: Option<String>,   // File path to output core dump on trap
+    },
+    Debug,
+    Dap,
+    CoreDumpInspect {
+        path: Option<String>,
+    },
+    Wat2Wasm {
+        output: Option<String>,
+        names: bool,
+    },
+    Wasm2Wat {
+        output: Option<String>,
+        folded: bool,
+    },
+    Preinit {
+        output: Option<String>,
+        init_func: Option<String>, // Function to run for initialization. '_initialize' by default
+        allowed: Vec<String>,      // Host functions allowed to be called while initialization
+    },
+    Inspect {
+        json: bool,
+    },
+}
+
 struct Options {
     file: InputOption,
+    command: Command,
     help: bool,
     version: bool,
 }
 
 fn parse_args() -> Result<Options, String> {
     let mut file = InputOption::Stdin;
+    let mut command = Command::Run {
+        trace: None,
+        profile: None,
+        coverage: None,
+        coredump: None,
+    };
     let mut help = false;
     let mut version = false;
 
-    for arg in env::args().skip(1) {
+    let mut args = env::args().skip(1).peekable();
+    match args.peek().map(String::as_str) {
+        Some("wat2wasm") => {
+            args.next();
+            command = Command::Wat2Wasm {
+                output: None,
+                names: false,
+            };
+        }
+        Some("wasm2wat") => {
+            args.next();
+            command = Command::Wasm2Wat {
+                output: None,
+                folded: false,
+            };
+        }
+        Some("preinit") => {
+            args.next();
+            command = Command::Preinit {
+                output: None,
+                init_func: None,
+                allowed: vec![],
+            };
+        }
+        Some("inspect") => {
+            args.next();
+            command = Command::Inspect { json: false };
+        }
+        Some("debug") => {
+            args.next();
+            command = Command::Debug;
+        }
+        Some("dap") => {
+            args.next();
+            command = Command::Dap;
+        }
+        Some("coredump") => {
+            args.next();
+            match args.next().as_deref() {
+                Some("inspect") => command = Command::CoreDumpInspect { path: None },
+                Some("--help") | Some("-h") => help = true,
+                _ => {
+                    return Err(
+                        "coredump subcommand requires 'inspect' command. See --help".to_string()
+                    )
+                }
+            }
+        }
+        _ => {}
+    }
+
+    while let Some(arg) = args.next() {
         if arg == "--help" || arg == "-h" {
             help = true;
             break;
@@ -70,6 +169,109 @@ fn parse_args() -> Result<Options, String> {
             break;
         }
 
+        match &mut command {
+            Command::Wat2Wasm { output, .. }
+            | Command::Wasm2Wat { output, .. }
+            | Command::Preinit { output, .. }
+                if arg == "-o" =>
+            {
+                match args.next() {
+                    Some(path) => *output = Some(path),
+                    None => {
+                        return Err("-o option requires output file path. See --help".to_string())
+                    }
+                }
+                continue;
+            }
+            Command::Run { trace, .. } if arg == "--trace" => {
+                trace.get_or_insert_with(Vec::new);
+                continue;
+            }
+            Command::Run { trace, .. } if arg == "--trace-func" => {
+                match args.next() {
+                    Some(name) => trace.get_or_insert_with(Vec::new).push(name),
+                    None => {
+                        return Err(
+                            "--trace-func option requires function name. See --help".to_string()
+                        )
+                    }
+                }
+                continue;
+            }
+            Command::Run { profile, .. } if arg == "--profile" => {
+                match args.next() {
+                    Some(path) => *profile = Some(path),
+                    None => {
+                        return Err(
+                            "--profile option requires output file path. See --help".to_string()
+                        )
+                    }
+                }
+                continue;
+            }
+            Command::Run { coverage, .. } if arg == "--coverage" => {
+                match args.next() {
+                    Some(path) => *coverage = Some(path),
+                    None => {
+                        return Err(
+                            "--coverage option requires output file path. See --help".to_string()
+                        )
+                    }
+                }
+                continue;
+            }
+            Command::Run { coredump, .. } if arg == "--coredump" => {
+                match args.next() {
+                    Some(path) => *coredump = Some(path),
+                    None => {
+                        return Err(
+                            "--coredump option requires output file path. See --help".to_string()
+                        )
+                    }
+                }
+                continue;
+            }
+            Command::CoreDumpInspect { path } if path.is_none() && !arg.starts_with('-') => {
+                *path = Some(arg);
+                continue;
+            }
+            Command::Wat2Wasm { names, .. } if arg == "--debug-names" => {
+                *names = true;
+                continue;
+            }
+            Command::Inspect { json } if arg == "--json" => {
+                *json = true;
+                continue;
+            }
+            Command::Wasm2Wat { folded, .. } if arg == "--fold-exprs" => {
+                *folded = true;
+                continue;
+            }
+            Command::Preinit { init_func, .. } if arg == "--init-func" => {
+                match args.next() {
+                    Some(name) => *init_func = Some(name),
+                    None => {
+                        return Err(
+                            "--init-func option requires function name. See --help".to_string()
+                        )
+                    }
+                }
+                continue;
+            }
+            Command::Preinit { allowed, .. } if arg == "--allow-import" => {
+                match args.next() {
+                    Some(name) => allowed.push(name),
+                    None => {
+                        return Err(
+                            "--allow-import option requires function name. See --help".to_string()
+                        )
+                    }
+                }
+                continue;
+            }
+            _ => {}
+        }
+
         if let Some(f) = file.filename() {
             return Err(format!(
                 "Only one file can be specified for now. But '{}' and '{}' are specified. See --help",
@@ -99,8 +301,59 @@ fn parse_args() -> Result<Options, String> {
         ));
     }
 
+    match (&command, &file) {
+        (Command::Wat2Wasm { .. }, InputOption::Binary(f)) => {
+            return Err(format!(
+                "wat2wasm subcommand requires '.wat' file but got '{}'. See --help",
+                f
+            ));
+        }
+        (Command::Wasm2Wat { .. }, InputOption::Text(f)) => {
+            return Err(format!(
+                "wasm2wat subcommand requires '.wasm' file but got '{}'. See --help",
+                f
+            ));
+        }
+        (Command::Dap, _) if file.filename().is_some() => {
+            return Err(
+                "dap subcommand does not take file since program is specified by 'launch' request. See --help"
+                    .to_string(),
+            );
+        }
+        (
+            Command::Run {
+                trace,
+                profile,
+                coverage,
+                ..
+            },
+            _,
+        ) if trace.is_some() as u8 + profile.is_some() as u8 + coverage.is_some() as u8 > 1 => {
+            return Err(
+                "Only one of --trace, --profile and --coverage options can be used. See --help"
+                    .to_string(),
+            );
+        }
+        (Command::Preinit { output: None, .. }, _) if !help && !version => {
+            return Err(
+                "preinit subcommand requires output file with -o option. See --help".to_string(),
+            );
+        }
+        (Command::CoreDumpInspect { path: None }, _) if !help && !version => {
+            return Err("coredump inspect requires core dump file. See --help".to_string());
+        }
+        (Command::Debug, InputOption::Stdin) if !help && !version => {
+            return Err(
+                "debug subcommand requires file since STDIN is used for commands. See --help"
+                    .to_string(),
+            );
+        }
+        _ => {}
+    }
+
     Ok(Options {
         file,
+        command,
         help,
         version,
     })
@@ -113,16 +366,71 @@ wain: a WebAssembly INterpreter written in Safe Rust with zero dependencies
 
 USAGE:
     wain [OPTIONS] [{{file}}]
+    wain wat2wasm [OPTIONS] [{{file}}] [-o {{output}}]
+    wain wasm2wat [OPTIONS] [{{file}}] [-o {{output}}]
+    wain preinit [OPTIONS] [{{file}}] -o {{output}}
+    wain inspect [OPTIONS] [{{file}}]
+    wain debug [OPTIONS] {{file}}
+    wain dap [OPTIONS]
+    wain coredump inspect {{file}}
 
 OPTIONS:
-    --help | -h    : Show this help
-    --version | -v : Show version
+    --help | -h              : Show this help
+    --version | -v           : Show version
+    --trace                  : Print trace of function calls, executed
+                               instructions with values on top of operand
+                               stack, and host calls to STDERR
+    --trace-func {{name}}      : Trace only the function specified by name or
+                               index. This option can be repeated
+    --profile {{file}}         : Profile execution and write call stacks in
+                               folded format to the file for flamegraph tools.
+                               Summary of functions and opcodes is printed to
+                               STDERR
+    --coverage {{file}}        : Collect hit counts of instructions and branch
+                               directions and write them to the file. LCOV
+                               format is used for '.wat' file. For '.wasm'
+                               file, counts are listed per function and byte
+                               offset
+    --coredump {{file}}        : Write core dump to the file when execution is
+                               trapped. The core dump contains call stack with
+                               locals, globals, table and memory
 
 ARGUMENTS:
     Currently one '.wat' file or '.wasm' file can be specified. If no file is
     specified, STDIN will be interpreted as binary or text. wain automatically
     detect binary-format or text-format from the input.
 
+SUBCOMMANDS:
+    wat2wasm : Convert '.wat' file into '.wasm' file. Output file is specified
+               by '-o' option. When it is omitted, input file name with '.wasm'
+               extension is used. With '--debug-names' option, 'name' custom
+               section is emitted from identifiers in the text.
+    wasm2wat : Convert '.wasm' file into '.wat' file. The text is output to
+               STDOUT unless output file is specified by '-o' option. With
+               '--fold-exprs' option, instructions are printed in folded form.
+               Names in 'name' custom section are printed as identifiers.
+    preinit  : Run start function and initialization function specified by
+               '--init-func {{name}}' ('_initialize' by default), then write
+               '.wasm' file to '-o' output whose data segments and globals hold
+               the initialized state. The start function and the export of the
+               initialization function are removed. Calling host functions
+               while initialization fails unless they are allowed by
+               '--allow-import {{name}}' option. The option can be repeated.
+    inspect  : Print imports and exports of '.wat' or '.wasm' file with their
+               types, and sizes of sections. Sizes of '.wat' file are of its
+               encoded binary. With '--json' option, they are output as JSON.
+    debug    : Run '.wat' or '.wasm' file with interactive debugger. It can
+               set breakpoints, step through instructions and inspect locals,
+               operand stack, globals and memory. Type 'help' in the debugger
+               to see available commands.
+    dap      : Run Debug Adapter Protocol server communicating via STDIN and
+               STDOUT. Editors such as VS Code can debug '.wat' or '.wasm' file
+               through it. The file is specified by 'program' argument of
+               'launch' request.
+    coredump : 'coredump inspect {{file}}' prints call stack with locals and
+               operand stack, globals, table and non-zero memory regions in
+               the core dump file written by '--coredump' option.
+
 REPOSITORY:
     https://github.com/rhysd/wain
 "
@@ -140,29 +448,436 @@ fn unwrap<T, E: std::fmt::Display>(phase: &'static str, result: Result<T, E>) ->
     }
 }
 
-fn run<S: wain_ast::source::Source>(ast: wain_ast::Root<'_, S>) -> wain_exec::Run {
-    unwrap("validation", wain_validate::validate(&ast));
-    unwrap("running wasm", wain_exec::execute(&ast.module))
+// Features in 'target_features' section which wain does not support often explain why parsing or
+// validation failed
+fn unsupported_features_note(features: &[wain_ast::TargetFeature<'_>]) -> String {
+    let names = wain_exec::unsupported_features(features);
+    if names.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\nNote: the module uses features which are not supported: {}",
+            names.join(", ")
+        )
+    }
+}
+
+// The CLI neither suspends execution at host calls nor watches memory, and its debugger exits the
+// process to quit. Execution can end only by a trap
+fn trap_of(err: ExecError) -> Box<Trap> {
+    match err {
+        ExecError::Trap(trap) => trap,
+        err => unreachable!("execution was stopped: {}", err),
+    }
 }
 
 #[cfg(feature = "binary")]
-fn run_binary(bin: Vec<u8>) -> wain_exec::Run {
-    run(unwrap("parsing", wain_syntax_binary::parse(&bin)))
+fn parse_binary(bin: &[u8]) -> wain_ast::Root<'_, wain_syntax_binary::source::BinarySource<'_>> {
+    match wain_syntax_binary::parse(bin) {
+        Ok(ast) => ast,
+        Err(err) => {
+            // 'target_features' section is usually put at the end of binary so it is decoded separately
+            let features = wain_syntax_binary::target_features(bin).unwrap_or_default();
+            let note = unsupported_features_note(&features);
+            eprintln!("Error on parsing: {}{}", err, note);
+            exit(1);
+        }
+    }
+}
+
+fn validate<S: wain_ast::source::Source>(ast: &wain_ast::Root<'_, S>) {
+    if let Err(err) = wain_validate::validate(ast) {
+        let note = unsupported_features_note(&ast.module.target_features);
+        eprintln!("Error on validation: {}{}", err, note);
+        exit(1);
+    }
+}
+
+// Display byte offset in the source with hex dump around it
+struct Describe<'a, S: wain_ast::source::Source>(&'a S, usize);
+
+impl<'a, S: wain_ast::source::Source> std::fmt::Display for Describe<'a, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.describe(f, self.1)
+    }
+}
+
+// How to execute the module
+enum Mode {
+    Run,
+    Debug(debugger::Cli),
+    Trace(Vec<String>),
+    Profile(String),
+    Coverage(String, CoverageReport),
+}
+
+// Format of coverage report
+enum CoverageReport {
+    Lcov { file: String, text: String }, // LCOV against lines of '.wat' file
+    Offsets,                             // Hit counts per function and byte offset
+}
+
+impl Mode {
+    fn new<F, G>(command: Command, locate: F, report: G) -> Self
+    where
+        F: FnOnce() -> debugger::Locate,
+        G: FnOnce() -> CoverageReport,
+    {
+        match command {
+            Command::Debug => Mode::Debug(debugger::Cli::new(locate())),
+            Command::Run {
+                trace: Some(funcs), ..
+            } => Mode::Trace(funcs),
+            Command::Run {
+                profile: Some(path),
+                ..
+            } => Mode::Profile(path),
+            Command::Run {
+                coverage: Some(path),
+                ..
+            } => Mode::Coverage(path, report()),
+            _ => Mode::Run,
+        }
+    }
+}
+
+impl Command {
+    fn coredump(&self) -> Option<String> {
+        match self {
+            Command::Run { coredump, .. } => coredump.clone(),
+            _ => None,
+        }
+    }
+}
+
+fn run<S, F>(
+    ast: wain_ast::Root<'_, S>,
+    mode: Mode,
+    coredump: Option<String>,
+    on_trap: F,
+) -> wain_exec::Run
+where
+    S: wain_ast::source::Source,
+    F: FnOnce(&Trap),
+{
+    validate(&ast);
+    let module = &ast.module;
+    let dump = coredump.is_some();
+    let result = match mode {
+        Mode::Run => execute(module, dump, |_| {}),
+        Mode::Debug(debugger) => debugger.run(&ast.module),
+        Mode::Trace(funcs) => {
+            let tracer = trace::Printer::new(&ast.module, &funcs);
+            let tracer = unwrap("parsing command line", tracer);
+            execute(module, dump, |m| m.set_tracer(tracer))
+        }
+        Mode::Profile(path) => {
+            let profiler = Arc::new(Mutex::new(wain_exec::Profiler::new(&ast.module)));
+            let result = execute(module, dump, |m| m.set_tracer(profiler.clone()));
+            // Profile is output even if the execution was trapped
+            let profile = profiler.lock().unwrap().profile();
+            let file =
+                fs::File::create(&path).and_then(|f| profile.write_folded(io::BufWriter::new(f)));
+            unwrap("writing profile", file);
+            unwrap("writing profile", profile.write_summary(io::stderr()));
+            result
+        }
+        Mode::Coverage(path, report) => {
+            let coverage = Arc::new(Mutex::new(wain_exec::Coverage::new(&ast.module)));
+            let result = execute(module, dump, |m| m.set_tracer(coverage.clone()));
+            let coverage = coverage.lock().unwrap();
+            let written = fs::File::create(&path).and_then(|f| {
+                let w = io::BufWriter::new(f);
+                match &report {
+                    CoverageReport::Lcov { file, text } => coverage.write_lcov(w, file, text),
+                    CoverageReport::Offsets => coverage.write_report(w),
+                }
+            });
+            unwrap("writing coverage", written);
+            let (hit, insns) = coverage.insns_hit();
+            let (taken, branches) = coverage.branches_hit();
+            eprintln!(
+                "Coverage: {}/{} instructions, {}/{} branch directions",
+                hit, insns, taken, branches,
+            );
+            result
+        }
+    };
+    match result {
+        Ok(run) => run,
+        Err(trap) => {
+            eprintln!("Error on running wasm: {}", trap);
+            eprintln!("\n{}", Describe(&ast.source, trap.offset));
+            on_trap(&trap);
+            if let (Some(path), Some(dump)) = (coredump, &trap.coredump) {
+                unwrap("writing core dump", fs::write(&path, dump.encode()));
+                eprintln!("\nCore dump was written to {}", path);
+            }
+            exit(1);
+        }
+    }
+}
+
+type StdioMachine<'m, 's, 'io> =
+    wain_exec::Machine<'m, 's, wain_exec::DefaultImporter<io::StdinLock<'io>, io::StdoutLock<'io>>>;
+
+// Execute the module with locked STDIN and STDOUT after the machine is set up by the callback
+fn execute<F>(
+    module: &wain_ast::Module<'_>,
+    coredump: bool,
+    setup: F,
+) -> wain_exec::trap::Result<wain_exec::Run>
+where
+    F: for<'m, 's, 'io> FnOnce(&mut StdioMachine<'m, 's, 'io>),
+{
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let importer = wain_exec::DefaultImporter::with_stdio(stdin.lock(), stdout.lock());
+    let mut machine = wain_exec::Machine::instantiate(module, importer)?;
+    machine.set_coredump(coredump);
+    setup(&mut machine);
+    machine.execute().map_err(trap_of)
+}
+
+// Print source locations of the trapped Wasm call stack using DWARF sections in the binary
+#[cfg(feature = "binary")]
+fn print_source_locations(bin: &[u8], trap: &Trap) {
+    let info = match wain_dwarf::DebugInfo::from_binary(bin) {
+        Ok(Some(info)) => info,
+        Ok(None) => return,
+        Err(err) => {
+            eprintln!("Warning: DWARF debug information is broken: {}", err);
+            return;
+        }
+    };
+
+    let mut offsets: Vec<_> = trap.backtrace.iter().map(|f| f.offset).collect();
+    if offsets.is_empty() {
+        offsets.push(trap.offset);
+    }
+
+    eprintln!("\nsource locations:");
+    for (i, offset) in offsets.into_iter().enumerate() {
+        let frames = match info.address(offset) {
+            Some(addr) => info.frames(addr),
+            None => vec![],
+        };
+        if frames.is_empty() {
+            eprintln!("  #{}: ??", i);
+            continue;
+        }
+        for (j, frame) in frames.iter().enumerate() {
+            let head = if j == 0 {
+                format!("#{}:", i)
+            } else {
+                String::new()
+            };
+            eprint!("  {:<3} {}", head, frame.function.unwrap_or("??"));
+            if let Some(loc) = &frame.location {
+                eprint!(" at {}", loc);
+            }
+            if frame.inlined {
+                eprint!(" (inlined)");
+            }
+            eprintln!();
+        }
+    }
+}
+
+#[cfg(feature = "binary")]
+fn run_binary(bin: Vec<u8>, command: Command) -> wain_exec::Run {
+    let ast = parse_binary(&bin);
+    let coredump = command.coredump();
+    let mode = Mode::new(
+        command,
+        || {
+            // Source locations are shown with DWARF debug information if available
+            match wain_dwarf::DebugInfo::from_binary(&bin) {
+                Ok(Some(info)) => Box::new(move |offset| {
+                    let addr = info.address(offset)?;
+                    info.location(addr).map(|loc| loc.to_string())
+                }),
+                _ => Box::new(|_| None),
+            }
+        },
+        || CoverageReport::Offsets,
+    );
+    run(ast, mode, coredump, |trap| {
+        print_source_locations(&bin, trap)
+    })
 }
 #[cfg(not(feature = "binary"))]
-fn run_binary(_: Vec<u8>) -> wain_exec::Run {
+fn run_binary(_: Vec<u8>, _: Command) -> wain_exec::Run {
     unreachable!()
 }
 
 #[cfg(feature = "text")]
-fn run_text(text: String) -> wain_exec::Run {
-    run(unwrap("parsing", wain_syntax_text::parse(&text)))
+fn run_text(text: String, file: Option<&str>, command: Command) -> wain_exec::Run {
+    // Identifiers are used as function names in debugger, traces, profiles and backtraces
+    let ast = unwrap("parsing", wain_syntax_text::parse_with_id_names(&text));
+    let coredump = command.coredump();
+    let mode = Mode::new(
+        command,
+        || {
+            let file = file.unwrap_or("<stdin>").to_string();
+            let source = text.clone();
+            Box::new(move |offset| {
+                let before = source.get(..offset)?;
+                let line = before.matches('\n').count() + 1;
+                let col = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+                Some(format!("{}:{}:{}", file, line, col))
+            })
+        },
+        || CoverageReport::Lcov {
+            file: file.unwrap_or("<stdin>").to_string(),
+            text: text.clone(),
+        },
+    );
+    run(ast, mode, coredump, |_| {})
 }
 #[cfg(not(feature = "text"))]
-fn run_text(_: String) -> wain_exec::Run {
+fn run_text(_: String, _: Option<&str>, _: Command) -> wain_exec::Run {
     unreachable!()
 }
 
+#[cfg(feature = "text")]
+fn wat2wasm(file: InputOption, output: Option<String>, names: bool) {
+    let output = match (output, file.filename()) {
+        (Some(output), _) => output,
+        (None, Some(f)) => format!("{}.wasm", f.trim_end_matches(".wat")),
+        (None, None) => unwrap(
+            "parsing command line",
+            Err("output file must be specified with -o option when reading STDIN. See --help"),
+        ),
+    };
+    let text = match unwrap("reading input", file.read()) {
+        Input::Text(text) => text,
+        Input::Binary(_) => unwrap("reading input", Err("input is not text format")),
+    };
+    let ast = if names {
+        wain_syntax_text::parse_with_id_names(&text)
+    } else {
+        wain_syntax_text::parse(&text)
+    };
+    let ast = unwrap("parsing", ast);
+    validate(&ast);
+    let bin = if names {
+        wain_encode_binary::encode_with_names(&ast.module)
+    } else {
+        wain_encode_binary::encode(&ast.module)
+    };
+    unwrap("writing output", fs::write(output, bin));
+}
+#[cfg(not(feature = "text"))]
+fn wat2wasm(_: InputOption, _: Option<String>, _: bool) {
+    unwrap(
+        "parsing command line",
+        Err("wat2wasm subcommand is not available since 'text' feature is disabled"),
+    )
+}
+
+#[cfg(feature = "binary")]
+fn wasm2wat(file: InputOption, output: Option<String>, folded: bool) {
+    let bin = match unwrap("reading input", file.read()) {
+        Input::Binary(bin) => bin,
+        Input::Text(_) => unwrap("reading input", Err("input is not binary format")),
+    };
+    let ast = parse_binary(&bin);
+    validate(&ast);
+    let text = if folded {
+        wain_print_text::print_folded(&ast.module)
+    } else {
+        wain_print_text::print(&ast.module)
+    };
+    match output {
+        Some(output) => unwrap("writing output", fs::write(output, text)),
+        None => print!("{}", text),
+    }
+}
+#[cfg(not(feature = "binary"))]
+fn wasm2wat(_: InputOption, _: Option<String>, _: bool) {
+    unwrap(
+        "parsing command line",
+        Err("wasm2wat subcommand is not available since 'binary' feature is disabled"),
+    )
+}
+
+// Pre-initialize the module and encode it into binary
+fn preinit_module<S: wain_ast::source::Source>(
+    mut ast: wain_ast::Root<'_, S>,
+    init_func: Option<&str>,
+    allowed: &[String],
+) -> Vec<u8> {
+    validate(&ast);
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    let importer = wain_exec::DefaultImporter::with_stdio(stdin.lock(), stdout.lock());
+    let result = preinit::preinit(&mut ast.module, importer, init_func, allowed);
+    unwrap("pre-initialization", result);
+    wain_encode_binary::encode(&ast.module)
+}
+
+fn preinit(
+    file: InputOption,
+    output: Option<String>,
+    init_func: Option<String>,
+    allowed: Vec<String>,
+) {
+    let init_func = init_func.as_deref();
+    let bin = match unwrap("reading input", file.read()) {
+        #[cfg(feature = "binary")]
+        Input::Binary(bin) => {
+            let ast = parse_binary(&bin);
+            preinit_module(ast, init_func, &allowed)
+        }
+        #[cfg(feature = "text")]
+        Input::Text(text) => {
+            let ast = unwrap("parsing", wain_syntax_text::parse(&text));
+            preinit_module(ast, init_func, &allowed)
+        }
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    };
+    unwrap("writing output", fs::write(output.unwrap_or_default(), bin));
+}
+
+fn inspect_module<S: wain_ast::source::Source>(
+    ast: &wain_ast::Root<'_, S>,
+    bin: &[u8],
+    json: bool,
+) {
+    validate(ast);
+    #[cfg(feature = "binary")]
+    let sections = unwrap("parsing", inspect::sections(bin));
+    #[cfg(not(feature = "binary"))]
+    let sections = vec![];
+    if json {
+        println!("{}", inspect::json(&ast.module, &sections, bin.len()));
+    } else {
+        let stdout = io::stdout();
+        let result = inspect::inspect(&ast.module, &sections, bin.len(), stdout.lock());
+        unwrap("printing module information", result);
+    }
+}
+
+fn inspect(file: InputOption, json: bool) {
+    match unwrap("reading input", file.read()) {
+        #[cfg(feature = "binary")]
+        Input::Binary(bin) => {
+            let ast = parse_binary(&bin);
+            inspect_module(&ast, &bin, json);
+        }
+        #[cfg(feature = "text")]
+        Input::Text(text) => {
+            let ast = unwrap("parsing", wain_syntax_text::parse(&text));
+            let bin = wain_encode_binary::encode(&ast.module);
+            inspect_module(&ast, &bin, json);
+        }
+        #[allow(unreachable_patterns)]
+        _ => unreachable!(),
+    }
+}
+
 fn main() {
     let opts = unwrap("parsing command line", parse_args());
 
@@ -175,9 +890,49 @@ fn main() {
         exit(0);
     }
 
+    match opts.command {
+        Command::Wat2Wasm { output, names } => {
+            wat2wasm(opts.file, output, names);
+            return;
+        }
+        Command::Wasm2Wat { output, folded } => {
+            wasm2wat(opts.file, output, folded);
+            return;
+        }
+        Command::Preinit {
+            output,
+            init_func,
+            allowed,
+        } => {
+            preinit(opts.file, output, init_func, allowed);
+            return;
+        }
+        Command::Inspect { json } => {
+            inspect(opts.file, json);
+            return;
+        }
+        Command::Dap => {
+            let stdin = io::BufReader::new(io::stdin());
+            unwrap("debug adapter", dap::serve(stdin, io::stdout()));
+            return;
+        }
+        Command::CoreDumpInspect { path } => {
+            let path = path.unwrap_or_default();
+            let bytes = unwrap("reading core dump", fs::read(&path));
+            let dump = unwrap("reading core dump", wain_exec::CoreDump::decode(&bytes));
+            let stdout = io::stdout();
+            unwrap(
+                "printing core dump",
+                coredump::inspect(&dump, stdout.lock()),
+            );
+            return;
+        }
+        Command::Run { .. } | Command::Debug => {}
+    }
+
     let result = match unwrap("reading input", opts.file.read()) {
-        Input::Binary(bin) => run_binary(bin),
-        Input::Text(text) => run_text(text),
+        Input::Binary(bin) => run_binary(bin, opts.command),
+        Input::Text(text) => run_text(text, opts.file.filename(), opts.command),
     };
 
     if let wain_exec::Run::Warning(msg) = result {
@@ -0,0 +1,305 @@
+// Pre-initialization for 'preinit' subcommand
+//
+// The module is instantiated and its start function and initialization function are run. Then the
+// module is rewritten so that data segments hold the resulting memory and global initializers hold
+// the resulting global values. Calling the initialization again is prevented by removing the start
+// function and the export of the initialization function. Their bodies are kept to avoid
+// renumbering functions.
+
+use std::borrow::Cow;
+use std::fmt;
+use wain_ast::{DataSegment, ExportKind, GlobalKind, InsnKind, Instruction, Limits, Module};
+use wain_exec::trap::Trap;
+use wain_exec::{Importer, Machine, Value};
+
+pub const DEFAULT_INIT_FUNC: &str = "_initialize";
+
+pub enum Error {
+    Trap(Box<Trap>),
+    NoInitFunc(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Trap(trap) => write!(f, "{}", trap),
+            Error::NoInitFunc(name) => write!(
+                f,
+                "module has neither start function nor exported function '{}' to initialize",
+                name
+            ),
+        }
+    }
+}
+
+impl From<Box<Trap>> for Error {
+    fn from(trap: Box<Trap>) -> Self {
+        Error::Trap(trap)
+    }
+}
+
+// Host functions can be imported only from 'env' module so the allowlist holds their names. Since
+// state outside the module cannot be baked into the module, calling other host functions during
+// initialization is not allowed
+fn check_allowed(allowed: &[String], mod_name: &str, name: &str) -> Result<(), String> {
+    if mod_name == "env" && allowed.iter().any(|a| a == name) {
+        return Ok(());
+    }
+    Err(format!(
+        "host function '{}' is not allowed while pre-initialization. Allow it with --allow-import option",
+        name
+    ))
+}
+
+fn const_insn(value: &Value, start: usize) -> Instruction {
+    let kind = match value {
+        Value::I32(i) => InsnKind::I32Const(*i),
+        Value::I64(i) => InsnKind::I64Const(*i),
+        Value::F32(f) => InsnKind::F32Const(*f),
+        Value::F64(f) => InsnKind::F64Const(*f),
+    };
+    Instruction { start, kind }
+}
+
+// Run the start function and the initialization function and rewrite the module with the state after
+// the initialization. When the initialization function is not specified, '_initialize' is run if
+// it is exported. Host functions can be called only when they are in `allowed`
+pub fn preinit<I: Importer>(
+    module: &mut Module<'_>,
+    importer: I,
+    init_func: Option<&str>,
+    allowed: &[String],
+) -> Result<(), Error> {
+    let init_func = match init_func {
+        Some(name) => Some(name),
+        None if module.exports.iter().any(|e| e.name.0 == DEFAULT_INIT_FUNC) => {
+            Some(DEFAULT_INIT_FUNC)
+        }
+        None if module.entrypoint.is_some() => None,
+        None => return Err(Error::NoInitFunc(DEFAULT_INIT_FUNC.to_string())),
+    };
+
+    let snapshot = {
+        let mut machine = Machine::instantiate(module, importer)?;
+        let allowed = allowed.to_vec();
+        machine.set_host_call_guard(move |mod_name, name| check_allowed(&allowed, mod_name, name));
+        if module.entrypoint.is_some() {
+            machine.execute().map_err(crate::trap_of)?;
+        }
+        if let Some(name) = init_func {
+            machine.invoke(name, &[]).map_err(crate::trap_of)?;
+        }
+        machine.snapshot(&[])
+    };
+
+    module.entrypoint = None;
+    if let Some(name) = init_func {
+        module
+            .exports
+            .retain(|e| !(e.name.0 == name && matches!(e.kind, ExportKind::Func(_))));
+    }
+
+    if let (Some(memory), Some(data)) = (module.memories.first_mut(), &snapshot.memory) {
+        let pages = (data.len() / 65536) as u32;
+        memory.ty.limit = match memory.ty.limit {
+            Limits::Range(_, max) => Limits::Range(pages, max),
+            Limits::From(_) => Limits::From(pages),
+        };
+        let start = memory.start;
+        module.data = snapshot
+            .memory_regions()
+            .into_iter()
+            .map(|r| DataSegment {
+                start,
+                idx: 0,
+                offset: vec![const_insn(&Value::I32(r.start as i32), start)],
+                data: Cow::Owned(data[r].to_vec()),
+            })
+            .collect();
+    }
+
+    for (global, value) in module.globals.iter_mut().zip(snapshot.globals.iter()) {
+        if let GlobalKind::Init(_) = &global.kind {
+            global.kind = GlobalKind::Init(vec![const_insn(value, global.start)]);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use wain_ast::ValType;
+    use wain_exec::{
+        check_func_signature, Caller, DefaultImporter, ImportInvalidError, ImportInvokeError,
+        Memory, Stack,
+    };
+
+    const SOURCE: &str = r#"
+(module
+  (import "env" "putchar" (func $putchar (param i32) (result i32)))
+  (memory 1)
+  (global $counter (mut i32) (i32.const 0))
+  (global $scale (mut f64) (f64.const 1))
+  (global $tag i64 (i64.const 7))
+  (data (i32.const 0) "abc")
+  (func $start
+    i32.const 1
+    global.set $counter)
+  (func $init (export "_initialize")
+    i32.const 1
+    memory.grow
+    drop
+    i32.const 70000
+    i32.const 0x6e696177
+    i32.store
+    i32.const 1
+    i32.const 0x42
+    i32.store8
+    global.get $counter
+    i32.const 10
+    i32.add
+    global.set $counter
+    f64.const 0.5
+    global.set $scale)
+  (func (export "print") (result i32)
+    i32.const 33
+    call $putchar)
+  (func (export "get") (result i32)
+    global.get $counter
+    i32.const 70000
+    i32.load
+    i32.add)
+  (start $start))
+"#;
+
+    fn importer() -> DefaultImporter<io::Empty, io::Sink> {
+        DefaultImporter::with_stdio(io::empty(), io::sink())
+    }
+
+    #[test]
+    fn bake_initialized_state() {
+        let mut root = wain_syntax_text::parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        preinit(&mut root.module, importer(), None, &[]).unwrap_or_else(|e| panic!("{}", e));
+
+        let bin = wain_encode_binary::encode(&root.module);
+        let root = wain_syntax_binary::parse(&bin).unwrap_or_else(|e| panic!("{}", e));
+        wain_validate::validate(&root).unwrap_or_else(|e| panic!("{}", e));
+        let module = &root.module;
+
+        assert!(module.entrypoint.is_none());
+        let exports: Vec<_> = module.exports.iter().map(|e| e.name.0.as_ref()).collect();
+        assert_eq!(exports, ["print", "get"]);
+        let data: Vec<_> = module.data.iter().map(|d| d.data.as_ref()).collect();
+        assert_eq!(data, [&b"aBc"[..], &b"wain"[..]]);
+
+        // The initialized state is restored only from the module
+        let mut machine =
+            Machine::instantiate(module, importer()).unwrap_or_else(|t| panic!("{}", t));
+        assert_eq!(machine.memory().size(), 2);
+        let snapshot = machine.snapshot(&[]);
+        let globals: Vec<_> = snapshot.globals.iter().map(|v| v.to_string()).collect();
+        assert_eq!(globals, ["11i32", "0.5f64", "7i64"]);
+        let ret = machine
+            .invoke("get", &[])
+            .unwrap_or_else(|t| panic!("{}", t));
+        assert_eq!(
+            ret.map(|v| v.to_string()),
+            Some("1852400002i32".to_string())
+        );
+    }
+
+    #[test]
+    fn disallowed_host_call() {
+        let source = r#"
+(module
+  (import "env" "putchar" (func $putchar (param i32) (result i32)))
+  (func (export "_initialize")
+    i32.const 33
+    call $putchar
+    drop))
+"#;
+        let mut root = wain_syntax_text::parse(source).unwrap_or_else(|e| panic!("{}", e));
+        let err = match preinit(&mut root.module, importer(), None, &[]) {
+            Err(e) => e.to_string(),
+            Ok(()) => panic!("host call was not refused"),
+        };
+        assert!(
+            err.contains("host function 'putchar' is not allowed while pre-initialization"),
+            "{}",
+            err
+        );
+
+        let allowed = ["putchar".to_string()];
+        preinit(&mut root.module, importer(), None, &allowed).unwrap_or_else(|e| panic!("{}", e));
+        assert!(root.module.exports.is_empty());
+    }
+
+    #[test]
+    fn allowed_host_call_with_caller() {
+        // Host function which sets the global through the calling instance
+        struct Setup;
+        impl Importer for Setup {
+            fn validate(
+                &self,
+                name: &str,
+                params: &[ValType],
+                ret: Option<ValType>,
+            ) -> Option<ImportInvalidError> {
+                match name {
+                    "setup" => check_func_signature(params, ret, &[], None),
+                    _ => Some(ImportInvalidError::NotFound),
+                }
+            }
+            fn call(
+                &mut self,
+                _name: &str,
+                _stack: &mut Stack,
+                _memory: &mut Memory,
+            ) -> Result<(), ImportInvokeError> {
+                unreachable!("host function is called with caller")
+            }
+            fn call_with_caller(
+                caller: &mut Caller<'_, '_, '_, Self>,
+                _name: &str,
+            ) -> Result<(), ImportInvokeError> {
+                caller.set_global("g", Value::I32(42))?;
+                Ok(())
+            }
+        }
+
+        let source = r#"
+(module
+  (import "env" "setup" (func $setup))
+  (global $g (export "g") (mut i32) (i32.const 0))
+  (func (export "_initialize")
+    call $setup))
+"#;
+        let mut root = wain_syntax_text::parse(source).unwrap_or_else(|e| panic!("{}", e));
+        let allowed = ["setup".to_string()];
+        preinit(&mut root.module, Setup, None, &allowed).unwrap_or_else(|e| panic!("{}", e));
+
+        let machine = Machine::instantiate(&root.module, Setup).unwrap_or_else(|t| panic!("{}", t));
+        assert_eq!(
+            machine.get_global("g").map(|v| v.to_string()),
+            Some("42i32".to_string())
+        );
+    }
+
+    #[test]
+    fn no_init_func() {
+        let source = "(module (func (export \"f\")))";
+        let mut root = wain_syntax_text::parse(source).unwrap_or_else(|e| panic!("{}", e));
+        match preinit(&mut root.module, importer(), None, &[]) {
+            Err(Error::NoInitFunc(name)) => assert_eq!(name, "_initialize"),
+            _ => panic!("error did not occur"),
+        }
+        let err = match preinit(&mut root.module, importer(), Some("init"), &[]) {
+            Err(e) => e.to_string(),
+            Ok(()) => panic!("error did not occur"),
+        };
+        assert!(err.contains("'init'"), "{}", err);
+    }
+}
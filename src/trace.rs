@@ -0,0 +1,224 @@
+// Execution trace printer for '--trace' option. The trace is output to STDERR since STDOUT is used by
+// the program
+
+use crate::debugger::{find_func, func_name, insn_text};
+use std::io::{self, Write};
+use wain_ast::{InsnKind, Module};
+use wain_exec::{Tracer, Value};
+
+struct Values<'a>(&'a [Value]);
+
+impl<'a> std::fmt::Display for Values<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("[")?;
+        for (i, v) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}", v)?;
+        }
+        f.write_str("]")
+    }
+}
+
+struct Ret<'a>(Option<&'a Value>);
+
+impl<'a> std::fmt::Display for Ret<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(v) => write!(f, " -> {}", v),
+            None => Ok(()),
+        }
+    }
+}
+
+pub struct Printer<W: Write> {
+    out: W,
+    names: Vec<String>,
+    traced: Option<Vec<bool>>, // Functions to trace indexed by function index. None means all
+    calls: Vec<u32>,           // Functions being called to indent lines and filter host calls
+}
+
+impl Printer<io::Stderr> {
+    // Trace only the functions when names are not empty
+    pub fn new(module: &Module<'_>, funcs: &[String]) -> Result<Self, String> {
+        Printer::with_output(module, funcs, io::stderr())
+    }
+}
+
+impl<W: Write> Printer<W> {
+    fn with_output(module: &Module<'_>, funcs: &[String], out: W) -> Result<Self, String> {
+        let traced = if funcs.is_empty() {
+            None
+        } else {
+            let mut traced = vec![false; module.funcs.len()];
+            for name in funcs {
+                match find_func(module, name) {
+                    Some(idx) => traced[idx as usize] = true,
+                    None => return Err(format!("No function '{}' to trace. See --help", name)),
+                }
+            }
+            Some(traced)
+        };
+        let names = (0..module.funcs.len() as u32)
+            .map(|idx| func_name(module, idx))
+            .collect();
+        Ok(Self {
+            out,
+            names,
+            traced,
+            calls: vec![],
+        })
+    }
+
+    fn is_traced(&self, funcidx: u32) -> bool {
+        match &self.traced {
+            Some(traced) => traced[funcidx as usize],
+            None => true,
+        }
+    }
+
+    fn print(&mut self, line: String) {
+        let indent = self.calls.len().saturating_sub(1) * 2;
+        // Errors are ignored since tracing should not stop the execution
+        let _ = writeln!(self.out, "{:indent$}{}", "", line, indent = indent);
+    }
+}
+
+impl<W: Write> Tracer for Printer<W> {
+    fn enter(&mut self, funcidx: u32, args: &[Value]) {
+        self.calls.push(funcidx);
+        if self.is_traced(funcidx) {
+            let name = &self.names[funcidx as usize];
+            self.print(format!("enter {} {}", name, Values(args)));
+        }
+    }
+
+    fn exit(&mut self, funcidx: u32, result: Option<&Value>) {
+        if self.is_traced(funcidx) {
+            let name = &self.names[funcidx as usize];
+            self.print(format!("exit {}{}", name, Ret(result)));
+        }
+        self.calls.pop();
+    }
+
+    fn instruction(&mut self, funcidx: u32, insn: &InsnKind, offset: usize, operands: &[Value]) {
+        if self.is_traced(funcidx) {
+            let text = insn_text(insn);
+            self.print(format!(
+                "  0x{:x}: {:<24} {}",
+                offset,
+                text,
+                Values(operands)
+            ));
+        }
+    }
+
+    // Host call is traced when the imported function or its caller is traced
+    fn host_call(&mut self, funcidx: u32, name: &str, args: &[Value], result: Option<&Value>) {
+        let caller = self.calls.last().copied();
+        if self.is_traced(funcidx) || caller.map(|f| self.is_traced(f)).unwrap_or(false) {
+            self.print(format!(
+                "  host call {} {}{}",
+                name,
+                Values(args),
+                Ret(result)
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use wain_exec::{DefaultImporter, Machine};
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    const SOURCE: &str = r#"
+(module
+  (import "env" "putchar" (func $putchar (param i32) (result i32)))
+  (func $double (param i32) (result i32)
+    local.get 0
+    i32.const 2
+    i32.mul)
+  (func $start
+    i32.const 33
+    call $double
+    call $putchar
+    drop)
+  (start $start))
+"#;
+
+    fn trace(funcs: &[&str]) -> String {
+        let root =
+            wain_syntax_text::parse_with_id_names(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let funcs: Vec<_> = funcs.iter().map(|f| f.to_string()).collect();
+        let out = SharedBuf::default();
+        let printer = Printer::with_output(&root.module, &funcs, out.clone()).unwrap();
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine =
+            Machine::instantiate(&root.module, importer).unwrap_or_else(|t| panic!("{}", t));
+        machine.set_tracer(printer);
+        machine.execute().unwrap_or_else(|t| panic!("{}", t));
+        let out = out.0.lock().unwrap();
+        String::from_utf8(out.clone()).unwrap()
+    }
+
+    #[test]
+    fn print_all_functions() {
+        let trace = trace(&[]);
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "enter 'start' (function 2) []",
+                "  0xb6: i32.const 33             []",
+                "  0xc7: call 1                   [33i32]",
+                "  enter 'double' (function 1) [33i32]",
+                "    0x7a: local.get 0              []",
+                "    0x8a: i32.const 2              [33i32]",
+                "    0x9a: i32.mul                  [33i32, 2i32]",
+                "  exit 'double' (function 1) -> 66i32",
+                "  0xd8: call 0                   [66i32]",
+                "  host call putchar [66i32] -> 66i32",
+                "  0xea: drop                     [66i32]",
+                "exit 'start' (function 2)",
+            ],
+        );
+    }
+
+    #[test]
+    fn filter_functions() {
+        let trace = trace(&["$double"]);
+        let lines: Vec<_> = trace.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "  enter 'double' (function 1) [33i32]",
+                "    0x7a: local.get 0              []",
+                "    0x8a: i32.const 2              [33i32]",
+                "    0x9a: i32.mul                  [33i32, 2i32]",
+                "  exit 'double' (function 1) -> 66i32",
+            ],
+        );
+
+        let root =
+            wain_syntax_text::parse_with_id_names(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let funcs = ["nope".to_string()];
+        let err = Printer::new(&root.module, &funcs).err().unwrap();
+        assert_eq!(err, "No function 'nope' to trace. See --help");
+    }
+}
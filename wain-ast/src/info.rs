@@ -0,0 +1,85 @@
+// Summary of imports and exports of module with their types. This is useful to check if a module
+// can be instantiated before actually instantiating it.
+
+use crate::{ExternType, FuncKind, GlobalKind, Import, Module};
+
+pub struct ImportInfo<'m> {
+    pub mod_name: &'m str,
+    pub name: &'m str,
+    pub ty: ExternType<'m>,
+}
+
+pub struct ExportInfo<'m> {
+    pub name: &'m str,
+    pub ty: ExternType<'m>,
+}
+
+pub struct ModuleInfo<'m> {
+    // Imports are in the order of import section
+    pub imports: Vec<ImportInfo<'m>>,
+    pub exports: Vec<ExportInfo<'m>>,
+}
+
+impl<'m> ModuleInfo<'m> {
+    pub fn new(module: &'m Module<'_>) -> Self {
+        fn import<'m>(import: &'m Import<'_>, ty: ExternType<'m>) -> ImportInfo<'m> {
+            ImportInfo {
+                mod_name: &import.mod_name.0,
+                name: &import.name.0,
+                ty,
+            }
+        }
+
+        // AST separates imports by their kinds. The order in import section is restored from their
+        // start offsets
+        let mut imports = vec![];
+        for func in module.funcs.iter() {
+            if let FuncKind::Import(i) = &func.kind {
+                let ty = ExternType::Func(&module.types[func.idx as usize]);
+                imports.push((func.start, import(i, ty)));
+            }
+        }
+        for table in module.tables.iter() {
+            if let Some(i) = &table.import {
+                imports.push((table.start, import(i, ExternType::Table(&table.ty))));
+            }
+        }
+        for memory in module.memories.iter() {
+            if let Some(i) = &memory.import {
+                imports.push((memory.start, import(i, ExternType::Memory(&memory.ty))));
+            }
+        }
+        for global in module.globals.iter() {
+            if let GlobalKind::Import(i) = &global.kind {
+                let ty = ExternType::Global {
+                    ty: global.ty,
+                    mutable: global.mutable,
+                };
+                imports.push((global.start, import(i, ty)));
+            }
+        }
+        imports.sort_by_key(|(start, _)| *start);
+        let imports = imports.into_iter().map(|(_, i)| i).collect();
+
+        let exports = module
+            .exports
+            .iter()
+            .map(|e| ExportInfo {
+                name: &e.name.0,
+                ty: module.export_type(&e.kind),
+            })
+            .collect();
+
+        Self { imports, exports }
+    }
+
+    pub fn import(&self, mod_name: &str, name: &str) -> Option<&ImportInfo<'m>> {
+        self.imports
+            .iter()
+            .find(|i| i.mod_name == mod_name && i.name == name)
+    }
+
+    pub fn export(&self, name: &str) -> Option<&ExportInfo<'m>> {
+        self.exports.iter().find(|e| e.name == name)
+    }
+}
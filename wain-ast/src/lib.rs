@@ -1,5 +1,6 @@
 #![forbid(unsafe_code)]
 
+pub mod info;
 pub mod source;
 
 use std::borrow::Cow;
@@ -37,6 +38,10 @@ pub struct Module<'s> {
     pub memories: Vec<Memory<'s>>,
     pub globals: Vec<Global<'s>>,
     pub entrypoint: Option<StartFunction>,
+    pub names: Names<'s>,
+    pub producers: Vec<ProducersField<'s>>,
+    pub target_features: Vec<TargetFeature<'s>>,
+    pub customs: Vec<Custom<'s>>,
 }
 
 // https://webassembly.github.io/spec/core/syntax/modules.html#syntax-module
@@ -120,6 +125,14 @@ pub enum Limits {
     Range(u32, u32),
     From(u32),
 }
+impl fmt::Display for Limits {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limits::Range(min, max) => write!(f, "{} {}", min, max),
+            Limits::From(min) => write!(f, "{}", min),
+        }
+    }
+}
 
 // https://webassembly.github.io/spec/core/syntax/types.html#memory-types
 pub struct MemType {
@@ -139,6 +152,75 @@ pub struct Export<'s> {
     pub kind: ExportKind,
 }
 
+// https://webassembly.github.io/spec/core/syntax/types.html#external-types
+pub enum ExternType<'m> {
+    Func(&'m FuncType),
+    Table(&'m TableType),
+    Memory(&'m MemType),
+    Global { ty: ValType, mutable: bool },
+}
+
+impl<'m> ExternType<'m> {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ExternType::Func(_) => "func",
+            ExternType::Table(_) => "table",
+            ExternType::Memory(_) => "memory",
+            ExternType::Global { .. } => "global",
+        }
+    }
+}
+
+// Print the type in text format like "func (param i32) (result i32)" or "memory 1 2"
+impl<'m> fmt::Display for ExternType<'m> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.kind())?;
+        match self {
+            ExternType::Func(ty) => {
+                if !ty.params.is_empty() {
+                    f.write_str(" (param")?;
+                    for p in ty.params.iter() {
+                        write!(f, " {}", p)?;
+                    }
+                    f.write_str(")")?;
+                }
+                if !ty.results.is_empty() {
+                    f.write_str(" (result")?;
+                    for r in ty.results.iter() {
+                        write!(f, " {}", r)?;
+                    }
+                    f.write_str(")")?;
+                }
+                Ok(())
+            }
+            ExternType::Table(ty) => write!(f, " {} funcref", ty.limit),
+            ExternType::Memory(ty) => write!(f, " {}", ty.limit),
+            ExternType::Global { ty, mutable: true } => write!(f, " (mut {})", ty),
+            ExternType::Global { ty, mutable: false } => write!(f, " {}", ty),
+        }
+    }
+}
+
+impl<'s> Module<'s> {
+    // Type of the function, table, memory or global referred by the export
+    pub fn export_type(&self, kind: &ExportKind) -> ExternType<'_> {
+        match kind {
+            ExportKind::Func(idx) => {
+                ExternType::Func(&self.types[self.funcs[*idx as usize].idx as usize])
+            }
+            ExportKind::Table(idx) => ExternType::Table(&self.tables[*idx as usize].ty),
+            ExportKind::Memory(idx) => ExternType::Memory(&self.memories[*idx as usize].ty),
+            ExportKind::Global(idx) => {
+                let global = &self.globals[*idx as usize];
+                ExternType::Global {
+                    ty: global.ty,
+                    mutable: global.mutable,
+                }
+            }
+        }
+    }
+}
+
 // https://webassembly.github.io/spec/core/syntax/modules.html#syntax-func
 pub enum FuncKind<'s> {
     Import(Import<'s>),
@@ -160,6 +242,7 @@ pub struct Instruction {
 }
 
 // https://webassembly.github.io/spec/core/syntax/instructions.html#syntax-memarg
+// Alignment is exponent of 2. None means natural alignment of the instruction.
 pub struct Mem {
     pub align: Option<u8>, // TODO: Change this to Option<u32>
     pub offset: Option<u32>,
@@ -442,7 +525,7 @@ impl InsnKind {
             I32Rotr => "i32.rotr",
             I64Clz => "i64.clz",
             I64Ctz => "i64.ctz",
-            I64Popcnt => "i64.popcnd",
+            I64Popcnt => "i64.popcnt",
             I64Add => "i64.add",
             I64Sub => "i64.sub",
             I64Mul => "i64.mul",
@@ -552,6 +635,189 @@ impl InsnKind {
             F64ReinterpretI64 => "f64.reinterpret_i64",
         }
     }
+
+    // Opcode in binary format. Every kind has a distinct opcode so it can be used as a dense index
+    // of instruction kinds
+    pub fn opcode(&self) -> u8 {
+        use InsnKind::*;
+        match self {
+            Block { .. } => 0x02,
+            Loop { .. } => 0x03,
+            If { .. } => 0x04,
+            Unreachable => 0x00,
+            Nop => 0x01,
+            Br(_) => 0x0c,
+            BrIf(_) => 0x0d,
+            BrTable { .. } => 0x0e,
+            Return => 0x0f,
+            Call(_) => 0x10,
+            CallIndirect(_) => 0x11,
+            Drop => 0x1a,
+            Select => 0x1b,
+            LocalGet(_) => 0x20,
+            LocalSet(_) => 0x21,
+            LocalTee(_) => 0x22,
+            GlobalGet(_) => 0x23,
+            GlobalSet(_) => 0x24,
+            I32Load(_) => 0x28,
+            I64Load(_) => 0x29,
+            F32Load(_) => 0x2a,
+            F64Load(_) => 0x2b,
+            I32Load8S(_) => 0x2c,
+            I32Load8U(_) => 0x2d,
+            I32Load16S(_) => 0x2e,
+            I32Load16U(_) => 0x2f,
+            I64Load8S(_) => 0x30,
+            I64Load8U(_) => 0x31,
+            I64Load16S(_) => 0x32,
+            I64Load16U(_) => 0x33,
+            I64Load32S(_) => 0x34,
+            I64Load32U(_) => 0x35,
+            I32Store(_) => 0x36,
+            I64Store(_) => 0x37,
+            F32Store(_) => 0x38,
+            F64Store(_) => 0x39,
+            I32Store8(_) => 0x3a,
+            I32Store16(_) => 0x3b,
+            I64Store8(_) => 0x3c,
+            I64Store16(_) => 0x3d,
+            I64Store32(_) => 0x3e,
+            MemorySize => 0x3f,
+            MemoryGrow => 0x40,
+            I32Const(_) => 0x41,
+            I64Const(_) => 0x42,
+            F32Const(_) => 0x43,
+            F64Const(_) => 0x44,
+            I32Clz => 0x67,
+            I32Ctz => 0x68,
+            I32Popcnt => 0x69,
+            I32Add => 0x6a,
+            I32Sub => 0x6b,
+            I32Mul => 0x6c,
+            I32DivS => 0x6d,
+            I32DivU => 0x6e,
+            I32RemS => 0x6f,
+            I32RemU => 0x70,
+            I32And => 0x71,
+            I32Or => 0x72,
+            I32Xor => 0x73,
+            I32Shl => 0x74,
+            I32ShrS => 0x75,
+            I32ShrU => 0x76,
+            I32Rotl => 0x77,
+            I32Rotr => 0x78,
+            I64Clz => 0x79,
+            I64Ctz => 0x7a,
+            I64Popcnt => 0x7b,
+            I64Add => 0x7c,
+            I64Sub => 0x7d,
+            I64Mul => 0x7e,
+            I64DivS => 0x7f,
+            I64DivU => 0x80,
+            I64RemS => 0x81,
+            I64RemU => 0x82,
+            I64And => 0x83,
+            I64Or => 0x84,
+            I64Xor => 0x85,
+            I64Shl => 0x86,
+            I64ShrS => 0x87,
+            I64ShrU => 0x88,
+            I64Rotl => 0x89,
+            I64Rotr => 0x8a,
+            F32Abs => 0x8b,
+            F32Neg => 0x8c,
+            F32Ceil => 0x8d,
+            F32Floor => 0x8e,
+            F32Trunc => 0x8f,
+            F32Nearest => 0x90,
+            F32Sqrt => 0x91,
+            F32Add => 0x92,
+            F32Sub => 0x93,
+            F32Mul => 0x94,
+            F32Div => 0x95,
+            F32Min => 0x96,
+            F32Max => 0x97,
+            F32Copysign => 0x98,
+            F64Abs => 0x99,
+            F64Neg => 0x9a,
+            F64Ceil => 0x9b,
+            F64Floor => 0x9c,
+            F64Trunc => 0x9d,
+            F64Nearest => 0x9e,
+            F64Sqrt => 0x9f,
+            F64Add => 0xa0,
+            F64Sub => 0xa1,
+            F64Mul => 0xa2,
+            F64Div => 0xa3,
+            F64Min => 0xa4,
+            F64Max => 0xa5,
+            F64Copysign => 0xa6,
+            // i32 comparison
+            I32Eqz => 0x45,
+            I32Eq => 0x46,
+            I32Ne => 0x47,
+            I32LtS => 0x48,
+            I32LtU => 0x49,
+            I32GtS => 0x4a,
+            I32GtU => 0x4b,
+            I32LeS => 0x4c,
+            I32LeU => 0x4d,
+            I32GeS => 0x4e,
+            I32GeU => 0x4f,
+            // i64 comparison
+            I64Eqz => 0x50,
+            I64Eq => 0x51,
+            I64Ne => 0x52,
+            I64LtS => 0x53,
+            I64LtU => 0x54,
+            I64GtS => 0x55,
+            I64GtU => 0x56,
+            I64LeS => 0x57,
+            I64LeU => 0x58,
+            I64GeS => 0x59,
+            I64GeU => 0x5a,
+            // f32 comparison
+            F32Eq => 0x5b,
+            F32Ne => 0x5c,
+            F32Lt => 0x5d,
+            F32Gt => 0x5e,
+            F32Le => 0x5f,
+            F32Ge => 0x60,
+            // f64 comparison
+            F64Eq => 0x61,
+            F64Ne => 0x62,
+            F64Lt => 0x63,
+            F64Gt => 0x64,
+            F64Le => 0x65,
+            F64Ge => 0x66,
+            // Conversion
+            I32WrapI64 => 0xa7,
+            I32TruncF32S => 0xa8,
+            I32TruncF32U => 0xa9,
+            I32TruncF64S => 0xaa,
+            I32TruncF64U => 0xab,
+            I64ExtendI32S => 0xac,
+            I64ExtendI32U => 0xad,
+            I64TruncF32S => 0xae,
+            I64TruncF32U => 0xaf,
+            I64TruncF64S => 0xb0,
+            I64TruncF64U => 0xb1,
+            F32ConvertI32S => 0xb2,
+            F32ConvertI32U => 0xb3,
+            F32ConvertI64S => 0xb4,
+            F32ConvertI64U => 0xb5,
+            F32DemoteF64 => 0xb6,
+            F64ConvertI32S => 0xb7,
+            F64ConvertI32U => 0xb8,
+            F64ConvertI64S => 0xb9,
+            F64ConvertI64U => 0xba,
+            F64PromoteF32 => 0xbb,
+            I32ReinterpretF32 => 0xbc,
+            I64ReinterpretF64 => 0xbd,
+            F32ReinterpretI32 => 0xbe,
+            F64ReinterpretI64 => 0xbf,
+        }
+    }
 }
 
 // https://webassembly.github.io/spec/core/syntax/modules.html#element-segments
@@ -602,3 +868,126 @@ pub struct StartFunction {
     pub start: usize,
     pub idx: FuncIdx,
 }
+
+// https://webassembly.github.io/spec/core/binary/modules.html#sections
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SectionId {
+    Type,
+    Import,
+    Func,
+    Table,
+    Memory,
+    Global,
+    Export,
+    Start,
+    Elem,
+    Code,
+    Data,
+}
+impl AsRef<str> for SectionId {
+    fn as_ref(&self) -> &'_ str {
+        match self {
+            SectionId::Type => "type",
+            SectionId::Import => "import",
+            SectionId::Func => "func",
+            SectionId::Table => "table",
+            SectionId::Memory => "memory",
+            SectionId::Global => "global",
+            SectionId::Export => "export",
+            SectionId::Start => "start",
+            SectionId::Elem => "elem",
+            SectionId::Code => "code",
+            SectionId::Data => "data",
+        }
+    }
+}
+impl fmt::Display for SectionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_ref())
+    }
+}
+
+// Where a custom section is put relative to standard sections.
+// https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md#custom-annotations
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum CustomPlace {
+    BeforeFirst,
+    Before(SectionId),
+    After(SectionId),
+    AfterLast,
+}
+
+// https://webassembly.github.io/spec/core/binary/modules.html#custom-section
+pub struct Custom<'s> {
+    pub start: usize,
+    pub name: Name<'s>,
+    pub place: CustomPlace,
+    pub data: Cow<'s, [u8]>,
+}
+
+// https://webassembly.github.io/spec/core/appendix/custom.html#name-maps
+pub type NameMap<'s> = Vec<(u32, Name<'s>)>;
+pub type IndirectNameMap<'s> = Vec<(u32, NameMap<'s>)>;
+
+// https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+// Entries of name maps are sorted by their indices.
+#[derive(Default)]
+pub struct Names<'s> {
+    pub module: Option<Name<'s>>,
+    pub funcs: NameMap<'s>,
+    pub locals: IndirectNameMap<'s>,
+    // Following subsections are defined in extended name section proposal
+    // https://github.com/WebAssembly/extended-name-section/blob/main/proposals/extended-name-section/Overview.md
+    pub labels: IndirectNameMap<'s>,
+    pub types: NameMap<'s>,
+    pub tables: NameMap<'s>,
+    pub memories: NameMap<'s>,
+    pub globals: NameMap<'s>,
+    pub elems: NameMap<'s>,
+    pub data: NameMap<'s>,
+}
+
+fn lookup_name<'a, 's>(map: &'a NameMap<'s>, idx: u32) -> Option<&'a Name<'s>> {
+    let i = map.binary_search_by_key(&idx, |(i, _)| *i).ok()?;
+    Some(&map[i].1)
+}
+
+impl<'s> Names<'s> {
+    pub fn func(&self, funcidx: u32) -> Option<&Name<'s>> {
+        lookup_name(&self.funcs, funcidx)
+    }
+
+    pub fn local(&self, funcidx: u32, localidx: u32) -> Option<&Name<'s>> {
+        let i = self
+            .locals
+            .binary_search_by_key(&funcidx, |(i, _)| *i)
+            .ok()?;
+        lookup_name(&self.locals[i].1, localidx)
+    }
+
+    pub fn global(&self, globalidx: u32) -> Option<&Name<'s>> {
+        lookup_name(&self.globals, globalidx)
+    }
+}
+
+// https://github.com/WebAssembly/tool-conventions/blob/main/ProducersSection.md
+pub struct ProducersField<'s> {
+    pub start: usize,
+    pub name: Name<'s>,
+    // Pairs of program name and its version
+    pub values: Vec<(Name<'s>, Name<'s>)>,
+}
+
+// https://github.com/WebAssembly/tool-conventions/blob/main/Linking.md#target-features-section
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum FeaturePrefix {
+    Used,       // '+'
+    Required,   // '='
+    Disallowed, // '-'
+}
+
+pub struct TargetFeature<'s> {
+    pub start: usize,
+    pub prefix: FeaturePrefix,
+    pub name: Name<'s>,
+}
@@ -0,0 +1,111 @@
+// Constants defined in DWARF specification
+// https://dwarfstd.org/doc/DWARF5.pdf 7. Data Representation
+
+// 7.5.4 Attribute encodings
+pub(crate) const DW_AT_NAME: u64 = 0x03;
+pub(crate) const DW_AT_STMT_LIST: u64 = 0x10;
+pub(crate) const DW_AT_LOW_PC: u64 = 0x11;
+pub(crate) const DW_AT_HIGH_PC: u64 = 0x12;
+pub(crate) const DW_AT_COMP_DIR: u64 = 0x1b;
+pub(crate) const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+pub(crate) const DW_AT_SPECIFICATION: u64 = 0x47;
+pub(crate) const DW_AT_RANGES: u64 = 0x55;
+pub(crate) const DW_AT_CALL_COLUMN: u64 = 0x57;
+pub(crate) const DW_AT_CALL_FILE: u64 = 0x58;
+pub(crate) const DW_AT_CALL_LINE: u64 = 0x59;
+pub(crate) const DW_AT_LINKAGE_NAME: u64 = 0x6e;
+pub(crate) const DW_AT_STR_OFFSETS_BASE: u64 = 0x72;
+pub(crate) const DW_AT_ADDR_BASE: u64 = 0x73;
+pub(crate) const DW_AT_RNGLISTS_BASE: u64 = 0x74;
+pub(crate) const DW_AT_MIPS_LINKAGE_NAME: u64 = 0x2007;
+pub(crate) const DW_AT_GNU_ADDR_BASE: u64 = 0x2133;
+
+// 7.5.3 Debugging information entry tags
+pub(crate) const DW_TAG_INLINED_SUBROUTINE: u64 = 0x1d;
+pub(crate) const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+
+// 7.5.5 Attribute form encodings
+pub(crate) const DW_FORM_ADDR: u64 = 0x01;
+pub(crate) const DW_FORM_BLOCK2: u64 = 0x03;
+pub(crate) const DW_FORM_BLOCK4: u64 = 0x04;
+pub(crate) const DW_FORM_DATA2: u64 = 0x05;
+pub(crate) const DW_FORM_DATA4: u64 = 0x06;
+pub(crate) const DW_FORM_DATA8: u64 = 0x07;
+pub(crate) const DW_FORM_STRING: u64 = 0x08;
+pub(crate) const DW_FORM_BLOCK: u64 = 0x09;
+pub(crate) const DW_FORM_BLOCK1: u64 = 0x0a;
+pub(crate) const DW_FORM_DATA1: u64 = 0x0b;
+pub(crate) const DW_FORM_FLAG: u64 = 0x0c;
+pub(crate) const DW_FORM_SDATA: u64 = 0x0d;
+pub(crate) const DW_FORM_STRP: u64 = 0x0e;
+pub(crate) const DW_FORM_UDATA: u64 = 0x0f;
+pub(crate) const DW_FORM_REF_ADDR: u64 = 0x10;
+pub(crate) const DW_FORM_REF1: u64 = 0x11;
+pub(crate) const DW_FORM_REF2: u64 = 0x12;
+pub(crate) const DW_FORM_REF4: u64 = 0x13;
+pub(crate) const DW_FORM_REF8: u64 = 0x14;
+pub(crate) const DW_FORM_REF_UDATA: u64 = 0x15;
+pub(crate) const DW_FORM_INDIRECT: u64 = 0x16;
+pub(crate) const DW_FORM_SEC_OFFSET: u64 = 0x17;
+pub(crate) const DW_FORM_EXPRLOC: u64 = 0x18;
+pub(crate) const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+pub(crate) const DW_FORM_STRX: u64 = 0x1a;
+pub(crate) const DW_FORM_ADDRX: u64 = 0x1b;
+pub(crate) const DW_FORM_REF_SUP4: u64 = 0x1c;
+pub(crate) const DW_FORM_STRP_SUP: u64 = 0x1d;
+pub(crate) const DW_FORM_DATA16: u64 = 0x1e;
+pub(crate) const DW_FORM_LINE_STRP: u64 = 0x1f;
+pub(crate) const DW_FORM_REF_SIG8: u64 = 0x20;
+pub(crate) const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+pub(crate) const DW_FORM_LOCLISTX: u64 = 0x22;
+pub(crate) const DW_FORM_RNGLISTX: u64 = 0x23;
+pub(crate) const DW_FORM_REF_SUP8: u64 = 0x24;
+pub(crate) const DW_FORM_STRX1: u64 = 0x25;
+pub(crate) const DW_FORM_STRX2: u64 = 0x26;
+pub(crate) const DW_FORM_STRX3: u64 = 0x27;
+pub(crate) const DW_FORM_STRX4: u64 = 0x28;
+pub(crate) const DW_FORM_ADDRX1: u64 = 0x29;
+pub(crate) const DW_FORM_ADDRX2: u64 = 0x2a;
+pub(crate) const DW_FORM_ADDRX3: u64 = 0x2b;
+pub(crate) const DW_FORM_ADDRX4: u64 = 0x2c;
+pub(crate) const DW_FORM_GNU_ADDR_INDEX: u64 = 0x1f01;
+pub(crate) const DW_FORM_GNU_STR_INDEX: u64 = 0x1f02;
+pub(crate) const DW_FORM_GNU_REF_ALT: u64 = 0x1f20;
+pub(crate) const DW_FORM_GNU_STRP_ALT: u64 = 0x1f21;
+
+// 7.5.1 Unit headers
+pub(crate) const DW_UT_COMPILE: u8 = 0x01;
+pub(crate) const DW_UT_TYPE: u8 = 0x02;
+pub(crate) const DW_UT_PARTIAL: u8 = 0x03;
+pub(crate) const DW_UT_SKELETON: u8 = 0x04;
+pub(crate) const DW_UT_SPLIT_COMPILE: u8 = 0x05;
+pub(crate) const DW_UT_SPLIT_TYPE: u8 = 0x06;
+
+// 7.22 Line number information
+pub(crate) const DW_LNS_COPY: u8 = 0x01;
+pub(crate) const DW_LNS_ADVANCE_PC: u8 = 0x02;
+pub(crate) const DW_LNS_ADVANCE_LINE: u8 = 0x03;
+pub(crate) const DW_LNS_SET_FILE: u8 = 0x04;
+pub(crate) const DW_LNS_SET_COLUMN: u8 = 0x05;
+pub(crate) const DW_LNS_NEGATE_STMT: u8 = 0x06;
+pub(crate) const DW_LNS_SET_BASIC_BLOCK: u8 = 0x07;
+pub(crate) const DW_LNS_CONST_ADD_PC: u8 = 0x08;
+pub(crate) const DW_LNS_FIXED_ADVANCE_PC: u8 = 0x09;
+pub(crate) const DW_LNS_SET_PROLOGUE_END: u8 = 0x0a;
+pub(crate) const DW_LNS_SET_EPILOGUE_BEGIN: u8 = 0x0b;
+pub(crate) const DW_LNS_SET_ISA: u8 = 0x0c;
+pub(crate) const DW_LNE_END_SEQUENCE: u8 = 0x01;
+pub(crate) const DW_LNE_SET_ADDRESS: u8 = 0x02;
+pub(crate) const DW_LNE_DEFINE_FILE: u8 = 0x03;
+pub(crate) const DW_LNCT_PATH: u64 = 0x1;
+pub(crate) const DW_LNCT_DIRECTORY_INDEX: u64 = 0x2;
+
+// 7.25 Range list entries
+pub(crate) const DW_RLE_END_OF_LIST: u8 = 0x00;
+pub(crate) const DW_RLE_BASE_ADDRESSX: u8 = 0x01;
+pub(crate) const DW_RLE_STARTX_ENDX: u8 = 0x02;
+pub(crate) const DW_RLE_STARTX_LENGTH: u8 = 0x03;
+pub(crate) const DW_RLE_OFFSET_PAIR: u8 = 0x04;
+pub(crate) const DW_RLE_BASE_ADDRESS: u8 = 0x05;
+pub(crate) const DW_RLE_START_END: u8 = 0x06;
+pub(crate) const DW_RLE_START_LENGTH: u8 = 0x07;
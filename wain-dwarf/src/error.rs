@@ -0,0 +1,62 @@
+use std::fmt;
+use std::str::Utf8Error;
+
+#[cfg_attr(test, derive(Debug))]
+pub enum ErrorKind {
+    NotWasmBinary,
+    UnexpectedEof { expected: &'static str },
+    IntOverflow { ty: &'static str },
+    InvalidUtf8(Utf8Error),
+    UnsupportedVersion(u16),
+    UnsupportedAddressSize(u8),
+    UnknownForm(u64),
+    UnknownAbbrev(u64),
+    UnknownRangeListEntry(u8),
+    OutOfSection { what: &'static str, offset: u64 },
+}
+
+#[cfg_attr(test, derive(Debug))]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub section: &'static str,
+    pub offset: usize,
+}
+
+impl Error {
+    pub(crate) fn new(kind: ErrorKind, section: &'static str, offset: usize) -> Box<Error> {
+        Box::new(Error {
+            kind,
+            section,
+            offset,
+        })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ErrorKind::*;
+        match &self.kind {
+            NotWasmBinary => f.write_str("input is not WebAssembly binary")?,
+            UnexpectedEof { expected } => {
+                write!(f, "expected {} but reached end of section", expected)?
+            }
+            IntOverflow { ty } => write!(f, "LEB128-encoded integer is too large for {}", ty)?,
+            InvalidUtf8(err) => write!(f, "string must be UTF-8 sequence: {}", err)?,
+            UnsupportedVersion(v) => write!(f, "DWARF version {} is not supported", v)?,
+            UnsupportedAddressSize(s) => write!(f, "address size {} is not supported", s)?,
+            UnknownForm(form) => write!(f, "unknown attribute form 0x{:x}", form)?,
+            UnknownAbbrev(code) => write!(f, "unknown abbreviation code {}", code)?,
+            UnknownRangeListEntry(kind) => write!(f, "unknown range list entry kind 0x{:x}", kind)?,
+            OutOfSection { what, offset } => {
+                write!(f, "{} at offset 0x{:x} is out of section", what, offset)?
+            }
+        }
+        write!(
+            f,
+            " while reading '{}' section at offset 0x{:x}",
+            self.section, self.offset
+        )
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, Box<Error>>;
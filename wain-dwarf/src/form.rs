@@ -0,0 +1,186 @@
+use crate::consts::*;
+use crate::error::{ErrorKind, Result};
+use crate::reader::Reader;
+use crate::wasm::Sections;
+
+// Value of attribute. Values which are not necessary for looking up source locations are omitted.
+// https://dwarfstd.org/doc/DWARF5.pdf 7.5.5 Classes and Forms
+pub(crate) enum Value<'a> {
+    Addr(u64),
+    Addrx(u64),
+    Const(u64),
+    Sdata(i64),
+    Str(&'a str),
+    Strp(u64),
+    LineStrp(u64),
+    Strx(u64),
+    UnitRef(u64), // Offset relative to the start of unit
+    InfoRef(u64), // Offset in .debug_info section
+    SecOffset(u64),
+    Rnglistx(u64),
+    Other,
+}
+
+impl<'a> Value<'a> {
+    pub(crate) fn udata(&self) -> Option<u64> {
+        match self {
+            Value::Const(u) => Some(*u),
+            Value::Sdata(i) if *i >= 0 => Some(*i as u64),
+            _ => None,
+        }
+    }
+}
+
+// How values are encoded in the unit
+#[derive(Clone, Copy)]
+pub(crate) struct Encoding {
+    pub(crate) version: u16,
+    pub(crate) addr_size: u8,
+    pub(crate) offset_size: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn addr(&mut self, enc: &Encoding) -> Result<u64> {
+        self.uint(enc.addr_size as usize)
+    }
+
+    pub(crate) fn offset(&mut self, enc: &Encoding) -> Result<u64> {
+        self.uint(enc.offset_size)
+    }
+
+    fn block(&mut self, len: u64) -> Result<Value<'a>> {
+        self.split(len, "block")?;
+        Ok(Value::Other)
+    }
+
+    pub(crate) fn value(&mut self, form: u64, enc: &Encoding, implicit: i64) -> Result<Value<'a>> {
+        let value = match form {
+            DW_FORM_ADDR => Value::Addr(self.addr(enc)?),
+            DW_FORM_ADDRX | DW_FORM_GNU_ADDR_INDEX => Value::Addrx(self.uleb128()?),
+            DW_FORM_ADDRX1 => Value::Addrx(self.uint(1)?),
+            DW_FORM_ADDRX2 => Value::Addrx(self.uint(2)?),
+            DW_FORM_ADDRX3 => Value::Addrx(self.uint(3)?),
+            DW_FORM_ADDRX4 => Value::Addrx(self.uint(4)?),
+            DW_FORM_DATA1 => Value::Const(self.uint(1)?),
+            DW_FORM_DATA2 => Value::Const(self.uint(2)?),
+            DW_FORM_DATA4 => Value::Const(self.uint(4)?),
+            DW_FORM_DATA8 => Value::Const(self.uint(8)?),
+            DW_FORM_UDATA => Value::Const(self.uleb128()?),
+            DW_FORM_SDATA => Value::Sdata(self.sleb128()?),
+            DW_FORM_IMPLICIT_CONST => Value::Sdata(implicit),
+            DW_FORM_DATA16 => self.block(16)?,
+            DW_FORM_STRING => Value::Str(self.cstr()?),
+            DW_FORM_STRP => Value::Strp(self.offset(enc)?),
+            DW_FORM_LINE_STRP => Value::LineStrp(self.offset(enc)?),
+            DW_FORM_STRX | DW_FORM_GNU_STR_INDEX => Value::Strx(self.uleb128()?),
+            DW_FORM_STRX1 => Value::Strx(self.uint(1)?),
+            DW_FORM_STRX2 => Value::Strx(self.uint(2)?),
+            DW_FORM_STRX3 => Value::Strx(self.uint(3)?),
+            DW_FORM_STRX4 => Value::Strx(self.uint(4)?),
+            DW_FORM_REF1 => Value::UnitRef(self.uint(1)?),
+            DW_FORM_REF2 => Value::UnitRef(self.uint(2)?),
+            DW_FORM_REF4 => Value::UnitRef(self.uint(4)?),
+            DW_FORM_REF8 => Value::UnitRef(self.uint(8)?),
+            DW_FORM_REF_UDATA => Value::UnitRef(self.uleb128()?),
+            // In DWARF 2, DW_FORM_ref_addr has the same size as address
+            DW_FORM_REF_ADDR if enc.version <= 2 => Value::InfoRef(self.addr(enc)?),
+            DW_FORM_REF_ADDR => Value::InfoRef(self.offset(enc)?),
+            DW_FORM_SEC_OFFSET => Value::SecOffset(self.offset(enc)?),
+            DW_FORM_RNGLISTX => Value::Rnglistx(self.uleb128()?),
+            DW_FORM_LOCLISTX => {
+                self.uleb128()?;
+                Value::Other
+            }
+            DW_FORM_FLAG => {
+                self.u8()?;
+                Value::Other
+            }
+            DW_FORM_FLAG_PRESENT => Value::Other,
+            DW_FORM_BLOCK1 => {
+                let len = self.uint(1)?;
+                self.block(len)?
+            }
+            DW_FORM_BLOCK2 => {
+                let len = self.uint(2)?;
+                self.block(len)?
+            }
+            DW_FORM_BLOCK4 => {
+                let len = self.uint(4)?;
+                self.block(len)?
+            }
+            DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+                let len = self.uleb128()?;
+                self.block(len)?
+            }
+            DW_FORM_REF_SIG8 | DW_FORM_REF_SUP8 => self.block(8)?,
+            DW_FORM_REF_SUP4 => self.block(4)?,
+            DW_FORM_STRP_SUP | DW_FORM_GNU_REF_ALT | DW_FORM_GNU_STRP_ALT => {
+                self.offset(enc)?;
+                Value::Other
+            }
+            DW_FORM_INDIRECT => {
+                let form = self.uleb128()?;
+                self.value(form, enc, implicit)?
+            }
+            form => return self.error(ErrorKind::UnknownForm(form)),
+        };
+        Ok(value)
+    }
+}
+
+// Context of the unit to resolve indirect attribute values
+#[derive(Clone, Copy)]
+pub(crate) struct UnitContext {
+    pub(crate) enc: Encoding,
+    pub(crate) str_offsets_base: u64,
+    pub(crate) addr_base: u64,
+    pub(crate) rnglists_base: u64,
+}
+
+impl UnitContext {
+    pub(crate) fn string<'a>(
+        &self,
+        sections: &Sections<'a>,
+        value: &Value<'a>,
+    ) -> Result<Option<&'a str>> {
+        let s = match value {
+            Value::Str(s) => s,
+            Value::Strp(offset) => Reader::new(sections.debug_str, ".debug_str")
+                .at(*offset, "string")?
+                .cstr()?,
+            Value::LineStrp(offset) => Reader::new(sections.debug_line_str, ".debug_line_str")
+                .at(*offset, "string")?
+                .cstr()?,
+            Value::Strx(idx) => {
+                let pos = self.str_offsets_base + idx * self.enc.offset_size as u64;
+                let offset = Reader::new(sections.debug_str_offsets, ".debug_str_offsets")
+                    .at(pos, "string offset")?
+                    .offset(&self.enc)?;
+                Reader::new(sections.debug_str, ".debug_str")
+                    .at(offset, "string")?
+                    .cstr()?
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(s))
+    }
+
+    pub(crate) fn address(
+        &self,
+        sections: &Sections<'_>,
+        value: &Value<'_>,
+    ) -> Result<Option<u64>> {
+        match value {
+            Value::Addr(addr) => Ok(Some(*addr)),
+            Value::Addrx(idx) => self.indexed_address(sections, *idx).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub(crate) fn indexed_address(&self, sections: &Sections<'_>, idx: u64) -> Result<u64> {
+        let pos = self.addr_base + idx * u64::from(self.enc.addr_size);
+        Reader::new(sections.debug_addr, ".debug_addr")
+            .at(pos, "address")?
+            .addr(&self.enc)
+    }
+}
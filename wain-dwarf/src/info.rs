@@ -0,0 +1,391 @@
+// Debugging information entries in .debug_info section
+// https://dwarfstd.org/doc/DWARF5.pdf 2. General Description
+
+use crate::consts::*;
+use crate::error::{ErrorKind, Result};
+use crate::form::{Encoding, UnitContext, Value};
+use crate::line::{parse_line_program, LineProgram};
+use crate::reader::Reader;
+use crate::wasm::Sections;
+use std::collections::HashMap;
+
+// https://dwarfstd.org/doc/DWARF5.pdf 7.5.3 Abbreviations Tables
+struct Abbrev {
+    tag: u64,
+    has_children: bool,
+    attrs: Vec<(u64, u64, i64)>, // Attribute name, form and implicit constant
+}
+
+fn parse_abbrevs(sections: &Sections<'_>, offset: u64) -> Result<HashMap<u64, Abbrev>> {
+    let mut reader =
+        Reader::new(sections.debug_abbrev, ".debug_abbrev").at(offset, "abbreviations table")?;
+    let mut abbrevs = HashMap::new();
+    loop {
+        let code = reader.uleb128()?;
+        if code == 0 {
+            return Ok(abbrevs);
+        }
+        let tag = reader.uleb128()?;
+        let has_children = reader.u8()? != 0;
+        let mut attrs = vec![];
+        loop {
+            let name = reader.uleb128()?;
+            let form = reader.uleb128()?;
+            if name == 0 && form == 0 {
+                break;
+            }
+            let implicit = if form == DW_FORM_IMPLICIT_CONST {
+                reader.sleb128()?
+            } else {
+                0
+            };
+            attrs.push((name, form, implicit));
+        }
+        abbrevs.insert(
+            code,
+            Abbrev {
+                tag,
+                has_children,
+                attrs,
+            },
+        );
+    }
+}
+
+// Scope of subprogram or inlined subroutine which has code
+pub(crate) struct Scope {
+    pub(crate) offset: u64, // Offset of the entry in .debug_info
+    pub(crate) ranges: Vec<(u64, u64)>,
+    pub(crate) call_file: Option<u64>, // File number in line program of the unit
+    pub(crate) call_line: u32,
+    pub(crate) call_column: u32,
+    pub(crate) children: Vec<Scope>,
+}
+
+pub(crate) struct Unit {
+    pub(crate) line_program: Option<LineProgram>,
+    pub(crate) scopes: Vec<Scope>,
+}
+
+// Name of entry or reference to other entry which describes the name
+pub(crate) enum EntryName {
+    Name(String),
+    Origin(u64),
+}
+
+#[derive(Default)]
+struct Attrs<'a> {
+    name: Option<Value<'a>>,
+    linkage_name: Option<Value<'a>>,
+    low_pc: Option<Value<'a>>,
+    high_pc: Option<Value<'a>>,
+    ranges: Option<Value<'a>>,
+    origin: Option<u64>,
+    call_file: Option<u64>,
+    call_line: Option<u64>,
+    call_column: Option<u64>,
+    stmt_list: Option<u64>,
+    comp_dir: Option<Value<'a>>,
+    str_offsets_base: Option<u64>,
+    addr_base: Option<u64>,
+    rnglists_base: Option<u64>,
+}
+
+struct UnitParser<'s, 'a> {
+    sections: &'s Sections<'a>,
+    ctx: UnitContext,
+    unit_offset: u64,
+    base_address: u64,
+}
+
+impl<'s, 'a> UnitParser<'s, 'a> {
+    fn attrs(&self, reader: &mut Reader<'a>, abbrev: &Abbrev) -> Result<Attrs<'a>> {
+        let mut attrs = Attrs::default();
+        for (name, form, implicit) in abbrev.attrs.iter() {
+            let value = reader.value(*form, &self.ctx.enc, *implicit)?;
+            match *name {
+                DW_AT_NAME => attrs.name = Some(value),
+                DW_AT_LINKAGE_NAME | DW_AT_MIPS_LINKAGE_NAME => attrs.linkage_name = Some(value),
+                DW_AT_LOW_PC => attrs.low_pc = Some(value),
+                DW_AT_HIGH_PC => attrs.high_pc = Some(value),
+                DW_AT_RANGES => attrs.ranges = Some(value),
+                DW_AT_ABSTRACT_ORIGIN | DW_AT_SPECIFICATION => {
+                    attrs.origin = match value {
+                        Value::UnitRef(offset) => Some(self.unit_offset + offset),
+                        Value::InfoRef(offset) => Some(offset),
+                        _ => None,
+                    }
+                }
+                DW_AT_CALL_FILE => attrs.call_file = value.udata(),
+                DW_AT_CALL_LINE => attrs.call_line = value.udata(),
+                DW_AT_CALL_COLUMN => attrs.call_column = value.udata(),
+                DW_AT_STMT_LIST => attrs.stmt_list = sec_offset(&value),
+                DW_AT_COMP_DIR => attrs.comp_dir = Some(value),
+                DW_AT_STR_OFFSETS_BASE => attrs.str_offsets_base = sec_offset(&value),
+                DW_AT_ADDR_BASE | DW_AT_GNU_ADDR_BASE => attrs.addr_base = sec_offset(&value),
+                DW_AT_RNGLISTS_BASE => attrs.rnglists_base = sec_offset(&value),
+                _ => {}
+            }
+        }
+        Ok(attrs)
+    }
+
+    fn string(&self, value: &Option<Value<'a>>) -> Result<Option<&'a str>> {
+        match value {
+            Some(value) => self.ctx.string(self.sections, value),
+            None => Ok(None),
+        }
+    }
+
+    fn ranges(&self, attrs: &Attrs<'a>) -> Result<Vec<(u64, u64)>> {
+        if let Some(ranges) = &attrs.ranges {
+            return match *ranges {
+                Value::Rnglistx(idx) => {
+                    let base = self.ctx.rnglists_base;
+                    let pos = base + idx * self.ctx.enc.offset_size as u64;
+                    let offset = Reader::new(self.sections.debug_rnglists, ".debug_rnglists")
+                        .at(pos, "range list offset")?
+                        .offset(&self.ctx.enc)?;
+                    self.range_list(base + offset)
+                }
+                Value::SecOffset(offset) if self.ctx.enc.version >= 5 => self.range_list(offset),
+                Value::SecOffset(offset) => self.legacy_range_list(offset),
+                _ => Ok(vec![]),
+            };
+        }
+
+        let low = match &attrs.low_pc {
+            Some(value) => self.ctx.address(self.sections, value)?,
+            None => None,
+        };
+        let low = match low {
+            Some(low) => low,
+            None => return Ok(vec![]),
+        };
+        let high = match &attrs.high_pc {
+            Some(Value::Const(len)) => low.wrapping_add(*len),
+            Some(value) => match self.ctx.address(self.sections, value)? {
+                Some(high) => high,
+                None => return Ok(vec![]),
+            },
+            None => low.wrapping_add(1),
+        };
+        Ok(vec![(low, high)])
+    }
+
+    // https://dwarfstd.org/doc/DWARF5.pdf 2.17.3 Non-Contiguous Address Ranges
+    fn range_list(&self, offset: u64) -> Result<Vec<(u64, u64)>> {
+        let mut reader = Reader::new(self.sections.debug_rnglists, ".debug_rnglists")
+            .at(offset, "range list")?;
+        let enc = &self.ctx.enc;
+        let mut base = self.base_address;
+        let mut ranges = vec![];
+        loop {
+            let kind = reader.u8()?;
+            let range = match kind {
+                DW_RLE_END_OF_LIST => return Ok(ranges),
+                DW_RLE_BASE_ADDRESSX => {
+                    base = self.ctx.indexed_address(self.sections, reader.uleb128()?)?;
+                    continue;
+                }
+                DW_RLE_STARTX_ENDX => {
+                    let start = self.ctx.indexed_address(self.sections, reader.uleb128()?)?;
+                    let end = self.ctx.indexed_address(self.sections, reader.uleb128()?)?;
+                    (start, end)
+                }
+                DW_RLE_STARTX_LENGTH => {
+                    let start = self.ctx.indexed_address(self.sections, reader.uleb128()?)?;
+                    (start, start.wrapping_add(reader.uleb128()?))
+                }
+                DW_RLE_OFFSET_PAIR => {
+                    let start = base.wrapping_add(reader.uleb128()?);
+                    (start, base.wrapping_add(reader.uleb128()?))
+                }
+                DW_RLE_BASE_ADDRESS => {
+                    base = reader.addr(enc)?;
+                    continue;
+                }
+                DW_RLE_START_END => (reader.addr(enc)?, reader.addr(enc)?),
+                DW_RLE_START_LENGTH => {
+                    let start = reader.addr(enc)?;
+                    (start, start.wrapping_add(reader.uleb128()?))
+                }
+                kind => return reader.error(ErrorKind::UnknownRangeListEntry(kind)),
+            };
+            ranges.push(range);
+        }
+    }
+
+    // Range list in .debug_ranges section of DWARF 4 or earlier
+    fn legacy_range_list(&self, offset: u64) -> Result<Vec<(u64, u64)>> {
+        let mut reader =
+            Reader::new(self.sections.debug_ranges, ".debug_ranges").at(offset, "range list")?;
+        let enc = &self.ctx.enc;
+        let max_addr = u64::MAX >> (64 - 8 * u32::from(enc.addr_size));
+        let mut base = self.base_address;
+        let mut ranges = vec![];
+        loop {
+            let start = reader.addr(enc)?;
+            let end = reader.addr(enc)?;
+            if start == 0 && end == 0 {
+                return Ok(ranges);
+            }
+            if start == max_addr {
+                base = end; // Base address selection entry
+                continue;
+            }
+            ranges.push((base.wrapping_add(start), base.wrapping_add(end)));
+        }
+    }
+}
+
+fn sec_offset(value: &Value<'_>) -> Option<u64> {
+    match value {
+        Value::SecOffset(offset) => Some(*offset),
+        value => value.udata(),
+    }
+}
+
+// Attach the scope to the innermost enclosing scope which is still open
+fn attach(scope: Scope, open: &mut [Option<Scope>], toplevel: &mut Vec<Scope>) {
+    if scope.ranges.is_empty() {
+        return; // Entries without code such as abstract instances are not necessary for lookup
+    }
+    match open.iter_mut().rev().find_map(Option::as_mut) {
+        Some(parent) => parent.children.push(scope),
+        None => toplevel.push(scope),
+    }
+}
+
+pub(crate) fn parse_units(
+    sections: &Sections<'_>,
+    names: &mut HashMap<u64, EntryName>,
+) -> Result<Vec<Unit>> {
+    let mut units = vec![];
+    let mut reader = Reader::new(sections.debug_info, ".debug_info");
+    while !reader.is_empty() {
+        let unit_offset = reader.pos() as u64;
+        let (len, offset_size) = reader.initial_length()?;
+        let mut unit = reader.split(len, "unit")?;
+
+        let version = unit.u16()?;
+        if !(2..=5).contains(&version) {
+            return unit.error(ErrorKind::UnsupportedVersion(version));
+        }
+        let (addr_size, abbrev_offset) = if version >= 5 {
+            let unit_type = unit.u8()?;
+            let addr_size = unit.u8()?;
+            let abbrev_offset = unit.uint(offset_size)?;
+            match unit_type {
+                DW_UT_COMPILE | DW_UT_PARTIAL => {}
+                DW_UT_SKELETON | DW_UT_SPLIT_COMPILE => {
+                    unit.uint(8)?; // dwo_id
+                }
+                DW_UT_TYPE | DW_UT_SPLIT_TYPE => continue, // Type units have no code
+                _ => continue,
+            }
+            (addr_size, abbrev_offset)
+        } else {
+            let abbrev_offset = unit.uint(offset_size)?;
+            (unit.u8()?, abbrev_offset)
+        };
+        if addr_size == 0 || addr_size > 8 {
+            return unit.error(ErrorKind::UnsupportedAddressSize(addr_size));
+        }
+
+        let abbrevs = parse_abbrevs(sections, abbrev_offset)?;
+        let mut parser = UnitParser {
+            sections,
+            ctx: UnitContext {
+                enc: Encoding {
+                    version,
+                    addr_size,
+                    offset_size,
+                },
+                str_offsets_base: 0,
+                addr_base: 0,
+                rnglists_base: 0,
+            },
+            unit_offset,
+            base_address: 0,
+        };
+
+        let mut line_program = None;
+        let mut scopes = vec![];
+        let mut open: Vec<Option<Scope>> = vec![];
+        let mut is_first = true;
+        while !unit.is_empty() {
+            let offset = unit.pos() as u64;
+            let code = unit.uleb128()?;
+            if code == 0 {
+                // End of children
+                if let Some(Some(scope)) = open.pop() {
+                    attach(scope, &mut open, &mut scopes);
+                }
+                continue;
+            }
+            let abbrev = match abbrevs.get(&code) {
+                Some(abbrev) => abbrev,
+                None => return unit.error(ErrorKind::UnknownAbbrev(code)),
+            };
+            let attrs = parser.attrs(&mut unit, abbrev)?;
+
+            if is_first {
+                // Unit entry such as DW_TAG_compile_unit
+                is_first = false;
+                let ctx = &mut parser.ctx;
+                ctx.str_offsets_base = attrs.str_offsets_base.unwrap_or(0);
+                ctx.addr_base = attrs.addr_base.unwrap_or(0);
+                ctx.rnglists_base = attrs.rnglists_base.unwrap_or(0);
+                if let Some(low_pc) = &attrs.low_pc {
+                    parser.base_address = ctx.address(sections, low_pc)?.unwrap_or(0);
+                }
+                if let Some(offset) = attrs.stmt_list {
+                    let comp_dir = parser.string(&attrs.comp_dir)?;
+                    let name = parser.string(&attrs.name)?;
+                    line_program = Some(parse_line_program(
+                        sections, offset, parser.ctx, comp_dir, name,
+                    )?);
+                }
+                if abbrev.has_children {
+                    open.push(None);
+                }
+                continue;
+            }
+
+            let name = match parser.string(&attrs.name)? {
+                Some(name) => Some(name),
+                None => parser.string(&attrs.linkage_name)?,
+            };
+            if let Some(name) = name {
+                names.insert(offset, EntryName::Name(name.to_string()));
+            } else if let Some(origin) = attrs.origin {
+                names.insert(offset, EntryName::Origin(origin));
+            }
+
+            let scope = match abbrev.tag {
+                DW_TAG_SUBPROGRAM | DW_TAG_INLINED_SUBROUTINE => Some(Scope {
+                    offset,
+                    ranges: parser.ranges(&attrs)?,
+                    call_file: attrs.call_file,
+                    call_line: attrs.call_line.unwrap_or(0) as u32,
+                    call_column: attrs.call_column.unwrap_or(0) as u32,
+                    children: vec![],
+                }),
+                _ => None,
+            };
+
+            if abbrev.has_children {
+                open.push(scope);
+            } else if let Some(scope) = scope {
+                attach(scope, &mut open, &mut scopes);
+            }
+        }
+
+        units.push(Unit {
+            line_program,
+            scopes,
+        });
+    }
+    Ok(units)
+}
@@ -0,0 +1,383 @@
+#![forbid(unsafe_code)]
+
+mod consts;
+pub mod error;
+mod form;
+mod info;
+mod line;
+mod reader;
+mod wasm;
+
+use info::{EntryName, Scope};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+
+pub use error::{Error, Result};
+
+// Location in source file
+#[derive(Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Location<'d> {
+    pub file: Option<&'d str>,
+    pub line: u32,   // 0 means no line is associated
+    pub column: u32, // 0 means no column is associated
+}
+
+impl<'d> fmt::Display for Location<'d> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.file.unwrap_or("??"))?;
+        if self.line != 0 {
+            write!(f, ":{}", self.line)?;
+            if self.column != 0 {
+                write!(f, ":{}", self.column)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Frame of source-level function. One Wasm function call may consist of several frames due to inlining
+#[derive(Clone, PartialEq)]
+#[cfg_attr(test, derive(Debug))]
+pub struct Frame<'d> {
+    pub function: Option<&'d str>,
+    pub location: Option<Location<'d>>,
+    pub inlined: bool, // true when this function was inlined into the next (outer) frame
+}
+
+struct Row {
+    address: u64,
+    file: Option<usize>, // Index of DebugInfo::files
+    line: u32,
+    column: u32,
+    end_sequence: bool,
+}
+
+struct Function {
+    name: Option<usize>, // Index of DebugInfo::names
+    ranges: Vec<(u64, u64)>,
+    call_file: Option<usize>,
+    call_line: u32,
+    call_column: u32,
+    inlined: Vec<Function>,
+}
+
+impl Function {
+    fn start(&self, address: u64) -> Option<u64> {
+        self.ranges
+            .iter()
+            .find(|(start, end)| *start <= address && address < *end)
+            .map(|(start, _)| *start)
+    }
+}
+
+// Debug information to map addresses in code section to source locations. Address is a byte offset
+// relative to the start of payload of code section.
+// https://yurydelendik.github.io/webassembly-dwarf/
+pub struct DebugInfo {
+    code_start: usize,
+    files: Vec<String>,
+    names: Vec<String>,
+    rows: Vec<Row>, // Sorted by address
+    functions: Vec<Function>,
+}
+
+struct Builder<'n> {
+    entries: &'n HashMap<u64, EntryName>,
+    names: Vec<String>,
+    name_indices: HashMap<u64, usize>,
+}
+
+impl<'n> Builder<'n> {
+    fn name(&mut self, offset: u64) -> Option<usize> {
+        // Follow DW_AT_abstract_origin and DW_AT_specification. Limit the depth to avoid infinite loop
+        let mut current = offset;
+        for _ in 0..16 {
+            match self.entries.get(&current)? {
+                EntryName::Origin(origin) => current = *origin,
+                EntryName::Name(name) => {
+                    if let Some(idx) = self.name_indices.get(&current) {
+                        return Some(*idx);
+                    }
+                    let idx = self.names.len();
+                    self.names.push(name.clone());
+                    self.name_indices.insert(current, idx);
+                    return Some(idx);
+                }
+            }
+        }
+        None
+    }
+
+    fn function(&mut self, scope: Scope, files: &[Option<usize>]) -> Function {
+        let name = self.name(scope.offset);
+        let call_file = scope
+            .call_file
+            .and_then(|f| files.get(f as usize).copied().flatten());
+        let inlined = scope
+            .children
+            .into_iter()
+            .map(|s| self.function(s, files))
+            .collect();
+        Function {
+            name,
+            ranges: scope.ranges,
+            call_file,
+            call_line: scope.call_line,
+            call_column: scope.call_column,
+            inlined,
+        }
+    }
+}
+
+impl DebugInfo {
+    // Read DWARF sections in the Wasm binary. Returns None when the binary contains no debug information
+    pub fn from_binary(binary: &[u8]) -> Result<Option<DebugInfo>> {
+        let sections = wasm::Sections::scan(binary)?;
+        if !sections.has_debug_info() {
+            return Ok(None);
+        }
+
+        let mut entries = HashMap::new();
+        let units = info::parse_units(&sections, &mut entries)?;
+
+        let mut builder = Builder {
+            entries: &entries,
+            names: vec![],
+            name_indices: HashMap::new(),
+        };
+        let mut files = vec![];
+        let mut file_indices = HashMap::new();
+        let mut rows = vec![];
+        let mut functions = vec![];
+
+        for unit in units {
+            // Map file numbers in the unit to indices of files
+            let mut unit_files = vec![];
+            if let Some(program) = unit.line_program {
+                for path in program.files {
+                    let idx = path.map(|path| {
+                        let len = files.len();
+                        *file_indices.entry(path.clone()).or_insert_with(|| {
+                            files.push(path);
+                            len
+                        })
+                    });
+                    unit_files.push(idx);
+                }
+                rows.extend(program.rows.into_iter().map(|row| Row {
+                    address: row.address,
+                    file: unit_files.get(row.file as usize).copied().flatten(),
+                    line: row.line,
+                    column: row.column,
+                    end_sequence: row.end_sequence,
+                }));
+            }
+            for scope in unit.scopes {
+                functions.push(builder.function(scope, &unit_files));
+            }
+        }
+
+        // When rows at the same address, end of sequence should come first since it ends the
+        // previous sequence. Sort is stable so order of rows in the same sequence is preserved.
+        rows.sort_by_key(|row| (row.address, !row.end_sequence));
+
+        Ok(Some(DebugInfo {
+            code_start: sections.code_start,
+            files,
+            names: builder.names,
+            rows,
+            functions,
+        }))
+    }
+
+    // Convert byte offset in the Wasm binary to address in code section
+    pub fn address(&self, offset: usize) -> Option<u64> {
+        offset.checked_sub(self.code_start).map(|a| a as u64)
+    }
+
+    // Source location of the instruction at the address
+    pub fn location(&self, address: u64) -> Option<Location<'_>> {
+        // Find the first row whose address is greater than the address. The comparator never returns Equal
+        let idx = match self.rows.binary_search_by(|row| {
+            if row.address <= address {
+                Ordering::Less
+            } else {
+                Ordering::Greater
+            }
+        }) {
+            Ok(idx) | Err(idx) => idx,
+        };
+        if idx == 0 {
+            return None;
+        }
+        let row = &self.rows[idx - 1];
+        if row.end_sequence {
+            return None;
+        }
+        Some(Location {
+            file: row.file.map(|idx| self.files[idx].as_str()),
+            line: row.line,
+            column: row.column,
+        })
+    }
+
+    // Source-level frames of the instruction at the address. Innermost frame comes first. When some
+    // function calls are inlined, they are also included as frames.
+    pub fn frames(&self, address: u64) -> Vec<Frame<'_>> {
+        // When ranges are overlapping (e.g. functions removed by linker), choose the closest one
+        let mut chain = vec![];
+        let mut candidates = &self.functions;
+        loop {
+            let found = candidates
+                .iter()
+                .filter_map(|f| f.start(address).map(|start| (start, f)))
+                .max_by_key(|(start, _)| *start);
+            match found {
+                Some((_, f)) => {
+                    chain.push(f);
+                    candidates = &f.inlined;
+                }
+                None => break,
+            }
+        }
+
+        if chain.is_empty() {
+            return self
+                .location(address)
+                .map(|location| Frame {
+                    function: None,
+                    location: Some(location),
+                    inlined: false,
+                })
+                .into_iter()
+                .collect();
+        }
+
+        let mut frames = Vec::with_capacity(chain.len());
+        let mut location = self.location(address);
+        for (idx, f) in chain.iter().enumerate().rev() {
+            frames.push(Frame {
+                function: f.name.map(|idx| self.names[idx].as_str()),
+                location,
+                inlined: idx > 0,
+            });
+            // Location in the caller is the call site of this inlined function
+            location = Some(Location {
+                file: f.call_file.map(|idx| self.files[idx].as_str()),
+                line: f.call_line,
+                column: f.call_column,
+            });
+        }
+        frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn read_fixture(name: &str) -> Vec<u8> {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("..");
+        path.push("examples");
+        path.push("dwarf");
+        path.push(name);
+        fs::read(path).unwrap()
+    }
+
+    fn check_debug_info(info: &DebugInfo) {
+        let loc = info.location(0x19).unwrap();
+        assert_eq!(loc.to_string(), "/work/examples/dwarf/debug.c:6:9");
+        let loc = info.location(0x29).unwrap();
+        assert_eq!(loc.to_string(), "/work/examples/dwarf/debug.c:11:5");
+        let loc = info.location(0x11).unwrap();
+        assert_eq!(loc.to_string(), "/work/examples/dwarf/util.h:2:14");
+        assert_eq!(info.location(0x0), None);
+        assert_eq!(info.location(0x1000), None);
+
+        let frames = info.frames(0x19);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].function, Some("calc"));
+        assert!(!frames[0].inlined);
+
+        let frames = info.frames(0x11);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].function, Some("add"));
+        assert_eq!(
+            frames[0].location.as_ref().unwrap().to_string(),
+            "/work/examples/dwarf/util.h:2:14"
+        );
+        assert!(frames[0].inlined);
+        assert_eq!(frames[1].function, Some("calc"));
+        assert_eq!(
+            frames[1].location.as_ref().unwrap().to_string(),
+            "/work/examples/dwarf/debug.c:4:13"
+        );
+        assert!(!frames[1].inlined);
+
+        let frames = info.frames(0x29);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].function, Some("_start"));
+
+        let frames = info.frames(0x5);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].function, Some("twice"));
+
+        assert!(info.frames(0x1000).is_empty());
+    }
+
+    #[test]
+    fn dwarf5() {
+        let bin = read_fixture("debug.wasm");
+        let info = DebugInfo::from_binary(&bin).unwrap().unwrap();
+        check_debug_info(&info);
+    }
+
+    #[test]
+    fn dwarf4() {
+        let bin = read_fixture("debug_dwarf4.wasm");
+        let info = DebugInfo::from_binary(&bin).unwrap().unwrap();
+        check_debug_info(&info);
+    }
+
+    #[test]
+    fn offset_to_address() {
+        let bin = read_fixture("debug.wasm");
+        let info = DebugInfo::from_binary(&bin).unwrap().unwrap();
+        let addr = info.address(0x53).unwrap();
+        assert_eq!(info.frames(addr)[0].function, Some("calc"));
+        assert_eq!(info.address(0), None);
+    }
+
+    #[test]
+    fn no_debug_info() {
+        let bin = [0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00];
+        assert!(DebugInfo::from_binary(&bin).unwrap().is_none());
+    }
+
+    #[test]
+    fn not_wasm_binary() {
+        let err = DebugInfo::from_binary(b"hello").err().unwrap();
+        assert!(matches!(err.kind, error::ErrorKind::NotWasmBinary));
+    }
+
+    #[test]
+    fn location_display() {
+        let loc = Location {
+            file: Some("a.c"),
+            line: 3,
+            column: 0,
+        };
+        assert_eq!(loc.to_string(), "a.c:3");
+        let loc = Location {
+            file: None,
+            line: 0,
+            column: 4,
+        };
+        assert_eq!(loc.to_string(), "??");
+    }
+}
@@ -0,0 +1,271 @@
+// Line number information in .debug_line section
+// https://dwarfstd.org/doc/DWARF5.pdf 6.2 Line Number Information
+
+use crate::consts::*;
+use crate::error::{ErrorKind, Result};
+use crate::form::{Encoding, UnitContext};
+use crate::reader::Reader;
+use crate::wasm::Sections;
+
+// Row of line number matrix
+pub(crate) struct Row {
+    pub(crate) address: u64,
+    pub(crate) file: u64,
+    pub(crate) line: u32,
+    pub(crate) column: u32,
+    pub(crate) end_sequence: bool,
+}
+
+pub(crate) struct LineProgram {
+    pub(crate) files: Vec<Option<String>>, // Indexed by file number in the program
+    pub(crate) rows: Vec<Row>,
+}
+
+fn is_absolute(path: &str) -> bool {
+    let b = path.as_bytes();
+    path.starts_with('/') || path.starts_with('\\') || b.len() > 2 && b[1] == b':'
+}
+
+pub(crate) fn join_path(dir: &str, file: &str) -> String {
+    if dir.is_empty() || is_absolute(file) {
+        file.to_string()
+    } else if dir.ends_with('/') || dir.ends_with('\\') {
+        format!("{}{}", dir, file)
+    } else {
+        format!("{}/{}", dir, file)
+    }
+}
+
+struct Header<'s, 'a> {
+    sections: &'s Sections<'a>,
+    unit: UnitContext,
+    comp_dir: String,
+    dirs: Vec<String>,
+    files: Vec<Option<String>>,
+}
+
+impl<'s, 'a> Header<'s, 'a> {
+    fn file_path(&self, dir: u64, name: &str) -> String {
+        let dir = self
+            .dirs
+            .get(dir as usize)
+            .map(String::as_str)
+            .unwrap_or("");
+        join_path(&join_path(&self.comp_dir, dir), name)
+    }
+
+    // Directories and file names in DWARF 2, 3, 4
+    fn parse_legacy_entries(&mut self, reader: &mut Reader<'a>, cu_name: &str) -> Result<()> {
+        self.dirs.push(self.comp_dir.clone());
+        loop {
+            let dir = reader.cstr()?;
+            if dir.is_empty() {
+                break;
+            }
+            self.dirs.push(dir.to_string());
+        }
+        // File number 0 is not used in DWARF 4 or earlier. It is regarded as the primary source
+        self.files.push(Some(join_path(&self.comp_dir, cu_name)));
+        loop {
+            let name = reader.cstr()?;
+            if name.is_empty() {
+                break;
+            }
+            let dir = reader.uleb128()?;
+            reader.uleb128()?; // Modification time
+            reader.uleb128()?; // File length
+            let path = self.file_path(dir, name);
+            self.files.push(Some(path));
+        }
+        Ok(())
+    }
+
+    // Entries described by entry formats in DWARF 5. Returns pairs of path and directory index
+    fn parse_entries(
+        &self,
+        reader: &mut Reader<'a>,
+        enc: &Encoding,
+    ) -> Result<Vec<(Option<&'a str>, u64)>> {
+        let num_formats = reader.u8()?;
+        let mut formats = Vec::with_capacity(num_formats as usize);
+        for _ in 0..num_formats {
+            formats.push((reader.uleb128()?, reader.uleb128()?));
+        }
+        let count = reader.uleb128()?;
+        let mut entries = vec![];
+        for _ in 0..count {
+            let mut path = None;
+            let mut dir = 0;
+            for (content, form) in formats.iter() {
+                let value = reader.value(*form, enc, 0)?;
+                match *content {
+                    DW_LNCT_PATH => path = self.unit.string(self.sections, &value)?,
+                    DW_LNCT_DIRECTORY_INDEX => dir = value.udata().unwrap_or(0),
+                    _ => {}
+                }
+            }
+            entries.push((path, dir));
+        }
+        Ok(entries)
+    }
+}
+
+pub(crate) fn parse_line_program(
+    sections: &Sections<'_>,
+    offset: u64,
+    unit: UnitContext,
+    comp_dir: Option<&str>,
+    cu_name: Option<&str>,
+) -> Result<LineProgram> {
+    let mut reader = Reader::new(sections.debug_line, ".debug_line").at(offset, "line program")?;
+    let (len, offset_size) = reader.initial_length()?;
+    let mut reader = reader.split(len, "line program")?;
+
+    let version = reader.u16()?;
+    if !(2..=5).contains(&version) {
+        return reader.error(ErrorKind::UnsupportedVersion(version));
+    }
+    let mut enc = Encoding {
+        version,
+        addr_size: unit.enc.addr_size,
+        offset_size,
+    };
+    if version >= 5 {
+        enc.addr_size = reader.u8()?;
+        reader.u8()?; // Segment selector size
+    }
+    let header_len = reader.offset(&enc)?;
+    let mut header_reader = reader.split(header_len, "line program header")?;
+
+    let min_inst_len = u64::from(header_reader.u8()?);
+    if version >= 4 {
+        header_reader.u8()?; // Maximum operations per instruction. Wasm is not VLIW
+    }
+    header_reader.u8()?; // default_is_stmt
+    let line_base = i64::from(header_reader.u8()? as i8);
+    let line_range = header_reader.u8()?;
+    let opcode_base = header_reader.u8()?;
+    let standard_opcode_lengths = header_reader.bytes(
+        opcode_base.saturating_sub(1) as usize,
+        "standard opcode lengths",
+    )?;
+
+    let mut header = Header {
+        sections,
+        unit,
+        comp_dir: comp_dir.unwrap_or("").to_string(),
+        dirs: vec![],
+        files: vec![],
+    };
+    if version >= 5 {
+        let dirs = header.parse_entries(&mut header_reader, &enc)?;
+        header.dirs = dirs
+            .into_iter()
+            .map(|(path, _)| join_path(&header.comp_dir, path.unwrap_or("")))
+            .collect();
+        let files = header.parse_entries(&mut header_reader, &enc)?;
+        header.files = files
+            .into_iter()
+            .map(|(path, dir)| path.map(|p| header.file_path(dir, p)))
+            .collect();
+    } else {
+        header.parse_legacy_entries(&mut header_reader, cu_name.unwrap_or(""))?;
+    }
+    let mut files = std::mem::take(&mut header.files);
+
+    // Run the state machine of line number program
+    let mut rows = vec![];
+    let mut address = 0u64;
+    let mut file = 1u64;
+    let mut line = 1i64;
+    let mut column = 0u64;
+    while !reader.is_empty() {
+        let mut emit_row = false;
+        let mut end_sequence = false;
+        let opcode = reader.u8()?;
+        if opcode >= opcode_base {
+            // Special opcode
+            let adjusted = opcode - opcode_base;
+            let advance = adjusted.checked_div(line_range).unwrap_or(0);
+            let rest = adjusted.checked_rem(line_range).unwrap_or(0);
+            address = address.wrapping_add(u64::from(advance) * min_inst_len);
+            line = line.wrapping_add(line_base + i64::from(rest));
+            emit_row = true;
+        } else {
+            match opcode {
+                0 => {
+                    // Extended opcode
+                    let len = reader.uleb128()?;
+                    let mut ext = reader.split(len, "extended opcode")?;
+                    if len == 0 {
+                        continue;
+                    }
+                    match ext.u8()? {
+                        DW_LNE_END_SEQUENCE => {
+                            emit_row = true;
+                            end_sequence = true;
+                        }
+                        DW_LNE_SET_ADDRESS => address = ext.uint(len as usize - 1)?,
+                        DW_LNE_DEFINE_FILE if version < 5 => {
+                            let name = ext.cstr()?;
+                            let dir = ext.uleb128()?;
+                            files.push(Some(header.file_path(dir, name)));
+                        }
+                        _ => {} // Skip unknown extended opcodes including DW_LNE_set_discriminator
+                    }
+                }
+                DW_LNS_COPY => emit_row = true,
+                DW_LNS_ADVANCE_PC => {
+                    let advance = reader.uleb128()?;
+                    address = address.wrapping_add(advance.wrapping_mul(min_inst_len));
+                }
+                DW_LNS_ADVANCE_LINE => line = line.wrapping_add(reader.sleb128()?),
+                DW_LNS_SET_FILE => file = reader.uleb128()?,
+                DW_LNS_SET_COLUMN => column = reader.uleb128()?,
+                DW_LNS_NEGATE_STMT
+                | DW_LNS_SET_BASIC_BLOCK
+                | DW_LNS_SET_PROLOGUE_END
+                | DW_LNS_SET_EPILOGUE_BEGIN => {}
+                DW_LNS_CONST_ADD_PC => {
+                    if let Some(advance) = (255 - opcode_base).checked_div(line_range) {
+                        address = address.wrapping_add(u64::from(advance) * min_inst_len);
+                    }
+                }
+                DW_LNS_FIXED_ADVANCE_PC => {
+                    address = address.wrapping_add(u64::from(reader.u16()?));
+                }
+                DW_LNS_SET_ISA => {
+                    reader.uleb128()?;
+                }
+                _ => {
+                    // Unknown standard opcode. Skip its operands
+                    let num_args = standard_opcode_lengths
+                        .get(opcode as usize - 1)
+                        .copied()
+                        .unwrap_or(0);
+                    for _ in 0..num_args {
+                        reader.uleb128()?;
+                    }
+                }
+            }
+        }
+
+        if emit_row {
+            rows.push(Row {
+                address,
+                file,
+                line: line.max(0).min(u32::MAX as i64) as u32,
+                column: column.min(u32::MAX as u64) as u32,
+                end_sequence,
+            });
+        }
+        if end_sequence {
+            address = 0;
+            file = 1;
+            line = 1;
+            column = 0;
+        }
+    }
+
+    Ok(LineProgram { files, rows })
+}
@@ -0,0 +1,193 @@
+use crate::error::{Error, ErrorKind, Result};
+use std::str;
+
+// Little endian byte reader for DWARF sections. Wasm is always little endian.
+#[derive(Clone)]
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    section: &'static str,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8], section: &'static str) -> Self {
+        Reader {
+            data,
+            pos: 0,
+            section,
+        }
+    }
+
+    pub(crate) fn error<T>(&self, kind: ErrorKind) -> Result<T> {
+        Err(Error::new(kind, self.section, self.pos))
+    }
+
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    // Reader which starts at the offset in the same section
+    pub(crate) fn at(&self, offset: u64, what: &'static str) -> Result<Self> {
+        if offset > self.data.len() as u64 {
+            return self.error(ErrorKind::OutOfSection { what, offset });
+        }
+        Ok(Reader {
+            data: self.data,
+            pos: offset as usize,
+            section: self.section,
+        })
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize, expected: &'static str) -> Result<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            return self.error(ErrorKind::UnexpectedEof { expected });
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    // Split the following bytes as another reader. Positions in the reader are still relative to
+    // the start of section for error messages
+    pub(crate) fn split(&mut self, len: u64, expected: &'static str) -> Result<Self> {
+        let start = self.pos;
+        if len > (self.data.len() - self.pos) as u64 {
+            return self.error(ErrorKind::UnexpectedEof { expected });
+        }
+        self.pos += len as usize;
+        Ok(Reader {
+            data: &self.data[..self.pos],
+            pos: start,
+            section: self.section,
+        })
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1, "1 byte integer")?[0])
+    }
+
+    // Read unsigned integer of the size in little endian
+    pub(crate) fn uint(&mut self, size: usize) -> Result<u64> {
+        let bytes = self.bytes(size, "fixed size integer")?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0, |acc, b| (acc << 8) | u64::from(*b)))
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        Ok(self.uint(2)? as u16)
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        Ok(self.uint(4)? as u32)
+    }
+
+    // https://en.wikipedia.org/wiki/LEB128
+    pub(crate) fn uleb128(&mut self) -> Result<u64> {
+        let mut ret = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = self.bytes(1, "part of LEB128-encoded integer")?[0];
+            if shift >= 64 || shift == 63 && b > 1 {
+                return self.error(ErrorKind::IntOverflow { ty: "u64" });
+            }
+            ret |= u64::from(b & 0x7f) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Ok(ret);
+            }
+        }
+    }
+
+    pub(crate) fn sleb128(&mut self) -> Result<i64> {
+        let mut ret = 0i64;
+        let mut shift = 0;
+        loop {
+            let b = self.bytes(1, "part of LEB128-encoded integer")?[0];
+            if shift >= 64 {
+                return self.error(ErrorKind::IntOverflow { ty: "i64" });
+            }
+            ret |= i64::from(b & 0x7f) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                if shift < 64 && b & 0x40 != 0 {
+                    ret |= -1 << shift; // Sign extension
+                }
+                return Ok(ret);
+            }
+        }
+    }
+
+    // Null-terminated string
+    pub(crate) fn cstr(&mut self) -> Result<&'a str> {
+        let rest = &self.data[self.pos..];
+        let len = match rest.iter().position(|b| *b == 0) {
+            Some(len) => len,
+            None => {
+                return self.error(ErrorKind::UnexpectedEof {
+                    expected: "null byte",
+                })
+            }
+        };
+        match str::from_utf8(&rest[..len]) {
+            Ok(s) => {
+                self.pos += len + 1;
+                Ok(s)
+            }
+            Err(err) => self.error(ErrorKind::InvalidUtf8(err)),
+        }
+    }
+
+    // https://dwarfstd.org/doc/DWARF5.pdf 7.4 32-Bit and 64-Bit DWARF Formats
+    // Returns the length and size of offsets in the unit
+    pub(crate) fn initial_length(&mut self) -> Result<(u64, usize)> {
+        match self.u32()? {
+            0xffff_ffff => Ok((self.uint(8)?, 8)),
+            len => Ok((u64::from(len), 4)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn leb128() {
+        let mut r = Reader::new(&[0xe5, 0x8e, 0x26, 0x7f, 0x80, 0x7f, 0x02], "test");
+        assert_eq!(r.uleb128().unwrap(), 624485);
+        assert_eq!(r.sleb128().unwrap(), -1);
+        assert_eq!(r.sleb128().unwrap(), -128);
+        assert_eq!(r.sleb128().unwrap(), 2);
+        assert!(r.is_empty());
+        assert!(r.uleb128().is_err());
+
+        let mut r = Reader::new(&[0xff; 11], "test");
+        assert!(matches!(
+            r.uleb128().unwrap_err().kind,
+            ErrorKind::IntOverflow { .. }
+        ));
+    }
+
+    #[test]
+    fn fixed_size_integers() {
+        let mut r = Reader::new(&[0x34, 0x12, 0x78, 0x56, 0x34, 0x12, 0xff], "test");
+        assert_eq!(r.u16().unwrap(), 0x1234);
+        assert_eq!(r.u32().unwrap(), 0x1234_5678);
+        assert_eq!(r.u8().unwrap(), 0xff);
+        assert!(r.u8().is_err());
+    }
+
+    #[test]
+    fn null_terminated_string() {
+        let mut r = Reader::new(b"foo\0\0bar", "test");
+        assert_eq!(r.cstr().unwrap(), "foo");
+        assert_eq!(r.cstr().unwrap(), "");
+        assert!(r.cstr().is_err());
+    }
+}
@@ -0,0 +1,64 @@
+use crate::error::{Error, ErrorKind, Result};
+use crate::reader::Reader;
+
+// DWARF sections embedded in Wasm binary as custom sections
+// https://yurydelendik.github.io/webassembly-dwarf/#embedding-DWARF
+#[derive(Default)]
+pub(crate) struct Sections<'a> {
+    pub(crate) code_start: usize, // Byte offset of the payload of code section
+    pub(crate) debug_abbrev: &'a [u8],
+    pub(crate) debug_addr: &'a [u8],
+    pub(crate) debug_info: &'a [u8],
+    pub(crate) debug_line: &'a [u8],
+    pub(crate) debug_line_str: &'a [u8],
+    pub(crate) debug_ranges: &'a [u8],
+    pub(crate) debug_rnglists: &'a [u8],
+    pub(crate) debug_str: &'a [u8],
+    pub(crate) debug_str_offsets: &'a [u8],
+}
+
+impl<'a> Sections<'a> {
+    // https://webassembly.github.io/spec/core/binary/modules.html#sections
+    pub(crate) fn scan(binary: &'a [u8]) -> Result<Self> {
+        if !binary.starts_with(&[0x00, 0x61, 0x73, 0x6d]) || binary.len() < 8 {
+            return Err(Error::new(ErrorKind::NotWasmBinary, "module", 0));
+        }
+
+        let mut sections = Sections::default();
+        let mut reader = Reader::new(binary, "module");
+        reader.bytes(8, "magic and version")?;
+        while !reader.is_empty() {
+            let id = reader.u8()?;
+            let size = reader.uleb128()?;
+            let start = reader.pos();
+            let mut payload = reader.split(size, "section payload")?;
+            match id {
+                0 => {
+                    let len = payload.uleb128()?;
+                    let name = payload.bytes(len as usize, "custom section name")?;
+                    let data = &binary[payload.pos()..start + size as usize];
+                    let section = match name {
+                        b".debug_abbrev" => &mut sections.debug_abbrev,
+                        b".debug_addr" => &mut sections.debug_addr,
+                        b".debug_info" => &mut sections.debug_info,
+                        b".debug_line" => &mut sections.debug_line,
+                        b".debug_line_str" => &mut sections.debug_line_str,
+                        b".debug_ranges" => &mut sections.debug_ranges,
+                        b".debug_rnglists" => &mut sections.debug_rnglists,
+                        b".debug_str" => &mut sections.debug_str,
+                        b".debug_str_offsets" => &mut sections.debug_str_offsets,
+                        _ => continue,
+                    };
+                    *section = data;
+                }
+                10 => sections.code_start = start,
+                _ => {}
+            }
+        }
+        Ok(sections)
+    }
+
+    pub(crate) fn has_debug_info(&self) -> bool {
+        !self.debug_info.is_empty() || !self.debug_line.is_empty()
+    }
+}
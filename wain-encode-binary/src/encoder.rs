@@ -0,0 +1,972 @@
+use crate::leb128::Leb128;
+use std::mem;
+use wain_ast::*;
+
+// https://webassembly.github.io/spec/core/binary/modules.html#sections
+const SECTIONS: [SectionId; 11] = [
+    SectionId::Type,
+    SectionId::Import,
+    SectionId::Func,
+    SectionId::Table,
+    SectionId::Memory,
+    SectionId::Global,
+    SectionId::Export,
+    SectionId::Start,
+    SectionId::Elem,
+    SectionId::Code,
+    SectionId::Data,
+];
+
+#[derive(Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+    // Generate 'name' custom section from names in module
+    emit_names: bool,
+    names_emitted: bool,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_names() -> Self {
+        Self {
+            emit_names: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn encode<E: Encode>(&mut self, node: &E) {
+        node.encode(self)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+
+    fn byte(&mut self, b: u8) {
+        self.buf.push(b);
+    }
+
+    fn int<L: Leb128>(&mut self, i: L) {
+        i.write_leb128(&mut self.buf);
+    }
+
+    // https://webassembly.github.io/spec/core/binary/conventions.html#binary-vec
+    fn vec<'a, E, I>(&mut self, items: I)
+    where
+        E: Encode + 'a,
+        I: ExactSizeIterator<Item = &'a E>,
+    {
+        self.int(items.len() as u32);
+        for item in items {
+            item.encode(self);
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/values.html#bytes
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.int(bytes.len() as u32);
+        self.buf.extend_from_slice(bytes);
+    }
+
+    // Encode contents with the given function and prepend its size
+    fn sized<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        let outer = mem::take(&mut self.buf);
+        f(self);
+        let inner = mem::replace(&mut self.buf, outer);
+        self.bytes(&inner);
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#sections
+    fn section<F: FnOnce(&mut Self)>(&mut self, id: u8, f: F) {
+        self.byte(id);
+        self.sized(f);
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#custom-section
+    fn customs(&mut self, module: &Module<'_>, place: CustomPlace) {
+        for custom in module.customs.iter().filter(|c| c.place == place) {
+            if self.emit_names && custom.name.0 == "name" {
+                // Replace existing 'name' section with generated one
+                self.name_section(&module.names);
+            } else {
+                self.section(0, |e| {
+                    custom.name.encode(e);
+                    e.buf.extend_from_slice(&custom.data);
+                });
+            }
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+    fn name_section(&mut self, names: &Names<'_>) {
+        if self.names_emitted {
+            return;
+        }
+        self.names_emitted = true;
+
+        self.section(0, |e| {
+            e.bytes(b"name");
+            if let Some(name) = &names.module {
+                e.byte(0);
+                e.sized(|e| name.encode(e));
+            }
+            e.name_subsection(1, &names.funcs);
+            e.name_subsection(2, &names.locals);
+            e.name_subsection(3, &names.labels);
+            e.name_subsection(4, &names.types);
+            e.name_subsection(5, &names.tables);
+            e.name_subsection(6, &names.memories);
+            e.name_subsection(7, &names.globals);
+            e.name_subsection(8, &names.elems);
+            e.name_subsection(9, &names.data);
+        });
+    }
+
+    // Empty subsections are omitted
+    fn name_subsection<E: Encode>(&mut self, id: u8, map: &[E]) {
+        if !map.is_empty() {
+            self.byte(id);
+            self.sized(|e| e.vec(map.iter()));
+        }
+    }
+}
+
+pub trait Encode {
+    fn encode(&self, encoder: &mut Encoder);
+}
+
+// Encode u32 for typeidx, funcidx, memidx, ...
+impl Encode for u32 {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.int(*self);
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/modules.html#binary-module
+impl<'s> Encode for Module<'s> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.buf.extend_from_slice(&[0x00, 0x61, 0x73, 0x6d]); // magic
+        encoder.buf.extend_from_slice(&[0x01, 0x00, 0x00, 0x00]); // version
+
+        encoder.customs(self, CustomPlace::BeforeFirst);
+        for &id in SECTIONS.iter() {
+            encoder.customs(self, CustomPlace::Before(id));
+            match id {
+                SectionId::Type => encoder.types_section(self),
+                SectionId::Import => encoder.imports_section(self),
+                SectionId::Func => encoder.funcs_section(self),
+                SectionId::Table => encoder.tables_section(self),
+                SectionId::Memory => encoder.memories_section(self),
+                SectionId::Global => encoder.globals_section(self),
+                SectionId::Export => encoder.exports_section(self),
+                SectionId::Start => encoder.start_section(self),
+                SectionId::Elem => encoder.elems_section(self),
+                SectionId::Code => encoder.codes_section(self),
+                SectionId::Data => encoder.data_section(self),
+            }
+            encoder.customs(self, CustomPlace::After(id));
+        }
+        encoder.customs(self, CustomPlace::AfterLast);
+
+        let names = &self.names;
+        let has_names = names.module.is_some()
+            || !names.funcs.is_empty()
+            || !names.locals.is_empty()
+            || !names.labels.is_empty()
+            || !names.types.is_empty()
+            || !names.tables.is_empty()
+            || !names.memories.is_empty()
+            || !names.globals.is_empty()
+            || !names.elems.is_empty()
+            || !names.data.is_empty();
+        if encoder.emit_names && has_names {
+            encoder.name_section(names);
+        }
+    }
+}
+
+// Helpers to encode each section of module. Empty sections are omitted.
+impl Encoder {
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-typesec
+    fn types_section(&mut self, module: &Module<'_>) {
+        if !module.types.is_empty() {
+            self.section(1, |e| e.vec(module.types.iter()));
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-importsec
+    fn imports_section(&mut self, module: &Module<'_>) {
+        let funcs = module.funcs.iter().filter_map(|f| match &f.kind {
+            FuncKind::Import(i) => Some((f.start, i, ImportDesc::Func(f.idx))),
+            FuncKind::Body { .. } => None,
+        });
+        let tables = module.tables.iter().filter_map(|t| {
+            t.import
+                .as_ref()
+                .map(|i| (t.start, i, ImportDesc::Table(&t.ty)))
+        });
+        let memories = module.memories.iter().filter_map(|m| {
+            m.import
+                .as_ref()
+                .map(|i| (m.start, i, ImportDesc::Memory(&m.ty)))
+        });
+        let globals = module.globals.iter().filter_map(|g| match &g.kind {
+            GlobalKind::Import(i) => Some((g.start, i, ImportDesc::Global(g.ty, g.mutable))),
+            GlobalKind::Init(_) => None,
+        });
+
+        let mut imports: Vec<_> = funcs.chain(tables).chain(memories).chain(globals).collect();
+        if imports.is_empty() {
+            return;
+        }
+        // Imports of different kinds are interleaved in the original order
+        imports.sort_by_key(|(start, _, _)| *start);
+
+        self.section(2, |e| {
+            e.int(imports.len() as u32);
+            for (_, import, desc) in imports.iter() {
+                import.mod_name.encode(e);
+                import.name.encode(e);
+                desc.encode(e);
+            }
+        });
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-funcsec
+    fn funcs_section(&mut self, module: &Module<'_>) {
+        let indices: Vec<u32> = module
+            .funcs
+            .iter()
+            .filter_map(|f| match &f.kind {
+                FuncKind::Body { .. } => Some(f.idx),
+                FuncKind::Import(_) => None,
+            })
+            .collect();
+        if !indices.is_empty() {
+            self.section(3, |e| e.vec(indices.iter()));
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-tablesec
+    fn tables_section(&mut self, module: &Module<'_>) {
+        let tables: Vec<_> = module
+            .tables
+            .iter()
+            .filter(|t| t.import.is_none())
+            .collect();
+        if !tables.is_empty() {
+            self.section(4, |e| {
+                e.int(tables.len() as u32);
+                for table in tables.iter() {
+                    table.ty.encode(e);
+                }
+            });
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-memsec
+    fn memories_section(&mut self, module: &Module<'_>) {
+        let memories: Vec<_> = module
+            .memories
+            .iter()
+            .filter(|m| m.import.is_none())
+            .collect();
+        if !memories.is_empty() {
+            self.section(5, |e| {
+                e.int(memories.len() as u32);
+                for memory in memories.iter() {
+                    memory.ty.encode(e);
+                }
+            });
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-globalsec
+    fn globals_section(&mut self, module: &Module<'_>) {
+        let globals: Vec<_> = module
+            .globals
+            .iter()
+            .filter_map(|g| match &g.kind {
+                GlobalKind::Init(expr) => Some((g, expr)),
+                GlobalKind::Import(_) => None,
+            })
+            .collect();
+        if !globals.is_empty() {
+            self.section(6, |e| {
+                e.int(globals.len() as u32);
+                for (global, expr) in globals.iter() {
+                    e.encode(&global.ty);
+                    e.byte(global.mutable as u8);
+                    e.encode(&Expr(expr));
+                }
+            });
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-exportsec
+    fn exports_section(&mut self, module: &Module<'_>) {
+        if !module.exports.is_empty() {
+            self.section(7, |e| e.vec(module.exports.iter()));
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-startsec
+    fn start_section(&mut self, module: &Module<'_>) {
+        if let Some(start) = &module.entrypoint {
+            self.section(8, |e| e.int(start.idx));
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-elemsec
+    fn elems_section(&mut self, module: &Module<'_>) {
+        if !module.elems.is_empty() {
+            self.section(9, |e| e.vec(module.elems.iter()));
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-codesec
+    fn codes_section(&mut self, module: &Module<'_>) {
+        let codes: Vec<_> = module
+            .funcs
+            .iter()
+            .filter_map(|f| match &f.kind {
+                FuncKind::Body { locals, expr } => Some(Code { locals, expr }),
+                FuncKind::Import(_) => None,
+            })
+            .collect();
+        if !codes.is_empty() {
+            self.section(10, |e| e.vec(codes.iter()));
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/binary/modules.html#binary-datasec
+    fn data_section(&mut self, module: &Module<'_>) {
+        if !module.data.is_empty() {
+            self.section(11, |e| e.vec(module.data.iter()));
+        }
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/values.html#names
+impl<'s> Encode for Name<'s> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.bytes(self.0.as_bytes());
+    }
+}
+
+// https://webassembly.github.io/spec/core/appendix/custom.html#name-maps
+impl<'s> Encode for (u32, Name<'s>) {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.int(self.0);
+        self.1.encode(encoder);
+    }
+}
+
+// https://webassembly.github.io/spec/core/appendix/custom.html#binary-indirectnamemap
+impl<'s> Encode for (u32, NameMap<'s>) {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.int(self.0);
+        encoder.vec(self.1.iter());
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/types.html#function-types
+impl Encode for FuncType {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.byte(0x60);
+        encoder.vec(self.params.iter());
+        encoder.vec(self.results.iter());
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/types.html#binary-valtype
+impl Encode for ValType {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.byte(match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F32 => 0x7d,
+            ValType::F64 => 0x7c,
+        });
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/modules.html#binary-importdesc
+enum ImportDesc<'a> {
+    Func(TypeIdx),
+    Table(&'a TableType),
+    Memory(&'a MemType),
+    Global(ValType, bool),
+}
+impl<'a> Encode for ImportDesc<'a> {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            ImportDesc::Func(idx) => {
+                encoder.byte(0x00);
+                encoder.int(*idx);
+            }
+            ImportDesc::Table(ty) => {
+                encoder.byte(0x01);
+                ty.encode(encoder);
+            }
+            ImportDesc::Memory(ty) => {
+                encoder.byte(0x02);
+                ty.encode(encoder);
+            }
+            ImportDesc::Global(ty, mutable) => {
+                encoder.byte(0x03);
+                ty.encode(encoder);
+                encoder.byte(*mutable as u8);
+            }
+        }
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/types.html#table-types
+impl Encode for TableType {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.byte(0x70); // funcref
+        self.limit.encode(encoder);
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/types.html#limits
+impl Encode for Limits {
+    fn encode(&self, encoder: &mut Encoder) {
+        match self {
+            Limits::From(min) => {
+                encoder.byte(0x00);
+                encoder.int(*min);
+            }
+            Limits::Range(min, max) => {
+                encoder.byte(0x01);
+                encoder.int(*min);
+                encoder.int(*max);
+            }
+        }
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/types.html#memory-types
+impl Encode for MemType {
+    fn encode(&self, encoder: &mut Encoder) {
+        self.limit.encode(encoder);
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/modules.html#binary-export
+impl<'s> Encode for Export<'s> {
+    fn encode(&self, encoder: &mut Encoder) {
+        self.name.encode(encoder);
+        let (kind, idx) = match self.kind {
+            ExportKind::Func(idx) => (0x00, idx),
+            ExportKind::Table(idx) => (0x01, idx),
+            ExportKind::Memory(idx) => (0x02, idx),
+            ExportKind::Global(idx) => (0x03, idx),
+        };
+        encoder.byte(kind);
+        encoder.int(idx);
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/modules.html#binary-elem
+impl Encode for ElemSegment {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.int(self.idx);
+        encoder.encode(&Expr(&self.offset));
+        encoder.vec(self.init.iter());
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/modules.html#binary-data
+impl<'s> Encode for DataSegment<'s> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.int(self.idx);
+        encoder.encode(&Expr(&self.offset));
+        encoder.bytes(&self.data);
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/modules.html#binary-code
+struct Code<'a> {
+    locals: &'a [ValType],
+    expr: &'a [Instruction],
+}
+impl<'a> Encode for Code<'a> {
+    fn encode(&self, encoder: &mut Encoder) {
+        encoder.sized(|e| {
+            // https://webassembly.github.io/spec/core/binary/modules.html#binary-local
+            // Consecutive locals of the same type are compressed into one entry
+            let mut entries: Vec<(u32, ValType)> = vec![];
+            for ty in self.locals.iter().copied() {
+                match entries.last_mut() {
+                    Some((count, last)) if *last == ty => *count += 1,
+                    _ => entries.push((1, ty)),
+                }
+            }
+            e.int(entries.len() as u32);
+            for (count, ty) in entries {
+                e.int(count);
+                ty.encode(e);
+            }
+            e.encode(&Expr(self.expr));
+        });
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/instructions.html#binary-expr
+struct Expr<'a>(&'a [Instruction]);
+impl<'a> Encode for Expr<'a> {
+    fn encode(&self, encoder: &mut Encoder) {
+        for insn in self.0.iter() {
+            insn.encode(encoder);
+        }
+        encoder.byte(0x0b); // end
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/instructions.html#binary-blocktype
+struct BlockType(Option<ValType>);
+impl Encode for BlockType {
+    fn encode(&self, encoder: &mut Encoder) {
+        match &self.0 {
+            Some(ty) => ty.encode(encoder),
+            None => encoder.byte(0x40),
+        }
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/instructions.html#binary-memarg
+// Second element is exponent of natural alignment of the instruction
+struct MemArg<'a>(&'a Mem, u8);
+impl<'a> Encode for MemArg<'a> {
+    fn encode(&self, encoder: &mut Encoder) {
+        let MemArg(mem, natural) = self;
+        encoder.int(mem.align.unwrap_or(*natural) as u32);
+        encoder.int(mem.offset.unwrap_or(0));
+    }
+}
+
+// https://webassembly.github.io/spec/core/binary/instructions.html
+impl Encode for Instruction {
+    fn encode(&self, encoder: &mut Encoder) {
+        use InsnKind::*;
+        let (op, mem, natural) = match &self.kind {
+            // Control instructions
+            // https://webassembly.github.io/spec/core/binary/instructions.html#control-instructions
+            Block { ty, body } => {
+                encoder.byte(0x02);
+                encoder.encode(&BlockType(*ty));
+                encoder.encode(&Expr(body));
+                return;
+            }
+            Loop { ty, body } => {
+                encoder.byte(0x03);
+                encoder.encode(&BlockType(*ty));
+                encoder.encode(&Expr(body));
+                return;
+            }
+            If {
+                ty,
+                then_body,
+                else_body,
+            } => {
+                encoder.byte(0x04);
+                encoder.encode(&BlockType(*ty));
+                for insn in then_body.iter() {
+                    insn.encode(encoder);
+                }
+                if !else_body.is_empty() {
+                    encoder.byte(0x05);
+                    for insn in else_body.iter() {
+                        insn.encode(encoder);
+                    }
+                }
+                encoder.byte(0x0b);
+                return;
+            }
+            Br(idx) => return encoder.idx_insn(0x0c, *idx),
+            BrIf(idx) => return encoder.idx_insn(0x0d, *idx),
+            BrTable {
+                labels,
+                default_label,
+            } => {
+                encoder.byte(0x0e);
+                encoder.vec(labels.iter());
+                encoder.int(*default_label);
+                return;
+            }
+            Call(idx) => return encoder.idx_insn(0x10, *idx),
+            CallIndirect(idx) => {
+                encoder.idx_insn(0x11, *idx);
+                encoder.byte(0x00); // Reserved byte
+                return;
+            }
+            // Variable instructions
+            // https://webassembly.github.io/spec/core/binary/instructions.html#variable-instructions
+            LocalGet(idx) => return encoder.idx_insn(0x20, *idx),
+            LocalSet(idx) => return encoder.idx_insn(0x21, *idx),
+            LocalTee(idx) => return encoder.idx_insn(0x22, *idx),
+            GlobalGet(idx) => return encoder.idx_insn(0x23, *idx),
+            GlobalSet(idx) => return encoder.idx_insn(0x24, *idx),
+            // Memory instructions
+            // https://webassembly.github.io/spec/core/binary/instructions.html#memory-instructions
+            I32Load(m) => (0x28, m, 2),
+            I64Load(m) => (0x29, m, 3),
+            F32Load(m) => (0x2a, m, 2),
+            F64Load(m) => (0x2b, m, 3),
+            I32Load8S(m) => (0x2c, m, 0),
+            I32Load8U(m) => (0x2d, m, 0),
+            I32Load16S(m) => (0x2e, m, 1),
+            I32Load16U(m) => (0x2f, m, 1),
+            I64Load8S(m) => (0x30, m, 0),
+            I64Load8U(m) => (0x31, m, 0),
+            I64Load16S(m) => (0x32, m, 1),
+            I64Load16U(m) => (0x33, m, 1),
+            I64Load32S(m) => (0x34, m, 2),
+            I64Load32U(m) => (0x35, m, 2),
+            I32Store(m) => (0x36, m, 2),
+            I64Store(m) => (0x37, m, 3),
+            F32Store(m) => (0x38, m, 2),
+            F64Store(m) => (0x39, m, 3),
+            I32Store8(m) => (0x3a, m, 0),
+            I32Store16(m) => (0x3b, m, 1),
+            I64Store8(m) => (0x3c, m, 0),
+            I64Store16(m) => (0x3d, m, 1),
+            I64Store32(m) => (0x3e, m, 2),
+            MemorySize => {
+                encoder.byte(0x3f);
+                encoder.byte(0x00); // Reserved byte
+                return;
+            }
+            MemoryGrow => {
+                encoder.byte(0x40);
+                encoder.byte(0x00); // Reserved byte
+                return;
+            }
+            // Numeric instructions
+            // https://webassembly.github.io/spec/core/binary/instructions.html#numeric-instructions
+            I32Const(i) => {
+                encoder.byte(0x41);
+                encoder.int(*i);
+                return;
+            }
+            I64Const(i) => {
+                encoder.byte(0x42);
+                encoder.int(*i);
+                return;
+            }
+            // https://webassembly.github.io/spec/core/binary/values.html#floating-point
+            F32Const(f) => {
+                encoder.byte(0x43);
+                encoder.buf.extend_from_slice(&f.to_le_bytes());
+                return;
+            }
+            F64Const(f) => {
+                encoder.byte(0x44);
+                encoder.buf.extend_from_slice(&f.to_le_bytes());
+                return;
+            }
+            kind => return encoder.byte(kind.opcode()), // Instructions which have no immediate
+        };
+        encoder.byte(op);
+        encoder.encode(&MemArg(mem, natural));
+    }
+}
+
+impl Encoder {
+    fn idx_insn(&mut self, op: u8, idx: u32) {
+        self.byte(op);
+        self.int(idx);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use wain_print_text::print;
+    use wain_syntax_binary::parse as parse_binary;
+    use wain_syntax_binary::{sections, SectionKind};
+    use wain_syntax_text::parse as parse_text;
+
+    fn encode(module: &Module<'_>, names: bool) -> Vec<u8> {
+        let mut encoder = if names {
+            Encoder::with_names()
+        } else {
+            Encoder::new()
+        };
+        encoder.encode(module);
+        encoder.into_bytes()
+    }
+
+    fn example_files(ext: &str) -> Vec<PathBuf> {
+        let mut dir = env::current_dir().unwrap();
+        dir.pop();
+        dir.push("examples");
+        let mut files = vec![];
+        for dir in [dir.clone(), dir.join("hello")].iter() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().map(|e| e == ext).unwrap_or(false) {
+                    files.push(path);
+                }
+            }
+        }
+        assert!(!files.is_empty());
+        files
+    }
+
+    fn assert_bytes_eq(actual: &[u8], expected: &[u8], file: &PathBuf) {
+        if let Some(idx) = actual.iter().zip(expected.iter()).position(|(a, e)| a != e) {
+            panic!(
+                "{:?}: byte mismatch at offset 0x{:x}: actual={:x?} expected={:x?}",
+                file,
+                idx,
+                &actual[idx..actual.len().min(idx + 8)],
+                &expected[idx..expected.len().min(idx + 8)],
+            );
+        }
+        assert_eq!(actual.len(), expected.len(), "{:?}", file);
+    }
+
+    // Hand-assembled module in the shortest form containing all kinds of sections. Encoding the parsed
+    // module must reproduce exactly the same bytes.
+    const CANONICAL: &[&[u8]] = &[
+        // Magic and version
+        &[0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00],
+        // Type section: (i32) -> i32, () -> ()
+        &[
+            0x01, 0x09, 0x02, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x00, 0x00,
+        ],
+        // Custom section "x" with payload "y"
+        &[0x00, 0x03, 0x01, 0x78, 0x79],
+        // Import section: func "env" "f" (type 0), global "env" "g" i32
+        &[
+            0x02, 0x12, 0x02, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x66, 0x00, 0x00, 0x03, 0x65, 0x6e,
+            0x76, 0x01, 0x67, 0x03, 0x7f, 0x00,
+        ],
+        // Function section: (type 1)
+        &[0x03, 0x02, 0x01, 0x01],
+        // Table section: 1 funcref
+        &[0x04, 0x04, 0x01, 0x70, 0x00, 0x01],
+        // Memory section: 1 2
+        &[0x05, 0x04, 0x01, 0x01, 0x01, 0x02],
+        // Global section: (mut i64) (i64.const -1)
+        &[0x06, 0x06, 0x01, 0x7e, 0x01, 0x42, 0x7f, 0x0b],
+        // Export section: "run" (func 1)
+        &[0x07, 0x07, 0x01, 0x03, 0x72, 0x75, 0x6e, 0x00, 0x01],
+        // Start section: (func 1)
+        &[0x08, 0x01, 0x01],
+        // Element section: (i32.const 0) (func 1)
+        &[0x09, 0x07, 0x01, 0x00, 0x41, 0x00, 0x0b, 0x01, 0x01],
+        // Code section
+        &[0x0a, 0x40, 0x01, 0x3e, 0x01, 0x01, 0x7f],
+        // i32.load align=1 (explicit alignment 2^0 which is not natural)
+        &[0x41, 0x00, 0x28, 0x00, 0x00, 0x1a],
+        // i64.load offset=8
+        &[0x41, 0x00, 0x29, 0x03, 0x08, 0x1a],
+        // block, i32.const -128, br_table 0 0, end
+        &[0x02, 0x40, 0x41, 0x80, 0x7f, 0x0e, 0x01, 0x00, 0x00, 0x0b],
+        // call 0, call_indirect (type 0)
+        &[
+            0x41, 0x2a, 0x10, 0x00, 0x1a, 0x41, 0x05, 0x41, 0x00, 0x11, 0x00, 0x00, 0x1a,
+        ],
+        // global.get 0, f64.const 1
+        &[
+            0x23, 0x00, 0x1a, 0x44, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xf0, 0x3f, 0x1a,
+        ],
+        // f32.const 1, local.set 0, end
+        &[
+            0x43, 0x00, 0x00, 0x80, 0x3f, 0x1a, 0x41, 0x07, 0x21, 0x00, 0x0b,
+        ],
+        // Data section: (i32.const 16) "hi"
+        &[0x0b, 0x08, 0x01, 0x00, 0x41, 0x10, 0x0b, 0x02, 0x68, 0x69],
+    ];
+
+    // Split binary into raw bytes of sections including their IDs and sizes
+    fn split_sections<'b>(bin: &'b [u8], file: &PathBuf) -> Vec<(String, &'b [u8])> {
+        let sections = sections(bin).unwrap_or_else(|e| panic!("{:?}: {}", file, e));
+        let mut ends: Vec<_> = sections.iter().skip(1).map(|s| s.start).collect();
+        ends.push(bin.len());
+        sections
+            .iter()
+            .zip(ends)
+            .map(|(s, end)| {
+                let kind = match &s.kind {
+                    SectionKind::Standard(id) => id.to_string(),
+                    SectionKind::Custom(name) => format!("custom {}", name.0),
+                };
+                (kind, &bin[s.start..end])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn roundtrip_canonical_binary() {
+        let bin = CANONICAL.concat();
+        let root = parse_binary(&bin).unwrap_or_else(|e| panic!("{}", e));
+        let file = PathBuf::from("CANONICAL");
+        assert_bytes_eq(&encode(&root.module, false), &bin, &file);
+    }
+
+    // Note: Binaries in examples/ are not always encoded in the shortest form since wasm-ld pads LEB128
+    // integers for relocations in code section. Other sections must be encoded into exactly the same
+    // bytes. Code section is normalized by parsing it. Function bodies parsed from the original and
+    // encoded binaries must be printed as the same text.
+    #[test]
+    fn roundtrip_binary() {
+        for file in example_files("wasm") {
+            let bin = fs::read(&file).unwrap();
+            let root = parse_binary(&bin).unwrap_or_else(|e| panic!("{:?}: {}", file, e));
+            let encoded = encode(&root.module, false);
+
+            let expected = split_sections(&bin, &file);
+            let actual = split_sections(&encoded, &file);
+            assert_eq!(actual.len(), expected.len(), "{:?}", file);
+            for ((a_kind, a), (e_kind, e)) in actual.iter().zip(expected.iter()) {
+                assert_eq!(a_kind, e_kind, "{:?}", file);
+                if a_kind != "code" {
+                    assert_bytes_eq(a, e, &file);
+                }
+            }
+
+            let reparsed = parse_binary(&encoded).unwrap_or_else(|e| panic!("{:?}: {}", file, e));
+            let expected = print(&root.module);
+            let actual = print(&reparsed.module);
+            for (i, (a, e)) in actual.lines().zip(expected.lines()).enumerate() {
+                assert_eq!(a, e, "{:?}: line {} of printed module", file, i + 1);
+            }
+            assert_eq!(
+                actual.lines().count(),
+                expected.lines().count(),
+                "{:?}",
+                file
+            );
+
+            let root = reparsed;
+            assert_eq!(root.module.customs.len(), 2, "{:?}", file);
+            assert_eq!(
+                root.module.names.funcs.len(),
+                root.module.funcs.len(),
+                "{:?}",
+                file
+            );
+            assert_bytes_eq(&encode(&root.module, false), &encoded, &file);
+
+            // Generated 'name' section replaces the original one at the same place
+            assert_bytes_eq(&encode(&root.module, true), &encoded, &file);
+        }
+    }
+
+    #[test]
+    fn text_to_binary() {
+        for file in example_files("wat") {
+            let source = fs::read_to_string(&file).unwrap();
+            let root = parse_text(&source).unwrap_or_else(|e| panic!("{:?}: {}", file, e));
+            let encoded = encode(&root.module, false);
+
+            let bin = fs::read(file.with_extension("wasm")).unwrap();
+            let mut expected = parse_binary(&bin).unwrap_or_else(|e| panic!("{:?}: {}", file, e));
+            expected.module.customs.clear();
+            assert_bytes_eq(&encoded, &encode(&expected.module, false), &file);
+        }
+    }
+
+    #[test]
+    fn names_from_text() {
+        let source = r#"
+            (module $m
+              (type $t (func (param i32)))
+              (import "env" "putchar" (func $putchar (type $t)))
+              (memory $mem 1)
+              (global $g (mut i32) (i32.const 0))
+              (func $f (@name "my func") (param $p i32) (local i32) (local $l i64)
+                local.get $p
+                call $putchar)
+            )
+        "#;
+        let root =
+            wain_syntax_text::parse_with_id_names(source).unwrap_or_else(|e| panic!("{}", e));
+
+        let bin = encode(&root.module, false);
+        let decoded = parse_binary(&bin).unwrap_or_else(|e| panic!("{}", e));
+        assert!(decoded.module.customs.is_empty());
+        assert!(decoded.module.names.funcs.is_empty());
+
+        let bin = encode(&root.module, true);
+        let decoded = parse_binary(&bin).unwrap_or_else(|e| panic!("{}", e));
+        let customs = &decoded.module.customs;
+        assert_eq!(customs.len(), 1);
+        assert_eq!(customs[0].name.0, "name");
+        assert_eq!(customs[0].place, CustomPlace::After(SectionId::Code));
+
+        let names = &decoded.module.names;
+        assert_eq!(names.module.as_ref().unwrap().0, "m");
+        assert_eq!(names.func(0).unwrap().0, "putchar");
+        assert_eq!(names.func(1).unwrap().0, "my func");
+        assert_eq!(names.local(1, 0).unwrap().0, "p");
+        assert!(names.local(1, 1).is_none());
+        assert_eq!(names.local(1, 2).unwrap().0, "l");
+        assert_eq!(names.types[0].1 .0, "t");
+        assert_eq!(names.memories[0].1 .0, "mem");
+        assert_eq!(names.global(0).unwrap().0, "g");
+    }
+
+    #[test]
+    fn custom_places() {
+        let source = r#"
+            (module
+              (@custom "a" (before first) "A")
+              (@custom "b" (after func) "B")
+              (@custom "c" (before data) "C")
+              (@custom "d" "D")
+              (func)
+            )
+        "#;
+        let root = parse_text(source).unwrap_or_else(|e| panic!("{}", e));
+        let bin = encode(&root.module, false);
+        let decoded = parse_binary(&bin).unwrap_or_else(|e| panic!("{}", e));
+        let customs: Vec<_> = decoded
+            .module
+            .customs
+            .iter()
+            .map(|c| (c.name.0.as_ref(), c.place, c.data.as_ref()))
+            .collect();
+        assert_eq!(
+            customs,
+            vec![
+                ("a", CustomPlace::BeforeFirst, b"A".as_ref()),
+                ("b", CustomPlace::After(SectionId::Func), b"B".as_ref()),
+                ("c", CustomPlace::After(SectionId::Code), b"C".as_ref()),
+                ("d", CustomPlace::After(SectionId::Code), b"D".as_ref()),
+            ]
+        );
+    }
+
+    #[test]
+    fn memarg_alignment() {
+        let source = r#"
+            (module
+              (memory 1)
+              (func
+                (drop (i64.load (i32.const 0)))
+                (drop (i64.load offset=4 align=2 (i32.const 0)))
+                (i32.store8 (i32.const 0) (i32.const 0)))
+            )
+        "#;
+        let root = parse_text(source).unwrap_or_else(|e| panic!("{}", e));
+        let bin = encode(&root.module, false);
+        // i64.load with natural alignment 2^3
+        assert!(bin.windows(3).any(|w| w == [0x29, 0x03, 0x00]));
+        // i64.load with alignment 2^1 and offset 4
+        assert!(bin.windows(3).any(|w| w == [0x29, 0x01, 0x04]));
+        // i32.store8 with natural alignment 2^0
+        assert!(bin.windows(3).any(|w| w == [0x3a, 0x00, 0x00]));
+    }
+}
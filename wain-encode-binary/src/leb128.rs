@@ -0,0 +1,153 @@
+// Integers are encoded with LEB128 (Little Endian Base 128)
+// - https://webassembly.github.io/spec/core/binary/values.html#integers
+// - https://en.wikipedia.org/wiki/LEB128
+//
+// Encoders always emit the shortest byte sequence.
+
+pub trait Leb128 {
+    fn write_leb128(self, buf: &mut Vec<u8>);
+}
+
+impl Leb128 for u64 {
+    fn write_leb128(self, buf: &mut Vec<u8>) {
+        write_unsigned(self, buf)
+    }
+}
+
+impl Leb128 for u32 {
+    fn write_leb128(self, buf: &mut Vec<u8>) {
+        write_unsigned(self as u64, buf)
+    }
+}
+
+impl Leb128 for i64 {
+    fn write_leb128(self, buf: &mut Vec<u8>) {
+        write_signed(self, buf)
+    }
+}
+
+impl Leb128 for i32 {
+    fn write_leb128(self, buf: &mut Vec<u8>) {
+        write_signed(self as i64, buf)
+    }
+}
+
+fn write_unsigned(mut u: u64, buf: &mut Vec<u8>) {
+    loop {
+        let b = (u & 0b0111_1111) as u8;
+        u >>= 7;
+        if u == 0 {
+            buf.push(b);
+            return;
+        }
+        buf.push(b | 0b1000_0000);
+    }
+}
+
+fn write_signed(mut i: i64, buf: &mut Vec<u8>) {
+    loop {
+        let b = (i & 0b0111_1111) as u8;
+        i >>= 7; // Arithmetic shift extends sign bit
+                 // Stop when the rest bits are only sign extension of the sign bit of current byte
+        let sign = b & 0b0100_0000 != 0;
+        if i == 0 && !sign || i == -1 && sign {
+            buf.push(b);
+            return;
+        }
+        buf.push(b | 0b1000_0000);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode<L: Leb128>(l: L) -> Vec<u8> {
+        let mut buf = vec![];
+        l.write_leb128(&mut buf);
+        buf
+    }
+
+    #[test]
+    fn i32_value() {
+        for (input, expected) in [
+            (0, vec![0x00]),
+            (128, vec![0x80, 0x01]),
+            (123456, vec![0xc0, 0xc4, 0x07]),
+            (1, vec![0x01]),
+            (63, vec![0x3f]),
+            (64, vec![0xc0, 0x00]),
+            (i32::MAX, vec![0xff, 0xff, 0xff, 0xff, 0x07]),
+            (-123456, vec![0xc0, 0xbb, 0x78]),
+            (-1, vec![0x7f]),
+            (-64, vec![0x40]),
+            (-65, vec![0xbf, 0x7f]),
+            (-128, vec![0x80, 0x7f]),
+            (i32::MIN, vec![0x80, 0x80, 0x80, 0x80, 0x78]),
+        ]
+        .iter()
+        {
+            assert_eq!(&encode(*input), expected, "for {}", input);
+        }
+    }
+
+    #[test]
+    fn i64_value() {
+        for (input, expected) in [
+            (0, vec![0x00]),
+            (128, vec![0x80, 0x01]),
+            (123456, vec![0xc0, 0xc4, 0x07]),
+            (i32::MAX as i64, vec![0xff, 0xff, 0xff, 0xff, 0x07]),
+            (
+                i64::MAX,
+                vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00],
+            ),
+            (-123456, vec![0xc0, 0xbb, 0x78]),
+            (-1, vec![0x7f]),
+            (i32::MIN as u32 as i64, vec![0x80, 0x80, 0x80, 0x80, 0x08]),
+            (
+                i64::MIN,
+                vec![0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f],
+            ),
+        ]
+        .iter()
+        {
+            assert_eq!(&encode(*input), expected, "for {}", input);
+        }
+    }
+
+    #[test]
+    fn u32_value() {
+        for (input, expected) in [
+            (0, vec![0x00]),
+            (127, vec![0x7f]),
+            (128, vec![0x80, 0x01]),
+            (123456, vec![0xc0, 0xc4, 0x07]),
+            (u32::MAX, vec![0xff, 0xff, 0xff, 0xff, 0x0f]),
+        ]
+        .iter()
+        {
+            assert_eq!(&encode(*input), expected, "for {}", input);
+        }
+    }
+
+    #[test]
+    fn u64_value() {
+        for (input, expected) in [
+            (0, vec![0x00]),
+            (128, vec![0x80, 0x01]),
+            (
+                124318196531103,
+                vec![0x9f, 0xff, 0xc5, 0xaf, 0x91, 0xa2, 0x1c],
+            ),
+            (
+                u64::MAX,
+                vec![0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            ),
+        ]
+        .iter()
+        {
+            assert_eq!(&encode(*input), expected, "for {}", input);
+        }
+    }
+}
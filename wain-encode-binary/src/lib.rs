@@ -0,0 +1,22 @@
+#![forbid(unsafe_code)]
+
+mod encoder;
+mod leb128;
+
+pub use encoder::{Encode, Encoder};
+use wain_ast::Module;
+
+pub fn encode(module: &Module<'_>) -> Vec<u8> {
+    let mut encoder = Encoder::new();
+    encoder.encode(module);
+    encoder.into_bytes()
+}
+
+// Encode the module with 'name' custom section generated from names in the module. For text format,
+// the names are collected from @name annotations, and also from identifiers when the module is
+// parsed by wain_syntax_text::parse_with_id_names().
+pub fn encode_with_names(module: &Module<'_>) -> Vec<u8> {
+    let mut encoder = Encoder::with_names();
+    encoder.encode(module);
+    encoder.into_bytes()
+}
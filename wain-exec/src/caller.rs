@@ -0,0 +1,331 @@
+// Context of the instance calling a host function
+//
+// Importer::call only receives the operand stack and linear memory. Host functions implemented
+// with Importer::call_with_caller receive Caller instead. It can invoke guest functions
+// re-entrantly and access exports, globals, table and memory of the calling instance. Guest
+// functions invoked from the host function run on the same stack above the frame of the host
+// function so its arguments on the stack are kept after the invocation even if it traps.
+
+use crate::export::{ExportError, ExportResult};
+use crate::import::{ImportInvokeError, Importer};
+use crate::machine::Machine;
+use crate::memory::Memory;
+use crate::stack::Stack;
+use crate::stop::{ExecError, Result};
+use crate::trap::Trap;
+use crate::value::Value;
+use wain_ast as ast;
+
+pub struct Caller<'c, 'm, 's, I: Importer> {
+    machine: &'c mut Machine<'m, 's, I>,
+}
+
+impl<'c, 'm, 's, I: Importer> Caller<'c, 'm, 's, I> {
+    pub(crate) fn new(machine: &'c mut Machine<'m, 's, I>) -> Self {
+        Self { machine }
+    }
+
+    pub(crate) fn call_importer(
+        &mut self,
+        name: &str,
+    ) -> ::std::result::Result<(), ImportInvokeError> {
+        self.machine.call_importer(name)
+    }
+
+    pub fn module(&self) -> &'m ast::Module<'s> {
+        self.machine.module()
+    }
+
+    pub fn importer(&self) -> &I {
+        self.machine.importer()
+    }
+
+    pub fn importer_mut(&mut self) -> &mut I {
+        self.machine.importer_mut()
+    }
+
+    // Arguments of the host function are on top of the stack. Its result must be pushed on return
+    pub fn stack(&mut self) -> &mut Stack {
+        self.machine.stack_mut()
+    }
+
+    pub fn memory(&self) -> &Memory {
+        self.machine.memory()
+    }
+
+    // Memory::grow works as memory.grow instruction
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        self.machine.linear_memory_mut()
+    }
+
+    // Names and types of exports. See Machine::exports
+    pub fn exports(&self) -> Vec<(&'m str, ast::ExternType<'m>)> {
+        self.machine.exports()
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.machine.get_global(name)
+    }
+
+    // Set the value to the exported mutable global variable. See Machine::set_global
+    pub fn set_global(&mut self, name: &str, value: Value) -> ExportResult<()> {
+        self.machine.set_global(name, value)
+    }
+
+    pub fn table_size(&self) -> usize {
+        self.machine.table().size()
+    }
+
+    // Index of the function at the index of the table. None when the element is out of the table
+    // or not initialized
+    pub fn table_get(&self, idx: usize) -> Option<u32> {
+        self.machine.table().get(idx).flatten()
+    }
+
+    // Invoke the exported function
+    pub fn invoke(&mut self, name: impl AsRef<str>, args: &[Value]) -> Result<Option<Value>> {
+        let name = name.as_ref();
+        let (funcidx, start) = self.machine.find_func_to_invoke(name)?;
+        self.machine.reenter(funcidx, name, args, start)
+    }
+
+    // Invoke the function at the index of the table as call_indirect instruction
+    pub fn invoke_indirect(&mut self, idx: usize, args: &[Value]) -> Result<Option<Value>> {
+        let funcidx = self.machine.table().at(idx, 0)?;
+        let name = format!("table[{}]", idx);
+        let start = self.module().funcs[funcidx as usize].start;
+        self.machine.reenter(funcidx, &name, args, start)
+    }
+}
+
+// Traps of guest functions invoked from a host function can be returned from the host function
+impl From<Box<Trap>> for ImportInvokeError {
+    fn from(trap: Box<Trap>) -> Self {
+        ImportInvokeError::Fatal {
+            message: trap.to_string(),
+        }
+    }
+}
+
+impl From<ExecError> for ImportInvokeError {
+    fn from(err: ExecError) -> Self {
+        ImportInvokeError::Fatal {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<Box<ExportError>> for ImportInvokeError {
+    fn from(err: Box<ExportError>) -> Self {
+        ImportInvokeError::Fatal {
+            message: err.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{check_func_signature, ImportInvalidError};
+    use wain_ast::ValType;
+    use wain_syntax_text::parse;
+
+    const SOURCE: &str = r#"
+(module
+  (import "env" "make_str" (func $make_str (param i32) (result i32)))
+  (import "env" "apply" (func $apply (param i32 i32) (result i32)))
+  (import "env" "recover" (func $recover (param i32) (result i32)))
+  (import "env" "grow" (func $grow (param i32) (result i32)))
+  (memory (export "memory") 1 3)
+  (table 2 funcref)
+  (global $heap (export "heap") (mut i32) (i32.const 16))
+  (elem (i32.const 0) $double $count)
+  (func $malloc (export "malloc") (param i32) (result i32)
+    global.get $heap
+    global.get $heap
+    local.get 0
+    i32.add
+    global.set $heap)
+  (func $double (param i32) (result i32)
+    local.get 0
+    i32.const 2
+    i32.mul)
+  ;; Count down by calling itself through the host function
+  (func $count (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if (result i32)
+      i32.const 0
+    else
+      i32.const 1
+      local.get 0
+      i32.const 1
+      i32.sub
+      call $apply
+      i32.const 1
+      i32.add
+    end)
+  (func (export "fail") (param i32) (result i32)
+    unreachable)
+  (func (export "str") (result i32)
+    i32.const 5
+    call $make_str)
+  (func (export "apply") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    call $apply)
+  (func (export "recover") (param i32) (result i32)
+    i32.const 100
+    local.get 0
+    call $recover
+    i32.add)
+  (func (export "grow") (param i32) (result i32)
+    local.get 0
+    call $grow))
+"#;
+
+    struct Host;
+
+    impl Importer for Host {
+        fn validate(
+            &self,
+            name: &str,
+            params: &[ValType],
+            ret: Option<ValType>,
+        ) -> Option<ImportInvalidError> {
+            use ValType::*;
+            match name {
+                "make_str" | "recover" | "grow" => {
+                    check_func_signature(params, ret, &[I32], Some(I32))
+                }
+                "apply" => check_func_signature(params, ret, &[I32, I32], Some(I32)),
+                _ => Some(ImportInvalidError::NotFound),
+            }
+        }
+
+        fn call(
+            &mut self,
+            name: &str,
+            _stack: &mut Stack,
+            _memory: &mut Memory,
+        ) -> ::std::result::Result<(), ImportInvokeError> {
+            Err(ImportInvokeError::Fatal {
+                message: format!("'{}' must be called with caller", name),
+            })
+        }
+
+        fn call_with_caller(
+            caller: &mut Caller<'_, '_, '_, Self>,
+            name: &str,
+        ) -> ::std::result::Result<(), ImportInvokeError> {
+            match name {
+                "make_str" => {
+                    // Allocate the string in guest memory with the guest allocator
+                    let len: i32 = caller.stack().pop();
+                    let ptr = match caller.invoke("malloc", &[Value::I32(len)])? {
+                        Some(Value::I32(ptr)) => ptr,
+                        _ => unreachable!(),
+                    };
+                    let s = &b"hello, world"[..len as usize];
+                    assert!(caller.memory_mut().write_range(ptr as usize, s));
+                    caller.stack().push(ptr);
+                }
+                "apply" => {
+                    let arg: i32 = caller.stack().pop();
+                    let idx: i32 = caller.stack().pop();
+                    let depth = caller.stack().frames().len();
+                    let ret = caller.invoke_indirect(idx as usize, &[Value::I32(arg)])?;
+                    assert_eq!(caller.stack().frames().len(), depth);
+                    match ret {
+                        Some(Value::I32(i)) => caller.stack().push(i),
+                        _ => unreachable!(),
+                    }
+                }
+                "recover" => {
+                    // Traps of guest functions can be handled by host functions
+                    let depth = caller.stack().frames().len();
+                    let msg = match caller.invoke("fail", &[Value::I32(0)]) {
+                        Err(trap) => trap.to_string(),
+                        Ok(_) => panic!("trap did not occur"),
+                    };
+                    assert!(msg.contains("unreachable"), "{}", msg);
+                    assert!(caller.invoke("fail", &[]).is_err());
+                    assert_eq!(caller.stack().frames().len(), depth);
+                    let v: i32 = caller.stack().pop();
+                    caller.stack().push(v + 1);
+                }
+                "grow" => {
+                    let pages: i32 = caller.stack().pop();
+                    let prev = caller.memory_mut().grow(pages as u32);
+                    caller.stack().push(prev);
+                }
+                _ => unreachable!(),
+            }
+            Ok(())
+        }
+    }
+
+    fn invoke(machine: &mut Machine<'_, '_, Host>, name: &str, args: &[Value]) -> Option<Value> {
+        machine
+            .invoke(name, args)
+            .unwrap_or_else(|t| panic!("{}", t))
+    }
+
+    #[test]
+    fn call_guest_from_host() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let mut machine =
+            Machine::instantiate(&root.module, Host).unwrap_or_else(|t| panic!("{}", t));
+
+        assert_eq!(invoke(&mut machine, "str", &[]), Some(Value::I32(16)));
+        assert_eq!(machine.get_global("heap"), Some(Value::I32(21)));
+        assert_eq!(machine.memory().read_range(16..21), Some(b"hello".to_vec()));
+        assert_eq!(invoke(&mut machine, "str", &[]), Some(Value::I32(21)));
+
+        let args = [Value::I32(0), Value::I32(21)];
+        assert_eq!(invoke(&mut machine, "apply", &args), Some(Value::I32(42)));
+
+        // Host function and guest function call each other recursively
+        let args = [Value::I32(1), Value::I32(3)];
+        assert_eq!(invoke(&mut machine, "apply", &args), Some(Value::I32(3)));
+
+        assert_eq!(
+            invoke(&mut machine, "recover", &[Value::I32(1)]),
+            Some(Value::I32(102))
+        );
+
+        assert_eq!(
+            invoke(&mut machine, "grow", &[Value::I32(2)]),
+            Some(Value::I32(1))
+        );
+        assert_eq!(
+            invoke(&mut machine, "grow", &[Value::I32(1)]),
+            Some(Value::I32(-1))
+        );
+        assert_eq!(machine.memory().size(), 3);
+    }
+
+    #[test]
+    fn caller_errors() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let mut machine = Machine::instantiate(&root.module, Host).unwrap();
+
+        // Uninitialized table element
+        let msg = match machine.invoke("apply", &[Value::I32(5), Value::I32(0)]) {
+            Err(trap) => trap.to_string(),
+            Ok(v) => panic!("trap did not occur: {:?}", v),
+        };
+        assert!(
+            msg.contains("calling imported function 'apply' in module 'env'"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("out of table size 2"), "{}", msg);
+
+        // The machine can invoke functions after the error
+        assert_eq!(
+            invoke(&mut machine, "recover", &[Value::I32(1)]),
+            Some(Value::I32(102))
+        );
+    }
+}
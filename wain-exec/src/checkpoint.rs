@@ -0,0 +1,670 @@
+// Checkpoint of invocation suspended at a host call
+//
+// Execution runs on Rust call stack by recursively executing instructions. When execution is
+// suspended, the position of each nested instruction sequence, labels of control instructions and
+// call frames are recorded while unwinding the Rust call stack, as steps. Values of locals, labels
+// and operands remain in `Stack`. On resuming, the recorded steps are followed from the outermost
+// function to the suspended host call without executing instructions again.
+//
+// The checkpoint is encoded as below. All integers are fixed-width little endian.
+//
+//   magic "WAINCKPT", version (u32), snapshot (see snapshot.rs)
+//   stack: count (u32), then value type byte and bits of each value
+//   steps: count (u32), then a tag byte and operands of each step
+//     0: frame of function (function index, base address and base type index of stack (u32))
+//     1: index of instruction in instruction sequence (u32)
+//     2: label of control instruction (address and type index of stack (u32) and flags (u8))
+//     3: suspended host call (function index (u32))
+
+use crate::snapshot::{incompatible, write_value, Decoder, Snapshot, SnapshotError};
+use crate::stack::Label;
+use crate::value::Value;
+use wain_ast as ast;
+
+const MAGIC: &[u8; 8] = b"WAINCKPT";
+const VERSION: u32 = 1;
+const LABEL_HAS_RESULT: u8 = 1;
+const LABEL_ELSE_BODY: u8 = 2;
+
+type Result<T> = ::std::result::Result<T, Box<SnapshotError>>;
+
+// Position of suspended execution. Steps are ordered from the outermost function
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum Step {
+    Frame {
+        funcidx: u32,
+        base_addr: usize,
+        base_idx: usize,
+    },
+    Insn(usize),
+    Label(Label, bool), // Label of block, loop or if. Second value is true on else body of if
+    Host(u32),
+}
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone)]
+pub struct Checkpoint {
+    pub snapshot: Snapshot,
+    pub(crate) stack: Vec<Value>,
+    pub(crate) steps: Vec<Step>,
+}
+
+impl Checkpoint {
+    // Index of the imported function which is called first on resuming
+    pub fn host_func(&self) -> u32 {
+        match self.steps.last() {
+            Some(Step::Host(funcidx)) => *funcidx,
+            _ => unreachable!("checkpoint must end with host call"),
+        }
+    }
+
+    // Indices of functions being invoked. The outermost function comes first
+    pub fn funcs(&self) -> Vec<u32> {
+        self.steps
+            .iter()
+            .filter_map(|s| match s {
+                Step::Frame { funcidx, .. } | Step::Host(funcidx) => Some(*funcidx),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = MAGIC.to_vec();
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        self.snapshot.encode_to(&mut buf);
+
+        buf.extend_from_slice(&(self.stack.len() as u32).to_le_bytes());
+        for v in self.stack.iter() {
+            write_value(&mut buf, v);
+        }
+
+        buf.extend_from_slice(&(self.steps.len() as u32).to_le_bytes());
+        for step in self.steps.iter() {
+            match step {
+                Step::Frame {
+                    funcidx,
+                    base_addr,
+                    base_idx,
+                } => {
+                    buf.push(0);
+                    buf.extend_from_slice(&funcidx.to_le_bytes());
+                    buf.extend_from_slice(&(*base_addr as u32).to_le_bytes());
+                    buf.extend_from_slice(&(*base_idx as u32).to_le_bytes());
+                }
+                Step::Insn(idx) => {
+                    buf.push(1);
+                    buf.extend_from_slice(&(*idx as u32).to_le_bytes());
+                }
+                Step::Label(label, else_body) => {
+                    buf.push(2);
+                    buf.extend_from_slice(&(label.addr as u32).to_le_bytes());
+                    buf.extend_from_slice(&(label.type_idx as u32).to_le_bytes());
+                    let mut flags = 0;
+                    if label.has_result {
+                        flags |= LABEL_HAS_RESULT;
+                    }
+                    if *else_body {
+                        flags |= LABEL_ELSE_BODY;
+                    }
+                    buf.push(flags);
+                }
+                Step::Host(funcidx) => {
+                    buf.push(3);
+                    buf.extend_from_slice(&funcidx.to_le_bytes());
+                }
+            }
+        }
+
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Checkpoint> {
+        let mut d = Decoder::new(bytes);
+        d.header(MAGIC, VERSION)?;
+        let snapshot = Snapshot::decode_from(&mut d)?;
+
+        let mut stack = vec![];
+        for _ in 0..d.u32()? {
+            stack.push(d.value()?);
+        }
+
+        let mut steps = vec![];
+        for _ in 0..d.u32()? {
+            let step = match d.byte()? {
+                0 => Step::Frame {
+                    funcidx: d.u32()?,
+                    base_addr: d.u32()? as usize,
+                    base_idx: d.u32()? as usize,
+                },
+                1 => Step::Insn(d.u32()? as usize),
+                2 => {
+                    let addr = d.u32()? as usize;
+                    let type_idx = d.u32()? as usize;
+                    let flags = d.byte()?;
+                    let label = Label {
+                        addr,
+                        type_idx,
+                        has_result: flags & LABEL_HAS_RESULT != 0,
+                    };
+                    Step::Label(label, flags & LABEL_ELSE_BODY != 0)
+                }
+                3 => Step::Host(d.u32()?),
+                b => {
+                    d.pos -= 1;
+                    return d.error(format!("invalid step 0x{:x}", b));
+                }
+            };
+            steps.push(step);
+        }
+        if !matches!(steps.last(), Some(Step::Host(_))) {
+            return d.error("checkpoint does not end with host call");
+        }
+        d.end()?;
+
+        Ok(Checkpoint {
+            snapshot,
+            stack,
+            steps,
+        })
+    }
+
+    // Check the steps can be followed in the module. Stack addresses in steps must point to the
+    // boundaries of values on the stack. Called functions must be the ones which call instructions
+    // can call
+    pub(crate) fn check(&self, module: &ast::Module<'_>, source: &[u8]) -> Result<()> {
+        self.snapshot.check(module, source)?;
+
+        let mut addrs = Vec::with_capacity(self.stack.len() + 1);
+        let mut addr = 0;
+        addrs.push(addr);
+        for v in self.stack.iter() {
+            addr += v.valtype().bytes();
+            addrs.push(addr);
+        }
+        let check_addr = |addr: usize, idx: usize| {
+            if addrs.get(idx) == Some(&addr) {
+                Ok(())
+            } else {
+                incompatible(format!(
+                    "address {} with type index {} does not point to value on stack",
+                    addr, idx
+                ))
+            }
+        };
+
+        // Function called by the previous step. The outermost function can be any function
+        #[derive(Clone, Copy)]
+        enum Callee {
+            Any,
+            Func(u32), // By call instruction
+            Type(u32), // By call_indirect instruction
+        }
+        let check_callee = |callee: Callee, funcidx: u32| match callee {
+            Callee::Func(idx) if idx != funcidx => incompatible(format!(
+                "function {} is called where call instruction calls function {}",
+                funcidx, idx
+            )),
+            Callee::Type(typeidx) => {
+                let expected = &module.types[typeidx as usize];
+                let actual = &module.types[module.funcs[funcidx as usize].idx as usize];
+                if actual.params == expected.params && actual.results == expected.results {
+                    Ok(())
+                } else {
+                    incompatible(format!(
+                        "function {} is called where call_indirect instruction expects type {}",
+                        funcidx, typeidx
+                    ))
+                }
+            }
+            _ => Ok(()),
+        };
+
+        enum Expect<'a> {
+            Call(Callee),
+            Insn(&'a [ast::Instruction]),
+            Label(&'a ast::Instruction),
+        }
+        let mut expect = Expect::Call(Callee::Any);
+        for (i, step) in self.steps.iter().enumerate() {
+            expect = match (expect, step) {
+                (
+                    Expect::Call(callee),
+                    Step::Frame {
+                        funcidx,
+                        base_addr,
+                        base_idx,
+                    },
+                ) => {
+                    let func = match module.funcs.get(*funcidx as usize) {
+                        Some(f) => f,
+                        None => {
+                            return incompatible(format!("function {} does not exist", funcidx))
+                        }
+                    };
+                    let (locals, body) = match &func.kind {
+                        ast::FuncKind::Body { locals, expr } => (locals, expr),
+                        ast::FuncKind::Import(_) => {
+                            return incompatible(format!("function {} is imported", funcidx))
+                        }
+                    };
+                    check_callee(callee, *funcidx)?;
+                    check_addr(*base_addr, *base_idx)?;
+                    let params = &module.types[func.idx as usize].params;
+                    let types = params.iter().chain(locals.iter());
+                    if self.stack.len() < base_idx + params.len() + locals.len()
+                        || self.stack[*base_idx..]
+                            .iter()
+                            .zip(types)
+                            .any(|(v, t)| v.valtype() != *t)
+                    {
+                        return incompatible(format!(
+                            "locals of function {} do not match to stack",
+                            funcidx
+                        ));
+                    }
+                    Expect::Insn(body)
+                }
+                (Expect::Call(callee), Step::Host(funcidx)) if i == self.steps.len() - 1 => {
+                    match module.funcs.get(*funcidx as usize).map(|f| &f.kind) {
+                        Some(ast::FuncKind::Import(_)) => {}
+                        _ => {
+                            return incompatible(format!(
+                                "function {} is not imported function",
+                                funcidx
+                            ))
+                        }
+                    }
+                    check_callee(callee, *funcidx)?;
+                    Expect::Call(Callee::Any)
+                }
+                (Expect::Insn(insns), Step::Insn(idx)) => match insns.get(*idx) {
+                    Some(insn) => match &insn.kind {
+                        ast::InsnKind::Block { .. }
+                        | ast::InsnKind::Loop { .. }
+                        | ast::InsnKind::If { .. } => Expect::Label(insn),
+                        ast::InsnKind::Call(funcidx) => Expect::Call(Callee::Func(*funcidx)),
+                        ast::InsnKind::CallIndirect(typeidx) => {
+                            Expect::Call(Callee::Type(*typeidx))
+                        }
+                        _ => {
+                            return incompatible(format!(
+                                "instruction at index {} is not call nor control instruction",
+                                idx
+                            ))
+                        }
+                    },
+                    None => {
+                        return incompatible(format!(
+                            "instruction index {} is out of instruction sequence",
+                            idx
+                        ))
+                    }
+                },
+                (Expect::Label(insn), Step::Label(label, else_body)) => {
+                    check_addr(label.addr, label.type_idx)?;
+                    let (ty, body) = match &insn.kind {
+                        ast::InsnKind::Block { ty, body } | ast::InsnKind::Loop { ty, body }
+                            if !else_body =>
+                        {
+                            (ty, body)
+                        }
+                        ast::InsnKind::If {
+                            ty,
+                            then_body,
+                            else_body: body,
+                        } => (ty, if *else_body { body } else { then_body }),
+                        _ => return incompatible("else body of non-if instruction".to_string()),
+                    };
+                    if ty.is_some() != label.has_result {
+                        return incompatible(format!(
+                            "label at address {} does not match to block type",
+                            label.addr
+                        ));
+                    }
+                    Expect::Insn(body)
+                }
+                _ => return incompatible(format!("unexpected step at index {}", i)),
+            };
+        }
+        if !matches!(self.steps.last(), Some(Step::Host(_))) {
+            return incompatible("steps do not end with host call".to_string());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::{check_func_signature, ImportInvalidError, ImportInvokeError, Importer};
+    use crate::machine::Machine;
+    use crate::memory::Memory;
+    use crate::stack::Stack;
+    use crate::stop::{ExecError, StopReason};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wain_syntax_text::parse;
+
+    const SOURCE: &str = r#"
+(module
+  (import "env" "emit" (func $emit (param i32) (result i32)))
+  (memory 1)
+  (global $last (mut i32) (i32.const 0))
+  (func $step (param $n i32) (result i32)
+    (local $half i32)
+    local.get $n
+    i32.const 2
+    i32.div_u
+    local.set $half
+    i32.const 100
+    block (result i32)
+      local.get $n
+      i32.const 2
+      i32.rem_u
+      if (result i32)
+        local.get $n
+        call $emit
+      else
+        local.get $half
+        call $emit
+      end
+    end
+    i32.add
+    local.get $n
+    global.set $last)
+  (func (export "run") (param $n i32) (result i32)
+    (local $sum i32)
+    block
+      loop
+        local.get $n
+        i32.eqz
+        br_if 1
+        local.get $n
+        call $step
+        local.get $sum
+        i32.add
+        local.set $sum
+        local.get $n
+        i32.const 1
+        i32.sub
+        local.set $n
+        br 0
+      end
+    end
+    local.get $n
+    local.get $sum
+    i32.store
+    local.get $sum))
+"#;
+
+    // Records emitted values and returns doubled values
+    struct Emitter(Rc<RefCell<Vec<i32>>>);
+
+    impl Importer for Emitter {
+        fn validate(
+            &self,
+            name: &str,
+            params: &[ast::ValType],
+            ret: Option<ast::ValType>,
+        ) -> Option<ImportInvalidError> {
+            use ast::ValType::I32;
+            match name {
+                "emit" => check_func_signature(params, ret, &[I32], Some(I32)),
+                _ => Some(ImportInvalidError::NotFound),
+            }
+        }
+
+        fn call(
+            &mut self,
+            _name: &str,
+            stack: &mut Stack,
+            _memory: &mut Memory,
+        ) -> std::result::Result<(), ImportInvokeError> {
+            let v: i32 = stack.pop();
+            self.0.borrow_mut().push(v);
+            stack.push(v * 2);
+            Ok(())
+        }
+    }
+
+    fn is_suspended(err: &ExecError) -> bool {
+        matches!(err.stop(), Some(StopReason::SuspendedAtHostCall { name, .. }) if name == "emit")
+    }
+
+    #[test]
+    fn suspend_and_resume() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let source = SOURCE.as_bytes();
+
+        let expected_log = Rc::new(RefCell::new(vec![]));
+        let mut machine = Machine::instantiate(&root.module, Emitter(expected_log.clone()))
+            .unwrap_or_else(|t| panic!("{}", t));
+        let expected = machine
+            .invoke("run", &[Value::I32(5)])
+            .unwrap_or_else(|t| panic!("{}", t));
+        assert_eq!(expected, Some(Value::I32(524)));
+        assert_eq!(*expected_log.borrow(), [5, 2, 3, 1, 1]);
+        assert!(machine.checkpoint(source).is_none());
+        let expected_state = machine.snapshot(source).encode();
+
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut machine = Machine::instantiate(&root.module, Emitter(log.clone()))
+            .unwrap_or_else(|t| panic!("{}", t));
+        machine.suspend_at_host_call(true);
+        let err = match machine.invoke("run", &[Value::I32(5)]) {
+            Err(err) => err,
+            Ok(_) => panic!("execution was not suspended"),
+        };
+        assert!(is_suspended(&err), "{}", err);
+        assert!(log.borrow().is_empty());
+
+        // Resume each checkpoint on a new instance until the invocation completes
+        let mut checkpoint = machine.checkpoint(source).unwrap();
+        let mut suspended = 1;
+        let actual = loop {
+            assert_eq!(checkpoint.host_func(), 0);
+            assert_eq!(checkpoint.funcs(), [2, 1, 0]);
+            let encoded = checkpoint.encode();
+            let decoded = Checkpoint::decode(&encoded).unwrap_or_else(|e| panic!("{}", e));
+            assert!(decoded.steps == checkpoint.steps);
+
+            let mut machine = Machine::instantiate(&root.module, Emitter(log.clone()))
+                .unwrap_or_else(|t| panic!("{}", t));
+            machine.suspend_at_host_call(true);
+            match machine.resume_from(&decoded, source) {
+                Ok(ret) => {
+                    assert_eq!(machine.snapshot(source).encode(), expected_state);
+                    break ret;
+                }
+                Err(err) => {
+                    assert!(is_suspended(&err), "{}", err);
+                    checkpoint = machine.checkpoint(source).unwrap();
+                    suspended += 1;
+                }
+            }
+        };
+
+        assert_eq!(actual, expected);
+        assert_eq!(*log.borrow(), *expected_log.borrow());
+        assert_eq!(suspended, 5);
+    }
+
+    #[test]
+    fn invalid_checkpoint() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let source = SOURCE.as_bytes();
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut machine = Machine::instantiate(&root.module, Emitter(log.clone()))
+            .unwrap_or_else(|t| panic!("{}", t));
+        machine.suspend_at_host_call(true);
+        assert!(machine.invoke("run", &[Value::I32(2)]).is_err());
+        let checkpoint = machine.checkpoint(source).unwrap();
+
+        let mut broken = checkpoint.clone();
+        broken.steps[1] = Step::Insn(999);
+        let mut wrong_label = checkpoint.clone();
+        wrong_label.steps[2] = Step::Label(
+            Label {
+                addr: 3,
+                type_idx: 1,
+                has_result: false,
+            },
+            false,
+        );
+        let mut wrong_locals = checkpoint.clone();
+        wrong_locals.stack[0] = Value::I64(2);
+
+        for (checkpoint, source, expected) in &[
+            (
+                &checkpoint,
+                &b"(module)"[..],
+                "snapshot was taken from other module",
+            ),
+            (
+                &broken,
+                source,
+                "instruction index 999 is out of instruction sequence",
+            ),
+            (
+                &wrong_label,
+                source,
+                "address 3 with type index 1 does not point to value",
+            ),
+            (
+                &wrong_locals,
+                source,
+                "locals of function 2 do not match to stack",
+            ),
+        ] {
+            let mut machine = Machine::instantiate(&root.module, Emitter(log.clone()))
+                .unwrap_or_else(|t| panic!("{}", t));
+            let msg = match machine.resume_from(checkpoint, source) {
+                Err(err) => {
+                    assert!(matches!(err, ExecError::Checkpoint(_)));
+                    err.to_string()
+                }
+                Ok(_) => panic!("resumed from invalid checkpoint"),
+            };
+            assert!(msg.contains(expected), "{}", msg);
+        }
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn wrong_callee() {
+        const CALLS: &str = r#"
+(module
+  (type $t (func (param i32) (result i32)))
+  (import "env" "emit" (func $a (type $t)))
+  (import "env" "emit" (func $b (type $t)))
+  (table 1 funcref)
+  (elem (i32.const 0) $a)
+  (func $other (param i64) (result i32)
+    i32.const 0)
+  (func (export "direct") (param i32) (result i32)
+    local.get 0
+    call $a)
+  (func (export "indirect") (param i32) (result i32)
+    local.get 0
+    i32.const 0
+    call_indirect (type $t)))
+"#;
+        let log = Rc::new(RefCell::new(vec![]));
+        let suspend = |module: &ast::Module<'_>, source: &[u8], name: &str| {
+            let mut machine = Machine::instantiate(module, Emitter(log.clone()))
+                .unwrap_or_else(|t| panic!("{}", t));
+            machine.suspend_at_host_call(true);
+            assert!(machine.invoke(name, &[Value::I32(1)]).is_err());
+            let encoded = machine.checkpoint(source).unwrap().encode();
+            Checkpoint::decode(&encoded).unwrap_or_else(|e| panic!("{}", e))
+        };
+
+        // Frame of function "run" whose locals have the same types as the called function $step
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let mut other_frame = suspend(&root.module, SOURCE.as_bytes(), "run");
+        for step in other_frame.steps.iter_mut() {
+            match step {
+                Step::Frame { funcidx, .. } if *funcidx == 1 => *funcidx = 2,
+                _ => {}
+            }
+        }
+        let mut machine = Machine::instantiate(&root.module, Emitter(log.clone()))
+            .unwrap_or_else(|t| panic!("{}", t));
+        let msg = match machine.resume_from(&other_frame, SOURCE.as_bytes()) {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("resumed from invalid checkpoint"),
+        };
+        let expected = "function 2 is called where call instruction calls function 1";
+        assert!(msg.contains(expected), "{}", msg);
+
+        let root = parse(CALLS).unwrap_or_else(|e| panic!("{}", e));
+        let source = CALLS.as_bytes();
+
+        // Other host function than the one called by call instruction
+        let mut other_host = suspend(&root.module, source, "direct");
+        assert_eq!(other_host.steps.last(), Some(&Step::Host(0)));
+        *other_host.steps.last_mut().unwrap() = Step::Host(1);
+
+        // Function whose type is not the type of call_indirect instruction
+        let mut other_type = suspend(&root.module, source, "indirect");
+        other_type.steps[2] = Step::Frame {
+            funcidx: 2,
+            base_addr: 4,
+            base_idx: 1,
+        };
+        other_type.steps.push(Step::Host(0));
+
+        for (checkpoint, expected) in &[
+            (
+                other_host,
+                "function 1 is called where call instruction calls function 0",
+            ),
+            (
+                other_type,
+                "function 2 is called where call_indirect instruction expects type 0",
+            ),
+        ] {
+            let mut machine = Machine::instantiate(&root.module, Emitter(log.clone()))
+                .unwrap_or_else(|t| panic!("{}", t));
+            let msg = match machine.resume_from(checkpoint, source) {
+                Err(err) => err.to_string(),
+                Ok(_) => panic!("resumed from invalid checkpoint"),
+            };
+            assert!(msg.contains(expected), "{}", msg);
+        }
+        assert!(log.borrow().is_empty());
+    }
+
+    #[test]
+    fn decode_errors() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let source = SOURCE.as_bytes();
+        let log = Rc::new(RefCell::new(vec![]));
+        let mut machine =
+            Machine::instantiate(&root.module, Emitter(log)).unwrap_or_else(|t| panic!("{}", t));
+        machine.suspend_at_host_call(true);
+        assert!(machine.invoke("run", &[Value::I32(1)]).is_err());
+        let encoded = machine.checkpoint(source).unwrap().encode();
+
+        let mut bad_tag = encoded.clone();
+        let len = bad_tag.len();
+        bad_tag[len - 5] = 9;
+        let mut no_host = encoded.clone();
+        no_host[len - 5] = 1;
+
+        for (bytes, expected) in &[
+            (&b"WAINSNAP"[..], "magic"),
+            (&encoded[..len - 1], "unexpected end"),
+            (&bad_tag, "invalid step 0x9"),
+            (&no_host, "checkpoint does not end with host call"),
+        ] {
+            match Checkpoint::decode(bytes) {
+                Err(e) => assert!(e.to_string().contains(expected), "{}", e),
+                Ok(_) => panic!("decoding invalid checkpoint succeeded"),
+            }
+        }
+    }
+}
@@ -0,0 +1,790 @@
+// Core dump of trapped machine in the format of WebAssembly tool conventions
+// https://github.com/WebAssembly/tool-conventions/blob/main/Coredump.md
+//
+// The core dump is a Wasm module which contains linear memory as data segments, table as element
+// segments and globals with their values at the trap. Call stack is stored in 'corestack' custom
+// section. In addition, function names are stored in 'name' custom section and the trap reason is
+// stored in 'wain:trap' custom section.
+
+use crate::value::Value;
+use std::fmt;
+use std::ops::Range;
+use std::str;
+use wain_ast::ValType;
+
+const PAGE_SIZE: usize = 65536;
+// Zero bytes between non-zero bytes shorter than this are included in the same memory region
+const MIN_ZERO_GAP: usize = 16;
+
+// Frame of call stack in core dump
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct CoreFrame {
+    pub funcidx: u32,
+    pub codeoffset: u32, // Offset of the instruction relative to the start of the function
+    pub locals: Vec<Value>, // Parameters and local variables
+    pub stack: Vec<Value>, // Operand stack. The top of stack comes last
+}
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct CoreGlobal {
+    pub value: Value,
+    pub mutable: bool,
+}
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct CoreDump {
+    pub executable: String,
+    pub module: String,
+    pub reason: String,
+    pub offset: usize, // Byte offset in the module source where the trap occurred
+    pub frames: Vec<CoreFrame>, // The innermost frame comes first
+    pub globals: Vec<CoreGlobal>,
+    pub memory: Option<Vec<u8>>,
+    pub table: Option<Vec<Option<u32>>>, // Function indices of table elements
+    pub func_names: Vec<(u32, String)>,
+}
+
+#[cfg_attr(test, derive(Debug))]
+pub struct CoreDumpError {
+    pub msg: String,
+    pub offset: usize,
+}
+
+impl fmt::Display for CoreDumpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "broken core dump: {} at byte offset 0x{:x}",
+            self.msg, self.offset
+        )
+    }
+}
+
+type Result<T> = ::std::result::Result<T, Box<CoreDumpError>>;
+
+pub(crate) fn valtype_byte(ty: ValType) -> u8 {
+    match ty {
+        ValType::I32 => 0x7f,
+        ValType::I64 => 0x7e,
+        ValType::F32 => 0x7d,
+        ValType::F64 => 0x7c,
+    }
+}
+
+struct Encoder(Vec<u8>);
+
+impl Encoder {
+    fn u32(&mut self, mut v: u32) {
+        loop {
+            let b = (v & 0x7f) as u8;
+            v >>= 7;
+            if v == 0 {
+                self.0.push(b);
+                return;
+            }
+            self.0.push(b | 0x80);
+        }
+    }
+
+    fn i64(&mut self, mut v: i64) {
+        loop {
+            let b = (v & 0x7f) as u8;
+            v >>= 7;
+            if (v == 0 && b & 0x40 == 0) || (v == -1 && b & 0x40 != 0) {
+                self.0.push(b);
+                return;
+            }
+            self.0.push(b | 0x80);
+        }
+    }
+
+    fn name(&mut self, s: &str) {
+        self.u32(s.len() as u32);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    // Value in 'corestack' section
+    fn value(&mut self, v: &Value) {
+        self.0.push(valtype_byte(v.valtype()));
+        self.value_payload(v);
+    }
+
+    fn value_payload(&mut self, v: &Value) {
+        match v {
+            Value::I32(i) => self.i64(*i as i64),
+            Value::I64(i) => self.i64(*i),
+            Value::F32(f) => self.0.extend_from_slice(&f.to_le_bytes()),
+            Value::F64(f) => self.0.extend_from_slice(&f.to_le_bytes()),
+        }
+    }
+
+    fn values(&mut self, vs: &[Value]) {
+        self.u32(vs.len() as u32);
+        for v in vs {
+            self.value(v);
+        }
+    }
+
+    // i32.const offset end
+    fn offset_expr(&mut self, offset: usize) {
+        self.0.push(0x41);
+        self.i64(offset as i32 as i64);
+        self.0.push(0x0b);
+    }
+
+    fn section<F: FnOnce(&mut Encoder)>(&mut self, id: u8, f: F) {
+        let mut payload = Encoder(vec![]);
+        f(&mut payload);
+        self.0.push(id);
+        self.u32(payload.0.len() as u32);
+        self.0.extend_from_slice(&payload.0);
+    }
+
+    fn custom<F: FnOnce(&mut Encoder)>(&mut self, name: &str, f: F) {
+        self.section(0, |e| {
+            e.name(name);
+            f(e);
+        });
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    base: usize, // Offset of the bytes in the whole core dump
+}
+
+impl<'a> Decoder<'a> {
+    fn error<T>(&self, msg: impl Into<String>) -> Result<T> {
+        Err(Box::new(CoreDumpError {
+            msg: msg.into(),
+            offset: self.base + self.pos,
+        }))
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < len {
+            return self.error("unexpected end of input");
+        }
+        let b = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(b)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn expect(&mut self, expected: u8, what: &str) -> Result<()> {
+        let b = self.byte()?;
+        if b != expected {
+            self.pos -= 1;
+            return self.error(format!(
+                "expected {} 0x{:02x} but got 0x{:02x}",
+                what, expected, b
+            ));
+        }
+        Ok(())
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let mut v = 0u64;
+        for shift in (0..35).step_by(7) {
+            let b = self.byte()?;
+            v |= ((b & 0x7f) as u64) << shift;
+            if b & 0x80 == 0 {
+                return if v > u32::MAX as u64 {
+                    self.error("integer too large")
+                } else {
+                    Ok(v as u32)
+                };
+            }
+        }
+        self.error("integer representation too long")
+    }
+
+    fn i64(&mut self) -> Result<i64> {
+        let mut v = 0i64;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift < 64 {
+                v |= ((b & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                if shift < 64 && b & 0x40 != 0 {
+                    v |= -1 << shift;
+                }
+                return Ok(v);
+            }
+            if shift >= 70 {
+                return self.error("integer representation too long");
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        let start = self.pos;
+        let bytes = self.bytes(len)?;
+        match str::from_utf8(bytes) {
+            Ok(s) => Ok(s.to_string()),
+            Err(e) => {
+                self.pos = start;
+                self.error(format!("invalid UTF-8 name: {}", e))
+            }
+        }
+    }
+
+    fn value_payload(&mut self, ty: u8) -> Result<Value> {
+        Ok(match ty {
+            0x7f => Value::I32(self.i64()? as i32),
+            0x7e => Value::I64(self.i64()?),
+            0x7d => {
+                let mut b = [0; 4];
+                b.copy_from_slice(self.bytes(4)?);
+                Value::F32(f32::from_le_bytes(b))
+            }
+            0x7c => {
+                let mut b = [0; 8];
+                b.copy_from_slice(self.bytes(8)?);
+                Value::F64(f64::from_le_bytes(b))
+            }
+            0x01 => return self.error("optimized out value is not supported"),
+            b => return self.error(format!("unknown value type 0x{:02x}", b)),
+        })
+    }
+
+    fn value(&mut self) -> Result<Value> {
+        let ty = self.byte()?;
+        self.value_payload(ty)
+    }
+
+    fn vec<T, F>(&mut self, mut f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&mut Self) -> Result<T>,
+    {
+        let len = self.u32()?;
+        let mut v = Vec::with_capacity((len as usize).min(self.bytes.len()));
+        for _ in 0..len {
+            v.push(f(self)?);
+        }
+        Ok(v)
+    }
+
+    fn limits(&mut self) -> Result<u32> {
+        match self.byte()? {
+            0x00 => self.u32(),
+            0x01 => {
+                let min = self.u32()?;
+                self.u32()?;
+                Ok(min)
+            }
+            b => self.error(format!("invalid limits 0x{:02x}", b)),
+        }
+    }
+
+    fn offset_expr(&mut self) -> Result<usize> {
+        self.expect(0x41, "i32.const opcode")?;
+        let offset = self.i64()? as u32 as usize;
+        self.expect(0x0b, "end opcode")?;
+        Ok(offset)
+    }
+
+    fn section(&mut self) -> Result<(u8, Decoder<'a>)> {
+        let id = self.byte()?;
+        let len = self.u32()? as usize;
+        let base = self.base + self.pos;
+        let bytes = self.bytes(len)?;
+        Ok((
+            id,
+            Decoder {
+                bytes,
+                pos: 0,
+                base,
+            },
+        ))
+    }
+}
+
+// Non-zero regions of memory. Short runs of zeros are included in regions
+pub(crate) fn nonzero_regions(memory: &[u8]) -> Vec<Range<usize>> {
+    let mut regions: Vec<Range<usize>> = vec![];
+    for (i, b) in memory.iter().enumerate() {
+        if *b == 0 {
+            continue;
+        }
+        match regions.last_mut() {
+            Some(r) if i - r.end < MIN_ZERO_GAP => r.end = i + 1,
+            _ => regions.push(i..i + 1),
+        }
+    }
+    regions
+}
+
+impl CoreDump {
+    // Non-zero regions of memory. Short runs of zeros are included in regions
+    pub fn memory_regions(&self) -> Vec<Range<usize>> {
+        match &self.memory {
+            Some(m) => nonzero_regions(m),
+            None => vec![],
+        }
+    }
+
+    pub fn func_name(&self, funcidx: u32) -> Option<&str> {
+        let i = self
+            .func_names
+            .binary_search_by_key(&funcidx, |(i, _)| *i)
+            .ok()?;
+        Some(&self.func_names[i].1)
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut e = Encoder(b"\0asm\x01\0\0\0".to_vec());
+
+        e.custom("core", |e| {
+            e.0.push(0x00);
+            e.name(&self.executable);
+        });
+        e.custom("coremodules", |e| {
+            e.u32(1);
+            e.0.push(0x00);
+            e.name(&self.module);
+        });
+        e.custom("coreinstances", |e| {
+            e.u32(1);
+            e.0.push(0x00);
+            e.u32(0); // Module index
+            if self.memory.is_some() {
+                e.u32(1);
+                e.u32(0);
+            } else {
+                e.u32(0);
+            }
+            e.u32(self.globals.len() as u32);
+            for i in 0..self.globals.len() {
+                e.u32(i as u32);
+            }
+        });
+        e.custom("corestack", |e| {
+            e.0.push(0x00);
+            e.name("main");
+            e.u32(self.frames.len() as u32);
+            for frame in self.frames.iter() {
+                e.0.push(0x00);
+                e.u32(0); // Instance index
+                e.u32(frame.funcidx);
+                e.u32(frame.codeoffset);
+                e.values(&frame.locals);
+                e.values(&frame.stack);
+            }
+        });
+        e.custom("wain:trap", |e| {
+            e.name(&self.reason);
+            e.u32(self.offset as u32);
+        });
+
+        // Table section
+        if let Some(table) = &self.table {
+            e.section(4, |e| {
+                e.u32(1);
+                e.0.push(0x70); // funcref
+                e.0.push(0x00);
+                e.u32(table.len() as u32);
+            });
+        }
+        // Memory section
+        if let Some(memory) = &self.memory {
+            e.section(5, |e| {
+                e.u32(1);
+                e.0.push(0x00);
+                e.u32((memory.len() / PAGE_SIZE) as u32);
+            });
+        }
+        // Global section
+        if !self.globals.is_empty() {
+            e.section(6, |e| {
+                e.u32(self.globals.len() as u32);
+                for g in self.globals.iter() {
+                    e.0.push(valtype_byte(g.value.valtype()));
+                    e.0.push(g.mutable as u8);
+                    e.0.push(match g.value {
+                        Value::I32(_) => 0x41,
+                        Value::I64(_) => 0x42,
+                        Value::F32(_) => 0x43,
+                        Value::F64(_) => 0x44,
+                    });
+                    e.value_payload(&g.value);
+                    e.0.push(0x0b);
+                }
+            });
+        }
+        // Element section. Each run of initialized elements is one segment
+        if let Some(table) = &self.table {
+            let mut segments: Vec<(usize, Vec<u32>)> = vec![];
+            for (i, elem) in table.iter().enumerate() {
+                match (elem, segments.last_mut()) {
+                    (Some(f), Some((start, funcs))) if *start + funcs.len() == i => funcs.push(*f),
+                    (Some(f), _) => segments.push((i, vec![*f])),
+                    (None, _) => {}
+                }
+            }
+            if !segments.is_empty() {
+                e.section(9, |e| {
+                    e.u32(segments.len() as u32);
+                    for (start, funcs) in segments.iter() {
+                        e.u32(0);
+                        e.offset_expr(*start);
+                        e.u32(funcs.len() as u32);
+                        for f in funcs {
+                            e.u32(*f);
+                        }
+                    }
+                });
+            }
+        }
+        // Data section. Each non-zero region is one segment
+        if let Some(memory) = &self.memory {
+            let regions = self.memory_regions();
+            if !regions.is_empty() {
+                e.section(11, |e| {
+                    e.u32(regions.len() as u32);
+                    for r in regions {
+                        e.u32(0);
+                        e.offset_expr(r.start);
+                        e.u32(r.len() as u32);
+                        e.0.extend_from_slice(&memory[r]);
+                    }
+                });
+            }
+        }
+
+        e.custom("name", |e| {
+            e.0.push(1); // Function names subsection
+            let mut sub = Encoder(vec![]);
+            sub.u32(self.func_names.len() as u32);
+            for (idx, name) in self.func_names.iter() {
+                sub.u32(*idx);
+                sub.name(name);
+            }
+            e.u32(sub.0.len() as u32);
+            e.0.extend_from_slice(&sub.0);
+        });
+
+        e.0
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<CoreDump> {
+        let mut d = Decoder {
+            bytes,
+            pos: 0,
+            base: 0,
+        };
+        if d.bytes(4).ok() != Some(b"\0asm") {
+            d.pos = 0;
+            return d.error("core dump must start with Wasm magic");
+        }
+        if d.bytes(4)? != b"\x01\0\0\0" {
+            d.pos = 4;
+            return d.error("unsupported Wasm version");
+        }
+
+        let mut dump = CoreDump {
+            executable: String::new(),
+            module: String::new(),
+            reason: String::new(),
+            offset: 0,
+            frames: vec![],
+            globals: vec![],
+            memory: None,
+            table: None,
+            func_names: vec![],
+        };
+        let mut has_process_info = false;
+
+        while !d.eof() {
+            let (id, mut s) = d.section()?;
+            match id {
+                0 => match s.name()?.as_str() {
+                    "core" => {
+                        s.expect(0x00, "process-info")?;
+                        dump.executable = s.name()?;
+                        has_process_info = true;
+                    }
+                    "coremodules" => {
+                        let mut modules = s.vec(|s| {
+                            s.expect(0x00, "coremodule")?;
+                            s.name()
+                        })?;
+                        if !modules.is_empty() {
+                            dump.module = modules.swap_remove(0);
+                        }
+                    }
+                    "corestack" => {
+                        s.expect(0x00, "thread-info")?;
+                        s.name()?;
+                        dump.frames = s.vec(|s| {
+                            s.expect(0x00, "frame")?;
+                            s.u32()?; // Instance index
+                            Ok(CoreFrame {
+                                funcidx: s.u32()?,
+                                codeoffset: s.u32()?,
+                                locals: s.vec(Decoder::value)?,
+                                stack: s.vec(Decoder::value)?,
+                            })
+                        })?;
+                    }
+                    "wain:trap" => {
+                        dump.reason = s.name()?;
+                        dump.offset = s.u32()? as usize;
+                    }
+                    "name" => {
+                        while !s.eof() {
+                            let (id, mut sub) = s.section()?;
+                            if id == 1 {
+                                dump.func_names = sub.vec(|s| Ok((s.u32()?, s.name()?)))?;
+                            }
+                        }
+                    }
+                    _ => {} // 'coreinstances' is not needed since only one instance exists
+                },
+                4 => {
+                    let mut tables = s.vec(|s| {
+                        s.expect(0x70, "funcref")?;
+                        s.limits()
+                    })?;
+                    if let Some(len) = tables.pop() {
+                        dump.table = Some(vec![None; len as usize]);
+                    }
+                }
+                5 => {
+                    let mut memories = s.vec(Decoder::limits)?;
+                    if let Some(pages) = memories.pop() {
+                        dump.memory = Some(vec![0; pages as usize * PAGE_SIZE]);
+                    }
+                }
+                6 => {
+                    dump.globals = s.vec(|s| {
+                        let ty = s.byte()?;
+                        let mutable = s.byte()? != 0;
+                        let opcode = s.byte()?;
+                        let expected = match ty {
+                            0x7f => 0x41,
+                            0x7e => 0x42,
+                            0x7d => 0x43,
+                            _ => 0x44,
+                        };
+                        if opcode != expected {
+                            s.pos -= 1;
+                            return s.error("global must be initialized with constant");
+                        }
+                        let value = s.value_payload(ty)?;
+                        s.expect(0x0b, "end opcode")?;
+                        Ok(CoreGlobal { value, mutable })
+                    })?;
+                }
+                9 => {
+                    let segments = s.vec(|s| {
+                        s.expect(0x00, "active element segment")?;
+                        let offset = s.offset_expr()?;
+                        let funcs = s.vec(Decoder::u32)?;
+                        Ok((offset, funcs))
+                    })?;
+                    let table = match &mut dump.table {
+                        Some(t) => t,
+                        None => return s.error("element section without table"),
+                    };
+                    for (offset, funcs) in segments {
+                        for (i, f) in funcs.into_iter().enumerate() {
+                            match table.get_mut(offset + i) {
+                                Some(elem) => *elem = Some(f),
+                                None => return s.error("element segment out of table"),
+                            }
+                        }
+                    }
+                }
+                11 => {
+                    let segments = s.vec(|s| {
+                        s.expect(0x00, "active data segment")?;
+                        let offset = s.offset_expr()?;
+                        let len = s.u32()? as usize;
+                        Ok((offset, s.bytes(len)?))
+                    })?;
+                    let memory = match &mut dump.memory {
+                        Some(m) => m,
+                        None => return s.error("data section without memory"),
+                    };
+                    for (offset, data) in segments {
+                        match memory.get_mut(offset..offset + data.len()) {
+                            Some(m) => m.copy_from_slice(data),
+                            None => return s.error("data segment out of memory"),
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !has_process_info {
+            return d.error("'core' custom section is missing");
+        }
+        Ok(dump)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::DefaultImporter;
+    use crate::machine::Machine;
+    use crate::stop::ExecError;
+    use crate::trap::TrapReason;
+    use std::io;
+    use wain_syntax_text::parse_with_id_names as parse;
+
+    const SOURCE: &str = r#"
+(module
+  (memory 1)
+  (table 4 funcref)
+  (global $g (mut i32) (i32.const 0))
+  (global f64 (f64.const 1.5))
+  (elem (i32.const 1) $div $main)
+  (func $div (param $a i32) (param $b i32) (result i32)
+    (local $tmp i64)
+    i64.const -7
+    local.set $tmp
+    local.get $a
+    local.get $b
+    i32.div_u)
+  (func $main (export "main")
+    i32.const 100
+    i32.const 0xdeadbeef
+    i32.store
+    i32.const 42
+    global.set $g
+    i32.const 3
+    f32.const 2.5
+    drop
+    i32.const 10
+    i32.const 0
+    call $div
+    drop
+    drop))
+"#;
+
+    fn trap(enabled: bool) -> Option<Box<CoreDump>> {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine = Machine::instantiate(&root.module, importer).unwrap();
+        machine.set_coredump(enabled);
+        let trap = match machine.invoke("main", &[]) {
+            Err(ExecError::Trap(trap)) => trap,
+            r => panic!("unexpected result: {:?}", r),
+        };
+        match trap.reason {
+            TrapReason::DivByZeroOrOverflow => {}
+            r => panic!("unexpected trap: {:?}", r),
+        }
+        trap.coredump
+    }
+
+    #[test]
+    fn capture_on_trap() {
+        assert!(trap(false).is_none());
+        let dump = trap(true).unwrap();
+
+        assert_eq!(
+            dump.reason,
+            "integer overflow or attempt to devide integer by zero"
+        );
+        let frames: Vec<_> = dump
+            .frames
+            .iter()
+            .map(|f| (f.funcidx, f.locals.clone(), f.stack.clone()))
+            .collect();
+        assert_eq!(
+            frames,
+            [
+                // Operands of i32.div_u were already popped
+                (
+                    0,
+                    vec![Value::I32(10), Value::I32(0), Value::I64(-7)],
+                    vec![Value::I32(10)],
+                ),
+                (1, vec![], vec![Value::I32(3)]),
+            ],
+        );
+        assert!(dump.frames[0].codeoffset > 0);
+        assert_eq!(
+            dump.globals,
+            [
+                CoreGlobal {
+                    value: Value::I32(42),
+                    mutable: true,
+                },
+                CoreGlobal {
+                    value: Value::F64(1.5),
+                    mutable: false,
+                },
+            ],
+        );
+        let memory = dump.memory.as_ref().unwrap();
+        assert_eq!(memory.len(), PAGE_SIZE);
+        assert_eq!(&memory[100..104], &[0xef, 0xbe, 0xad, 0xde]);
+        assert_eq!(dump.memory_regions().first(), Some(&(100..104)));
+        assert_eq!(dump.table, Some(vec![None, Some(0), Some(1), None]));
+        assert_eq!(dump.func_name(0), Some("div"));
+        assert_eq!(dump.func_name(1), Some("main"));
+        assert_eq!(dump.func_name(2), None);
+    }
+
+    #[test]
+    fn encode_and_decode() {
+        let dump = trap(true).unwrap();
+        let bytes = dump.encode();
+        assert!(bytes.starts_with(b"\0asm\x01\0\0\0"));
+        let decoded = CoreDump::decode(&bytes).unwrap();
+        assert_eq!(decoded, *dump);
+    }
+
+    #[test]
+    fn memory_regions() {
+        let mut memory = vec![0; 100];
+        memory[1] = 1;
+        memory[10] = 1; // Merged with previous region
+        memory[50] = 1;
+        memory[51] = 1;
+        memory[99] = 1;
+        let dump = CoreDump {
+            executable: String::new(),
+            module: String::new(),
+            reason: String::new(),
+            offset: 0,
+            frames: vec![],
+            globals: vec![],
+            memory: Some(memory),
+            table: None,
+            func_names: vec![],
+        };
+        assert_eq!(dump.memory_regions(), [1..11, 50..52, 99..100]);
+    }
+
+    #[test]
+    fn decode_errors() {
+        let err = CoreDump::decode(b"\0asx\x01\0\0\0").unwrap_err();
+        assert_eq!(err.msg, "core dump must start with Wasm magic");
+        let err = CoreDump::decode(b"\0asm\x01\0\0\0").unwrap_err();
+        assert_eq!(err.msg, "'core' custom section is missing");
+        let err = CoreDump::decode(b"\0asm\x01\0\0\0\x05\x03\x01\x00").unwrap_err();
+        assert_eq!(err.msg, "unexpected end of input");
+        assert_eq!(err.offset, 10);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::profile::func_name;
+use crate::trace::Tracer;
+use crate::value::Value;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use wain_ast as ast;
+
+// Hit count of one instruction
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct InsnCoverage {
+    pub offset: usize,
+    pub name: &'static str,
+    pub hits: u64,
+}
+
+// Hit counts of each direction of branch instruction. Directions are [then, else] for 'if',
+// [taken, not taken] for 'br_if', and labels followed by default label for 'br_table'
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct BranchCoverage {
+    pub offset: usize,
+    pub hits: Vec<u64>,
+}
+
+// Coverage of function which has body. Instructions and branches are sorted by their offsets
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct FuncCoverage {
+    pub funcidx: u32,
+    pub name: String,
+    pub start: usize,
+    pub calls: u64,
+    pub insns: Vec<InsnCoverage>,
+    pub branches: Vec<BranchCoverage>,
+}
+
+impl FuncCoverage {
+    fn new(module: &ast::Module<'_>, funcidx: u32, body: &[ast::Instruction]) -> Self {
+        let mut func = FuncCoverage {
+            funcidx,
+            name: func_name(module, funcidx),
+            start: module.funcs[funcidx as usize].start,
+            calls: 0,
+            insns: vec![],
+            branches: vec![],
+        };
+        func.collect(body);
+        func.insns.sort_by_key(|i| i.offset);
+        func.branches.sort_by_key(|b| b.offset);
+        func
+    }
+
+    // Nested instructions may appear before their parent in folded text format. They are sorted
+    // after collecting
+    fn collect(&mut self, insns: &[ast::Instruction]) {
+        use ast::InsnKind::*;
+        for insn in insns {
+            self.insns.push(InsnCoverage {
+                offset: insn.start,
+                name: insn.kind.name(),
+                hits: 0,
+            });
+            let directions = match &insn.kind {
+                Block { body, .. } | Loop { body, .. } => {
+                    self.collect(body);
+                    continue;
+                }
+                If {
+                    then_body,
+                    else_body,
+                    ..
+                } => {
+                    self.collect(then_body);
+                    self.collect(else_body);
+                    2
+                }
+                BrIf(_) => 2,
+                BrTable { labels, .. } => labels.len() + 1,
+                _ => continue,
+            };
+            self.branches.push(BranchCoverage {
+                offset: insn.start,
+                hits: vec![0; directions],
+            });
+        }
+    }
+}
+
+// Coverage collector which counts executed instructions and taken branch directions as Tracer.
+// Share it with Arc<Mutex<_>> to get the result after execution
+pub struct Coverage {
+    funcs: Vec<FuncCoverage>,
+    func_index: HashMap<u32, usize>,
+    insn_index: HashMap<usize, (usize, usize)>, // Offset to indices of function and instruction
+    branch_index: HashMap<usize, (usize, usize)>, // Offset to indices of function and branch
+}
+
+// Line numbers of byte offsets in text
+struct Lines(Vec<usize>); // Offsets of line starts
+
+impl Lines {
+    fn new(text: &str) -> Self {
+        let starts = std::iter::once(0)
+            .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Lines(starts)
+    }
+
+    fn line(&self, offset: usize) -> usize {
+        match self.0.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+}
+
+impl Coverage {
+    pub fn new(module: &ast::Module<'_>) -> Self {
+        let mut funcs = vec![];
+        for (idx, func) in module.funcs.iter().enumerate() {
+            if let ast::FuncKind::Body { expr, .. } = &func.kind {
+                funcs.push(FuncCoverage::new(module, idx as u32, expr));
+            }
+        }
+
+        let mut func_index = HashMap::new();
+        let mut insn_index = HashMap::new();
+        let mut branch_index = HashMap::new();
+        for (i, func) in funcs.iter().enumerate() {
+            func_index.insert(func.funcidx, i);
+            for (j, insn) in func.insns.iter().enumerate() {
+                insn_index.insert(insn.offset, (i, j));
+            }
+            for (j, branch) in func.branches.iter().enumerate() {
+                branch_index.insert(branch.offset, (i, j));
+            }
+        }
+
+        Self {
+            funcs,
+            func_index,
+            insn_index,
+            branch_index,
+        }
+    }
+
+    pub fn funcs(&self) -> &[FuncCoverage] {
+        &self.funcs
+    }
+
+    // Returns (executed, all) numbers of instructions
+    pub fn insns_hit(&self) -> (usize, usize) {
+        let insns = self.funcs.iter().flat_map(|f| f.insns.iter());
+        insns.fold((0, 0), |(hit, all), i| {
+            (hit + (i.hits > 0) as usize, all + 1)
+        })
+    }
+
+    // Returns (taken, all) numbers of branch directions
+    pub fn branches_hit(&self) -> (usize, usize) {
+        let hits = self
+            .funcs
+            .iter()
+            .flat_map(|f| f.branches.iter())
+            .flat_map(|b| b.hits.iter());
+        hits.fold((0, 0), |(hit, all), h| (hit + (*h > 0) as usize, all + 1))
+    }
+
+    // LCOV tracefile for text format source. `path` is the source file path written in 'SF:' line
+    // http://ltp.sourceforge.net/coverage/lcov/geninfo.1.php
+    pub fn write_lcov<W: Write>(&self, mut w: W, path: &str, source: &str) -> io::Result<()> {
+        let lines = Lines::new(source);
+        writeln!(w, "TN:")?;
+        writeln!(w, "SF:{}", path)?;
+
+        for f in self.funcs.iter() {
+            writeln!(w, "FN:{},{}", lines.line(f.start), f.name)?;
+        }
+        for f in self.funcs.iter() {
+            writeln!(w, "FNDA:{},{}", f.calls, f.name)?;
+        }
+        let called = self.funcs.iter().filter(|f| f.calls > 0).count();
+        writeln!(w, "FNF:{}", self.funcs.len())?;
+        writeln!(w, "FNH:{}", called)?;
+
+        let mut block = 0;
+        for f in self.funcs.iter() {
+            for b in f.branches.iter() {
+                let line = lines.line(b.offset);
+                let executed = self.insn_index[&b.offset];
+                let executed = self.funcs[executed.0].insns[executed.1].hits > 0;
+                for (i, hits) in b.hits.iter().enumerate() {
+                    if executed {
+                        writeln!(w, "BRDA:{},{},{},{}", line, block, i, hits)?;
+                    } else {
+                        writeln!(w, "BRDA:{},{},{},-", line, block, i)?;
+                    }
+                }
+                block += 1;
+            }
+        }
+        let (taken, branches) = self.branches_hit();
+        writeln!(w, "BRF:{}", branches)?;
+        writeln!(w, "BRH:{}", taken)?;
+
+        // Execution count of line is the max count of instructions in the line
+        let mut counts: Vec<(usize, u64)> = vec![];
+        for f in self.funcs.iter() {
+            for i in f.insns.iter() {
+                counts.push((lines.line(i.offset), i.hits));
+            }
+        }
+        counts.sort_by_key(|(line, _)| *line);
+        counts.dedup_by(|(l1, h1), (l2, h2)| {
+            if l1 != l2 {
+                return false;
+            }
+            *h2 = (*h1).max(*h2);
+            true
+        });
+        for (line, hits) in counts.iter() {
+            writeln!(w, "DA:{},{}", line, hits)?;
+        }
+        writeln!(w, "LF:{}", counts.len())?;
+        writeln!(w, "LH:{}", counts.iter().filter(|(_, h)| *h > 0).count())?;
+        writeln!(w, "end_of_record")?;
+        w.flush()
+    }
+
+    // Hit counts per function and instruction offset. This is useful for binary format
+    pub fn write_report<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for f in self.funcs.iter() {
+            let hit = f.insns.iter().filter(|i| i.hits > 0).count();
+            let directions = f.branches.iter().flat_map(|b| b.hits.iter());
+            let (taken, branches) =
+                directions.fold((0, 0), |(hit, all), h| (hit + (*h > 0) as usize, all + 1));
+            writeln!(
+                w,
+                "function {} '{}': {} calls, {}/{} instructions, {}/{} branches",
+                f.funcidx,
+                f.name,
+                f.calls,
+                hit,
+                f.insns.len(),
+                taken,
+                branches,
+            )?;
+
+            let mut branches = f.branches.iter().peekable();
+            for i in f.insns.iter() {
+                write!(w, "  0x{:x}: {:<20} {}", i.offset, i.name, i.hits)?;
+                if let Some(b) = branches.peek() {
+                    if b.offset == i.offset {
+                        let hits: Vec<_> = b.hits.iter().map(|h| h.to_string()).collect();
+                        write!(w, " [{}]", hits.join(", "))?;
+                        branches.next();
+                    }
+                }
+                writeln!(w)?;
+            }
+        }
+        w.flush()
+    }
+}
+
+impl Tracer for Coverage {
+    fn enter(&mut self, funcidx: u32, _args: &[Value]) {
+        if let Some(i) = self.func_index.get(&funcidx) {
+            self.funcs[*i].calls += 1;
+        }
+    }
+
+    // The condition of branch is on top of the operand stack
+    fn instruction(&mut self, _: u32, insn: &ast::InsnKind, offset: usize, operands: &[Value]) {
+        if let Some((f, i)) = self.insn_index.get(&offset) {
+            self.funcs[*f].insns[*i].hits += 1;
+        }
+        let (f, b) = match self.branch_index.get(&offset) {
+            Some(idx) => *idx,
+            None => return,
+        };
+        let cond = match operands.last() {
+            Some(Value::I32(i)) => *i,
+            _ => return,
+        };
+        let direction = match insn {
+            ast::InsnKind::If { .. } | ast::InsnKind::BrIf(_) => (cond == 0) as usize,
+            ast::InsnKind::BrTable { labels, .. } => (cond as u32 as usize).min(labels.len()),
+            _ => return,
+        };
+        self.funcs[f].branches[b].hits[direction] += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::DefaultImporter;
+    use crate::machine::Machine;
+    use std::sync::{Arc, Mutex};
+    use wain_syntax_text::parse_with_id_names as parse;
+
+    const SOURCE: &str = r#"(module
+  (func $sign (export "sign") (param i32) (result i32)
+    local.get 0
+    i32.eqz
+    if (result i32)
+      i32.const 0
+    else
+      i32.const 1
+    end)
+  (func $pick (export "pick") (param i32) (result i32)
+    block
+      block
+        local.get 0
+        br_table 0 1
+      end
+      i32.const 10
+      return
+    end
+    local.get 0
+    i32.const 5
+    i32.gt_s
+    br_if 0
+    i32.const 20)
+  (func $unused
+    nop))
+"#;
+
+    fn coverage(invocations: &[(&str, i32)]) -> Coverage {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine = Machine::instantiate(&root.module, importer).unwrap();
+        let coverage = Arc::new(Mutex::new(Coverage::new(&root.module)));
+        machine.set_tracer(coverage.clone());
+        for (name, arg) in invocations {
+            machine.invoke(name, &[Value::I32(*arg)]).unwrap();
+        }
+        drop(machine);
+        match Arc::try_unwrap(coverage) {
+            Ok(coverage) => coverage.into_inner().unwrap(),
+            Err(_) => panic!("coverage is still shared"),
+        }
+    }
+
+    #[test]
+    fn count_hits() {
+        let coverage = coverage(&[("sign", 0), ("sign", 3), ("sign", 4), ("pick", 1)]);
+        let funcs = coverage.funcs();
+        assert_eq!(funcs.len(), 3);
+
+        let sign = &funcs[0];
+        assert_eq!(sign.name, "sign");
+        assert_eq!(sign.calls, 3);
+        let hits: Vec<_> = sign.insns.iter().map(|i| (i.name, i.hits)).collect();
+        assert_eq!(
+            hits,
+            [
+                ("local.get", 3),
+                ("i32.eqz", 3),
+                ("if", 3),
+                ("i32.const", 1),
+                ("i32.const", 2),
+            ],
+        );
+        // i32.eqz results in 1 for 0
+        assert_eq!(sign.branches.len(), 1);
+        assert_eq!(sign.branches[0].hits, [1, 2]);
+
+        let pick = &funcs[1];
+        assert_eq!(pick.calls, 1);
+        let hits: Vec<_> = pick.branches.iter().map(|b| b.hits.clone()).collect();
+        assert_eq!(hits, [vec![0, 1], vec![0, 1]]);
+
+        assert_eq!(funcs[2].calls, 0);
+        assert_eq!(coverage.insns_hit(), (14, 17));
+        assert_eq!(coverage.branches_hit(), (4, 6));
+    }
+
+    #[test]
+    fn branch_directions() {
+        // br_table with out of range index takes default label
+        let coverage = coverage(&[("pick", 0), ("pick", 7), ("pick", 9), ("pick", -1)]);
+        let hits: Vec<_> = coverage.funcs()[1]
+            .branches
+            .iter()
+            .map(|b| b.hits.clone())
+            .collect();
+        assert_eq!(hits, [vec![1, 3], vec![2, 1]]);
+    }
+
+    #[test]
+    fn lcov() {
+        let coverage = coverage(&[("sign", 1)]);
+        let mut out = vec![];
+        coverage.write_lcov(&mut out, "test.wat", SOURCE).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "TN:",
+                "SF:test.wat",
+                "FN:2,sign",
+                "FN:10,pick",
+                "FN:24,unused",
+                "FNDA:1,sign",
+                "FNDA:0,pick",
+                "FNDA:0,unused",
+                "FNF:3",
+                "FNH:1",
+                "BRDA:5,0,0,0",
+                "BRDA:5,0,1,1",
+                "BRDA:14,1,0,-",
+                "BRDA:14,1,1,-",
+                "BRDA:22,2,0,-",
+                "BRDA:22,2,1,-",
+                "BRF:6",
+                "BRH:1",
+                "DA:3,1",
+                "DA:4,1",
+                "DA:5,1",
+                "DA:6,0",
+                "DA:8,1",
+                "DA:11,0",
+                "DA:12,0",
+                "DA:13,0",
+                "DA:14,0",
+                "DA:16,0",
+                "DA:17,0",
+                "DA:19,0",
+                "DA:20,0",
+                "DA:21,0",
+                "DA:22,0",
+                "DA:23,0",
+                "DA:25,0",
+                "LF:17",
+                "LH:4",
+                "end_of_record",
+            ],
+        );
+    }
+
+    #[test]
+    fn report() {
+        let coverage = coverage(&[("sign", 0)]);
+        let mut out = vec![];
+        coverage.write_report(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            "function 0 'sign': 1 calls, 4/5 instructions, 1/2 branches"
+        );
+        assert!(
+            lines[3].ends_with(" if                   1 [1, 0]"),
+            "{}",
+            out
+        );
+        assert!(out.contains("\nfunction 2 'unused': 0 calls, 0/1 instructions, 0/0 branches\n"));
+    }
+}
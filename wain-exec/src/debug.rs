@@ -0,0 +1,450 @@
+use crate::globals::Globals;
+use crate::memory::Memory;
+use crate::stack::{CallFrame, Stack};
+use crate::stop::{Stop, StopReason};
+use crate::trap::Frame;
+use crate::value::Value;
+use wain_ast as ast;
+
+// Where the machine pauses execution
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Breakpoint {
+    Func(u32),     // Before executing the first instruction of the function
+    Offset(usize), // Before executing the instruction at the byte offset in source
+}
+
+// How the machine resumes execution after pause
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Resume {
+    Continue,  // Run until the next breakpoint
+    StepIn,    // Pause at the next instruction
+    StepOver,  // Pause at the next instruction in the same function or its callers
+    StepOut,   // Pause at the next instruction in the callers
+    Terminate, // Stop execution with StopReason::TerminatedByDebugger
+}
+
+// Frontend of debugger such as interactive command line interface. It is called when the machine
+// pauses execution and decides how to resume the execution. It must be Send since the machine owns
+// it and the machine can be moved to other thread.
+pub trait Debugger {
+    fn pause(&mut self, ctx: &mut DebugContext<'_, '_, '_>) -> Resume;
+}
+
+impl<F> Debugger for F
+where
+    F: FnMut(&mut DebugContext<'_, '_, '_>) -> Resume,
+{
+    fn pause(&mut self, ctx: &mut DebugContext<'_, '_, '_>) -> Resume {
+        self(ctx)
+    }
+}
+
+pub(crate) struct DebugState {
+    debugger: Option<Box<dyn Debugger + Send>>, // None while the debugger is being called
+    pub(crate) breakpoints: Vec<Breakpoint>,
+    pub(crate) resume: Resume,
+    depth: usize,              // Call depth where execution was resumed
+    pub(crate) entering: bool, // Function was invoked and its first instruction is not executed yet
+}
+
+impl DebugState {
+    pub(crate) fn new() -> Self {
+        Self {
+            debugger: None,
+            breakpoints: vec![],
+            resume: Resume::Continue,
+            depth: 0,
+            entering: false,
+        }
+    }
+
+    pub(crate) fn set_debugger(&mut self, debugger: Box<dyn Debugger + Send>) {
+        self.debugger = Some(debugger);
+    }
+
+    fn should_pause(&self, funcidx: u32, offset: usize, depth: usize) -> bool {
+        let stepped = match self.resume {
+            Resume::Continue => false,
+            Resume::StepIn => true,
+            Resume::StepOver => depth <= self.depth,
+            Resume::StepOut => depth < self.depth,
+            Resume::Terminate => true,
+        };
+        stepped
+            || self.breakpoints.iter().any(|bp| match bp {
+                Breakpoint::Func(idx) => self.entering && *idx == funcidx,
+                Breakpoint::Offset(o) => *o == offset,
+            })
+    }
+
+    pub(crate) fn hook(
+        &mut self,
+        module: &ast::Module<'_>,
+        insn: &ast::Instruction,
+        frame: &CallFrame<'_>,
+        stack: &mut Stack,
+        memory: &mut Memory,
+        globals: &mut Globals,
+    ) -> Result<(), Box<Stop>> {
+        if self.resume == Resume::Terminate {
+            return Err(Stop::new(StopReason::TerminatedByDebugger, insn.start));
+        }
+        let depth = stack.frames().len();
+        let funcidx = match stack.frames().last() {
+            Some(f) => f.funcidx,
+            None => return Ok(()),
+        };
+        let pause = self.should_pause(funcidx, insn.start, depth);
+        self.entering = false;
+        if !pause {
+            return Ok(());
+        }
+        let mut debugger = match self.debugger.take() {
+            Some(debugger) => debugger,
+            None => return Ok(()),
+        };
+
+        let mut ctx = DebugContext {
+            module,
+            insn,
+            frame,
+            funcidx,
+            stack,
+            memory,
+            globals,
+            breakpoints: &mut self.breakpoints,
+        };
+        self.resume = debugger.pause(&mut ctx);
+        self.depth = depth;
+        self.debugger = Some(debugger);
+        if self.resume == Resume::Terminate {
+            return Err(Stop::new(StopReason::TerminatedByDebugger, insn.start));
+        }
+        Ok(())
+    }
+}
+
+// Capture Wasm call stack. Offset of the innermost frame is the current position
+pub(crate) fn backtrace(module: &ast::Module<'_>, stack: &Stack, offset: usize) -> Vec<Frame> {
+    let names = &module.names;
+    let mut offset = Some(offset);
+    stack
+        .frames()
+        .iter()
+        .rev()
+        .map(|frame| Frame {
+            funcidx: frame.funcidx,
+            name: names.func(frame.funcidx).map(|n| n.0.to_string()),
+            offset: offset.take().unwrap_or(frame.offset),
+        })
+        .collect()
+}
+
+// State of the paused machine which debugger can inspect and modify
+pub struct DebugContext<'a, 'f, 's> {
+    module: &'a ast::Module<'s>,
+    insn: &'a ast::Instruction,
+    frame: &'a CallFrame<'f>,
+    funcidx: u32,
+    stack: &'a mut Stack,
+    memory: &'a mut Memory,
+    globals: &'a mut Globals,
+    breakpoints: &'a mut Vec<Breakpoint>,
+}
+
+impl<'a, 'f, 's> DebugContext<'a, 'f, 's> {
+    pub fn module(&self) -> &'a ast::Module<'s> {
+        self.module
+    }
+
+    // Instruction which will be executed next
+    pub fn instruction(&self) -> &ast::Instruction {
+        self.insn
+    }
+
+    // Index of the function being executed
+    pub fn funcidx(&self) -> u32 {
+        self.funcidx
+    }
+
+    // Call frame of the function being executed to access its locals on stack
+    pub fn frame(&self) -> &CallFrame<'f> {
+        self.frame
+    }
+
+    pub fn stack(&self) -> &Stack {
+        self.stack
+    }
+
+    pub fn backtrace(&self) -> Vec<Frame> {
+        backtrace(self.module, self.stack, self.insn.start)
+    }
+
+    // Parameters and local variables of the function being executed
+    pub fn locals(&self) -> Vec<Value> {
+        (0..self.frame.num_locals() as u32)
+            .map(|idx| {
+                self.stack
+                    .read_any(self.frame.local_addr(idx), self.frame.local_type(idx))
+            })
+            .collect()
+    }
+
+    // Returns false when the index is out of range or the type of the value mismatches
+    pub fn set_local(&mut self, localidx: u32, val: Value) -> bool {
+        if localidx as usize >= self.frame.num_locals()
+            || self.frame.local_type(localidx) != val.valtype()
+        {
+            return false;
+        }
+        self.stack.write_any(self.frame.local_addr(localidx), val);
+        true
+    }
+
+    // Values on operand stack of the function being executed. The top of stack comes last
+    pub fn operands(&self) -> Vec<Value> {
+        let (addr, idx) = self.frame.operands_base();
+        self.stack.values(addr, idx)
+    }
+
+    pub fn globals(&self) -> Vec<Value> {
+        self.module
+            .globals
+            .iter()
+            .enumerate()
+            .map(|(idx, g)| self.globals.get_any(idx as u32, g.ty))
+            .collect()
+    }
+
+    // Returns false when the index is out of range or the type of the value mismatches
+    pub fn set_global(&mut self, globalidx: u32, val: Value) -> bool {
+        match self.module.globals.get(globalidx as usize) {
+            Some(g) if g.ty == val.valtype() => {
+                self.globals.set_any(globalidx, val);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn memory(&self) -> &Memory {
+        self.memory
+    }
+
+    pub fn memory_mut(&mut self) -> &mut Memory {
+        self.memory
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        self.breakpoints
+    }
+
+    // Returns false when the breakpoint already exists
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) -> bool {
+        add_breakpoint(self.breakpoints, bp)
+    }
+
+    // Returns false when the breakpoint does not exist
+    pub fn remove_breakpoint(&mut self, bp: Breakpoint) -> bool {
+        remove_breakpoint(self.breakpoints, bp)
+    }
+}
+
+pub(crate) fn add_breakpoint(breakpoints: &mut Vec<Breakpoint>, bp: Breakpoint) -> bool {
+    if breakpoints.contains(&bp) {
+        return false;
+    }
+    breakpoints.push(bp);
+    true
+}
+
+pub(crate) fn remove_breakpoint(breakpoints: &mut Vec<Breakpoint>, bp: Breakpoint) -> bool {
+    let len = breakpoints.len();
+    breakpoints.retain(|b| *b != bp);
+    breakpoints.len() != len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::DefaultImporter;
+    use crate::machine::Machine;
+    use crate::stop::ExecError;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use wain_syntax_text::parse;
+    use wain_validate::validate;
+
+    const SOURCE: &str = r#"
+        (module
+          (global $g (mut i32) (i32.const 10))
+          (memory 1)
+          (func $add (param i32 i32) (result i32)
+            local.get 0
+            local.get 1
+            i32.add)
+          (func (export "main") (result i32)
+            (local i32)
+            i32.const 1
+            local.set 0
+            local.get 0
+            i32.const 2
+            call $add
+            global.set $g
+            global.get $g))
+    "#;
+
+    type Log = Arc<Mutex<Vec<(u32, &'static str, Vec<Value>, Vec<Value>)>>>;
+
+    fn record(log: &Log, ctx: &DebugContext<'_, '_, '_>) {
+        log.lock().unwrap().push((
+            ctx.funcidx(),
+            ctx.instruction().kind.name(),
+            ctx.locals(),
+            ctx.operands(),
+        ));
+    }
+
+    fn run<D: Debugger + Send + 'static>(
+        debugger: D,
+        setup: impl FnOnce(&mut Machine<'_, '_, DefaultImporter<io::Empty, io::Sink>>),
+    ) -> Value {
+        let ast = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        validate(&ast).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine = Machine::instantiate(&ast.module, importer).unwrap();
+        machine.set_debugger(debugger);
+        setup(&mut machine);
+        let ret = machine.invoke("main", &[]).unwrap().unwrap();
+        assert_eq!(machine.memory().read_range(0..1), Some(vec![0]));
+        ret
+    }
+
+    #[test]
+    fn step_in_all_instructions() {
+        let log = Log::default();
+        let l = log.clone();
+        let ret = run(
+            move |ctx: &mut DebugContext<'_, '_, '_>| {
+                record(&l, ctx);
+                Resume::StepIn
+            },
+            |m| m.set_resume(Resume::StepIn),
+        );
+        assert_eq!(ret, Value::I32(3));
+
+        let log = log.lock().unwrap();
+        let names: Vec<_> = log.iter().map(|(i, n, _, _)| (*i, *n)).collect();
+        assert_eq!(
+            names,
+            [
+                (1, "i32.const"),
+                (1, "local.set"),
+                (1, "local.get"),
+                (1, "i32.const"),
+                (1, "call"),
+                (0, "local.get"),
+                (0, "local.get"),
+                (0, "i32.add"),
+                (1, "global.set"),
+                (1, "global.get"),
+            ]
+        );
+        let (_, _, locals, operands) = &log[4];
+        assert_eq!(locals, &[Value::I32(1)]);
+        assert_eq!(operands, &[Value::I32(1), Value::I32(2)]);
+        let (_, _, locals, operands) = &log[7];
+        assert_eq!(locals, &[Value::I32(1), Value::I32(2)]);
+        assert_eq!(operands, &[Value::I32(1), Value::I32(2)]);
+    }
+
+    #[test]
+    fn breakpoint_and_step_over_out() {
+        let log = Log::default();
+        let l = log.clone();
+        let mut resumes = vec![Resume::Continue, Resume::StepOut, Resume::StepOver];
+        let ret = run(
+            move |ctx: &mut DebugContext<'_, '_, '_>| {
+                record(&l, ctx);
+                if ctx.funcidx() == 0 {
+                    let frames: Vec<_> = ctx.backtrace().iter().map(|f| f.funcidx).collect();
+                    assert_eq!(frames, [0, 1]);
+                }
+                resumes.pop().unwrap()
+            },
+            |m| {
+                assert!(m.add_breakpoint(Breakpoint::Func(0)));
+                assert!(!m.add_breakpoint(Breakpoint::Func(0)));
+            },
+        );
+        assert_eq!(ret, Value::I32(3));
+
+        let log = log.lock().unwrap();
+        let names: Vec<_> = log.iter().map(|(i, n, _, _)| (*i, *n)).collect();
+        assert_eq!(
+            names,
+            [(0, "local.get"), (0, "local.get"), (1, "global.set")]
+        );
+        let (_, _, _, operands) = &log[2];
+        assert_eq!(operands, &[Value::I32(3)]);
+    }
+
+    #[test]
+    fn modify_state_while_paused() {
+        let offset = SOURCE.find("local.get 1").unwrap();
+        let ret = run(
+            move |ctx: &mut DebugContext<'_, '_, '_>| {
+                assert_eq!(ctx.instruction().start, offset);
+                assert_eq!(ctx.breakpoints(), &[Breakpoint::Offset(offset)]);
+                assert!(ctx.remove_breakpoint(Breakpoint::Offset(offset)));
+                assert!(!ctx.remove_breakpoint(Breakpoint::Offset(offset)));
+
+                assert!(ctx.set_local(1, Value::I32(41)));
+                assert!(!ctx.set_local(1, Value::I64(41)));
+                assert!(!ctx.set_local(2, Value::I32(41)));
+
+                assert_eq!(ctx.globals(), [Value::I32(10)]);
+                assert!(ctx.set_global(0, Value::I32(20)));
+                assert!(!ctx.set_global(0, Value::F32(1.0)));
+                assert_eq!(ctx.globals(), [Value::I32(20)]);
+
+                assert!(ctx.memory_mut().write_range(0, &[0])); // Memory is writable
+                assert_eq!(ctx.memory().size(), 1);
+                Resume::Continue
+            },
+            |m| {
+                assert!(m.add_breakpoint(Breakpoint::Offset(offset)));
+            },
+        );
+        assert_eq!(ret, Value::I32(42));
+    }
+
+    #[test]
+    fn terminate_execution() {
+        let ast = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        validate(&ast).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine = Machine::instantiate(&ast.module, importer).unwrap();
+        machine.add_breakpoint(Breakpoint::Func(0));
+        machine.set_debugger(|_: &mut DebugContext<'_, '_, '_>| Resume::Terminate);
+        let stop = match machine.invoke("main", &[]) {
+            Err(ExecError::Stop(stop)) => stop,
+            r => panic!("unexpected result: {:?}", r),
+        };
+        assert!(matches!(stop.reason, StopReason::TerminatedByDebugger));
+        assert_eq!(stop.offset, SOURCE.find("local.get 0").unwrap());
+        let funcs: Vec<_> = stop.backtrace.iter().map(|f| f.funcidx).collect();
+        assert_eq!(funcs, [0, 1]);
+    }
+
+    #[test]
+    fn no_pause_without_breakpoints() {
+        let ret = run(
+            |_: &mut DebugContext<'_, '_, '_>| -> Resume { panic!("should not pause") },
+            |m| assert!(m.breakpoints().is_empty()),
+        );
+        assert_eq!(ret, Value::I32(3));
+    }
+}
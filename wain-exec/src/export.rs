@@ -0,0 +1,89 @@
+use crate::trap::JoinWritable;
+use crate::value::Value;
+use std::fmt;
+use wain_ast::ValType;
+
+// Error on accessing exports of an instance from host. It is separate from Trap since it is caused
+// by misuse of the host API, not by executing Wasm code
+#[cfg_attr(test, derive(Debug))]
+pub enum ExportError {
+    NotFound {
+        name: String,
+        expected: &'static str,
+    },
+    WrongKind {
+        name: String,
+        expected: &'static str,
+        actual: &'static str,
+    },
+    Immutable(String),
+    TypeMismatch {
+        name: String,
+        value: Value,
+        ty: ValType,
+    },
+    IdxOutOfTable {
+        idx: usize,
+        table_size: usize,
+    },
+    UnknownFunc(u32),
+    SignatureMismatch {
+        name: String,
+        expected_params: &'static [ValType],
+        expected_results: &'static [ValType],
+        actual_params: Box<[ValType]>,
+        actual_results: Box<[ValType]>,
+    },
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::NotFound { name, expected } => {
+                write!(f, "{} '{}' is not exported", expected, name)
+            }
+            ExportError::WrongKind {
+                name,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "export '{}' is {} but {} is expected",
+                name, actual, expected,
+            ),
+            ExportError::Immutable(name) => write!(
+                f,
+                "cannot set value to immutable global variable '{}'",
+                name
+            ),
+            ExportError::TypeMismatch { name, value, ty } => write!(
+                f,
+                "cannot set value {} to global variable '{}' of type {}",
+                value, name, ty,
+            ),
+            ExportError::IdxOutOfTable { idx, table_size } => {
+                write!(f, "index {} is out of table size {}", idx, table_size)
+            }
+            ExportError::UnknownFunc(idx) => {
+                write!(f, "cannot set unknown function {} to table", idx)
+            }
+            ExportError::SignatureMismatch {
+                name,
+                expected_params,
+                expected_results,
+                actual_params,
+                actual_results,
+            } => write!(
+                f,
+                "signature of function '{}' mismatches. expected '[{}] -> [{}]' but got '[{}] -> [{}]'",
+                name,
+                JoinWritable(expected_params, " "),
+                JoinWritable(expected_results, " "),
+                JoinWritable(actual_params, " "),
+                JoinWritable(actual_results, " "),
+            ),
+        }
+    }
+}
+
+pub(crate) type ExportResult<T> = ::std::result::Result<T, Box<ExportError>>;
@@ -4,6 +4,7 @@ use wain_ast::{Global, GlobalKind, InsnKind, ValType};
 
 // Fixed-size any values store indexed in advance
 #[cfg_attr(test, derive(Debug))]
+#[derive(Clone)]
 pub struct Globals {
     bytes: Box<[u8]>,
     offsets: Box<[usize]>,
@@ -73,6 +74,11 @@ impl Globals {
         LittleEndian::write(&mut self.bytes, offset, v);
     }
 
+    // Reset all values to the image of the same module keeping the buffer
+    pub(crate) fn reset(&mut self, image: &Globals) {
+        self.bytes.copy_from_slice(&image.bytes);
+    }
+
     pub fn set_any(&mut self, idx: u32, val: Value) {
         match val {
             Value::I32(i) => self.set(idx, i),
@@ -0,0 +1,305 @@
+// Host functions defined with Rust signatures
+//
+// Implementing Importer by hand requires to pop arguments from stack in reverse order, to push the
+// result and to write the expected signature in validate separately. impl_importer! generates both
+// of validate and call from Rust functions so that the signature checked at instantiation is always
+// consistent with the values popped and pushed at calls.
+
+use crate::import::{check_func_signature, ImportInvalidError, ImportInvokeError};
+use crate::stack::Stack;
+use crate::typed::FromWasmValues;
+use wain_ast::{AsValType, ValType};
+
+// Return value of host function. `()` for no result. Returning Err(ImportInvokeError) stops the
+// execution
+pub trait HostResult {
+    const VAL_TYPE: Option<ValType>;
+    fn push_to(self, stack: &mut Stack) -> Result<(), ImportInvokeError>;
+}
+
+impl HostResult for () {
+    const VAL_TYPE: Option<ValType> = None;
+    fn push_to(self, _stack: &mut Stack) -> Result<(), ImportInvokeError> {
+        Ok(())
+    }
+}
+
+macro_rules! impl_host_result {
+    ($t:ty) => {
+        impl HostResult for $t {
+            const VAL_TYPE: Option<ValType> = Some(<$t as AsValType>::VAL_TYPE);
+            fn push_to(self, stack: &mut Stack) -> Result<(), ImportInvokeError> {
+                stack.push(self);
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_host_result!(i32);
+impl_host_result!(i64);
+impl_host_result!(f32);
+impl_host_result!(f64);
+
+impl<T: HostResult> HostResult for Result<T, ImportInvokeError> {
+    const VAL_TYPE: Option<ValType> = T::VAL_TYPE;
+    fn push_to(self, stack: &mut Stack) -> Result<(), ImportInvokeError> {
+        self?.push_to(stack)
+    }
+}
+
+// Check the signature of imported function against parameter types P and result type R
+pub fn check_func_type<P: FromWasmValues, R: HostResult>(
+    params: &[ValType],
+    ret: Option<ValType>,
+) -> Option<ImportInvalidError> {
+    check_func_signature(params, ret, P::VAL_TYPES, R::VAL_TYPE)
+}
+
+// Implement Importer with host functions. Each function is imported by its name. Parameters and
+// result must be Wasm types (i32, i64, f32 or f64). The result can be wrapped with
+// Result<_, ImportInvokeError>. The first parameter after `&mut self` can be `&mut Memory` to
+// access the linear memory of the instance.
+//
+//   struct Env;
+//   impl_importer! {
+//       impl Importer for Env {
+//           fn putchar(&mut self, c: i32) -> i32 { ... }
+//           fn load(&mut self, memory: &mut Memory, addr: i32) -> i32 { ... }
+//       }
+//   }
+//
+// The functions are also defined as methods of the type.
+#[macro_export]
+macro_rules! impl_importer {
+    (
+        impl $(<$($gen:ident : $bound:path),* $(,)?>)? Importer for $ty:ty {
+            $(
+                $(#[$attr:meta])*
+                $vis:vis fn $name:ident ( $($params:tt)* ) $(-> $ret:ty)? $body:block
+            )*
+        }
+    ) => {
+        impl<$($($gen: $bound),*)?> $ty {
+            $(
+                $(#[$attr])*
+                $vis fn $name($($params)*) $(-> $ret)? $body
+            )*
+        }
+
+        impl<$($($gen: $bound),*)?> $crate::Importer for $ty {
+            fn validate(
+                &self,
+                name: &str,
+                params: &[$crate::wain_ast::ValType],
+                ret: ::std::option::Option<$crate::wain_ast::ValType>,
+            ) -> ::std::option::Option<$crate::ImportInvalidError> {
+                $(
+                    if name == stringify!($name) {
+                        return $crate::__impl_importer_validate!(
+                            params, ret, ($($params)*), ($crate::__impl_importer_ret!($($ret)?))
+                        );
+                    }
+                )*
+                ::std::option::Option::Some($crate::ImportInvalidError::NotFound)
+            }
+
+            #[allow(unused_variables)]
+            fn call(
+                &mut self,
+                name: &str,
+                stack: &mut $crate::Stack,
+                memory: &mut $crate::Memory,
+            ) -> ::std::result::Result<(), $crate::ImportInvokeError> {
+                $(
+                    if name == stringify!($name) {
+                        return $crate::__impl_importer_call!(
+                            self, stack, memory, $name, ($($params)*)
+                        );
+                    }
+                )*
+                ::std::result::Result::Err($crate::ImportInvokeError::Fatal {
+                    message: format!("fatal: invalid import function '{}'", name),
+                })
+            }
+        }
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_importer_ret {
+    () => {
+        ()
+    };
+    ($ret:ty) => {
+        $ret
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_importer_validate {
+    (
+        $params:ident, $ret:ident,
+        (&mut self, $mem:ident : &mut $memty:ty $(, $arg:ident : $t:ty)*), ($r:ty)
+    ) => {
+        $crate::check_func_type::<($($t,)*), $r>($params, $ret)
+    };
+    ($params:ident, $ret:ident, (&mut self $(, $arg:ident : $t:ty)*), ($r:ty)) => {
+        $crate::check_func_type::<($($t,)*), $r>($params, $ret)
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __impl_importer_call {
+    (
+        $this:ident, $stack:ident, $memory:ident, $name:ident,
+        (&mut self, $mem:ident : &mut $memty:ty $(, $arg:ident : $t:ty)*)
+    ) => {{
+        let ($($arg,)*): ($($t,)*) = $crate::FromWasmValues::pop_from($stack);
+        $crate::HostResult::push_to($this.$name($memory $(, $arg)*), $stack)
+    }};
+    (
+        $this:ident, $stack:ident, $memory:ident, $name:ident,
+        (&mut self $(, $arg:ident : $t:ty)*)
+    ) => {{
+        let ($($arg,)*): ($($t,)*) = $crate::FromWasmValues::pop_from($stack);
+        $crate::HostResult::push_to($this.$name($($arg),*), $stack)
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::import::{ImportInvalidError, ImportInvokeError, Importer};
+    use crate::machine::Machine;
+    use crate::memory::Memory;
+    use crate::stack::Stack;
+    use crate::value::Value;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use wain_ast::ValType;
+    use wain_syntax_text::parse;
+
+    #[derive(Default)]
+    struct Env {
+        log: Rc<RefCell<Vec<i64>>>,
+    }
+
+    impl_importer! {
+        impl Importer for Env {
+            fn add(&mut self, a: i64, b: f64) -> f64 {
+                self.log.borrow_mut().push(a);
+                a as f64 + b
+            }
+
+            fn store(&mut self, memory: &mut Memory, addr: i32, v: i32) {
+                memory.write_range(addr as usize, &v.to_le_bytes());
+            }
+
+            fn check(&mut self, v: i32) -> Result<i32, ImportInvokeError> {
+                if v < 0 {
+                    return Err(ImportInvokeError::Fatal {
+                        message: format!("negative value {}", v),
+                    });
+                }
+                Ok(v * 2)
+            }
+
+            fn tick(&mut self) {
+                self.log.borrow_mut().push(0);
+            }
+        }
+    }
+
+    const SOURCE: &str = r#"
+(module
+  (import "env" "add" (func $add (param i64 f64) (result f64)))
+  (import "env" "store" (func $store (param i32 i32)))
+  (import "env" "check" (func $check (param i32) (result i32)))
+  (import "env" "tick" (func $tick))
+  (memory 1)
+  (func (export "add") (result f64)
+    call $tick
+    i64.const 40
+    f64.const 2.5
+    call $add)
+  (func (export "store") (param i32) (result i32)
+    i32.const 8
+    local.get 0
+    call $check
+    call $store
+    i32.const 8
+    i32.load))
+"#;
+
+    #[test]
+    fn call_host_functions() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let env = Env::default();
+        let log = env.log.clone();
+        let mut machine =
+            Machine::instantiate(&root.module, env).unwrap_or_else(|t| panic!("{}", t));
+
+        let ret = machine
+            .invoke("add", &[])
+            .unwrap_or_else(|t| panic!("{}", t));
+        assert_eq!(ret, Some(Value::F64(42.5)));
+        assert_eq!(*log.borrow(), vec![0, 40]);
+
+        let ret = machine.invoke("store", &[Value::I32(21)]).unwrap();
+        assert_eq!(ret, Some(Value::I32(42)));
+
+        let msg = match machine.invoke("store", &[Value::I32(-1)]) {
+            Err(trap) => trap.to_string(),
+            Ok(v) => panic!("error was not returned: {:?}", v),
+        };
+        assert!(msg.contains("negative value -1"), "{}", msg);
+
+        // Host functions are also methods
+        assert_eq!(Env::default().add(1, 0.5), 1.5);
+    }
+
+    #[test]
+    fn validate_signatures() {
+        use ValType::*;
+        let env = Env::default();
+        assert!(env.validate("add", &[I64, F64], Some(F64)).is_none());
+        assert!(env.validate("store", &[I32, I32], None).is_none());
+        assert!(env.validate("check", &[I32], Some(I32)).is_none());
+        assert!(env.validate("tick", &[], None).is_none());
+
+        match env.validate("add", &[F64, I64], Some(F64)) {
+            Some(ImportInvalidError::SignatureMismatch {
+                expected_params,
+                expected_ret,
+            }) => {
+                assert_eq!(expected_params, &[I64, F64]);
+                assert_eq!(expected_ret, Some(F64));
+            }
+            _ => panic!("signature was not checked"),
+        }
+        assert!(env.validate("check", &[I32], None).is_some());
+        assert!(matches!(
+            env.validate("unknown", &[], None),
+            Some(ImportInvalidError::NotFound)
+        ));
+
+        let root = parse(r#"(module (import "env" "store" (func (param i32))))"#)
+            .unwrap_or_else(|e| panic!("{}", e));
+        assert!(Machine::instantiate(&root.module, Env::default()).is_err());
+    }
+
+    #[test]
+    fn call_unknown_function() {
+        let mut stack = Stack::default();
+        let mut memory = Memory::allocate(&[]).unwrap();
+        match Env::default().call("unknown", &mut stack, &mut memory) {
+            Err(ImportInvokeError::Fatal { message }) => {
+                assert!(message.contains("'unknown'"), "{}", message)
+            }
+            Ok(()) => panic!("unknown function was called"),
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::caller::Caller;
+use crate::impl_importer;
 use crate::memory::Memory;
 use crate::stack::Stack;
 use std::io::{Read, Write};
@@ -28,6 +30,18 @@ pub trait Importer {
         stack: &mut Stack,
         memory: &mut Memory,
     ) -> Result<(), ImportInvokeError>;
+    // Call the host function with the context of the calling instance. The machine calls this
+    // instead of `call`. Override this to invoke guest functions or to access exports of the instance
+    // from the host function. By default it calls `call`
+    fn call_with_caller(
+        caller: &mut Caller<'_, '_, '_, Self>,
+        name: &str,
+    ) -> Result<(), ImportInvokeError>
+    where
+        Self: Sized,
+    {
+        caller.call_importer(name)
+    }
 }
 
 pub fn check_func_signature(
@@ -60,92 +74,64 @@ impl<R: Read, W: Write> DefaultImporter<R, W> {
     pub fn with_stdio(stdin: R, stdout: W) -> Self {
         Self { stdin, stdout }
     }
-
-    // (func (param i32) (result i32))
-    fn putchar(&mut self, stack: &mut Stack) {
-        let v: i32 = stack.pop();
-        let b = v as u8;
-        let ret = match self.stdout.write(&[b]) {
-            Ok(_) => b as i32,
-            Err(_) => -1, // EOF
-        };
-        stack.push(ret);
-    }
-
-    // (func () (result i32))
-    fn getchar(&mut self, stack: &mut Stack) {
-        let mut buf = [0u8];
-        let v = match self.stdin.read_exact(&mut buf) {
-            Ok(()) => buf[0] as i32,
-            Err(_) => -1, // EOF
-        };
-        stack.push(v);
-    }
-
-    // (func (param i32 i32 i32) (result i32))
-    fn memcpy(&mut self, stack: &mut Stack, memory: &mut Memory) -> Result<(), ImportInvokeError> {
-        // memcpy(void *dest, void *src, size_t n)
-        let size = stack.pop::<i32>() as usize;
-        let src_start = stack.pop::<i32>() as usize;
-        let dest_i32: i32 = stack.pop();
-        let dest_start = dest_i32 as usize;
-        let src_end = src_start + size;
-        let dest_end = dest_start + size;
-
-        let (dest, src) = if dest_end <= src_start {
-            let (dest, src) = memory.data_mut().split_at_mut(src_start);
-            (&mut dest[dest_start..dest_end], &mut src[..size])
-        } else if src_end <= dest_start {
-            let (src, dest) = memory.data_mut().split_at_mut(dest_start);
-            (&mut dest[..size], &mut src[src_start..src_end])
-        } else {
-            return Err(ImportInvokeError::Fatal {
-                message: format!(
-                    "range overwrap on memcpy: src={}..{} and dest={}..{}",
-                    src_start, src_end, dest_start, dest_end
-                ),
-            });
-        };
-
-        dest.copy_from_slice(&src);
-        stack.push(dest_i32);
-        Ok(())
-    }
 }
 
-impl<R: Read, W: Write> Importer for DefaultImporter<R, W> {
-    fn validate(
-        &self,
-        name: &str,
-        params: &[ValType],
-        ret: Option<ValType>,
-    ) -> Option<ImportInvalidError> {
-        use ValType::*;
-        match name {
-            "putchar" => check_func_signature(params, ret, &[I32], Some(I32)),
-            "getchar" => check_func_signature(params, ret, &[], Some(I32)),
-            "memcpy" => check_func_signature(params, ret, &[I32, I32, I32], Some(I32)),
-            _ => Some(ImportInvalidError::NotFound),
+impl_importer! {
+    impl<R: Read, W: Write> Importer for DefaultImporter<R, W> {
+        // (func (param i32) (result i32))
+        // Only errors are reported as EOF. The written amount of the byte is not checked
+        #[allow(clippy::unused_io_amount)]
+        fn putchar(&mut self, c: i32) -> i32 {
+            let b = c as u8;
+            match self.stdout.write(&[b]) {
+                Ok(_) => b as i32,
+                Err(_) => -1, // EOF
+            }
         }
-    }
 
-    fn call(
-        &mut self,
-        name: &str,
-        stack: &mut Stack,
-        memory: &mut Memory,
-    ) -> Result<(), ImportInvokeError> {
-        match name {
-            "putchar" => {
-                self.putchar(stack);
-                Ok(())
+        // (func () (result i32))
+        fn getchar(&mut self) -> i32 {
+            let mut buf = [0u8];
+            match self.stdin.read_exact(&mut buf) {
+                Ok(()) => buf[0] as i32,
+                Err(_) => -1, // EOF
             }
-            "getchar" => {
-                self.getchar(stack);
-                Ok(())
+        }
+
+        // (func (param i32 i32 i32) (result i32))
+        fn memcpy(
+            &mut self,
+            memory: &mut Memory,
+            dest: i32,
+            src: i32,
+            size: i32
+        ) -> Result<i32, ImportInvokeError> {
+            // memcpy(void *dest, void *src, size_t n)
+            let size = size as usize;
+            let src_start = src as usize;
+            let dest_start = dest as usize;
+            let src_end = src_start + size;
+            let dest_end = dest_start + size;
+
+            if dest_end > src_start && src_end > dest_start {
+                return Err(ImportInvokeError::Fatal {
+                    message: format!(
+                        "range overwrap on memcpy: src={}..{} and dest={}..{}",
+                        src_start, src_end, dest_start, dest_end
+                    ),
+                });
             }
-            "memcpy" => self.memcpy(stack, memory),
-            _ => unreachable!("fatal: invalid import function '{}'", name),
+
+            if !memory.copy_within(src_start..src_end, dest_start) {
+                return Err(ImportInvokeError::Fatal {
+                    message: format!(
+                        "out of memory range on memcpy: src={}..{} and dest={}..{}",
+                        src_start, src_end, dest_start, dest_end
+                    ),
+                });
+            }
+
+            Ok(dest)
         }
     }
 }
@@ -1,28 +1,56 @@
 #![forbid(unsafe_code)]
 
-extern crate wain_ast;
+// Referred by impl_importer! macro
+pub extern crate wain_ast;
 
+pub mod stop;
 pub mod trap;
 
+mod caller;
 mod cast;
+mod checkpoint;
+mod coredump;
+mod coverage;
+mod debug;
+mod export;
 mod globals;
+mod host;
 mod import;
 mod machine;
 mod memory;
+mod pool;
+mod profile;
+mod snapshot;
 mod stack;
 mod table;
+mod trace;
+mod typed;
 mod value;
+mod watch;
 
+pub use caller::Caller;
+pub use checkpoint::Checkpoint;
+pub use coredump::{CoreDump, CoreDumpError, CoreFrame, CoreGlobal};
+pub use coverage::{BranchCoverage, Coverage, FuncCoverage, InsnCoverage};
+pub use debug::{Breakpoint, DebugContext, Debugger, Resume};
+pub use export::ExportError;
+pub use host::{check_func_type, HostResult};
 pub use import::{
     check_func_signature, DefaultImporter, ImportInvalidError, ImportInvokeError, Importer,
 };
-pub use machine::{Machine, Run};
+pub use machine::{unsupported_features, Machine, Run};
 pub use memory::Memory;
-pub use stack::Stack;
+pub use pool::{InstancePool, PoolConfig, PoolError};
+pub use profile::{FuncProfile, Profile, Profiler};
+pub use snapshot::{Snapshot, SnapshotError};
+pub use stack::{CallFrame, Stack, StackFrame};
+pub use trace::Tracer;
+pub use typed::{FromWasmValues, IntoWasmValues, TypedFunc, WasmType};
 pub use value::Value;
+pub use watch::{Access, MemoryAccess, MemoryHook, Watch, WatchAction};
 
 use std::io;
-use trap::Result;
+use stop::Result;
 use wain_ast::Module;
 
 /// A convenient function to execute a WebAssembly module.
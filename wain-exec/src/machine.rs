@@ -1,11 +1,24 @@
+use crate::caller::Caller;
 use crate::cast;
+use crate::checkpoint::{Checkpoint, Step};
+use crate::coredump::{CoreDump, CoreFrame, CoreGlobal};
+use crate::debug::{self, Breakpoint, DebugState, Debugger, Resume};
+use crate::export::{ExportError, ExportResult};
 use crate::globals::Globals;
 use crate::import::{ImportInvalidError, ImportInvokeError, Importer};
 use crate::memory::Memory;
-use crate::stack::{CallFrame, Stack, StackAccess};
+use crate::snapshot::{Snapshot, SnapshotError};
+use crate::stack::{CallFrame, Label, Stack, StackAccess};
+use crate::stop::{self, ExecError, Stop, StopReason};
 use crate::table::Table;
-use crate::trap::{Result, Trap, TrapReason};
+use crate::trace::{Tracer, MAX_TRACED_OPERANDS};
+use crate::trap::{Frame, Result, Trap, TrapReason};
+use crate::typed::{FromWasmValues, IntoWasmValues, TypedFunc};
 use crate::value::{LittleEndian, Value};
+use crate::watch::{MemoryHook, Watch, WatchState, Watched};
+use std::mem;
+use std::ops::Range;
+use std::slice;
 use wain_ast as ast;
 use wain_ast::AsValType;
 
@@ -27,7 +40,77 @@ enum ExecState {
     Continue,      // Continuing execution
 }
 
-type ExecResult = Result<ExecState>;
+type ExecResult = stop::Result<ExecState>;
+
+// Takes module name and name of the host function to be called. Returns an error message to refuse
+// the call
+type HostCallGuard = Box<dyn FnMut(&str, &str) -> std::result::Result<(), String> + Send>;
+
+// Features in 'target_features' custom section which are supported by this implementation. Only
+// required ('=') features are checked on instantiation since compilers list features enabled by
+// default as used ('+') even if the module does not use them. Instructions of unsupported features
+// are rejected by parser or validator, and `unsupported_features` explains the failure
+// https://github.com/WebAssembly/tool-conventions/blob/main/Linking.md#target-features-section
+const SUPPORTED_FEATURES: [&str; 1] = ["mutable-globals"];
+
+// Names of used or required features in 'target_features' section which are not supported. When
+// parsing or validating a module failed, they are likely the cause of the failure.
+pub fn unsupported_features<'a>(features: &'a [ast::TargetFeature<'_>]) -> Vec<&'a str> {
+    features
+        .iter()
+        .filter(|f| f.prefix != ast::FeaturePrefix::Disallowed)
+        .map(|f| f.name.0.as_ref())
+        .filter(|name| !SUPPORTED_FEATURES.contains(name))
+        .collect()
+}
+
+// Instance state allocated on instantiation. Its buffers can be reused by InstancePool
+pub(crate) struct Store {
+    pub(crate) table: Table,
+    pub(crate) memory: Memory,
+    pub(crate) globals: Globals,
+    pub(crate) stack: Stack,
+}
+
+impl Store {
+    // 5. to 12. https://webassembly.github.io/spec/core/exec/modules.html#instantiation
+    pub(crate) fn instantiate(module: &ast::Module<'_>) -> Result<Self> {
+        // 5. global initialization values determined by module and externval
+        let globals = Globals::instantiate(&module.globals)?;
+
+        // 6. a new module instance allocated from module in store S
+        // https://webassembly.github.io/spec/core/exec/modules.html#alloc-module
+
+        // 6.2 allocate functions (nothing to do since we run abstract tree directly)
+
+        // 6.3 allocate table
+        let mut table = Table::allocate(&module.tables)?;
+        // 6.4 allocate memory
+        let mut memory = Memory::allocate(&module.memories)?;
+
+        // 7. and 8. push empty frame (unnecessary for now)
+        let stack = Stack::default();
+
+        // 9. add element segments to table
+        for elem in module.elems.iter() {
+            table.new_elem(elem, &globals)?;
+        }
+
+        // 10. add data segments to memory
+        for data in module.data.iter() {
+            memory.new_data(data, &globals)?;
+        }
+
+        // 11. and 12. pop frame (unnecessary for now)
+
+        Ok(Self {
+            table,
+            memory,
+            globals,
+            stack,
+        })
+    }
+}
 
 // State of abtract machine to run wasm code. This struct contains both store and stack
 pub struct Machine<'module, 'source, I: Importer> {
@@ -37,11 +120,34 @@ pub struct Machine<'module, 'source, I: Importer> {
     memory: Memory, // Only one memory is allowed for MVP
     globals: Globals,
     importer: I,
+    debug: Option<Box<DebugState>>, // Only allocated when debugging to avoid overhead
+    watch: Option<Box<WatchState>>, // Only allocated while watchpoints exist to avoid overhead
+    tracer: Option<Box<dyn Tracer + Send>>,
+    trace_operands: bool, // Cache of Tracer::wants_operands
+    guard: Option<HostCallGuard>,
+    coredump: bool,
+    suspend: bool,                // Suspend execution before calling host functions
+    suspended: Option<Vec<Step>>, // Steps recorded while unwinding on suspension. The innermost first
+}
+
+// Machine can be moved to other thread when its importer is Send
+fn _assert_send<T: Send>() {}
+fn _assert_machine_is_send() {
+    _assert_send::<
+        Machine<'static, 'static, crate::DefaultImporter<std::io::Stdin, std::io::Stdout>>,
+    >();
 }
 
 impl<'m, 's, I: Importer> Machine<'m, 's, I> {
     // https://webassembly.github.io/spec/core/exec/modules.html#instantiation
     pub fn instantiate(module: &'m ast::Module<'s>, importer: I) -> Result<Self> {
+        Self::check_imports(module, &importer)?;
+        let store = Store::instantiate(module)?;
+        Ok(Self::with_store(module, importer, store))
+    }
+
+    // 1. to 4. https://webassembly.github.io/spec/core/exec/modules.html#instantiation
+    pub(crate) fn check_imports(module: &ast::Module<'s>, importer: &I) -> Result<()> {
         // TODO: 2., 3., 4. Validate external values before instantiate globals
 
         fn unknown_import<'s>(import: &ast::Import<'s>, at: usize) -> Box<Trap> {
@@ -55,6 +161,17 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
             )
         }
 
+        for feature in module.target_features.iter() {
+            let name = feature.name.0.as_ref();
+            if feature.prefix == ast::FeaturePrefix::Required && !SUPPORTED_FEATURES.contains(&name)
+            {
+                return Err(Trap::new(
+                    TrapReason::UnsupportedFeature(name.to_string()),
+                    feature.start,
+                ));
+            }
+        }
+
         for func in module.funcs.iter() {
             match &func.kind {
                 ast::FuncKind::Body { .. } => break, // All imports precedes other definitions
@@ -91,42 +208,66 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
             }
         }
 
-        // 5. global initialization values determined by module and externval
-        let globals = Globals::instantiate(&module.globals)?;
-
-        // 6. a new module instance allocated from module in store S
-        // https://webassembly.github.io/spec/core/exec/modules.html#alloc-module
-
-        // 6.2 allocate functions (nothing to do since we run abstract tree directly)
-
-        // 6.3 allocate table
-        let mut table = Table::allocate(&module.tables)?;
-        // 6.4 allocate memory
-        let mut memory = Memory::allocate(&module.memories)?;
-
-        // 7. and 8. push empty frame (unnecessary for now)
-        let stack = Stack::default();
+        Ok(())
+    }
 
-        // 9. add element segments to table
-        for elem in module.elems.iter() {
-            table.new_elem(elem, &globals)?;
+    pub(crate) fn with_store(module: &'m ast::Module<'s>, importer: I, store: Store) -> Self {
+        Self {
+            module,
+            table: store.table,
+            stack: store.stack,
+            memory: store.memory,
+            globals: store.globals,
+            importer,
+            debug: None,
+            watch: None,
+            tracer: None,
+            trace_operands: false,
+            guard: None,
+            coredump: false,
+            suspend: false,
+            suspended: None,
         }
+    }
 
-        // 10. add data segments to memory
-        for data in module.data.iter() {
-            memory.new_data(data, &globals)?;
+    pub(crate) fn into_store(self) -> Store {
+        Store {
+            table: self.table,
+            memory: self.memory,
+            globals: self.globals,
+            stack: self.stack,
         }
+    }
 
-        // 11. and 12. pop frame (unnecessary for now)
-
-        Ok(Self {
-            module,
-            table,
-            stack,
-            memory,
-            globals,
+    // Create an independent instance with the current state. Pages of linear memory are shared
+    // copy-on-write so forking is much cheaper than instantiating and initializing the module
+    // again. Globals and table are copied. Debugger, watchpoints, tracer and host call guard are not
+    // inherited
+    pub fn fork_with(&self, importer: I) -> Self {
+        Self {
+            module: self.module,
+            table: self.table.clone(),
+            stack: Stack::default(),
+            memory: self.memory.clone(),
+            globals: self.globals.clone(),
             importer,
-        })
+            debug: None,
+            watch: None,
+            tracer: None,
+            trace_operands: false,
+            guard: None,
+            coredump: self.coredump,
+            suspend: self.suspend,
+            suspended: None,
+        }
+    }
+
+    // Fork the instance with the clone of the importer. See Machine::fork_with
+    pub fn fork(&self) -> Self
+    where
+        I: Clone,
+    {
+        self.fork_with(self.importer.clone())
     }
 
     pub fn module(&self) -> &'m ast::Module<'s> {
@@ -137,6 +278,33 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
         &self.memory
     }
 
+    pub(crate) fn linear_memory_mut(&mut self) -> &mut Memory {
+        &mut self.memory
+    }
+
+    pub(crate) fn stack_mut(&mut self) -> &mut Stack {
+        &mut self.stack
+    }
+
+    pub(crate) fn table(&self) -> &Table {
+        &self.table
+    }
+
+    pub(crate) fn importer(&self) -> &I {
+        &self.importer
+    }
+
+    pub(crate) fn importer_mut(&mut self) -> &mut I {
+        &mut self.importer
+    }
+
+    pub(crate) fn call_importer(
+        &mut self,
+        name: &str,
+    ) -> ::std::result::Result<(), ImportInvokeError> {
+        self.importer.call(name, &mut self.stack, &mut self.memory)
+    }
+
     pub fn get_global(&self, name: &str) -> Option<Value> {
         self.module
             .exports
@@ -151,18 +319,122 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
             })
     }
 
+    // Names and types of exports in the order of export section
+    pub fn exports(&self) -> Vec<(&'m str, ast::ExternType<'m>)> {
+        let module = self.module;
+        module
+            .exports
+            .iter()
+            .map(|e| (e.name.0.as_ref(), module.export_type(&e.kind)))
+            .collect()
+    }
+
+    // Find the export by name and return its index. `expected` is the kind of the export
+    fn find_export(&self, name: &str, expected: &'static str) -> ExportResult<u32> {
+        let export = match self.module.exports.iter().find(|e| e.name.0 == name) {
+            Some(export) => export,
+            None => {
+                return Err(Box::new(ExportError::NotFound {
+                    name: name.to_string(),
+                    expected,
+                }))
+            }
+        };
+        let (actual, idx) = match export.kind {
+            ast::ExportKind::Func(idx) => ("function", idx),
+            ast::ExportKind::Table(idx) => ("table", idx),
+            ast::ExportKind::Memory(idx) => ("memory", idx),
+            ast::ExportKind::Global(idx) => ("global variable", idx),
+        };
+        if actual != expected {
+            return Err(Box::new(ExportError::WrongKind {
+                name: name.to_string(),
+                expected,
+                actual,
+            }));
+        }
+        Ok(idx)
+    }
+
+    // Exported linear memory. Memory::grow works as memory.grow instruction
+    pub fn memory_mut(&mut self, name: &str) -> ExportResult<&mut Memory> {
+        self.find_export(name, "memory")?;
+        Ok(&mut self.memory)
+    }
+
+    // Set the value to the exported mutable global variable. Its type must match to the value
+    pub fn set_global(&mut self, name: &str, value: Value) -> ExportResult<()> {
+        let idx = self.find_export(name, "global variable")?;
+        let global = &self.module.globals[idx as usize];
+        if !global.mutable {
+            return Err(Box::new(ExportError::Immutable(name.to_string())));
+        }
+        if global.ty != value.valtype() {
+            return Err(Box::new(ExportError::TypeMismatch {
+                name: name.to_string(),
+                value,
+                ty: global.ty,
+            }));
+        }
+        self.globals.set_any(idx, value);
+        Ok(())
+    }
+
+    pub fn table_size(&self, name: &str) -> ExportResult<usize> {
+        self.find_export(name, "table")?;
+        Ok(self.table.size())
+    }
+
+    // Index of the function in the exported table. None when the element is not initialized
+    pub fn table_get(&self, name: &str, idx: usize) -> ExportResult<Option<u32>> {
+        self.find_export(name, "table")?;
+        self.table.get(idx).ok_or_else(|| {
+            Box::new(ExportError::IdxOutOfTable {
+                idx,
+                table_size: self.table.size(),
+            })
+        })
+    }
+
+    // Set the function to the exported table. None makes the element uninitialized
+    pub fn table_set(&mut self, name: &str, idx: usize, funcidx: Option<u32>) -> ExportResult<()> {
+        self.find_export(name, "table")?;
+        if let Some(funcidx) = funcidx {
+            if funcidx as usize >= self.module.funcs.len() {
+                return Err(Box::new(ExportError::UnknownFunc(funcidx)));
+            }
+        }
+        if !self.table.set(idx, funcidx) {
+            return Err(Box::new(ExportError::IdxOutOfTable {
+                idx,
+                table_size: self.table.size(),
+            }));
+        }
+        Ok(())
+    }
+
+    // Grow the exported table with uninitialized elements. Returns the previous size or -1 when
+    // the table cannot grow
+    pub fn table_grow(&mut self, name: &str, num_elems: u32) -> ExportResult<i32> {
+        self.find_export(name, "table")?;
+        Ok(self.table.grow(num_elems))
+    }
+
     // Returns if it has return value on stack or not
     fn invoke_import(
         &mut self,
         import: &ast::Import<'s>,
         has_ret: bool,
         pos: usize,
-    ) -> Result<bool> {
+    ) -> stop::Result<bool> {
         if import.mod_name.0 == "env" {
-            match self
-                .importer
-                .call(&import.name.0, &mut self.stack, &mut self.memory)
-            {
+            let result = match &mut self.guard {
+                Some(guard) => guard(&import.mod_name.0, &import.name.0)
+                    .map_err(|message| ImportInvokeError::Fatal { message }),
+                None => Ok(()),
+            }
+            .and_then(|()| I::call_with_caller(&mut Caller::new(self), &import.name.0));
+            match result {
                 Ok(()) => return Ok(has_ret),
                 Err(ImportInvokeError::Fatal { message }) => {
                     return Err(Trap::new(
@@ -172,7 +444,8 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
                             msg: message,
                         },
                         pos,
-                    ))
+                    )
+                    .into())
                 }
             }
         }
@@ -182,81 +455,632 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
         );
     }
 
-    // https://webassembly.github.io/spec/core/exec/instructions.html#function-calls
+    // Capture current Wasm call stack. Offset of the innermost frame is where the trap occurred
+    fn backtrace(&self, offset: usize) -> Vec<Frame> {
+        debug::backtrace(self.module, &self.stack, offset)
+    }
+
+    fn debug_state(&mut self) -> &mut DebugState {
+        self.debug
+            .get_or_insert_with(|| Box::new(DebugState::new()))
+    }
+
+    // Attach debugger which is called when execution pauses at breakpoints or after stepping
+    pub fn set_debugger<D: Debugger + Send + 'static>(&mut self, debugger: D) {
+        self.debug_state().set_debugger(Box::new(debugger));
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        match &self.debug {
+            Some(debug) => &debug.breakpoints,
+            None => &[],
+        }
+    }
+
+    // Returns false when the breakpoint already exists
+    pub fn add_breakpoint(&mut self, bp: Breakpoint) -> bool {
+        debug::add_breakpoint(&mut self.debug_state().breakpoints, bp)
+    }
+
+    // Returns false when the breakpoint does not exist
+    pub fn remove_breakpoint(&mut self, bp: Breakpoint) -> bool {
+        debug::remove_breakpoint(&mut self.debug_state().breakpoints, bp)
+    }
+
+    // How to start the next execution. Resume::StepIn pauses at the first instruction
+    pub fn set_resume(&mut self, resume: Resume) {
+        self.debug_state().resume = resume;
+    }
+
+    fn debug_hook(
+        &mut self,
+        insn: &ast::Instruction,
+        frame: &CallFrame<'_>,
+    ) -> std::result::Result<(), Box<Stop>> {
+        match &mut self.debug {
+            Some(debug) => debug.hook(
+                self.module,
+                insn,
+                frame,
+                &mut self.stack,
+                &mut self.memory,
+                &mut self.globals,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    // Attach tracer which observes function calls, instructions and host calls
+    pub fn set_tracer<T: Tracer + Send + 'static>(&mut self, tracer: T) {
+        self.trace_operands = tracer.wants_operands();
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    // Set the guard called before calling each host function with its module name and name. When it
+    // returns an error message, the host function is not called and the invocation traps with it
+    pub fn set_host_call_guard<G>(&mut self, guard: G)
+    where
+        G: FnMut(&str, &str) -> std::result::Result<(), String> + Send + 'static,
+    {
+        self.guard = Some(Box::new(guard));
+    }
+
+    // When enabled, a trap while invoking functions captures core dump in Trap::coredump
+    pub fn set_coredump(&mut self, enabled: bool) {
+        self.coredump = enabled;
+    }
+
+    // Take snapshot of memory, globals and table. `source` is the source bytes of the module, which
+    // is hashed to check the snapshot is restored to the same module
+    pub fn snapshot(&self, source: &[u8]) -> Snapshot {
+        let module = self.module;
+        Snapshot {
+            module_hash: Snapshot::module_hash(source),
+            memory: if module.memories.is_empty() {
+                None
+            } else {
+                Some(self.memory.to_vec())
+            },
+            globals: module
+                .globals
+                .iter()
+                .enumerate()
+                .map(|(i, g)| self.globals.get_any(i as u32, g.ty))
+                .collect(),
+            table: if module.tables.is_empty() {
+                None
+            } else {
+                Some(self.table.elems().to_vec())
+            },
+        }
+    }
+
+    // Restore memory, globals and table from the snapshot. The instance is not modified when the
+    // snapshot was not taken from the same module
+    pub fn restore(
+        &mut self,
+        snapshot: &Snapshot,
+        source: &[u8],
+    ) -> std::result::Result<(), Box<SnapshotError>> {
+        snapshot.check(self.module, source)?;
+        self.apply_snapshot(snapshot);
+        Ok(())
+    }
+
+    fn apply_snapshot(&mut self, snapshot: &Snapshot) {
+        if let Some(data) = &snapshot.memory {
+            self.memory.restore(data);
+        }
+        for (i, v) in snapshot.globals.iter().enumerate() {
+            self.globals.set_any(i as u32, v.clone());
+        }
+        if let Some(elems) = &snapshot.table {
+            self.table.restore(elems);
+        }
+    }
+
+    // When enabled, execution is suspended before calling a host function. The invocation returns
+    // a stop with StopReason::SuspendedAtHostCall and its state can be taken by Machine::checkpoint
+    pub fn suspend_at_host_call(&mut self, enabled: bool) {
+        self.suspend = enabled;
+    }
+
+    // State of the last invocation suspended at a host call. `source` is the source bytes of the
+    // module. None when the last invocation was not suspended
+    pub fn checkpoint(&self, source: &[u8]) -> Option<Checkpoint> {
+        let steps = self.suspended.as_ref()?;
+        Some(Checkpoint {
+            snapshot: self.snapshot(source),
+            stack: self.stack.values(0, 0),
+            steps: steps.iter().rev().copied().collect(),
+        })
+    }
+
+    // Restore the state from the checkpoint and resume the suspended invocation from calling the
+    // host function. It returns the result of the invocation. When execution is suspended again,
+    // a stop with StopReason::SuspendedAtHostCall is returned as well as Machine::invoke. An invalid
+    // checkpoint is reported as ExecError::Checkpoint
+    pub fn resume_from(
+        &mut self,
+        checkpoint: &Checkpoint,
+        source: &[u8],
+    ) -> stop::Result<Option<Value>> {
+        checkpoint
+            .check(self.module, source)
+            .map_err(ExecError::Checkpoint)?;
+        self.apply_snapshot(&checkpoint.snapshot);
+        self.stack = Stack::default();
+        for v in checkpoint.stack.iter() {
+            self.stack.push(v.clone());
+        }
+        self.suspended = None;
+
+        if self.resume_call(&mut checkpoint.steps.iter())? {
+            Ok(Some(self.stack.pop()))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Record the position while unwinding the call stack on suspension
+    fn unwind(&mut self, err: ExecError, step: Step) -> ExecError {
+        if let Some(StopReason::SuspendedAtHostCall { .. }) = err.stop() {
+            if let Some(steps) = &mut self.suspended {
+                steps.push(step);
+            }
+        }
+        err
+    }
+
+    fn unwind_frame(&mut self, err: ExecError, funcidx: u32, frame: &CallFrame<'_>) -> ExecError {
+        match err {
+            ExecError::Stop(stop) => {
+                if let StopReason::SuspendedAtHostCall { .. } = stop.reason {
+                    let step = Step::Frame {
+                        funcidx,
+                        base_addr: frame.base_addr,
+                        base_idx: frame.base_idx,
+                    };
+                    return self.unwind(ExecError::Stop(stop), step);
+                }
+                ExecError::Stop(stop)
+            }
+            ExecError::Trap(trap) if self.coredump => {
+                ExecError::Trap(self.dump_frame(trap, funcidx, frame))
+            }
+            err => err,
+        }
+    }
+
+    // Follow the steps of the function call at the suspended position
+    fn resume_call(&mut self, steps: &mut slice::Iter<'_, Step>) -> stop::Result<bool> {
+        let (funcidx, base_addr, base_idx) = match steps.next() {
+            Some(Step::Frame {
+                funcidx,
+                base_addr,
+                base_idx,
+            }) => (*funcidx, *base_addr, *base_idx),
+            Some(Step::Host(funcidx)) => {
+                // Call the host function without suspending again
+                let suspend = mem::replace(&mut self.suspend, false);
+                let result = self.invoke_by_funcidx(*funcidx);
+                self.suspend = suspend;
+                return result;
+            }
+            _ => unreachable!("function call is expected in checkpoint"),
+        };
+
+        let func = &self.module.funcs[funcidx as usize];
+        let fty = &self.module.types[func.idx as usize];
+        let (locals, body) = match &func.kind {
+            ast::FuncKind::Body { locals, expr } => (locals, expr),
+            ast::FuncKind::Import(_) => unreachable!("imported function in checkpoint frame"),
+        };
+        let frame = CallFrame::with_base(base_addr, base_idx, &fty.params, locals);
+
+        self.stack.push_frame(funcidx, func.start);
+        let result = match self.resume_insns(body, &frame, steps) {
+            Ok(_) => Ok(self.return_from(funcidx, &fty.results, &frame)),
+            Err(err) => Err(self.unwind_frame(err, funcidx, &frame)),
+        };
+        self.stack.pop_frame();
+        result
+    }
+
+    // Follow the steps of the instruction sequence and execute the rest of the sequence
+    fn resume_insns(
+        &mut self,
+        insns: &[ast::Instruction],
+        frame: &CallFrame<'_>,
+        steps: &mut slice::Iter<'_, Step>,
+    ) -> ExecResult {
+        let idx = match steps.next() {
+            Some(Step::Insn(idx)) => *idx,
+            _ => unreachable!("instruction is expected in checkpoint"),
+        };
+        let insn = &insns[idx];
+        let state = self
+            .resume_insn(insn, frame, steps)
+            .map_err(|trap| self.unwind(trap, Step::Insn(idx)))?;
+        match state {
+            ExecState::Continue => execute_insns(insns, idx + 1, self, frame),
+            state => Ok(state),
+        }
+    }
+
+    fn resume_insn(
+        &mut self,
+        insn: &ast::Instruction,
+        frame: &CallFrame<'_>,
+        steps: &mut slice::Iter<'_, Step>,
+    ) -> ExecResult {
+        use ast::InsnKind::*;
+
+        let (label, else_body) = match &insn.kind {
+            Call(_) | CallIndirect(_) => {
+                self.stack.set_frame_offset(insn.start);
+                self.resume_call(steps)?;
+                return Ok(ExecState::Continue);
+            }
+            _ => match steps.next() {
+                Some(Step::Label(label, else_body)) => (*label, *else_body),
+                _ => unreachable!("label is expected in checkpoint"),
+            },
+        };
+        let step = Step::Label(label, else_body);
+
+        match &insn.kind {
+            Block { body, .. } => {
+                let state = self
+                    .resume_insns(body, frame, steps)
+                    .map_err(|trap| self.unwind(trap, step))?;
+                Ok(self.end_block(label, state))
+            }
+            Loop { ty, body } => {
+                let state = self
+                    .resume_insns(body, frame, steps)
+                    .map_err(|trap| self.unwind(trap, step))?;
+                match state {
+                    ExecState::Breaking(0) => self.exec_loop(*ty, body, frame),
+                    state => Ok(self.end_loop(label, state)),
+                }
+            }
+            If {
+                then_body,
+                else_body: insns,
+                ..
+            } => {
+                let insns = if else_body { insns } else { then_body };
+                let state = self
+                    .resume_insns(insns, frame, steps)
+                    .map_err(|trap| self.unwind(trap, step))?;
+                Ok(self.end_block(label, state))
+            }
+            _ => unreachable!("control instruction is expected in checkpoint"),
+        }
+    }
+
+    // Leave block or if instruction
+    fn end_block(&mut self, label: Label, state: ExecState) -> ExecState {
+        match state {
+            ExecState::Continue | ExecState::Breaking(0) => {
+                self.stack.pop_label(label);
+                ExecState::Continue
+            }
+            ExecState::Ret => ExecState::Ret,
+            ExecState::Breaking(level) => ExecState::Breaking(level - 1),
+        }
+    }
+
+    // Leave loop instruction. Breaking to the loop itself is handled by the caller
+    fn end_loop(&mut self, label: Label, state: ExecState) -> ExecState {
+        match state {
+            ExecState::Continue => {
+                self.stack.pop_label(label);
+                ExecState::Continue
+            }
+            ExecState::Ret => ExecState::Ret,
+            ExecState::Breaking(level) => ExecState::Breaking(level - 1),
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/exec/instructions.html#exec-loop
+    fn exec_loop(
+        &mut self,
+        ty: Option<ast::ValType>,
+        body: &[ast::Instruction],
+        frame: &CallFrame<'_>,
+    ) -> ExecResult {
+        loop {
+            // Note: Difference between block and loop is the position on breaking. When reaching
+            // to the end of instruction sequence, loop instruction ends execution of subsequence.
+            let label = self.stack.push_label(ty);
+            let state = body
+                .execute(self, frame)
+                .map_err(|trap| self.unwind(trap, Step::Label(label, false)))?;
+            match state {
+                ExecState::Breaking(0) => continue,
+                state => return Ok(self.end_loop(label, state)),
+            }
+        }
+    }
+
+    // Memory, table and globals at the trap
+    fn capture(&self, trap: &Trap) -> CoreDump {
+        let module = self.module;
+        let globals = module
+            .globals
+            .iter()
+            .enumerate()
+            .map(|(i, g)| CoreGlobal {
+                value: self.globals.get_any(i as u32, g.ty),
+                mutable: g.mutable,
+            })
+            .collect();
+        let func_names = module
+            .names
+            .funcs
+            .iter()
+            .map(|(i, n)| (*i, n.0.to_string()))
+            .collect();
+        CoreDump {
+            executable: String::new(),
+            module: module
+                .names
+                .module
+                .as_ref()
+                .map(|n| n.0.to_string())
+                .unwrap_or_default(),
+            reason: trap.reason.to_string(),
+            offset: trap.offset,
+            frames: vec![],
+            globals,
+            memory: if module.memories.is_empty() {
+                None
+            } else {
+                Some(self.memory.to_vec())
+            },
+            table: if module.tables.is_empty() {
+                None
+            } else {
+                Some(self.table.elems().to_vec())
+            },
+            func_names,
+        }
+    }
+
+    // Add the frame of the function unwound by the trap to its core dump. Memory, table and globals
+    // are captured when the innermost frame is unwound. Values of the frame are popped so that the
+    // operand stack of the caller ends at the top of stack
+    fn dump_frame(
+        &mut self,
+        mut trap: Box<Trap>,
+        funcidx: u32,
+        frame: &CallFrame<'_>,
+    ) -> Box<Trap> {
+        let offset = if trap.backtrace.is_empty() {
+            trap.offset // The trap occurred in this function
+        } else {
+            self.stack
+                .frames()
+                .last()
+                .map(|f| f.offset)
+                .unwrap_or(trap.offset)
+        };
+        if trap.coredump.is_none() {
+            trap.coredump = Some(Box::new(self.capture(&trap)));
+        }
+
+        let locals = (0..frame.num_locals() as u32)
+            .map(|idx| {
+                self.stack
+                    .read_any(frame.local_addr(idx), frame.local_type(idx))
+            })
+            .collect();
+        let (addr, idx) = frame.operands_base();
+        let stack = self.stack.values(addr, idx);
+        self.stack.restore(frame.base_addr, frame.base_idx);
+
+        let start = self.module.funcs[funcidx as usize].start;
+        if let Some(dump) = &mut trap.coredump {
+            dump.frames.push(CoreFrame {
+                funcidx,
+                codeoffset: offset.saturating_sub(start) as u32,
+                locals,
+                stack,
+            });
+        }
+        trap
+    }
+
+    fn trace_insn(&mut self, insn: &ast::Instruction, frame: &CallFrame<'_>) {
+        if let Some(tracer) = &mut self.tracer {
+            let funcidx = self.stack.frames().last().map(|f| f.funcidx).unwrap_or(0);
+            let operands = if self.trace_operands {
+                let (_, base_idx) = frame.operands_base();
+                self.stack.top_values(MAX_TRACED_OPERANDS, base_idx)
+            } else {
+                vec![]
+            };
+            tracer.instruction(funcidx, &insn.kind, insn.start, &operands);
+        }
+    }
+
     // Returns if it has return value on stack or not
-    fn invoke_by_funcidx(&mut self, funcidx: u32) -> Result<bool> {
+    fn invoke_by_funcidx(&mut self, funcidx: u32) -> stop::Result<bool> {
+        let start = self.module.funcs[funcidx as usize].start;
+        self.stack.push_frame(funcidx, start);
+        let result = self.invoke_func(funcidx).map_err(|err| match err {
+            // The innermost function captures the call stack while unwinding
+            ExecError::Trap(mut trap) if trap.backtrace.is_empty() => {
+                trap.backtrace = self.backtrace(trap.offset);
+                ExecError::Trap(trap)
+            }
+            ExecError::Stop(mut stop) if stop.backtrace.is_empty() => {
+                stop.backtrace = self.backtrace(stop.offset);
+                ExecError::Stop(stop)
+            }
+            err => err,
+        });
+        self.stack.pop_frame();
+        result
+    }
+
+    // https://webassembly.github.io/spec/core/exec/instructions.html#function-calls
+    fn invoke_func(&mut self, funcidx: u32) -> stop::Result<bool> {
         let func = &self.module.funcs[funcidx as usize];
         let fty = &self.module.types[func.idx as usize];
 
         // Call this function with params
         let (locals, body) = match &func.kind {
-            ast::FuncKind::Import(i) => {
+            ast::FuncKind::Import(i) if self.suspend => {
+                self.suspended = Some(vec![Step::Host(funcidx)]);
+                return Err(Stop::new(
+                    StopReason::SuspendedAtHostCall {
+                        mod_name: i.mod_name.0.to_string(),
+                        name: i.name.0.to_string(),
+                    },
+                    func.start,
+                )
+                .into());
+            }
+            ast::FuncKind::Import(i) if self.tracer.is_none() => {
                 return self.invoke_import(i, !fty.results.is_empty(), func.start)
             }
+            ast::FuncKind::Import(i) => {
+                let args = self.stack.top_values(fty.params.len(), 0);
+                let has_ret = self.invoke_import(i, !fty.results.is_empty(), func.start)?;
+                let ret = if has_ret {
+                    self.stack.top_values(1, 0).pop()
+                } else {
+                    None
+                };
+                if let Some(tracer) = &mut self.tracer {
+                    tracer.host_call(funcidx, &i.name.0, &args, ret.as_ref());
+                }
+                return Ok(has_ret);
+            }
             ast::FuncKind::Body { locals, expr } => (locals, expr),
         };
 
         // Push call frame
         let frame = CallFrame::new(&self.stack, &fty.params, locals);
 
+        if let Some(tracer) = &mut self.tracer {
+            let args = self.stack.top_values(fty.params.len(), frame.base_idx);
+            tracer.enter(funcidx, &args);
+        }
+
         self.stack.extend_zero_values(&locals);
 
-        for insn in body.iter() {
-            match insn.execute(self, &frame)? {
-                ExecState::Continue => {}
-                // When using br or br_if outside control instructions, it unwinds execution in
-                // the function body. Label with empty continuation is put before invoking the
-                // function body (11.). It means that breaking outside control instructions will be
-                // caught by this label.
-                ExecState::Ret | ExecState::Breaking(_) => break,
-            }
+        if let Some(debug) = &mut self.debug {
+            debug.entering = true;
         }
 
-        if fty.results.is_empty() {
+        // When using br or br_if outside control instructions, it unwinds execution in the function
+        // body. Label with empty continuation is put before invoking the function body (11.). It
+        // means that breaking outside control instructions will be caught by this label.
+        if let Err(err) = body.execute(self, &frame) {
+            return Err(self.unwind_frame(err, funcidx, &frame));
+        }
+
+        if let Some(debug) = &mut self.debug {
+            debug.entering = false; // When the function body is empty
+        }
+
+        Ok(self.return_from(funcidx, &fty.results, &frame))
+    }
+
+    // Pop the call frame and push the result value. Returns if it has return value on stack or not
+    fn return_from(
+        &mut self,
+        funcidx: u32,
+        results: &[ast::ValType],
+        frame: &CallFrame<'_>,
+    ) -> bool {
+        let has_ret = if results.is_empty() {
             self.stack.restore(frame.base_addr, frame.base_idx); // Pop call frame
-            Ok(false)
+            false
         } else {
             // Push 1st result value since number of result type is 1 or 0 for MVP
             let v: Value = self.stack.pop();
             self.stack.restore(frame.base_addr, frame.base_idx); // Pop call frame
             self.stack.push(v); // push result value
-            Ok(true)
-        }
-    }
-
-    pub fn invoke(&mut self, name: impl AsRef<str>, args: &[Value]) -> Result<Option<Value>> {
-        fn find_func_to_invoke<'s>(
-            name: &str,
-            exports: &[ast::Export<'s>],
-        ) -> Result<(u32, usize)> {
-            for export in exports {
-                if export.name.0 == name {
-                    let actual = match export.kind {
-                        ast::ExportKind::Func(idx) => return Ok((idx, export.start)),
-                        ast::ExportKind::Table(_) => "table",
-                        ast::ExportKind::Memory(_) => "memory",
-                        ast::ExportKind::Global(_) => "global variable",
-                    };
-                    return Err(Trap::new(
-                        TrapReason::WrongInvokeTarget {
-                            name: name.to_string(),
-                            actual: Some(actual),
-                        },
-                        export.start,
-                    ));
-                }
+            true
+        };
+
+        if let Some(tracer) = &mut self.tracer {
+            let ret = if has_ret {
+                self.stack.top_values(1, 0).pop()
+            } else {
+                None
+            };
+            tracer.exit(funcidx, ret.as_ref());
+        }
+
+        has_ret
+    }
+
+    pub(crate) fn find_func_to_invoke(&self, name: &str) -> Result<(u32, usize)> {
+        for export in self.module.exports.iter() {
+            if export.name.0 == name {
+                let actual = match export.kind {
+                    ast::ExportKind::Func(idx) => return Ok((idx, export.start)),
+                    ast::ExportKind::Table(_) => "table",
+                    ast::ExportKind::Memory(_) => "memory",
+                    ast::ExportKind::Global(_) => "global variable",
+                };
+                return Err(Trap::new(
+                    TrapReason::WrongInvokeTarget {
+                        name: name.to_string(),
+                        actual: Some(actual),
+                    },
+                    export.start,
+                ));
             }
-            Err(Trap::new(
-                TrapReason::WrongInvokeTarget {
-                    name: name.to_string(),
-                    actual: None,
-                },
-                0,
-            ))
         }
+        Err(Trap::new(
+            TrapReason::WrongInvokeTarget {
+                name: name.to_string(),
+                actual: None,
+            },
+            0,
+        ))
+    }
 
+    pub fn invoke(&mut self, name: impl AsRef<str>, args: &[Value]) -> stop::Result<Option<Value>> {
         let name = name.as_ref();
-        let (funcidx, start) = find_func_to_invoke(name, &self.module.exports)?;
+        let (funcidx, start) = self.find_func_to_invoke(name)?;
+        self.suspended = None;
+        self.invoke_with_args(funcidx, name, args, start)
+    }
+
+    // Invoke the function from a host function. Values and frames on the stack are kept even if the
+    // invocation traps. Suspension is disabled since the host call cannot be recorded in checkpoint
+    pub(crate) fn reenter(
+        &mut self,
+        funcidx: u32,
+        name: &str,
+        args: &[Value],
+        start: usize,
+    ) -> stop::Result<Option<Value>> {
+        let label = self.stack.push_label(None);
+        let suspend = mem::replace(&mut self.suspend, false);
+        let result = self.invoke_with_args(funcidx, name, args, start);
+        self.suspend = suspend;
+        if result.is_err() {
+            self.stack.pop_label(label);
+        }
+        result
+    }
+
+    fn invoke_with_args(
+        &mut self,
+        funcidx: u32,
+        name: &str,
+        args: &[Value],
+        start: usize,
+    ) -> stop::Result<Option<Value>> {
         let arg_types = &self.module.types[self.module.funcs[funcidx as usize].idx as usize].params;
 
         // Check parameter types
@@ -272,7 +1096,8 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
                     arg_types: arg_types.iter().copied().collect(),
                 },
                 start,
-            ));
+            )
+            .into());
         }
 
         // Push values to stack for invoking the function
@@ -287,8 +1112,42 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
         }
     }
 
+    // Get handle of the exported function to call it with Rust values. The signature is checked
+    // here so calling the handle does not check types of arguments
+    pub fn get_typed_func<P: IntoWasmValues, R: FromWasmValues>(
+        &self,
+        name: impl AsRef<str>,
+    ) -> ExportResult<TypedFunc<'m, 's, P, R>> {
+        let name = name.as_ref();
+        let funcidx = self.find_export(name, "function")?;
+        let fty = &self.module.types[self.module.funcs[funcidx as usize].idx as usize];
+        if P::VAL_TYPES != &fty.params[..] || R::VAL_TYPES != &fty.results[..] {
+            return Err(Box::new(ExportError::SignatureMismatch {
+                name: name.to_string(),
+                expected_params: P::VAL_TYPES,
+                expected_results: R::VAL_TYPES,
+                actual_params: fty.params.clone().into_boxed_slice(),
+                actual_results: fty.results.clone().into_boxed_slice(),
+            }));
+        }
+        Ok(TypedFunc::new(self.module, funcidx))
+    }
+
+    pub(crate) fn invoke_typed<P: IntoWasmValues, R: FromWasmValues>(
+        &mut self,
+        funcidx: u32,
+        params: P,
+    ) -> stop::Result<R> {
+        self.suspended = None;
+        params.push_to(&mut self.stack);
+        self.invoke_by_funcidx(funcidx)?;
+        Ok(R::pop_from(&mut self.stack))
+    }
+
     // As the last step of instantiation, invoke start function
-    pub fn execute(&mut self) -> Result<Run> {
+    pub fn execute(&mut self) -> stop::Result<Run> {
+        self.suspended = None;
+
         // 15. If the start function is not empty, invoke it
         if let Some(start) = &self.module.entrypoint {
             // Execute entrypoint
@@ -317,17 +1176,62 @@ impl<'m, 's, I: Importer> Machine<'m, 's, I> {
         addr
     }
 
-    fn load<V: LittleEndian>(&mut self, mem: &ast::Mem, at: usize) -> Result<V> {
+    fn load<V: Watched>(&mut self, mem: &ast::Mem, at: usize) -> stop::Result<V> {
         let addr = self.mem_addr(mem);
+        if self.watch.is_some() {
+            self.watch_hook::<V>(addr, None, at)?;
+        }
         Ok(self.memory.load(addr, at)?)
     }
 
-    fn store<V: LittleEndian>(&mut self, mem: &ast::Mem, v: V, at: usize) -> Result<()> {
+    fn store<V: Watched>(&mut self, mem: &ast::Mem, v: V, at: usize) -> stop::Result<()> {
         let addr = self.mem_addr(mem);
+        if self.watch.is_some() {
+            self.watch_hook(addr, Some(v), at)?;
+        }
         self.memory.store(addr, v, at)?;
         Ok(())
     }
 
+    fn watch_hook<V: Watched>(
+        &mut self,
+        addr: usize,
+        new: Option<V>,
+        at: usize,
+    ) -> std::result::Result<(), Box<Stop>> {
+        let funcidx = self.stack.frames().last().map(|f| f.funcidx).unwrap_or(0);
+        match &mut self.watch {
+            Some(watch) => watch.hook(&self.memory, funcidx, addr, new, at),
+            None => Ok(()),
+        }
+    }
+
+    // Watch memory accesses by load/store instructions in the address range. The hook is called
+    // before the access. Accesses by importers or debuggers are not watched. Returns ID of the
+    // watchpoint to remove it later
+    pub fn add_watchpoint<H: MemoryHook + Send + 'static>(
+        &mut self,
+        range: Range<usize>,
+        watch: Watch,
+        hook: H,
+    ) -> usize {
+        self.watch
+            .get_or_insert_with(Default::default)
+            .add(range, watch, Box::new(hook))
+    }
+
+    // Returns false when the watchpoint does not exist
+    pub fn remove_watchpoint(&mut self, id: usize) -> bool {
+        let removed = match &mut self.watch {
+            Some(watch) => watch.remove(id),
+            None => false,
+        };
+        if self.watch.as_ref().map(|w| w.is_empty()).unwrap_or(false) {
+            self.watch = None;
+        }
+        removed
+    }
+
     // https://webassembly.github.io/spec/core/exec/instructions.html#exec-unop
     fn unop<T, F>(&mut self, op: F)
     where
@@ -404,17 +1308,27 @@ trait Execute<'f, 'm, 's, I: Importer> {
 }
 
 // https://webassembly.github.io/spec/core/exec/instructions.html#blocks
-impl<'f, 'm, 's, I: Importer> Execute<'f, 'm, 's, I> for Vec<ast::Instruction> {
+impl<'f, 'm, 's, I: Importer> Execute<'f, 'm, 's, I> for [ast::Instruction] {
     fn execute(&self, machine: &mut Machine<'m, 's, I>, frame: &CallFrame<'f>) -> ExecResult {
-        // Run instruction sequence as block
-        for insn in self.iter() {
-            match insn.execute(machine, frame)? {
-                ExecState::Continue => {}
-                state => return Ok(state), // Stop executing this block on return or break
-            }
+        execute_insns(self, 0, machine, frame)
+    }
+}
+
+// Run instruction sequence from the index as block
+fn execute_insns<'f, 'm, 's, I: Importer>(
+    insns: &[ast::Instruction],
+    start: usize,
+    machine: &mut Machine<'m, 's, I>,
+    frame: &CallFrame<'f>,
+) -> ExecResult {
+    for (i, insn) in insns[start..].iter().enumerate() {
+        match insn.execute(machine, frame) {
+            Ok(ExecState::Continue) => {}
+            Ok(state) => return Ok(state), // Stop executing this block on return or break
+            Err(trap) => return Err(machine.unwind(trap, Step::Insn(start + i))),
         }
-        Ok(ExecState::Continue)
     }
+    Ok(ExecState::Continue)
 }
 
 // https://webassembly.github.io/spec/core/exec/instructions.html
@@ -422,35 +1336,27 @@ impl<'f, 'm, 's, I: Importer> Execute<'f, 'm, 's, I> for ast::Instruction {
     #[allow(clippy::cognitive_complexity)]
     fn execute(&self, machine: &mut Machine<'m, 's, I>, frame: &CallFrame<'f>) -> ExecResult {
         use ast::InsnKind::*;
+
+        if machine.debug.is_some() {
+            machine.debug_hook(self, frame)?;
+        }
+        if machine.tracer.is_some() {
+            machine.trace_insn(self, frame);
+        }
+
         #[allow(clippy::float_cmp)]
         match &self.kind {
             // Control instructions
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-block
             Block { ty, body } => {
                 let label = machine.stack.push_label(*ty);
-                match body.execute(machine, frame)? {
-                    ExecState::Continue => {}
-                    ExecState::Ret => return Ok(ExecState::Ret),
-                    ExecState::Breaking(0) => {}
-                    ExecState::Breaking(level) => return Ok(ExecState::Breaking(level - 1)),
-                }
-                machine.stack.pop_label(label);
+                let state = body
+                    .execute(machine, frame)
+                    .map_err(|trap| machine.unwind(trap, Step::Label(label, false)))?;
+                return Ok(machine.end_block(label, state));
             }
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-loop
-            Loop { ty, body } => loop {
-                // Note: Difference between block and loop is the position on breaking. When reaching
-                // to the end of instruction sequence, loop instruction ends execution of subsequence.
-                let label = machine.stack.push_label(*ty);
-                match body.execute(machine, frame)? {
-                    ExecState::Continue => {
-                        machine.stack.pop_label(label);
-                        break;
-                    }
-                    ExecState::Ret => return Ok(ExecState::Ret),
-                    ExecState::Breaking(0) => continue,
-                    ExecState::Breaking(level) => return Ok(ExecState::Breaking(level - 1)),
-                }
-            },
+            Loop { ty, body } => return machine.exec_loop(*ty, body, frame),
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-if
             If {
                 ty,
@@ -460,16 +1366,13 @@ impl<'f, 'm, 's, I: Importer> Execute<'f, 'm, 's, I> for ast::Instruction {
                 let cond: i32 = machine.stack.pop();
                 let label = machine.stack.push_label(*ty);
                 let insns = if cond != 0 { then_body } else { else_body };
-                match insns.execute(machine, frame)? {
-                    ExecState::Continue => {}
-                    ExecState::Ret => return Ok(ExecState::Ret),
-                    ExecState::Breaking(0) => {}
-                    ExecState::Breaking(level) => return Ok(ExecState::Breaking(level - 1)),
-                }
-                machine.stack.pop_label(label);
+                let state = insns
+                    .execute(machine, frame)
+                    .map_err(|trap| machine.unwind(trap, Step::Label(label, cond == 0)))?;
+                return Ok(machine.end_block(label, state));
             }
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-unreachable
-            Unreachable => return Err(Trap::new(TrapReason::ReachUnreachable, self.start)),
+            Unreachable => return Err(Trap::new(TrapReason::ReachUnreachable, self.start).into()),
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-nop
             Nop => { /* yay! nothing to do */ }
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-br
@@ -499,6 +1402,7 @@ impl<'f, 'm, 's, I: Importer> Execute<'f, 'm, 's, I> for ast::Instruction {
             Return => return Ok(ExecState::Ret),
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-call
             Call(funcidx) => {
+                machine.stack.set_frame_offset(self.start);
                 machine.invoke_by_funcidx(*funcidx)?;
             }
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-call-indirect
@@ -520,8 +1424,10 @@ impl<'f, 'm, 's, I: Importer> Execute<'f, 'm, 's, I> for ast::Instruction {
                             actual_results: actual.results.clone().into_boxed_slice(),
                         },
                         self.start,
-                    ));
+                    )
+                    .into());
                 }
+                machine.stack.set_frame_offset(self.start);
                 machine.invoke_by_funcidx(funcidx)?;
             }
             // Parametric instructions
@@ -574,7 +1480,7 @@ impl<'f, 'm, 's, I: Importer> Execute<'f, 'm, 's, I> for ast::Instruction {
                 ast::ValType::F64 => machine.stack.push(machine.globals.get::<f64>(*globalidx)),
             },
             // https://webassembly.github.io/spec/core/exec/instructions.html#exec-global-set
-            GlobalSet(globalidx) => machine.globals.set_any(*globalidx, machine.stack.top()),
+            GlobalSet(globalidx) => machine.globals.set_any(*globalidx, machine.stack.pop()),
             // Memory instructions
             // https://webassembly.github.io/spec/core/exec/instructions.html#and
             I32Load(mem) => {
@@ -945,6 +1851,7 @@ impl<'f, 'm, 's, I: Importer> Execute<'f, 'm, 's, I> for ast::Instruction {
 mod tests {
     use super::*;
     use crate::import::DefaultImporter;
+    use crate::stack::StackFrame;
     use std::borrow::Cow;
     use std::env;
     use std::fmt;
@@ -952,7 +1859,7 @@ mod tests {
     use std::io::{self, Read, Write};
     use std::path::PathBuf;
     use std::result;
-    use wain_syntax_text::parse;
+    use wain_syntax_text::parse_with_id_names as parse;
     use wain_validate::validate;
 
     struct Discard;
@@ -1052,7 +1959,10 @@ mod tests {
 
         let importer = DefaultImporter::with_stdio(Discard, Discard);
         let mut machine = Machine::instantiate(&module, importer)?;
-        machine.invoke("test", &[])
+        machine.invoke("test", &[]).map_err(|err| match err {
+            ExecError::Trap(trap) => trap,
+            err => panic!("unexpected error: {}", err),
+        })
     }
 
     #[test]
@@ -1161,4 +2071,401 @@ mod tests {
         let e = exec_insns(I64, vec![I64Const(1), I64Const(0), I64RemU]).unwrap_err();
         assert!(matches!(e.reason, TrapReason::RemZeroDivisor));
     }
+
+    #[test]
+    fn unsupported_target_feature() {
+        fn feature(prefix: ast::FeaturePrefix, name: &'static str) -> ast::TargetFeature<'static> {
+            ast::TargetFeature {
+                start: 0,
+                prefix,
+                name: ast::Name(Cow::Borrowed(name)),
+            }
+        }
+
+        let mut module = ast::Module::default();
+        module
+            .target_features
+            .push(feature(ast::FeaturePrefix::Used, "mutable-globals"));
+        module
+            .target_features
+            .push(feature(ast::FeaturePrefix::Disallowed, "simd128"));
+        module
+            .target_features
+            .push(feature(ast::FeaturePrefix::Used, "sign-ext"));
+        module
+            .target_features
+            .push(feature(ast::FeaturePrefix::Required, "mutable-globals"));
+        let importer = DefaultImporter::with_stdio(Discard, Discard);
+        assert!(Machine::instantiate(&module, importer).is_ok());
+
+        module
+            .target_features
+            .push(feature(ast::FeaturePrefix::Required, "atomics"));
+        let importer = DefaultImporter::with_stdio(Discard, Discard);
+        match Machine::instantiate(&module, importer) {
+            Ok(_) => panic!("unsupported feature was not reported"),
+            Err(e) => {
+                assert!(matches!(&e.reason, TrapReason::UnsupportedFeature(n) if n == "atomics"));
+                assert!(e.to_string().contains("-mno-atomics"), "{}", e);
+            }
+        }
+    }
+
+    fn custom(name: &str, payload: &[&[u8]]) -> Vec<u8> {
+        let payload = payload.concat();
+        let size = 1 + name.len() + payload.len();
+        let mut sec = vec![0x00, size as u8, name.len() as u8];
+        sec.extend_from_slice(name.as_bytes());
+        sec.extend_from_slice(&payload);
+        sec
+    }
+
+    #[test]
+    fn compiler_target_features() {
+        // Custom sections emitted by clang with default options
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        bin.extend(custom(
+            "producers",
+            &[b"\x01", b"\x0cprocessed-by\x01", b"\x05clang\x0616.0.0"],
+        ));
+        bin.extend(custom(
+            "target_features",
+            &[b"\x02", b"+\x0fmutable-globals", b"+\x08sign-ext"],
+        ));
+        let root = wain_syntax_binary::parse(&bin).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(root.module.target_features.len(), 2);
+        let importer = DefaultImporter::with_stdio(Discard, Discard);
+        if let Err(e) = Machine::instantiate(&root.module, importer) {
+            panic!("used features were rejected: {}", e);
+        }
+
+        // Required feature is rejected
+        bin.extend(custom("target_features", &[b"\x01", b"=\x07atomics"]));
+        let root = wain_syntax_binary::parse(&bin).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(Discard, Discard);
+        assert!(Machine::instantiate(&root.module, importer).is_err());
+    }
+
+    #[test]
+    fn used_features_explain_parse_error() {
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        bin.extend_from_slice(b"\x01\x04\x01\x60\x00\x00"); // Type section
+        bin.extend_from_slice(b"\x03\x02\x01\x00"); // Function section
+        bin.extend_from_slice(b"\x0a\x17\x01\x15\x00\xfd\x0c"); // Code section with v128.const
+        bin.extend_from_slice(&[0; 16]);
+        bin.extend_from_slice(b"\x1a\x0b");
+        bin.extend(custom(
+            "target_features",
+            &[
+                b"\x03",
+                b"+\x0fmutable-globals",
+                b"+\x07simd128",
+                b"-\x07atomics",
+            ],
+        ));
+
+        let err = match wain_syntax_binary::parse(&bin) {
+            Err(err) => err,
+            Ok(_) => panic!("SIMD instruction was parsed"),
+        };
+        assert!(err.to_string().contains("0xfd"), "{}", err);
+        let features =
+            wain_syntax_binary::target_features(&bin).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(features.len(), 3);
+        assert_eq!(unsupported_features(&features), ["simd128"]);
+    }
+
+    #[test]
+    fn trap_backtrace() {
+        let source = r#"
+            (module
+              (func $inner (param i32) (result i32)
+                unreachable
+                local.get 0)
+              (func $middle (result i32)
+                i32.const 1
+                call $inner)
+              (func (export "outer") (result i32)
+                nop
+                call $middle))
+        "#;
+        let ast = parse(source).unwrap_or_else(|e| panic!("{}", e));
+        validate(&ast).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(Discard, Discard);
+        let mut machine = Machine::instantiate(&ast.module, importer).unwrap();
+        let trap = match machine.invoke("outer", &[]) {
+            Err(ExecError::Trap(trap)) => trap,
+            r => panic!("unexpected result: {:?}", r),
+        };
+        assert!(matches!(trap.reason, TrapReason::ReachUnreachable));
+
+        let funcs: Vec<_> = trap
+            .backtrace
+            .iter()
+            .map(|f| (f.funcidx, f.name.as_deref()))
+            .collect();
+        assert_eq!(funcs, [(0, Some("inner")), (1, Some("middle")), (2, None)]);
+
+        let offset_of = |pat: &str| source.find(pat).unwrap();
+        assert_eq!(trap.backtrace[0].offset, trap.offset);
+        assert_eq!(trap.backtrace[0].offset, offset_of("unreachable"));
+        assert_eq!(trap.backtrace[1].offset, offset_of("call $inner"));
+        assert_eq!(trap.backtrace[2].offset, offset_of("call $middle"));
+
+        let msg = trap.to_string();
+        assert!(
+            msg.contains("\nbacktrace:\n  #0: 'inner' (function 0)"),
+            "{}",
+            msg
+        );
+        assert!(msg.contains("\n  #2: function 2 at byte offset"), "{}", msg);
+
+        // Call stack is unwound after trap
+        assert!(machine.stack.frames().is_empty());
+    }
+
+    #[test]
+    fn stack_frames_in_importer() {
+        struct Recorder(Vec<Vec<StackFrame>>);
+        impl Importer for Recorder {
+            fn validate(
+                &self,
+                _: &str,
+                _: &[ast::ValType],
+                _: Option<ast::ValType>,
+            ) -> Option<ImportInvalidError> {
+                None
+            }
+            fn call(
+                &mut self,
+                _: &str,
+                stack: &mut Stack,
+                _: &mut Memory,
+            ) -> result::Result<(), ImportInvokeError> {
+                self.0.push(stack.frames().to_vec());
+                Ok(())
+            }
+        }
+
+        let source = r#"
+            (module
+              (import "env" "record" (func $record))
+              (func $f
+                call $record)
+              (func (export "start")
+                call $f
+                call $record))
+        "#;
+        let ast = parse(source).unwrap_or_else(|e| panic!("{}", e));
+        validate(&ast).unwrap_or_else(|e| panic!("{}", e));
+        let mut machine = Machine::instantiate(&ast.module, Recorder(vec![])).unwrap();
+        machine.invoke("start", &[]).unwrap();
+
+        let offset_of = |pat: &str| source.find(pat).unwrap();
+        let frame = |funcidx, offset| StackFrame { funcidx, offset };
+        let import_start = ast.module.funcs[0].start;
+        assert_eq!(
+            machine.importer.0,
+            [
+                vec![
+                    frame(2, offset_of("call $f")),
+                    frame(1, offset_of("call $record)")),
+                    frame(0, import_start),
+                ],
+                vec![
+                    frame(2, offset_of("call $record))")),
+                    frame(0, import_start),
+                ],
+            ],
+        );
+    }
+
+    #[test]
+    fn fork_instance() {
+        #[derive(Clone)]
+        struct NoImport;
+        impl Importer for NoImport {
+            fn validate(
+                &self,
+                _: &str,
+                _: &[ast::ValType],
+                _: Option<ast::ValType>,
+            ) -> Option<ImportInvalidError> {
+                Some(ImportInvalidError::NotFound)
+            }
+            fn call(
+                &mut self,
+                _: &str,
+                _: &mut Stack,
+                _: &mut Memory,
+            ) -> result::Result<(), ImportInvokeError> {
+                unreachable!()
+            }
+        }
+
+        let source = r#"
+            (module
+              (memory 2)
+              (table 2 funcref)
+              (global $n (export "n") (mut i32) (i32.const 0))
+              (func $f (result i32)
+                global.get $n)
+              (elem (i32.const 0) $f)
+              (func (export "set") (param $addr i32) (param $v i32)
+                local.get $addr
+                local.get $v
+                i32.store
+                local.get $v
+                global.set $n)
+              (func (export "get") (param $addr i32) (result i32)
+                local.get $addr
+                i32.load))
+        "#;
+        let ast = parse(source).unwrap_or_else(|e| panic!("{}", e));
+        validate(&ast).unwrap_or_else(|e| panic!("{}", e));
+        let mut parent = Machine::instantiate(&ast.module, NoImport).unwrap();
+        let invoke = |m: &mut Machine<'_, '_, NoImport>, name, args: &[Value]| {
+            m.invoke(name, args).unwrap_or_else(|t| panic!("{}", t))
+        };
+        invoke(&mut parent, "set", &[Value::I32(65534), Value::I32(42)]);
+
+        let mut child = parent.fork();
+        assert_eq!(child.get_global("n"), Some(Value::I32(42)));
+        assert_eq!(
+            invoke(&mut child, "get", &[Value::I32(65534)]),
+            Some(Value::I32(42))
+        );
+        assert_eq!(child.table.elems(), parent.table.elems());
+
+        // Instances are independent after forking
+        invoke(&mut child, "set", &[Value::I32(65534), Value::I32(7)]);
+        invoke(&mut parent, "set", &[Value::I32(0), Value::I32(9)]);
+        assert_eq!(child.get_global("n"), Some(Value::I32(7)));
+        assert_eq!(parent.get_global("n"), Some(Value::I32(9)));
+        assert_eq!(
+            invoke(&mut parent, "get", &[Value::I32(65534)]),
+            Some(Value::I32(42))
+        );
+        assert_eq!(
+            invoke(&mut child, "get", &[Value::I32(0)]),
+            Some(Value::I32(0))
+        );
+
+        let grandchild = child.fork_with(NoImport);
+        assert_eq!(
+            grandchild.memory().read_range(65534..65538),
+            Some(vec![7, 0, 0, 0])
+        );
+    }
+
+    #[test]
+    fn exports_api() {
+        let source = r#"
+            (module
+              (memory (export "mem") 1 2)
+              (table (export "tbl") 2 3 funcref)
+              (global $cfg (export "cfg") (mut i32) (i32.const 1))
+              (global (export "pi") f64 (f64.const 3.14))
+              (func $one (result i32) i32.const 1)
+              (func $two (result i32) i32.const 2)
+              (elem (i32.const 0) $one)
+              (func (export "run") (param $i i32) (result i32)
+                i32.const 0
+                i32.load
+                global.get $cfg
+                i32.add
+                local.get $i
+                call_indirect (result i32)
+                i32.add))
+        "#;
+        let ast = parse(source).unwrap_or_else(|e| panic!("{}", e));
+        validate(&ast).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine = Machine::instantiate(&ast.module, importer).unwrap();
+
+        let exports: Vec<_> = machine
+            .exports()
+            .into_iter()
+            .map(|(name, ty)| match ty {
+                ast::ExternType::Func(fty) => {
+                    format!("{}: func {:?} {:?}", name, fty.params, fty.results)
+                }
+                ast::ExternType::Table(_) => format!("{}: table", name),
+                ast::ExternType::Memory(_) => format!("{}: memory", name),
+                ast::ExternType::Global { ty, mutable } => {
+                    format!("{}: global {} {}", name, ty, mutable)
+                }
+            })
+            .collect();
+        assert_eq!(
+            exports,
+            vec![
+                "mem: memory",
+                "tbl: table",
+                "cfg: global i32 true",
+                "pi: global f64 false",
+                "run: func [I32] [I32]",
+            ]
+        );
+
+        // Write input to memory and tweak configurations before invoking
+        let memory = machine
+            .memory_mut("mem")
+            .unwrap_or_else(|t| panic!("{}", t));
+        assert!(memory.write_range(0, &10i32.to_le_bytes()));
+        assert_eq!(memory.grow(1), 1);
+        assert_eq!(memory.grow(1), -1);
+        machine.set_global("cfg", Value::I32(20)).unwrap();
+        assert_eq!(machine.get_global("cfg"), Some(Value::I32(20)));
+        assert_eq!(machine.table_get("tbl", 1).unwrap(), None);
+        machine.table_set("tbl", 1, Some(1)).unwrap();
+        assert_eq!(machine.table_get("tbl", 1).unwrap(), Some(1));
+        assert_eq!(
+            machine.invoke("run", &[Value::I32(1)]).unwrap(),
+            Some(Value::I32(32))
+        );
+        assert_eq!(machine.table_grow("tbl", 1).unwrap(), 2);
+        assert_eq!(machine.table_grow("tbl", 1).unwrap(), -1);
+        assert_eq!(machine.table_size("tbl").unwrap(), 3);
+        assert_eq!(machine.table_get("tbl", 2).unwrap(), None);
+        machine.table_set("tbl", 0, None).unwrap();
+        assert!(machine.invoke("run", &[Value::I32(0)]).is_err());
+
+        let error = |r: ExportResult<()>| match r {
+            Err(e) => e,
+            Ok(()) => panic!("error did not occur"),
+        };
+        let err = error(machine.set_global("pi", Value::F64(3.0)));
+        assert!(matches!(*err, ExportError::Immutable(_)));
+        assert_eq!(
+            err.to_string(),
+            "cannot set value to immutable global variable 'pi'"
+        );
+        let err = error(machine.set_global("cfg", Value::I64(1)));
+        assert!(matches!(*err, ExportError::TypeMismatch { .. }));
+        assert_eq!(
+            err.to_string(),
+            "cannot set value 1i64 to global variable 'cfg' of type i32"
+        );
+        let err = error(machine.set_global("mem", Value::I32(1)));
+        assert!(matches!(*err, ExportError::WrongKind { .. }));
+        assert_eq!(
+            err.to_string(),
+            "export 'mem' is memory but global variable is expected"
+        );
+        let err = error(machine.memory_mut("unknown").map(|_| ()));
+        assert!(matches!(*err, ExportError::NotFound { .. }));
+        assert_eq!(err.to_string(), "memory 'unknown' is not exported");
+        let err = error(machine.table_set("tbl", 3, Some(0)));
+        assert!(matches!(
+            *err,
+            ExportError::IdxOutOfTable {
+                idx: 3,
+                table_size: 3
+            }
+        ));
+        let err = error(machine.table_set("tbl", 0, Some(3)));
+        assert!(matches!(*err, ExportError::UnknownFunc(3)));
+        assert_eq!(machine.get_global("cfg"), Some(Value::I32(20)));
+    }
 }
@@ -3,18 +3,39 @@ use crate::trap::{Result, Trap, TrapReason};
 use crate::value::LittleEndian;
 use std::any;
 use std::mem::size_of;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
 use wain_ast as ast;
 
 const PAGE_SIZE: usize = 65536; // 64Ki
 const MAX_MEMORY_BYTES: usize = u32::MAX as usize; // Address space of Wasm is 32bits
 
+type Page = [u8; PAGE_SIZE];
+
+static ZERO_PAGE: OnceLock<Arc<Page>> = OnceLock::new();
+
+// Page filled with zeros shared by all untouched pages
+fn zero_page() -> Arc<Page> {
+    ZERO_PAGE.get_or_init(|| Arc::new([0; PAGE_SIZE])).clone()
+}
+
+fn is_zero_page(page: &Arc<Page>) -> bool {
+    Arc::ptr_eq(page, &zero_page())
+}
+
 // Memory instance
 //
+// Memory buffer is split into pages. Cloning memory shares the pages and a shared page is copied
+// on the first write to it (copy-on-write). It makes forking an instance cheap. Untouched pages
+// share the zero page so they are not allocated until they are written (lazy commit). Allocating
+// or growing large memory only costs a pointer per page.
+//
 // Note: It is more efficient to implement memory buffer by memory mapped buffer. However there is
 // no way to use mmap without unsafe.
+#[derive(Clone)]
 pub struct Memory {
     max: Option<u32>,
-    data: Vec<u8>,
+    pages: Vec<Arc<Page>>,
 }
 
 impl Memory {
@@ -30,19 +51,14 @@ impl Memory {
                     ast::Limits::Range(min, max) => (*min, Some(*max)),
                     ast::Limits::From(min) => (*min, None),
                 };
-                let data = if min == 0 {
-                    vec![]
-                } else {
-                    let len = (min as usize) * PAGE_SIZE;
-                    vec![0; len]
-                };
-                Ok(Self { max, data })
+                let pages = vec![zero_page(); min as usize];
+                Ok(Self { max, pages })
             }
         } else {
             // When no table is set use dummy empty table
             Ok(Self {
                 max: Some(0),
-                data: vec![],
+                pages: vec![],
             })
         }
     }
@@ -74,35 +90,36 @@ impl Memory {
             }
         }
 
-        if self.data.len() < end_addr {
+        if self.byte_len() < end_addr {
             return Err(Trap::new(
                 TrapReason::DataSegmentOutOfBuffer {
                     segment_end: end_addr,
-                    buffer_size: self.data.len(),
+                    buffer_size: self.byte_len(),
                 },
                 segment.start,
             ));
         }
 
-        // TODO: copy_from_slice is slower here. I need to investigate the reason
-        #[allow(clippy::manual_memcpy)]
-        {
-            for i in 0..data.len() {
-                self.data[offset + i] = data[i];
-            }
-        }
-
+        self.write_at(offset, data);
         Ok(())
     }
 
     pub fn size(&self) -> u32 {
-        (self.data.len() / PAGE_SIZE) as u32
+        self.pages.len() as u32
+    }
+
+    // Size of memory in bytes
+    pub fn byte_len(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
     }
 
     pub fn grow(&mut self, num_pages: u32) -> i32 {
         // https://webassembly.github.io/spec/core/exec/instructions.html#exec-memory-grow
         let prev = self.size();
-        let next = prev + num_pages;
+        let next = match prev.checked_add(num_pages) {
+            Some(next) => next,
+            None => return -1,
+        };
         if let Some(max) = self.max {
             if next > max {
                 return -1;
@@ -116,7 +133,7 @@ impl Memory {
             // And memory_grow.wast expects allocating more than 2^32 - 1 to fail.
             return -1;
         }
-        self.data.resize(next_len, 0);
+        self.pages.resize(next as usize, zero_page());
         prev as i32
     }
 
@@ -126,10 +143,10 @@ impl Memory {
         at: usize,
         operation: &'static str,
     ) -> Result<()> {
-        if addr.saturating_add(size_of::<V>()) > self.data.len() {
+        if addr.saturating_add(size_of::<V>()) > self.byte_len() {
             Err(Trap::new(
                 TrapReason::LoadMemoryOutOfRange {
-                    max: self.data.len(),
+                    max: self.byte_len(),
                     addr,
                     operation,
                     ty: any::type_name::<V>(),
@@ -142,23 +159,345 @@ impl Memory {
     }
 
     // https://webassembly.github.io/spec/core/exec/instructions.html#and
+    #[inline]
     pub fn load<V: LittleEndian>(&self, addr: usize, at: usize) -> Result<V> {
+        let offset = addr % PAGE_SIZE;
+        if offset + size_of::<V>() <= PAGE_SIZE {
+            if let Some(page) = self.pages.get(addr / PAGE_SIZE) {
+                return Ok(LittleEndian::read(&page[..], offset));
+            }
+        }
+        self.load_slow(addr, at)
+    }
+
+    // The value lies across the page boundary or out of range
+    #[cold]
+    fn load_slow<V: LittleEndian>(&self, addr: usize, at: usize) -> Result<V> {
         self.check_addr::<V>(addr, at, "load")?;
-        Ok(LittleEndian::read(&self.data, addr))
+        let mut buf = [0; 8];
+        let buf = &mut buf[..size_of::<V>()];
+        self.read_at(addr, buf);
+        Ok(LittleEndian::read(buf, 0))
     }
 
     // https://webassembly.github.io/spec/core/exec/instructions.html#and
+    #[inline]
     pub fn store<V: LittleEndian>(&mut self, addr: usize, v: V, at: usize) -> Result<()> {
+        let offset = addr % PAGE_SIZE;
+        if offset + size_of::<V>() <= PAGE_SIZE {
+            if let Some(page) = self.pages.get_mut(addr / PAGE_SIZE) {
+                LittleEndian::write(&mut Arc::make_mut(page)[..], offset, v);
+                return Ok(());
+            }
+        }
+        self.store_slow(addr, v, at)
+    }
+
+    // The value lies across the page boundary or out of range
+    #[cold]
+    fn store_slow<V: LittleEndian>(&mut self, addr: usize, v: V, at: usize) -> Result<()> {
         self.check_addr::<V>(addr, at, "store")?;
-        LittleEndian::write(&mut self.data, addr, v);
+        let mut buf = [0; 8];
+        let buf = &mut buf[..size_of::<V>()];
+        LittleEndian::write(buf, 0, v);
+        self.write_at(addr, buf);
         Ok(())
     }
 
-    pub fn data(&self) -> &'_ [u8] {
-        &self.data
+    // Copy bytes from the address into the buffer. The range must be in memory
+    fn read_at(&self, mut addr: usize, mut buf: &mut [u8]) {
+        while !buf.is_empty() {
+            let offset = addr % PAGE_SIZE;
+            let len = buf.len().min(PAGE_SIZE - offset);
+            let (dest, rest) = buf.split_at_mut(len);
+            dest.copy_from_slice(&self.pages[addr / PAGE_SIZE][offset..offset + len]);
+            addr += len;
+            buf = rest;
+        }
+    }
+
+    // Copy the bytes to the address. The range must be in memory
+    fn write_at(&mut self, mut addr: usize, mut bytes: &[u8]) {
+        while !bytes.is_empty() {
+            let offset = addr % PAGE_SIZE;
+            let len = bytes.len().min(PAGE_SIZE - offset);
+            let page = Arc::make_mut(&mut self.pages[addr / PAGE_SIZE]);
+            page[offset..offset + len].copy_from_slice(&bytes[..len]);
+            addr += len;
+            bytes = &bytes[len..];
+        }
+    }
+
+    // Read bytes in the range. None when the range is out of memory
+    pub fn read_range(&self, range: Range<usize>) -> Option<Vec<u8>> {
+        if range.start > range.end || range.end > self.byte_len() {
+            return None;
+        }
+        let mut buf = vec![0; range.end - range.start];
+        self.read_at(range.start, &mut buf);
+        Some(buf)
+    }
+
+    // Write the bytes at the address. Returns false when the range is out of memory
+    pub fn write_range(&mut self, addr: usize, bytes: &[u8]) -> bool {
+        match addr.checked_add(bytes.len()) {
+            Some(end) if end <= self.byte_len() => {
+                self.write_at(addr, bytes);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Copy bytes in the source range to the address like memmove. Ranges may overlap. Returns false
+    // when either of the ranges is out of memory
+    pub fn copy_within(&mut self, src: Range<usize>, dest: usize) -> bool {
+        let len = match src.end.checked_sub(src.start) {
+            Some(len) => len,
+            None => return false,
+        };
+        match dest.checked_add(len) {
+            Some(end) if end <= self.byte_len() && src.end <= self.byte_len() => {}
+            _ => return false,
+        }
+
+        // Copy chunks which do not cross page boundaries. Copy from the last chunk when the
+        // destination is after the source so that overlapped bytes are read before overwritten
+        let backward = dest > src.start;
+        let mut done = 0;
+        while done < len {
+            let rest = len - done;
+            let (from, to, n) = if backward {
+                let (from_end, to_end) = (src.start + rest, dest + rest);
+                let n = rest
+                    .min((from_end - 1) % PAGE_SIZE + 1)
+                    .min((to_end - 1) % PAGE_SIZE + 1);
+                (from_end - n, to_end - n, n)
+            } else {
+                let (from, to) = (src.start + done, dest + done);
+                let n = rest
+                    .min(PAGE_SIZE - from % PAGE_SIZE)
+                    .min(PAGE_SIZE - to % PAGE_SIZE);
+                (from, to, n)
+            };
+            let (from_page, from) = (from / PAGE_SIZE, from % PAGE_SIZE);
+            let (to_page, to) = (to / PAGE_SIZE, to % PAGE_SIZE);
+            if from_page == to_page {
+                Arc::make_mut(&mut self.pages[to_page]).copy_within(from..from + n, to);
+            } else {
+                let page = self.pages[from_page].clone();
+                Arc::make_mut(&mut self.pages[to_page])[to..to + n]
+                    .copy_from_slice(&page[from..from + n]);
+            }
+            done += n;
+        }
+        true
+    }
+
+    // Number of pages which were written and allocated. Other pages are read as zeros
+    pub fn committed_pages(&self) -> usize {
+        self.pages.iter().filter(|p| !is_zero_page(p)).count()
     }
 
-    pub fn data_mut(&mut self) -> &mut [u8] {
-        &mut self.data
+    // Limit the max number of pages. Growing memory over the cap fails
+    pub(crate) fn cap(&mut self, pages: u32) {
+        self.max = Some(self.max.map_or(pages, |max| max.min(pages)));
+    }
+
+    // Reset memory to the image keeping the buffer. Pages owned by this memory are overwritten with
+    // the image to reuse their allocations. Other pages are shared with the image copy-on-write
+    pub(crate) fn reset(&mut self, image: &Memory) {
+        self.max = image.max;
+        self.pages.truncate(image.pages.len());
+        for (page, src) in self.pages.iter_mut().zip(image.pages.iter()) {
+            match Arc::get_mut(page) {
+                Some(owned) => owned.copy_from_slice(&src[..]),
+                None => *page = src.clone(),
+            }
+        }
+        let len = self.pages.len();
+        self.pages.extend_from_slice(&image.pages[len..]);
+    }
+
+    // Whole memory as bytes
+    pub(crate) fn to_vec(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.byte_len());
+        for page in self.pages.iter() {
+            buf.extend_from_slice(&page[..]);
+        }
+        buf
+    }
+
+    // Replace whole memory with the snapshot. Its size was checked to be within the limits
+    pub(crate) fn restore(&mut self, data: &[u8]) {
+        self.pages = data
+            .chunks(PAGE_SIZE)
+            .map(|chunk| {
+                let mut page = zero_page();
+                if chunk.iter().any(|b| *b != 0) {
+                    Arc::make_mut(&mut page).copy_from_slice(chunk);
+                }
+                page
+            })
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(pages: u32) -> Memory {
+        Memory {
+            max: None,
+            pages: vec![zero_page(); pages as usize],
+        }
+    }
+
+    fn load<V: LittleEndian>(memory: &Memory, addr: usize) -> V {
+        memory.load(addr, 0).unwrap_or_else(|t| panic!("{}", t))
+    }
+
+    fn store<V: LittleEndian>(memory: &mut Memory, addr: usize, v: V) {
+        memory.store(addr, v, 0).unwrap_or_else(|t| panic!("{}", t))
+    }
+
+    #[test]
+    fn load_store_across_pages() {
+        let mut m = memory(2);
+        store(&mut m, PAGE_SIZE - 3, 0x0102_0304_0506_0708i64);
+        assert_eq!(load::<i64>(&m, PAGE_SIZE - 3), 0x0102_0304_0506_0708);
+        assert_eq!(load::<i32>(&m, PAGE_SIZE - 4), 0x0607_0800);
+        assert_eq!(
+            m.read_range(PAGE_SIZE - 1..PAGE_SIZE + 1),
+            Some(vec![0x06, 0x05])
+        );
+        assert!(m.load::<i64>(2 * PAGE_SIZE - 7, 0).is_err());
+
+        assert!(m.write_range(PAGE_SIZE - 2, &[1, 2, 3, 4]));
+        assert_eq!(load::<i32>(&m, PAGE_SIZE - 2), 0x0403_0201);
+        assert!(m.write_range(2 * PAGE_SIZE - 1, &[1]));
+        assert!(!m.write_range(2 * PAGE_SIZE - 1, &[1, 2]));
+        assert_eq!(m.read_range(2 * PAGE_SIZE..2 * PAGE_SIZE + 1), None);
+        assert_eq!(m.read_range(2 * PAGE_SIZE..2 * PAGE_SIZE), Some(vec![]));
+    }
+
+    #[test]
+    fn copy_on_write() {
+        let mut parent = memory(2);
+        store(&mut parent, 0, 1i32);
+        let mut child = parent.clone();
+        assert!(Arc::ptr_eq(&parent.pages[0], &child.pages[0]));
+
+        // Only the written page is copied
+        store(&mut child, 4, 2i32);
+        assert!(!Arc::ptr_eq(&parent.pages[0], &child.pages[0]));
+        assert!(Arc::ptr_eq(&parent.pages[1], &child.pages[1]));
+        assert_eq!(load::<i32>(&parent, 4), 0);
+        assert_eq!(load::<i32>(&child, 0), 1);
+        assert_eq!(load::<i32>(&child, 4), 2);
+
+        // The page is no longer shared so it is written in place
+        let page: *const Page = &*child.pages[0];
+        store(&mut child, 8, 3i32);
+        assert_eq!(page, &*child.pages[0] as *const Page);
+
+        assert_eq!(child.grow(1), 2);
+        assert_eq!(parent.size(), 2);
+    }
+
+    #[test]
+    fn lazy_commit() {
+        // 1GiB memory does not allocate its pages
+        let mut m = memory(16384);
+        assert_eq!(m.byte_len(), 1 << 30);
+        assert_eq!(m.committed_pages(), 0);
+        assert_eq!(load::<i64>(&m, (1 << 30) - 8), 0);
+        assert_eq!(m.read_range(100..104), Some(vec![0; 4]));
+        assert_eq!(m.committed_pages(), 0);
+
+        // Pages are committed on the first write
+        store(&mut m, 3 * PAGE_SIZE + 8, 42i32);
+        assert_eq!(m.committed_pages(), 1);
+        assert_eq!(load::<i32>(&m, 3 * PAGE_SIZE + 8), 42);
+        assert_eq!(load::<i32>(&m, 4 * PAGE_SIZE + 8), 0);
+        assert!(m.write_range(PAGE_SIZE - 1, &[1, 2]));
+        assert_eq!(m.committed_pages(), 3);
+
+        assert_eq!(m.grow(16384), 16384);
+        assert_eq!(m.committed_pages(), 3);
+        assert_eq!(load::<i32>(&m, 1 << 30), 0);
+
+        // Zero pages in snapshot are not committed on restoring
+        let mut data = vec![0; 4 * PAGE_SIZE];
+        data[2 * PAGE_SIZE] = 1;
+        m.restore(&data);
+        assert_eq!(m.size(), 4);
+        assert_eq!(m.committed_pages(), 1);
+        assert_eq!(m.to_vec(), data);
+    }
+
+    #[test]
+    fn grow_overflow() {
+        // Memory without max
+        let mut m = memory(1);
+        assert_eq!(m.grow(u32::MAX), -1);
+        assert_eq!(m.grow(u32::MAX - 1), -1);
+        assert_eq!(m.size(), 1);
+        assert_eq!(m.grow(1), 1);
+    }
+
+    #[test]
+    #[allow(clippy::reversed_empty_ranges)]
+    fn copy_within_pages() {
+        let mut m = memory(3);
+        let bytes: Vec<u8> = (0..2 * PAGE_SIZE).map(|i| (i % 251) as u8).collect();
+        assert!(m.write_range(100, &bytes));
+        let mut expected = m.to_vec();
+
+        // Across page boundaries, overlapped in both directions and empty
+        for &(src, dest, len) in &[
+            (100, 2 * PAGE_SIZE + 10, 1000),
+            (PAGE_SIZE - 8, 2 * PAGE_SIZE - 3, 300),
+            (200, 260, PAGE_SIZE),
+            (PAGE_SIZE + 70, PAGE_SIZE + 10, 5000),
+            (50, 50, 10),
+            (0, 0, 0),
+        ] {
+            assert!(m.copy_within(src..src + len, dest));
+            expected.copy_within(src..src + len, dest);
+            assert!(m.to_vec() == expected, "{} {} {}", src, dest, len);
+        }
+
+        let end = m.byte_len();
+        assert!(!m.copy_within(end - 4..end + 1, 0));
+        assert!(!m.copy_within(0..4, end - 3));
+        assert!(!m.copy_within(8..4, 0));
+        assert!(m.copy_within(end - 4..end, end - 8));
+    }
+
+    #[test]
+    fn reset_reuses_written_pages() {
+        let mut image = memory(3);
+        store(&mut image, 0, 1i32);
+        let mut m = image.clone();
+        store(&mut m, 4, 2i32);
+        store(&mut m, PAGE_SIZE, 3i32);
+        assert_eq!(m.grow(1), 3);
+        store(&mut m, 3 * PAGE_SIZE, 4i32);
+        let written: Vec<*const Page> = m.pages[..2].iter().map(|p| &**p as *const Page).collect();
+
+        m.reset(&image);
+        assert_eq!(m.size(), 3);
+        assert_eq!(m.to_vec(), image.to_vec());
+        // Allocations of written pages are reused. An untouched page is still shared with the image
+        let reused: Vec<*const Page> = m.pages[..2].iter().map(|p| &**p as *const Page).collect();
+        assert_eq!(reused, written);
+        assert!(Arc::ptr_eq(&m.pages[2], &image.pages[2]));
+
+        // Reused pages are owned so writing them does not copy
+        store(&mut m, 8, 5i32);
+        assert_eq!(&*m.pages[0] as *const Page, written[0]);
+        assert_eq!(load::<i32>(&image, 8), 0);
     }
 }
@@ -0,0 +1,244 @@
+// Pool of instances of the same module
+//
+// Instantiating a module allocates memory, table, globals and stack, and initializes them with
+// element and data segments. The pool instantiates the module once as an image and keeps the
+// buffers of released instances in slots. Acquiring an instance resets the buffers of a slot to
+// the image instead of allocating and initializing them again. Pages of linear memory written by
+// the released instance are overwritten with the image in place. Other pages are shared with the
+// image copy-on-write so resetting them does not copy their content.
+
+use crate::import::Importer;
+use crate::machine::{Machine, Store};
+use crate::stack::Stack;
+use crate::trap::{Result, Trap};
+use std::fmt;
+use std::ptr;
+use wain_ast as ast;
+
+pub struct PoolConfig {
+    pub max_slots: usize, // Max number of released instances kept for reuse
+    pub max_memory_pages: Option<u32>, // Cap of linear memory of each instance
+}
+
+// Error on creating a pool. Initializing the image of the module may trap, and the module may not
+// fit to the configuration of the pool
+#[cfg_attr(test, derive(Debug))]
+pub enum PoolError {
+    Trap(Box<Trap>),
+    MemoryOverCap { min: u32, cap: u32 },
+}
+
+impl fmt::Display for PoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolError::Trap(trap) => trap.fmt(f),
+            PoolError::MemoryOverCap { min, cap } => write!(
+                f,
+                "memory requires {} pages at least but instance pool caps memory to {} pages",
+                min, cap,
+            ),
+        }
+    }
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_slots: 16,
+            max_memory_pages: None,
+        }
+    }
+}
+
+pub struct InstancePool<'m, 's> {
+    module: &'m ast::Module<'s>,
+    image: Store,
+    slots: Vec<Store>,
+    max_slots: usize,
+}
+
+impl<'m, 's> InstancePool<'m, 's> {
+    // Imports are not checked here since they depend on the importer. Each acquire() checks them
+    // with its importer as Machine::instantiate does
+    pub fn new(
+        module: &'m ast::Module<'s>,
+        config: PoolConfig,
+    ) -> std::result::Result<Self, Box<PoolError>> {
+        let mut image =
+            Store::instantiate(module).map_err(|trap| Box::new(PoolError::Trap(trap)))?;
+        if let Some(cap) = config.max_memory_pages {
+            let min = image.memory.size();
+            if min > cap {
+                return Err(Box::new(PoolError::MemoryOverCap { min, cap }));
+            }
+            image.memory.cap(cap);
+        }
+        Ok(Self {
+            module,
+            image,
+            slots: Vec::with_capacity(config.max_slots),
+            max_slots: config.max_slots,
+        })
+    }
+
+    // Get an instance from the pool. Its state is the same as an instance just instantiated by
+    // Machine::instantiate
+    pub fn acquire<I: Importer>(&mut self, importer: I) -> Result<Machine<'m, 's, I>> {
+        Machine::check_imports(self.module, &importer)?;
+        let store = match self.slots.pop() {
+            Some(mut slot) => {
+                slot.table.reset(&self.image.table);
+                slot.memory.reset(&self.image.memory);
+                slot.globals.reset(&self.image.globals);
+                slot.stack.clear();
+                slot
+            }
+            None => Store {
+                table: self.image.table.clone(),
+                memory: self.image.memory.clone(),
+                globals: self.image.globals.clone(),
+                stack: Stack::default(),
+            },
+        };
+        Ok(Machine::with_store(self.module, importer, store))
+    }
+
+    // Return the instance to the pool to reuse its buffers. The instance is dropped when the pool
+    // is full or it was not acquired from this pool
+    pub fn release<I: Importer>(&mut self, machine: Machine<'m, 's, I>) {
+        if self.slots.len() < self.max_slots && ptr::eq(machine.module(), self.module) {
+            self.slots.push(machine.into_store());
+        }
+    }
+
+    // Number of released instances kept in the pool
+    pub fn idle_slots(&self) -> usize {
+        self.slots.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::DefaultImporter;
+    use crate::value::Value;
+    use std::io;
+    use wain_syntax_text::parse;
+
+    const SOURCE: &str = r#"
+(module
+  (memory 1 4)
+  (table 2 funcref)
+  (global $n (export "n") (mut i32) (i32.const 10))
+  (data (i32.const 0) "wain")
+  (func $get (result i32)
+    global.get $n)
+  (elem (i32.const 1) $get)
+  (func (export "run") (param $v i32) (result i32)
+    i32.const 0
+    i32.load
+    i32.const 4
+    local.get $v
+    i32.store
+    local.get $v
+    global.set $n
+    i32.const 1
+    call_indirect (result i32)
+    i32.add)
+  (func (export "grow") (param $n i32) (result i32)
+    local.get $n
+    memory.grow))
+"#;
+
+    type TestImporter = DefaultImporter<io::Empty, io::Sink>;
+
+    fn importer() -> TestImporter {
+        DefaultImporter::with_stdio(io::empty(), io::sink())
+    }
+
+    fn invoke(machine: &mut Machine<'_, '_, TestImporter>, name: &str, arg: i32) -> Option<Value> {
+        machine
+            .invoke(name, &[Value::I32(arg)])
+            .unwrap_or_else(|t| panic!("{}", t))
+    }
+
+    #[test]
+    fn reuse_instances() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let config = PoolConfig {
+            max_slots: 1,
+            ..PoolConfig::default()
+        };
+        let mut pool = InstancePool::new(&root.module, config).unwrap_or_else(|t| panic!("{}", t));
+
+        let mut fresh = Machine::instantiate(&root.module, importer()).unwrap();
+        let expected = invoke(&mut fresh, "run", 3);
+        let expected_state = fresh.snapshot(&[]).encode();
+
+        let mut first = pool.acquire(importer()).unwrap();
+        let mut second = pool.acquire(importer()).unwrap();
+        assert_eq!(invoke(&mut first, "run", 3), expected);
+        assert_eq!(invoke(&mut second, "run", 5), invoke(&mut fresh, "run", 5));
+        assert_eq!(invoke(&mut second, "grow", 2), Some(Value::I32(1)));
+        pool.release(first);
+        pool.release(second); // Dropped since the pool is full
+        assert_eq!(pool.idle_slots(), 1);
+
+        // The state is reset to the initial state
+        for _ in 0..3 {
+            let mut machine = pool.acquire(importer()).unwrap();
+            assert_eq!(pool.idle_slots(), 0);
+            assert_eq!(machine.get_global("n"), Some(Value::I32(10)));
+            assert_eq!(machine.memory().size(), 1);
+            assert_eq!(invoke(&mut machine, "run", 3), expected);
+            assert_eq!(machine.snapshot(&[]).encode(), expected_state);
+            assert_eq!(invoke(&mut machine, "grow", 1), Some(Value::I32(1)));
+            pool.release(machine);
+        }
+
+        // Instance of other module is not pooled
+        let other = parse("(module)").unwrap_or_else(|e| panic!("{}", e));
+        let mut pool = InstancePool::new(&root.module, PoolConfig::default()).unwrap();
+        pool.release(Machine::instantiate(&other.module, importer()).unwrap());
+        assert_eq!(pool.idle_slots(), 0);
+    }
+
+    #[test]
+    fn memory_cap() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let config = PoolConfig {
+            max_memory_pages: Some(2),
+            ..PoolConfig::default()
+        };
+        let mut pool = InstancePool::new(&root.module, config).unwrap_or_else(|t| panic!("{}", t));
+        for _ in 0..2 {
+            let mut machine = pool.acquire(importer()).unwrap();
+            assert_eq!(invoke(&mut machine, "grow", 2), Some(Value::I32(-1)));
+            assert_eq!(invoke(&mut machine, "grow", 1), Some(Value::I32(1)));
+            assert_eq!(invoke(&mut machine, "grow", 1), Some(Value::I32(-1)));
+            pool.release(machine);
+        }
+
+        let config = PoolConfig {
+            max_memory_pages: Some(0),
+            ..PoolConfig::default()
+        };
+        let err = match InstancePool::new(&root.module, config) {
+            Err(err) => err,
+            Ok(_) => panic!("memory cap was not checked"),
+        };
+        assert!(matches!(*err, PoolError::MemoryOverCap { min: 1, cap: 0 }));
+        assert_eq!(
+            err.to_string(),
+            "memory requires 1 pages at least but instance pool caps memory to 0 pages"
+        );
+    }
+
+    #[test]
+    fn check_imports_on_acquire() {
+        let root = parse(r#"(module (import "env" "unknown" (func)))"#)
+            .unwrap_or_else(|e| panic!("{}", e));
+        let mut pool = InstancePool::new(&root.module, PoolConfig::default()).unwrap();
+        assert!(pool.acquire(importer()).is_err());
+    }
+}
@@ -0,0 +1,412 @@
+use crate::trace::Tracer;
+use crate::value::Value;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+use wain_ast as ast;
+
+// Statistics of one function
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Default, PartialEq)]
+pub struct FuncProfile {
+    pub funcidx: u32,
+    pub name: String,
+    pub calls: u64,
+    pub inclusive_insns: u64, // Instructions executed in the function and its callees
+    pub exclusive_insns: u64, // Instructions executed in the function itself
+    pub inclusive_time: Duration,
+    pub exclusive_time: Duration,
+}
+
+// Result of profiling
+pub struct Profile {
+    // Called functions. The function which executed the most instructions comes first
+    pub funcs: Vec<FuncProfile>,
+    // Executed count of each opcode. The most frequent opcode comes first
+    pub opcodes: Vec<(&'static str, u64)>,
+    // Call stacks (the outermost function comes first) and instructions executed in the innermost
+    // function with the stacks
+    pub stacks: Vec<(Vec<u32>, u64)>,
+    names: Vec<String>,
+}
+
+impl Profile {
+    // Folded stacks format which flamegraph tools accept. Weight of each stack is the number of
+    // executed instructions instead of samples
+    // https://github.com/brendangregg/FlameGraph#2-fold-stacks
+    pub fn write_folded<W: Write>(&self, mut w: W) -> io::Result<()> {
+        for (stack, insns) in self.stacks.iter() {
+            let names: Vec<_> = stack
+                .iter()
+                .map(|idx| self.names[*idx as usize].replace(';', ":"))
+                .collect();
+            writeln!(w, "{} {}", names.join(";"), insns)?;
+        }
+        w.flush()
+    }
+
+    pub fn write_summary<W: Write>(&self, mut w: W) -> io::Result<()> {
+        let total: u64 = self.opcodes.iter().map(|(_, c)| c).sum();
+        let percent = |n: u64| {
+            if total == 0 {
+                0.0
+            } else {
+                n as f64 * 100.0 / total as f64
+            }
+        };
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+
+        writeln!(
+            w,
+            "{:<32} {:>10} {:>14} {:>14} {:>7} {:>12} {:>12}",
+            "function", "calls", "incl. insns", "excl. insns", "excl. %", "incl. ms", "excl. ms"
+        )?;
+        for f in self.funcs.iter() {
+            writeln!(
+                w,
+                "{:<32} {:>10} {:>14} {:>14} {:>6.2}% {:>12.3} {:>12.3}",
+                f.name,
+                f.calls,
+                f.inclusive_insns,
+                f.exclusive_insns,
+                percent(f.exclusive_insns),
+                ms(f.inclusive_time),
+                ms(f.exclusive_time),
+            )?;
+        }
+
+        writeln!(w)?;
+        writeln!(w, "{:<32} {:>14} {:>7}", "opcode", "count", "%")?;
+        for (name, count) in self.opcodes.iter() {
+            writeln!(w, "{:<32} {:>14} {:>6.2}%", name, count, percent(*count))?;
+        }
+        w.flush()
+    }
+}
+
+// Node of call tree to build folded stacks
+struct Node {
+    funcidx: u32,
+    parent: usize,
+    children: Vec<usize>,
+    insns: u64,
+}
+
+// Function being called
+struct Activation {
+    funcidx: u32,
+    insns: u64, // Total executed instructions when the function was entered
+    start: Instant,
+    callees: Duration, // Time spent in callees
+}
+
+// Profiler which records statistics of functions and opcodes as Tracer. Share it with Arc<Mutex<_>>
+// to get the profile after execution:
+//
+//   let profiler = Arc::new(Mutex::new(Profiler::new(&module)));
+//   machine.set_tracer(profiler.clone());
+//   machine.execute()?;
+//   let profile = profiler.lock().unwrap().profile();
+pub struct Profiler {
+    names: Vec<String>,
+    funcs: Vec<FuncProfile>,
+    active: Vec<Activation>,
+    recursion: Vec<u32>, // Number of activations of each function to avoid counting inclusive stats twice
+    nodes: Vec<Node>,    // The first node is root which is not a function
+    current: usize,
+    insns: u64,
+    opcodes: Vec<(&'static str, u64)>, // Name and executed count indexed by opcode
+}
+
+// Function name from name section or exports
+pub(crate) fn func_name(module: &ast::Module<'_>, funcidx: u32) -> String {
+    if let Some(name) = module.names.func(funcidx) {
+        return name.0.to_string();
+    }
+    let export = module.exports.iter().find(|e| match e.kind {
+        ast::ExportKind::Func(idx) => idx == funcidx,
+        _ => false,
+    });
+    if let Some(export) = export {
+        return export.name.0.to_string();
+    }
+    match &module.funcs[funcidx as usize].kind {
+        ast::FuncKind::Import(i) => format!("{}.{}", i.mod_name.0, i.name.0),
+        ast::FuncKind::Body { .. } => format!("function {}", funcidx),
+    }
+}
+
+impl Profiler {
+    pub fn new(module: &ast::Module<'_>) -> Self {
+        let names: Vec<_> = (0..module.funcs.len() as u32)
+            .map(|idx| func_name(module, idx))
+            .collect();
+        let funcs = names
+            .iter()
+            .enumerate()
+            .map(|(idx, name)| FuncProfile {
+                funcidx: idx as u32,
+                name: name.clone(),
+                ..FuncProfile::default()
+            })
+            .collect();
+        let root = Node {
+            funcidx: 0,
+            parent: 0,
+            children: vec![],
+            insns: 0,
+        };
+        Self {
+            funcs,
+            recursion: vec![0; names.len()],
+            names,
+            active: vec![],
+            nodes: vec![root],
+            current: 0,
+            insns: 0,
+            opcodes: vec![("", 0); 256],
+        }
+    }
+
+    fn child_node(&mut self, funcidx: u32) -> usize {
+        let nodes = &self.nodes;
+        let found = nodes[self.current]
+            .children
+            .iter()
+            .copied()
+            .find(|i| nodes[*i].funcidx == funcidx);
+        if let Some(idx) = found {
+            return idx;
+        }
+        let idx = self.nodes.len();
+        self.nodes.push(Node {
+            funcidx,
+            parent: self.current,
+            children: vec![],
+            insns: 0,
+        });
+        self.nodes[self.current].children.push(idx);
+        idx
+    }
+
+    fn collect_stacks(&self, node: usize, stack: &mut Vec<u32>, stacks: &mut Vec<(Vec<u32>, u64)>) {
+        for child in self.nodes[node].children.iter().copied() {
+            let node = &self.nodes[child];
+            stack.push(node.funcidx);
+            if node.insns > 0 {
+                stacks.push((stack.clone(), node.insns));
+            }
+            self.collect_stacks(child, stack, stacks);
+            stack.pop();
+        }
+    }
+
+    // Functions still being called (e.g. when execution was trapped) are counted until now
+    pub fn profile(&self) -> Profile {
+        let now = Instant::now();
+        let mut funcs = self.funcs.clone();
+        let mut callees = Duration::default();
+        for (i, a) in self.active.iter().enumerate().rev() {
+            let elapsed = now.duration_since(a.start);
+            let f = &mut funcs[a.funcidx as usize];
+            f.exclusive_time += elapsed.checked_sub(a.callees + callees).unwrap_or_default();
+            if !self.active[..i].iter().any(|b| b.funcidx == a.funcidx) {
+                f.inclusive_time += elapsed;
+                f.inclusive_insns += self.insns - a.insns;
+            }
+            callees = elapsed;
+        }
+        funcs.retain(|f| f.calls > 0);
+        funcs.sort_by(|a, b| {
+            b.exclusive_insns
+                .cmp(&a.exclusive_insns)
+                .then(b.calls.cmp(&a.calls))
+                .then(a.funcidx.cmp(&b.funcidx))
+        });
+
+        let mut opcodes: Vec<_> = self
+            .opcodes
+            .iter()
+            .copied()
+            .filter(|(_, c)| *c > 0)
+            .collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        let mut stacks = vec![];
+        self.collect_stacks(0, &mut vec![], &mut stacks);
+
+        Profile {
+            funcs,
+            opcodes,
+            stacks,
+            names: self.names.clone(),
+        }
+    }
+}
+
+impl Tracer for Profiler {
+    fn enter(&mut self, funcidx: u32, _args: &[Value]) {
+        self.funcs[funcidx as usize].calls += 1;
+        self.recursion[funcidx as usize] += 1;
+        self.current = self.child_node(funcidx);
+        self.active.push(Activation {
+            funcidx,
+            insns: self.insns,
+            start: Instant::now(),
+            callees: Duration::default(),
+        });
+    }
+
+    fn exit(&mut self, funcidx: u32, _result: Option<&Value>) {
+        let a = match self.active.pop() {
+            Some(a) => a,
+            None => return,
+        };
+        let elapsed = a.start.elapsed();
+        let idx = funcidx as usize;
+        self.funcs[idx].exclusive_time += elapsed.checked_sub(a.callees).unwrap_or_default();
+        self.recursion[idx] -= 1;
+        if self.recursion[idx] == 0 {
+            self.funcs[idx].inclusive_time += elapsed;
+            self.funcs[idx].inclusive_insns += self.insns - a.insns;
+        }
+        if let Some(caller) = self.active.last_mut() {
+            caller.callees += elapsed;
+        }
+        self.current = self.nodes[self.current].parent;
+    }
+
+    fn instruction(&mut self, funcidx: u32, insn: &ast::InsnKind, _offset: usize, _: &[Value]) {
+        self.insns += 1;
+        self.funcs[funcidx as usize].exclusive_insns += 1;
+        self.nodes[self.current].insns += 1;
+        let (name, count) = &mut self.opcodes[insn.opcode() as usize];
+        if *count == 0 {
+            *name = insn.name();
+        }
+        *count += 1;
+    }
+
+    fn wants_operands(&self) -> bool {
+        false
+    }
+
+    fn host_call(&mut self, funcidx: u32, _: &str, _: &[Value], _: Option<&Value>) {
+        self.funcs[funcidx as usize].calls += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::DefaultImporter;
+    use crate::machine::Machine;
+    use std::sync::{Arc, Mutex};
+    use wain_syntax_text::parse_with_id_names as parse;
+
+    const SOURCE: &str = r#"
+(module
+  (import "env" "putchar" (func (param i32) (result i32)))
+  ;; Recursive factorial to check inclusive counts are not counted twice
+  (func $fact (param $n i32) (result i32)
+    local.get $n
+    i32.const 1
+    i32.le_s
+    if (result i32)
+      i32.const 1
+    else
+      local.get $n
+      local.get $n
+      i32.const 1
+      i32.sub
+      call $fact
+      i32.mul
+    end)
+  (func (export "main")
+    i32.const 3
+    call $fact
+    call 0
+    drop))
+"#;
+
+    fn profile() -> Profile {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine = Machine::instantiate(&root.module, importer).unwrap();
+        let profiler = Arc::new(Mutex::new(Profiler::new(&root.module)));
+        machine.set_tracer(profiler.clone());
+        machine.invoke("main", &[]).unwrap();
+        let profile = profiler.lock().unwrap().profile();
+        profile
+    }
+
+    #[test]
+    fn function_stats() {
+        let profile = profile();
+        let stats: Vec<_> = profile
+            .funcs
+            .iter()
+            .map(|f| {
+                (
+                    f.name.as_str(),
+                    f.calls,
+                    f.inclusive_insns,
+                    f.exclusive_insns,
+                )
+            })
+            .collect();
+        // fact(3) and fact(2) execute 10 instructions. fact(1) executes 5 instructions
+        assert_eq!(
+            stats,
+            [
+                ("fact", 3, 25, 25),
+                ("main", 1, 29, 4),
+                ("env.putchar", 1, 0, 0)
+            ],
+        );
+        for f in profile.funcs.iter() {
+            assert!(f.exclusive_time <= f.inclusive_time || f.calls == 0);
+        }
+    }
+
+    #[test]
+    fn opcode_histogram() {
+        let profile = profile();
+        // Opcodes executed the same times are sorted by their names
+        assert_eq!(profile.opcodes[0], ("i32.const", 7));
+        assert_eq!(profile.opcodes[1], ("local.get", 7));
+        let total: u64 = profile.opcodes.iter().map(|(_, c)| c).sum();
+        assert_eq!(total, 29);
+    }
+
+    #[test]
+    fn folded_stacks() {
+        let profile = profile();
+        let mut out = vec![];
+        profile.write_folded(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "main 4",
+                "main;fact 10",
+                "main;fact;fact 10",
+                "main;fact;fact;fact 5",
+            ],
+        );
+    }
+
+    #[test]
+    fn summary_table() {
+        let profile = profile();
+        let mut out = vec![];
+        profile.write_summary(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert!(lines[0].starts_with("function"), "{}", out);
+        assert!(lines[1].starts_with("fact "), "{}", out);
+        assert!(lines[1].contains(" 86.21% "), "{}", out);
+        assert!(out.contains("\nopcode "), "{}", out);
+        assert!(out.contains("\nlocal.get "), "{}", out);
+    }
+}
@@ -0,0 +1,542 @@
+// Snapshot of machine state for warm starts and reproducing bugs
+//
+// The snapshot contains linear memory, globals and table of an instance. It is encoded in compact
+// binary format as below. All integers are fixed-width little endian.
+//
+//   magic "WAINSNAP", version (u32), hash of module source (u64)
+//   memory: 0 (absent) or 1 followed by pages (u32) and non-zero regions
+//           (count (u32), then offset (u32), length (u32) and bytes of each region)
+//   globals: count (u32), then value type byte and bits of each value
+//   table: 0 (absent) or 1 followed by length (u32) and function indices (u32). u32::MAX means
+//          uninitialized element
+
+use crate::coredump::{nonzero_regions, valtype_byte};
+use crate::value::Value;
+use std::fmt;
+use std::ops::Range;
+use wain_ast as ast;
+use wain_ast::ValType;
+
+const MAGIC: &[u8; 8] = b"WAINSNAP";
+const VERSION: u32 = 1;
+const PAGE_SIZE: usize = 65536;
+const MAX_PAGES: u32 = 65536; // Address space of Wasm is 32bits
+const NO_ELEM: u32 = u32::MAX;
+
+#[cfg_attr(test, derive(Debug))]
+pub enum SnapshotError {
+    Broken { msg: String, offset: usize },
+    ModuleMismatch { expected: u64, actual: u64 },
+    Incompatible(String),
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Broken { msg, offset } => {
+                write!(f, "broken snapshot: {} at byte offset 0x{:x}", msg, offset)
+            }
+            SnapshotError::ModuleMismatch { expected, actual } => write!(
+                f,
+                "snapshot was taken from other module: module hash is {:016x} but snapshot has {:016x}",
+                expected, actual
+            ),
+            SnapshotError::Incompatible(msg) => {
+                write!(f, "snapshot is incompatible with module: {}", msg)
+            }
+        }
+    }
+}
+
+type Result<T> = ::std::result::Result<T, Box<SnapshotError>>;
+
+pub(crate) fn incompatible<T>(msg: String) -> Result<T> {
+    Err(Box::new(SnapshotError::Incompatible(msg)))
+}
+
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct Snapshot {
+    pub module_hash: u64,
+    pub memory: Option<Vec<u8>>,
+    pub globals: Vec<Value>,
+    pub table: Option<Vec<Option<u32>>>, // Function indices of table elements
+}
+
+pub(crate) fn write_value(buf: &mut Vec<u8>, v: &Value) {
+    buf.push(valtype_byte(v.valtype()));
+    match v {
+        Value::I32(i) => buf.extend_from_slice(&i.to_le_bytes()),
+        Value::I64(i) => buf.extend_from_slice(&i.to_le_bytes()),
+        Value::F32(f) => buf.extend_from_slice(&f.to_bits().to_le_bytes()),
+        Value::F64(f) => buf.extend_from_slice(&f.to_bits().to_le_bytes()),
+    }
+}
+
+pub(crate) struct Decoder<'a> {
+    bytes: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub(crate) fn error<T>(&self, msg: impl Into<String>) -> Result<T> {
+        Err(Box::new(SnapshotError::Broken {
+            msg: msg.into(),
+            offset: self.pos,
+        }))
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.bytes.len() - self.pos < len {
+            return self.error(format!(
+                "unexpected end of snapshot while reading {} bytes",
+                len
+            ));
+        }
+        let b = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(b)
+    }
+
+    pub(crate) fn byte(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        let mut b = [0; 4];
+        b.copy_from_slice(self.bytes(4)?);
+        Ok(u32::from_le_bytes(b))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        let mut b = [0; 8];
+        b.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(b))
+    }
+
+    fn flag(&mut self) -> Result<bool> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            b => {
+                self.pos -= 1;
+                self.error(format!("invalid flag 0x{:x}", b))
+            }
+        }
+    }
+
+    pub(crate) fn header(&mut self, magic: &[u8; 8], version: u32) -> Result<()> {
+        if self.bytes(magic.len())? != magic {
+            self.pos -= magic.len();
+            let magic = String::from_utf8_lossy(magic);
+            return self.error(format!("magic '{}' is not found", magic));
+        }
+        let v = self.u32()?;
+        if v != version {
+            self.pos -= 4;
+            return self.error(format!("unsupported version {}", v));
+        }
+        Ok(())
+    }
+
+    pub(crate) fn end(&self) -> Result<()> {
+        if self.pos != self.bytes.len() {
+            return self.error("unexpected bytes after end of snapshot");
+        }
+        Ok(())
+    }
+
+    fn memory(&mut self) -> Result<Vec<u8>> {
+        let pages = self.u32()?;
+        if pages > MAX_PAGES {
+            self.pos -= 4;
+            return self.error(format!("too many memory pages {}", pages));
+        }
+        let mut memory = vec![0; pages as usize * PAGE_SIZE];
+        for _ in 0..self.u32()? {
+            let start = self.pos;
+            let offset = self.u32()? as usize;
+            let len = self.u32()? as usize;
+            if offset.saturating_add(len) > memory.len() {
+                self.pos = start;
+                return self.error(format!(
+                    "memory region 0x{:x}..0x{:x} is out of memory",
+                    offset,
+                    offset + len
+                ));
+            }
+            memory[offset..offset + len].copy_from_slice(self.bytes(len)?);
+        }
+        Ok(memory)
+    }
+
+    pub(crate) fn value(&mut self) -> Result<Value> {
+        Ok(match self.byte()? {
+            0x7f => Value::I32(self.u32()? as i32),
+            0x7e => Value::I64(self.u64()? as i64),
+            0x7d => Value::F32(f32::from_bits(self.u32()?)),
+            0x7c => Value::F64(f64::from_bits(self.u64()?)),
+            b => {
+                self.pos -= 1;
+                return self.error(format!("invalid value type 0x{:x}", b));
+            }
+        })
+    }
+}
+
+impl Snapshot {
+    // FNV-1a hash of module source to check the snapshot is restored to the module it came from
+    pub fn module_hash(source: &[u8]) -> u64 {
+        source.iter().fold(0xcbf2_9ce4_8422_2325, |h, b| {
+            (h ^ *b as u64).wrapping_mul(0x100_0000_01b3)
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut buf = vec![];
+        self.encode_to(&mut buf);
+        buf
+    }
+
+    pub(crate) fn encode_to(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(MAGIC);
+        buf.extend_from_slice(&VERSION.to_le_bytes());
+        buf.extend_from_slice(&self.module_hash.to_le_bytes());
+
+        if let Some(memory) = &self.memory {
+            buf.push(1);
+            buf.extend_from_slice(&((memory.len() / PAGE_SIZE) as u32).to_le_bytes());
+            let regions = nonzero_regions(memory);
+            buf.extend_from_slice(&(regions.len() as u32).to_le_bytes());
+            for r in regions {
+                buf.extend_from_slice(&(r.start as u32).to_le_bytes());
+                buf.extend_from_slice(&(r.len() as u32).to_le_bytes());
+                buf.extend_from_slice(&memory[r]);
+            }
+        } else {
+            buf.push(0);
+        }
+
+        buf.extend_from_slice(&(self.globals.len() as u32).to_le_bytes());
+        for v in self.globals.iter() {
+            write_value(buf, v);
+        }
+
+        if let Some(table) = &self.table {
+            buf.push(1);
+            buf.extend_from_slice(&(table.len() as u32).to_le_bytes());
+            for elem in table.iter() {
+                buf.extend_from_slice(&elem.unwrap_or(NO_ELEM).to_le_bytes());
+            }
+        } else {
+            buf.push(0);
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Snapshot> {
+        let mut d = Decoder::new(bytes);
+        let snapshot = Self::decode_from(&mut d)?;
+        d.end()?;
+        Ok(snapshot)
+    }
+
+    pub(crate) fn decode_from(d: &mut Decoder<'_>) -> Result<Snapshot> {
+        d.header(MAGIC, VERSION)?;
+        let module_hash = d.u64()?;
+
+        let memory = if d.flag()? { Some(d.memory()?) } else { None };
+
+        let num_globals = d.u32()?;
+        let mut globals = vec![];
+        for _ in 0..num_globals {
+            globals.push(d.value()?);
+        }
+
+        let table = if d.flag()? {
+            let len = d.u32()?;
+            let mut table = vec![];
+            for _ in 0..len {
+                let idx = d.u32()?;
+                table.push(if idx == NO_ELEM { None } else { Some(idx) });
+            }
+            Some(table)
+        } else {
+            None
+        };
+
+        Ok(Snapshot {
+            module_hash,
+            memory,
+            globals,
+            table,
+        })
+    }
+
+    // Non-zero regions of memory. Short runs of zeros are included in regions
+    pub fn memory_regions(&self) -> Vec<Range<usize>> {
+        match &self.memory {
+            Some(m) => nonzero_regions(m),
+            None => vec![],
+        }
+    }
+
+    // Check the snapshot can be restored to the instance of the module
+    pub(crate) fn check(&self, module: &ast::Module<'_>, source: &[u8]) -> Result<()> {
+        let expected = Self::module_hash(source);
+        if self.module_hash != expected {
+            return Err(Box::new(SnapshotError::ModuleMismatch {
+                expected,
+                actual: self.module_hash,
+            }));
+        }
+
+        fn check_limits(what: &str, limit: &ast::Limits, size: usize) -> Result<()> {
+            let (min, max) = match limit {
+                ast::Limits::Range(min, max) => (*min, Some(*max)),
+                ast::Limits::From(min) => (*min, None),
+            };
+            if size < min as usize || max.map(|m| size > m as usize).unwrap_or(false) {
+                incompatible(format!("{} size {} is out of limits", what, size))
+            } else {
+                Ok(())
+            }
+        }
+
+        match (module.memories.first(), &self.memory) {
+            (Some(m), Some(data)) => check_limits("memory", &m.ty.limit, data.len() / PAGE_SIZE)?,
+            (None, None) => {}
+            (Some(_), None) => return incompatible("memory is missing".to_string()),
+            (None, Some(_)) => return incompatible("module has no memory".to_string()),
+        }
+
+        match (module.tables.first(), &self.table) {
+            (Some(t), Some(elems)) => {
+                check_limits("table", &t.ty.limit, elems.len())?;
+                if let Some(idx) = elems
+                    .iter()
+                    .flatten()
+                    .find(|i| **i as usize >= module.funcs.len())
+                {
+                    return incompatible(format!("function {} in table does not exist", idx));
+                }
+            }
+            (None, None) => {}
+            (Some(_), None) => return incompatible("table is missing".to_string()),
+            (None, Some(_)) => return incompatible("module has no table".to_string()),
+        }
+
+        if self.globals.len() != module.globals.len() {
+            return incompatible(format!(
+                "module has {} globals but snapshot has {}",
+                module.globals.len(),
+                self.globals.len()
+            ));
+        }
+        for (i, (v, g)) in self.globals.iter().zip(module.globals.iter()).enumerate() {
+            let ty: ValType = g.ty;
+            if v.valtype() != ty {
+                return incompatible(format!(
+                    "type of global {} is {} but snapshot has {}",
+                    i,
+                    ty,
+                    v.valtype()
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::DefaultImporter;
+    use crate::machine::Machine;
+    use std::io;
+    use wain_syntax_text::parse;
+
+    const SOURCE: &str = r#"
+(module
+  (memory 1 4)
+  (table 4 funcref)
+  (global $n (export "n") (mut i32) (i32.const 0))
+  (global $big (export "big") (mut i64) (i64.const 0))
+  (global $nan (export "nan") (mut f32) (f32.const 0))
+  (global $ratio (export "ratio") (mut f64) (f64.const 0))
+  (elem (i32.const 2) $double)
+  (func $double (param i32) (result i32)
+    local.get 0
+    i32.const 2
+    i32.mul)
+  (func (export "init")
+    i32.const 1
+    memory.grow
+    drop
+    i32.const 70000
+    i32.const 0x6e696177
+    i32.store
+    i32.const 8
+    i64.const -1
+    i64.store
+    i32.const 21
+    global.set $n
+    i64.const 0x123456789abcdef
+    global.set $big
+    f32.const nan:0x200001
+    global.set $nan
+    f64.const -0.25
+    global.set $ratio)
+  (func (export "next") (result i32)
+    global.get $n
+    i32.const 2
+    call_indirect (param i32) (result i32)
+    global.set $n
+    global.get $n
+    i32.const 70000
+    i32.load
+    i32.add))
+"#;
+
+    type TestMachine<'m, 's> = Machine<'m, 's, DefaultImporter<io::Empty, io::Sink>>;
+
+    fn instantiate<'m, 's>(module: &'m ast::Module<'s>) -> TestMachine<'m, 's> {
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        Machine::instantiate(module, importer).unwrap_or_else(|t| panic!("{}", t))
+    }
+
+    fn initialized_snapshot() -> Snapshot {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let mut machine = instantiate(&root.module);
+        machine
+            .invoke("init", &[])
+            .unwrap_or_else(|t| panic!("{}", t));
+        machine.snapshot(SOURCE.as_bytes())
+    }
+
+    #[test]
+    fn snapshot_and_restore() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let mut original = instantiate(&root.module);
+        original
+            .invoke("init", &[])
+            .unwrap_or_else(|t| panic!("{}", t));
+
+        let encoded = original.snapshot(SOURCE.as_bytes()).encode();
+        assert!(encoded.len() < 128, "{} bytes", encoded.len());
+        let snapshot = Snapshot::decode(&encoded).unwrap_or_else(|e| panic!("{}", e));
+
+        let mut restored = instantiate(&root.module);
+        restored
+            .restore(&snapshot, SOURCE.as_bytes())
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        assert_eq!(restored.memory().size(), 2);
+        assert!(restored.memory().to_vec() == original.memory().to_vec());
+        assert_eq!(restored.get_global("n"), Some(Value::I32(21)));
+        assert_eq!(
+            restored.get_global("big"),
+            Some(Value::I64(0x123456789abcdef))
+        );
+        match restored.get_global("nan") {
+            Some(Value::F32(f)) => assert_eq!(f.to_bits(), 0x7fa00001),
+            v => panic!("unexpected value {:?}", v),
+        }
+        assert_eq!(restored.get_global("ratio"), Some(Value::F64(-0.25)));
+        assert_eq!(restored.snapshot(SOURCE.as_bytes()).encode(), encoded);
+
+        // Both instances behave in the same way after restoring
+        for _ in 0..3 {
+            let expected = original
+                .invoke("next", &[])
+                .unwrap_or_else(|t| panic!("{}", t));
+            let actual = restored
+                .invoke("next", &[])
+                .unwrap_or_else(|t| panic!("{}", t));
+            assert_eq!(actual, expected);
+        }
+        assert_eq!(restored.get_global("n"), Some(Value::I32(168)));
+    }
+
+    #[test]
+    fn restore_errors() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let mut machine = instantiate(&root.module);
+        let snapshot = initialized_snapshot();
+
+        let other = format!("{}\n", SOURCE);
+        match *machine.restore(&snapshot, other.as_bytes()).unwrap_err() {
+            SnapshotError::ModuleMismatch { expected, actual } => {
+                assert_eq!(expected, Snapshot::module_hash(other.as_bytes()));
+                assert_eq!(actual, snapshot.module_hash);
+            }
+            e => panic!("unexpected error: {}", e),
+        }
+
+        let mut s = snapshot.clone();
+        s.globals.pop();
+        let mut s2 = snapshot.clone();
+        s2.globals[0] = Value::I64(21);
+        let mut s3 = snapshot.clone();
+        s3.memory = Some(vec![0; 5 * PAGE_SIZE]);
+        let mut s4 = snapshot.clone();
+        s4.table = Some(vec![None, Some(99)]);
+        let mut s5 = snapshot;
+        s5.table = Some(vec![None, None, None, None, Some(99)]);
+        for (s, expected) in &[
+            (s, "module has 4 globals but snapshot has 3"),
+            (s2, "type of global 0 is i32 but snapshot has i64"),
+            (s3, "memory size 5 is out of limits"),
+            (s4, "table size 2 is out of limits"),
+            (s5, "function 99 in table does not exist"),
+        ] {
+            let err = machine.restore(s, SOURCE.as_bytes()).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("snapshot is incompatible with module: {}", expected)
+            );
+        }
+
+        // Failed restore does not modify the instance
+        assert_eq!(machine.memory().size(), 1);
+        assert_eq!(machine.get_global("n"), Some(Value::I32(0)));
+    }
+
+    #[test]
+    fn decode_errors() {
+        let encoded = initialized_snapshot().encode();
+
+        let mut trailing = encoded.clone();
+        trailing.push(0);
+        let mut bad_region = encoded.clone();
+        bad_region[21..25].copy_from_slice(&1u32.to_le_bytes()); // Memory pages
+
+        for (bytes, expected) in &[
+            (
+                b"WAINDUMP".to_vec(),
+                "magic 'WAINSNAP' is not found at byte offset 0x0",
+            ),
+            (
+                encoded[..30].to_vec(),
+                "unexpected end of snapshot while reading 4 bytes at byte offset 0x1d",
+            ),
+            (
+                trailing,
+                "unexpected bytes after end of snapshot at byte offset 0x6e",
+            ),
+            (
+                bad_region,
+                "memory region 0x11170..0x11174 is out of memory at byte offset 0x2d",
+            ),
+        ] {
+            match Snapshot::decode(bytes) {
+                Err(e) => assert_eq!(e.to_string(), format!("broken snapshot: {}", expected)),
+                Ok(_) => panic!("error did not occur for {:?}", expected),
+            }
+        }
+    }
+}
@@ -12,8 +12,18 @@ use wain_ast::{AsValType, ValType};
 
 #[derive(Default)]
 pub struct Stack {
-    bytes: Vec<u8>,      // Bytes buffer for actual values
-    types: Vec<ValType>, // this stack is necessary to pop arbitrary value
+    bytes: Vec<u8>,          // Bytes buffer for actual values
+    types: Vec<ValType>,     // this stack is necessary to pop arbitrary value
+    frames: Vec<StackFrame>, // Functions being invoked. The outermost function comes first
+}
+
+// Function being invoked. Offset is a byte offset of the function or the call instruction which is
+// currently calling other function
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub struct StackFrame {
+    pub funcidx: u32,
+    pub offset: usize,
 }
 
 pub trait StackAccess: Sized {
@@ -179,6 +189,35 @@ impl Stack {
         }
     }
 
+    pub fn read_any(&self, addr: usize, ty: ValType) -> Value {
+        match ty {
+            ValType::I32 => Value::I32(self.read(addr)),
+            ValType::I64 => Value::I64(self.read(addr)),
+            ValType::F32 => Value::F32(self.read(addr)),
+            ValType::F64 => Value::F64(self.read(addr)),
+        }
+    }
+
+    // Values from the address and the type index to the top of stack
+    pub(crate) fn values(&self, addr: usize, type_idx: usize) -> Vec<Value> {
+        let mut addr = addr;
+        self.types[type_idx..]
+            .iter()
+            .map(|ty| {
+                let v = self.read_any(addr, *ty);
+                addr += ty.bytes();
+                v
+            })
+            .collect()
+    }
+
+    // At most n values on top of stack above the type index. The top of stack comes last
+    pub(crate) fn top_values(&self, n: usize, type_idx: usize) -> Vec<Value> {
+        let idx = self.types.len().saturating_sub(n).max(type_idx);
+        let bytes = self.types[idx..].iter().fold(0, |acc, t| acc + t.bytes());
+        self.values(self.bytes.len() - bytes, idx)
+    }
+
     fn top_addr(&self) -> usize {
         self.bytes.len()
     }
@@ -192,6 +231,13 @@ impl Stack {
         self.types.truncate(type_idx);
     }
 
+    // Remove all values and frames keeping the buffers
+    pub(crate) fn clear(&mut self) {
+        self.bytes.clear();
+        self.types.clear();
+        self.frames.clear();
+    }
+
     pub fn push_label(&self, ty: Option<ValType>) -> Label {
         Label {
             addr: self.top_addr(),
@@ -211,6 +257,24 @@ impl Stack {
         }
     }
 
+    pub fn frames(&self) -> &[StackFrame] {
+        &self.frames
+    }
+
+    pub(crate) fn push_frame(&mut self, funcidx: u32, offset: usize) {
+        self.frames.push(StackFrame { funcidx, offset });
+    }
+
+    pub(crate) fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    pub(crate) fn set_frame_offset(&mut self, offset: usize) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.offset = offset;
+        }
+    }
+
     pub fn extend_zero_values(&mut self, types: &[ValType]) {
         self.types.extend_from_slice(types);
         let bytes = types.iter().fold(0, |acc, t| acc + t.bytes());
@@ -231,13 +295,21 @@ pub struct CallFrame<'func> {
 
 impl<'f> CallFrame<'f> {
     pub fn new(stack: &Stack, params: &'f [ValType], locals: &'f [ValType]) -> Self {
-        let mut addrs = Vec::with_capacity(params.len() + locals.len());
-
         // Note: Params were already pushed to stack
         let params_bytes = params.iter().fold(0, |acc, p| acc + p.bytes());
         let base_addr = stack.top_addr() - params_bytes;
         let base_idx = stack.top_idx() - params.len();
+        Self::with_base(base_addr, base_idx, params, locals)
+    }
 
+    // Frame whose params and locals were already put on stack at the base address
+    pub(crate) fn with_base(
+        base_addr: usize,
+        base_idx: usize,
+        params: &'f [ValType],
+        locals: &'f [ValType],
+    ) -> Self {
+        let mut addrs = Vec::with_capacity(params.len() + locals.len());
         let mut addr = 0;
         for p in params {
             addrs.push(base_addr + addr);
@@ -261,6 +333,19 @@ impl<'f> CallFrame<'f> {
         self.local_addrs[localidx as usize]
     }
 
+    pub fn num_locals(&self) -> usize {
+        self.local_addrs.len()
+    }
+
+    // Address and type index where operand stack of the function starts
+    pub(crate) fn operands_base(&self) -> (usize, usize) {
+        let addr = match self.local_addrs.last() {
+            Some(addr) => addr + self.local_type(self.local_addrs.len() as u32 - 1).bytes(),
+            None => self.base_addr,
+        };
+        (addr, self.base_idx + self.local_addrs.len())
+    }
+
     pub fn local_type(&self, localidx: u32) -> ValType {
         let idx = localidx as usize;
         if idx < self.params.len() {
@@ -274,10 +359,12 @@ impl<'f> CallFrame<'f> {
     }
 }
 
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Label {
-    addr: usize,
-    type_idx: usize,
-    has_result: bool,
+    pub(crate) addr: usize,
+    pub(crate) type_idx: usize,
+    pub(crate) has_result: bool,
 }
 
 #[cfg(test)]
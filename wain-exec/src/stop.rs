@@ -0,0 +1,119 @@
+use crate::snapshot::SnapshotError;
+use crate::trap::{write_backtrace, Frame, Trap};
+use crate::watch::{Access, MemoryAccess};
+use std::fmt;
+
+// Reason why execution stopped on request of the host. Unlike traps, Wasm code did not fail. The
+// machine keeps the state at the stop so that it can be inspected or checkpointed
+#[cfg_attr(test, derive(Debug))]
+pub enum StopReason {
+    TerminatedByDebugger,
+    Watchpoint(MemoryAccess), // Memory hook suspended execution
+    SuspendedAtHostCall { mod_name: String, name: String },
+}
+
+#[cfg_attr(test, derive(Debug))]
+pub struct Stop {
+    pub reason: StopReason,
+    pub offset: usize,
+    pub backtrace: Vec<Frame>, // Call stack at the stop. The innermost frame comes first
+}
+
+impl Stop {
+    pub(crate) fn new(reason: StopReason, offset: usize) -> Box<Stop> {
+        Box::new(Stop {
+            reason,
+            offset,
+            backtrace: vec![],
+        })
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use StopReason::*;
+        match self {
+            TerminatedByDebugger => f.write_str("execution was terminated by debugger"),
+            Watchpoint(access) => write!(
+                f,
+                "execution was suspended by watchpoint on {} of {} bytes at address 0x{:x} in function {} (old value {}, new value {})",
+                match access.access {
+                    Access::Load => "load",
+                    Access::Store => "store",
+                },
+                access.size,
+                access.addr,
+                access.funcidx,
+                access.old,
+                access.new,
+            ),
+            SuspendedAtHostCall { mod_name, name } => write!(
+                f,
+                "execution was suspended before calling imported function '{}' in module '{}'",
+                name, mod_name,
+            ),
+        }
+    }
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: execution was stopped at byte offset 0x{:x}",
+            self.reason, self.offset
+        )?;
+        write_backtrace(f, &self.backtrace)
+    }
+}
+
+// Error of invoking functions. An invocation ends without its result when Wasm code traps or when
+// execution is stopped by the host
+#[cfg_attr(test, derive(Debug))]
+pub enum ExecError {
+    Trap(Box<Trap>),
+    Stop(Box<Stop>),
+    Checkpoint(Box<SnapshotError>), // Only returned by Machine::resume_from
+}
+
+impl ExecError {
+    // Trap of Wasm code. None when execution was stopped or could not start
+    pub fn trap(&self) -> Option<&Trap> {
+        match self {
+            ExecError::Trap(trap) => Some(trap),
+            _ => None,
+        }
+    }
+
+    // Reason of the stop requested by the host. None when execution failed
+    pub fn stop(&self) -> Option<&StopReason> {
+        match self {
+            ExecError::Stop(stop) => Some(&stop.reason),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExecError::Trap(trap) => trap.fmt(f),
+            ExecError::Stop(stop) => stop.fmt(f),
+            ExecError::Checkpoint(err) => write!(f, "cannot resume from checkpoint: {}", err),
+        }
+    }
+}
+
+impl From<Box<Trap>> for ExecError {
+    fn from(trap: Box<Trap>) -> Self {
+        ExecError::Trap(trap)
+    }
+}
+
+impl From<Box<Stop>> for ExecError {
+    fn from(stop: Box<Stop>) -> Self {
+        ExecError::Stop(stop)
+    }
+}
+
+pub type Result<T> = ::std::result::Result<T, ExecError>;
@@ -2,7 +2,12 @@ use crate::globals::Globals;
 use crate::trap::{Result, Trap, TrapReason};
 use wain_ast as ast;
 
+// Implementation limit of the number of elements. WebAssembly spec does not limit the size of table
+// without max. The same limit as V8 is applied so that growing a table does not exhaust host memory
+const MAX_TABLE_ELEMS: usize = 10_000_000;
+
 // Table instance
+#[derive(Clone)]
 pub struct Table {
     max: Option<usize>,
     elems: Vec<Option<u32>>, // function indices
@@ -98,4 +103,76 @@ impl Table {
             Err(Trap::new(TrapReason::UninitializedElem(idx), source_offset))
         }
     }
+
+    pub fn size(&self) -> usize {
+        self.elems.len()
+    }
+
+    // Element at the index. None when the index is out of the table
+    pub fn get(&self, idx: usize) -> Option<Option<u32>> {
+        self.elems.get(idx).copied()
+    }
+
+    // Returns false when the index is out of the table
+    pub fn set(&mut self, idx: usize, funcidx: Option<u32>) -> bool {
+        match self.elems.get_mut(idx) {
+            Some(elem) => {
+                *elem = funcidx;
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Add uninitialized elements. Returns the previous size or -1 on failure as table.grow
+    pub fn grow(&mut self, num_elems: u32) -> i32 {
+        let prev = self.elems.len();
+        let next = match prev.checked_add(num_elems as usize) {
+            Some(next) if next <= MAX_TABLE_ELEMS => next,
+            _ => return -1,
+        };
+        if matches!(self.max, Some(max) if next > max) {
+            return -1;
+        }
+        self.elems.resize(next, None);
+        prev as i32
+    }
+
+    // Replace all elements with the snapshot. Its size was checked to be within the limits
+    pub(crate) fn restore(&mut self, elems: &[Option<u32>]) {
+        self.elems.clear();
+        self.elems.extend_from_slice(elems);
+    }
+
+    // Reset the table to the image keeping the buffer
+    pub(crate) fn reset(&mut self, image: &Table) {
+        self.max = image.max;
+        self.restore(&image.elems);
+    }
+
+    pub(crate) fn elems(&self) -> &[Option<u32>] {
+        &self.elems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grow_limit() {
+        let table = ast::Table {
+            start: 0,
+            ty: ast::TableType {
+                limit: ast::Limits::From(1),
+            },
+            import: None,
+        };
+        let mut t = Table::allocate(&[table]).unwrap_or_else(|t| panic!("{}", t));
+        assert_eq!(t.grow(u32::MAX), -1);
+        assert_eq!(t.grow(MAX_TABLE_ELEMS as u32), -1);
+        assert_eq!(t.size(), 1);
+        assert_eq!(t.grow(2), 1);
+        assert_eq!(t.size(), 3);
+    }
 }
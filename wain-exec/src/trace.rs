@@ -0,0 +1,192 @@
+use crate::value::Value;
+use std::sync::{Arc, Mutex};
+use wain_ast::InsnKind;
+
+// Max number of values on top of operand stack passed to Tracer::instruction
+pub(crate) const MAX_TRACED_OPERANDS: usize = 3;
+
+// Observer of execution called by the machine. All methods do nothing by default so that an
+// implementation can trace only events it is interested in. It must be Send since it is owned by
+// the machine.
+pub trait Tracer {
+    // Before the body of the function is executed
+    fn enter(&mut self, _funcidx: u32, _args: &[Value]) {}
+
+    // After the function returned. Not called when the function was trapped
+    fn exit(&mut self, _funcidx: u32, _result: Option<&Value>) {}
+
+    // Before the instruction is executed. Operands are values on top of operand stack of the current
+    // function (up to 3 values). The top of stack comes last
+    fn instruction(
+        &mut self,
+        _funcidx: u32,
+        _insn: &InsnKind,
+        _offset: usize,
+        _operands: &[Value],
+    ) {
+    }
+
+    // After the host function imported from 'env' module returned
+    fn host_call(&mut self, _funcidx: u32, _name: &str, _args: &[Value], _result: Option<&Value>) {}
+
+    // When false, operands passed to Tracer::instruction are always empty. Collecting them on each
+    // instruction is costly. This is checked once when the tracer is set to the machine
+    fn wants_operands(&self) -> bool {
+        true
+    }
+}
+
+// Shared tracer so that the caller can access its state after execution
+impl<T: Tracer> Tracer for Arc<Mutex<T>> {
+    fn enter(&mut self, funcidx: u32, args: &[Value]) {
+        self.lock().unwrap().enter(funcidx, args)
+    }
+
+    fn exit(&mut self, funcidx: u32, result: Option<&Value>) {
+        self.lock().unwrap().exit(funcidx, result)
+    }
+
+    fn instruction(&mut self, funcidx: u32, insn: &InsnKind, offset: usize, operands: &[Value]) {
+        self.lock()
+            .unwrap()
+            .instruction(funcidx, insn, offset, operands)
+    }
+
+    fn host_call(&mut self, funcidx: u32, name: &str, args: &[Value], result: Option<&Value>) {
+        self.lock().unwrap().host_call(funcidx, name, args, result)
+    }
+
+    fn wants_operands(&self) -> bool {
+        self.lock().unwrap().wants_operands()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::DefaultImporter;
+    use crate::machine::Machine;
+    use wain_syntax_text::parse;
+
+    #[derive(Default)]
+    struct Recorder(Arc<Mutex<Vec<String>>>);
+
+    fn join(values: &[Value]) -> String {
+        let v: Vec<_> = values.iter().map(|v| v.to_string()).collect();
+        v.join(" ")
+    }
+
+    fn result(v: Option<&Value>) -> String {
+        v.map(Value::to_string).unwrap_or_default()
+    }
+
+    impl Tracer for Recorder {
+        fn enter(&mut self, funcidx: u32, args: &[Value]) {
+            let s = format!("enter {} [{}]", funcidx, join(args));
+            self.0.lock().unwrap().push(s);
+        }
+        fn exit(&mut self, funcidx: u32, ret: Option<&Value>) {
+            let s = format!("exit {} [{}]", funcidx, result(ret));
+            self.0.lock().unwrap().push(s);
+        }
+        fn instruction(&mut self, funcidx: u32, insn: &InsnKind, _: usize, operands: &[Value]) {
+            let s = format!("{} {} [{}]", funcidx, insn.name(), join(operands));
+            self.0.lock().unwrap().push(s);
+        }
+        fn host_call(&mut self, funcidx: u32, name: &str, args: &[Value], ret: Option<&Value>) {
+            let s = format!(
+                "host {} {} [{}] [{}]",
+                funcidx,
+                name,
+                join(args),
+                result(ret)
+            );
+            self.0.lock().unwrap().push(s);
+        }
+    }
+
+    #[test]
+    fn trace_execution() {
+        let source = r#"
+(module
+  (import "env" "putchar" (func $putchar (param i32) (result i32)))
+  (func $add (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.add)
+  (func (export "_start")
+    i32.const 1
+    i32.const 2
+    i32.const 3
+    i32.const 4
+    call $add
+    call $putchar
+    drop
+    drop
+    drop))
+"#;
+        let root = parse(source).unwrap_or_else(|e| panic!("{}", e));
+        let mut stdout = vec![];
+        let importer = DefaultImporter::with_stdio(std::io::empty(), &mut stdout);
+        let mut machine = Machine::instantiate(&root.module, importer).unwrap();
+        let events = Arc::new(Mutex::new(vec![]));
+        machine.set_tracer(Recorder(events.clone()));
+        machine.execute().unwrap();
+
+        let events = events.lock().unwrap();
+        assert_eq!(
+            *events,
+            [
+                "enter 2 []",
+                "2 i32.const []",
+                "2 i32.const [1i32]",
+                "2 i32.const [1i32 2i32]",
+                "2 i32.const [1i32 2i32 3i32]",
+                "2 call [2i32 3i32 4i32]",
+                "enter 1 [3i32 4i32]",
+                "1 local.get []",
+                "1 local.get [3i32]",
+                "1 i32.add [3i32 4i32]",
+                "exit 1 [7i32]",
+                "2 call [1i32 2i32 7i32]",
+                "host 0 putchar [7i32] [7i32]",
+                "2 drop [1i32 2i32 7i32]",
+                "2 drop [1i32 2i32]",
+                "2 drop [1i32]",
+                "exit 2 []",
+            ],
+        );
+    }
+
+    #[test]
+    fn skip_operands() {
+        #[derive(Default)]
+        struct Counter(usize);
+        impl Tracer for Counter {
+            fn instruction(&mut self, _: u32, _: &InsnKind, _: usize, operands: &[Value]) {
+                assert!(operands.is_empty(), "{}", join(operands));
+                self.0 += 1;
+            }
+            fn wants_operands(&self) -> bool {
+                false
+            }
+        }
+
+        let source = r#"
+(module
+  (func (export "_start")
+    i32.const 1
+    i32.const 2
+    i32.add
+    drop))
+"#;
+        let root = parse(source).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(std::io::empty(), std::io::sink());
+        let mut machine = Machine::instantiate(&root.module, importer).unwrap();
+        // Shared tracer forwards the hook
+        let counter = Arc::new(Mutex::new(Counter::default()));
+        machine.set_tracer(counter.clone());
+        machine.execute().unwrap();
+        assert_eq!(counter.lock().unwrap().0, 4);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::coredump::CoreDump;
 use crate::value::Value;
 use std::fmt;
 use wain_ast::{Import, ValType};
@@ -58,12 +59,28 @@ pub enum TrapReason {
     },
     RemZeroDivisor,
     DivByZeroOrOverflow,
+    UnsupportedFeature(String),
+}
+
+// Frame of Wasm call stack captured when a trap occurred or execution stopped
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct Frame {
+    pub funcidx: u32,
+    pub name: Option<String>,
+    pub offset: usize,
 }
 
 #[cfg_attr(test, derive(Debug))]
 pub struct Trap {
     pub reason: TrapReason,
     pub offset: usize,
+    // Call stack at the trap. The innermost frame comes first. Empty when the trap did not occur while
+    // invoking functions (e.g. on instantiation)
+    pub backtrace: Vec<Frame>,
+    // Captured when core dump is enabled by Machine::set_coredump and the trap occurred while invoking
+    // functions
+    pub coredump: Option<Box<CoreDump>>,
 }
 
 impl Trap {
@@ -83,11 +100,16 @@ impl Trap {
     }
 
     pub(crate) fn new(reason: TrapReason, offset: usize) -> Box<Trap> {
-        Box::new(Trap { reason, offset })
+        Box::new(Trap {
+            reason,
+            offset,
+            backtrace: vec![],
+            coredump: None,
+        })
     }
 }
 
-struct JoinWritable<'a, D: fmt::Display>(&'a [D], &'static str);
+pub(crate) struct JoinWritable<'a, D: fmt::Display>(pub(crate) &'a [D], pub(crate) &'static str);
 
 impl<'a, D: fmt::Display> fmt::Display for JoinWritable<'a, D> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -101,10 +123,10 @@ impl<'a, D: fmt::Display> fmt::Display for JoinWritable<'a, D> {
     }
 }
 
-impl fmt::Display for Trap {
+impl fmt::Display for TrapReason {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         use TrapReason::*;
-        match &self.reason {
+        match self {
             UnknownImport {
                 mod_name,
                 name,
@@ -204,13 +226,41 @@ impl fmt::Display for Trap {
             )?,
             RemZeroDivisor => f.write_str("attempt to calculate reminder with zero divisor")?,
             DivByZeroOrOverflow => f.write_str("integer overflow or attempt to devide integer by zero")?,
+            UnsupportedFeature(name) => write!(
+                f,
+                "module uses WebAssembly feature '{name}' listed in 'target_features' section but wain does not support it. \
+                 please build the module without the feature (e.g. '-mno-{name}' option for clang)",
+                name = name,
+            )?,
         }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            ": execution was trapped at byte offset 0x{:x}",
-            self.offset
-        )
+            "{}: execution was trapped at byte offset 0x{:x}",
+            self.reason, self.offset
+        )?;
+        write_backtrace(f, &self.backtrace)
+    }
+}
+
+pub(crate) fn write_backtrace(f: &mut fmt::Formatter<'_>, backtrace: &[Frame]) -> fmt::Result {
+    if !backtrace.is_empty() {
+        f.write_str("\nbacktrace:")?;
+    }
+    for (i, frame) in backtrace.iter().enumerate() {
+        write!(f, "\n  #{}: ", i)?;
+        match &frame.name {
+            Some(name) => write!(f, "'{}' (function {})", name, frame.funcidx)?,
+            None => write!(f, "function {}", frame.funcidx)?,
+        }
+        write!(f, " at byte offset 0x{:x}", frame.offset)?;
     }
+    Ok(())
 }
 
 pub type Result<T> = ::std::result::Result<T, Box<Trap>>;
@@ -0,0 +1,244 @@
+// Typed handles of exported functions
+//
+// Machine::invoke looks up the export by name, checks types of argument values and wraps the result
+// in Value on every call. TypedFunc resolves the function and checks its signature against Rust
+// types once. Calling it pushes native Rust values to stack directly.
+
+use crate::import::Importer;
+use crate::machine::Machine;
+use crate::stack::{Stack, StackAccess};
+use crate::stop::Result;
+use std::marker::PhantomData;
+use std::ptr;
+use wain_ast as ast;
+use wain_ast::{AsValType, ValType};
+
+// Rust types of Wasm values. Implemented for i32, i64, f32 and f64
+pub trait WasmType: StackAccess + AsValType {}
+
+impl WasmType for i32 {}
+impl WasmType for i64 {}
+impl WasmType for f32 {}
+impl WasmType for f64 {}
+
+// Parameters of Wasm function as Rust values. `()` for no parameter
+pub trait IntoWasmValues {
+    const VAL_TYPES: &'static [ValType];
+    fn push_to(self, stack: &mut Stack);
+}
+
+// Results of Wasm function as Rust values. `()` for no result
+pub trait FromWasmValues: Sized {
+    const VAL_TYPES: &'static [ValType];
+    fn pop_from(stack: &mut Stack) -> Self;
+}
+
+impl IntoWasmValues for () {
+    const VAL_TYPES: &'static [ValType] = &[];
+    fn push_to(self, _stack: &mut Stack) {}
+}
+
+impl FromWasmValues for () {
+    const VAL_TYPES: &'static [ValType] = &[];
+    fn pop_from(_stack: &mut Stack) -> Self {}
+}
+
+macro_rules! impl_wasm_values_for_type {
+    ($t:ty) => {
+        impl IntoWasmValues for $t {
+            const VAL_TYPES: &'static [ValType] = &[<$t>::VAL_TYPE];
+            fn push_to(self, stack: &mut Stack) {
+                stack.push(self);
+            }
+        }
+
+        impl FromWasmValues for $t {
+            const VAL_TYPES: &'static [ValType] = &[<$t>::VAL_TYPE];
+            fn pop_from(stack: &mut Stack) -> Self {
+                stack.pop()
+            }
+        }
+    };
+}
+
+impl_wasm_values_for_type!(i32);
+impl_wasm_values_for_type!(i64);
+impl_wasm_values_for_type!(f32);
+impl_wasm_values_for_type!(f64);
+
+macro_rules! impl_wasm_values_for_tuple {
+    ($($t:ident)+; $($rev:ident)+) => {
+        impl<$($t: WasmType),+> IntoWasmValues for ($($t,)+) {
+            const VAL_TYPES: &'static [ValType] = &[$($t::VAL_TYPE),+];
+            #[allow(non_snake_case)]
+            fn push_to(self, stack: &mut Stack) {
+                let ($($t,)+) = self;
+                $(stack.push($t);)+
+            }
+        }
+
+        impl<$($t: WasmType),+> FromWasmValues for ($($t,)+) {
+            const VAL_TYPES: &'static [ValType] = &[$($t::VAL_TYPE),+];
+            #[allow(non_snake_case)]
+            fn pop_from(stack: &mut Stack) -> Self {
+                // The last value is at the top of stack
+                $(let $rev: $rev = stack.pop();)+
+                ($($t,)+)
+            }
+        }
+    };
+}
+
+impl_wasm_values_for_tuple!(A; A);
+impl_wasm_values_for_tuple!(A B; B A);
+impl_wasm_values_for_tuple!(A B C; C B A);
+impl_wasm_values_for_tuple!(A B C D; D C B A);
+impl_wasm_values_for_tuple!(A B C D E; E D C B A);
+impl_wasm_values_for_tuple!(A B C D E F; F E D C B A);
+impl_wasm_values_for_tuple!(A B C D E F G; G F E D C B A);
+impl_wasm_values_for_tuple!(A B C D E F G H; H G F E D C B A);
+
+// Handle of exported function taken by Machine::get_typed_func
+pub struct TypedFunc<'m, 's, P, R> {
+    module: &'m ast::Module<'s>,
+    funcidx: u32,
+    _signature: PhantomData<fn(P) -> R>,
+}
+
+impl<'m, 's, P: IntoWasmValues, R: FromWasmValues> TypedFunc<'m, 's, P, R> {
+    pub(crate) fn new(module: &'m ast::Module<'s>, funcidx: u32) -> Self {
+        Self {
+            module,
+            funcidx,
+            _signature: PhantomData,
+        }
+    }
+
+    // Index of the function in the module
+    pub fn funcidx(&self) -> u32 {
+        self.funcidx
+    }
+
+    // Invoke the function on the instance of the module where the handle was taken
+    pub fn call<I: Importer>(&self, machine: &mut Machine<'m, 's, I>, params: P) -> Result<R> {
+        assert!(
+            ptr::eq(self.module, machine.module()),
+            "typed function was taken from other module"
+        );
+        machine.invoke_typed(self.funcidx, params)
+    }
+}
+
+impl<'m, 's, P, R> Clone for TypedFunc<'m, 's, P, R> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'m, 's, P, R> Copy for TypedFunc<'m, 's, P, R> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportError;
+    use crate::import::DefaultImporter;
+    use std::io;
+    use wain_syntax_text::parse;
+
+    const SOURCE: &str = r#"
+(module
+  (global $count (mut i32) (i32.const 0))
+  (func (export "add") (param i32 i32) (result i32)
+    local.get 0
+    local.get 1
+    i32.sub)
+  (func (export "mix") (param i64 f32 f64 i32) (result f64)
+    local.get 0
+    f64.convert_i64_s
+    local.get 1
+    f64.promote_f32
+    f64.sub
+    local.get 2
+    f64.mul
+    local.get 3
+    f64.convert_i32_s
+    f64.div)
+  (func (export "tick")
+    global.get $count
+    i32.const 1
+    i32.add
+    global.set $count)
+  (func (export "count") (result i32)
+    global.get $count)
+  (func (export "trap") (param i32) (result i32)
+    i32.const 1
+    local.get 0
+    i32.div_u)
+  (memory (export "memory") 1))
+"#;
+
+    type TestImporter = DefaultImporter<io::Empty, io::Sink>;
+
+    fn instantiate<'m, 's>(module: &'m ast::Module<'s>) -> Machine<'m, 's, TestImporter> {
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        Machine::instantiate(module, importer).unwrap_or_else(|t| panic!("{}", t))
+    }
+
+    #[test]
+    fn call_typed_func() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let mut machine = instantiate(&root.module);
+
+        // Order of parameters is kept
+        let add = machine
+            .get_typed_func::<(i32, i32), i32>("add")
+            .unwrap_or_else(|t| panic!("{}", t));
+        assert_eq!(add.funcidx(), 0);
+        assert_eq!(add.call(&mut machine, (50, 8)).unwrap(), 42);
+        assert_eq!(add.call(&mut machine, (8, 50)).unwrap(), -42);
+
+        let mix = machine
+            .get_typed_func::<(i64, f32, f64, i32), f64>("mix")
+            .unwrap_or_else(|t| panic!("{}", t));
+        assert_eq!(mix.call(&mut machine, (10, 2.5, 3.0, 2)).unwrap(), 11.25);
+
+        let tick = machine.get_typed_func::<(), ()>("tick").unwrap();
+        let count = machine.get_typed_func::<(), i32>("count").unwrap();
+        for _ in 0..3 {
+            tick.call(&mut machine, ()).unwrap();
+        }
+        assert_eq!(count.call(&mut machine, ()).unwrap(), 3);
+    }
+
+    #[test]
+    fn typed_func_errors() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let mut machine = instantiate(&root.module);
+
+        let msg = match machine.get_typed_func::<(i32, i64), i32>("add") {
+            Err(err) => err.to_string(),
+            Ok(_) => panic!("signature was not checked"),
+        };
+        assert_eq!(
+            msg,
+            "signature of function 'add' mismatches. expected '[i32 i64] -> [i32]' but got '[i32 i32] -> [i32]'",
+        );
+        match machine.get_typed_func::<(i32, i32), ()>("add") {
+            Err(err) => assert!(matches!(*err, ExportError::SignatureMismatch { .. })),
+            Ok(_) => panic!("signature was not checked"),
+        }
+        match machine.get_typed_func::<(), ()>("memory") {
+            Err(err) => assert!(matches!(*err, ExportError::WrongKind { .. }), "{}", err),
+            Ok(_) => panic!("export kind was not checked"),
+        }
+        match machine.get_typed_func::<(), ()>("unknown") {
+            Err(err) => assert_eq!(err.to_string(), "function 'unknown' is not exported"),
+            Ok(_) => panic!("unknown function was found"),
+        }
+
+        let div = machine.get_typed_func::<i32, i32>("trap").unwrap();
+        assert_eq!(div.call(&mut machine, 1).unwrap(), 1);
+        assert!(div.call(&mut machine, 0).is_err());
+        assert_eq!(div.call(&mut machine, 1).unwrap(), 1);
+    }
+}
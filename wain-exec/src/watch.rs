@@ -0,0 +1,304 @@
+use crate::memory::Memory;
+use crate::stop::{Stop, StopReason};
+use crate::value::{LittleEndian, Value};
+use std::mem::size_of;
+use std::ops::Range;
+
+// Kind of memory access done by load/store instructions
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Access {
+    Load,
+    Store,
+}
+
+// Which kind of memory accesses a watchpoint watches
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Watch {
+    Load,
+    Store,
+    Any,
+}
+
+impl Watch {
+    fn matches(self, access: Access) -> bool {
+        match self {
+            Watch::Load => access == Access::Load,
+            Watch::Store => access == Access::Store,
+            Watch::Any => true,
+        }
+    }
+}
+
+// Memory access which hit a watchpoint. Narrow integers accessed by instructions such as i32.load8_s
+// or i64.store16 are extended to i32 (or i64 for u32) values. On load, old and new are the same value
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, PartialEq)]
+pub struct MemoryAccess {
+    pub access: Access,
+    pub funcidx: u32,  // Function which executed the instruction
+    pub offset: usize, // Byte offset of the load/store instruction in source
+    pub addr: usize,   // Effective address of the access
+    pub size: usize,   // Number of accessed bytes
+    pub old: Value,    // Value in memory before the access
+    pub new: Value,    // Value in memory after the access
+}
+
+// What the machine does after calling memory hook
+#[cfg_attr(test, derive(Debug))]
+#[derive(Clone, Copy, PartialEq)]
+pub enum WatchAction {
+    Continue, // Perform the access and continue execution
+    Suspend,  // Stop execution before the access with StopReason::Watchpoint
+}
+
+// Callback called when load/store instruction accesses a watched address range. It must be Send
+// since it is owned by the machine
+pub trait MemoryHook {
+    fn hit(&mut self, access: &MemoryAccess) -> WatchAction;
+}
+
+impl<F> MemoryHook for F
+where
+    F: FnMut(&MemoryAccess) -> WatchAction,
+{
+    fn hit(&mut self, access: &MemoryAccess) -> WatchAction {
+        self(access)
+    }
+}
+
+// Values which can be loaded from or stored to memory by instructions
+pub(crate) trait Watched: LittleEndian + Copy {
+    fn value(self) -> Value;
+}
+
+macro_rules! impl_watched {
+    ($t:ty, $variant:ident, $to:ty) => {
+        impl Watched for $t {
+            fn value(self) -> Value {
+                Value::$variant(self as $to)
+            }
+        }
+    };
+}
+
+impl_watched!(i8, I32, i32);
+impl_watched!(u8, I32, i32);
+impl_watched!(i16, I32, i32);
+impl_watched!(u16, I32, i32);
+impl_watched!(i32, I32, i32);
+impl_watched!(u32, I64, i64);
+impl_watched!(i64, I64, i64);
+impl_watched!(f32, F32, f32);
+impl_watched!(f64, F64, f64);
+
+struct Watchpoint {
+    id: usize,
+    range: Range<usize>,
+    watch: Watch,
+    hook: Box<dyn MemoryHook + Send>,
+}
+
+#[derive(Default)]
+pub(crate) struct WatchState {
+    watchpoints: Vec<Watchpoint>,
+    next_id: usize,
+}
+
+impl WatchState {
+    pub(crate) fn add(
+        &mut self,
+        range: Range<usize>,
+        watch: Watch,
+        hook: Box<dyn MemoryHook + Send>,
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.watchpoints.push(Watchpoint {
+            id,
+            range,
+            watch,
+            hook,
+        });
+        id
+    }
+
+    // Returns false when the watchpoint does not exist
+    pub(crate) fn remove(&mut self, id: usize) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| w.id != id);
+        self.watchpoints.len() != len
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.watchpoints.is_empty()
+    }
+
+    // Call hooks of watchpoints overlapping with the access. `new` is None on load. This must be
+    // called before the access is performed
+    pub(crate) fn hook<V: Watched>(
+        &mut self,
+        memory: &Memory,
+        funcidx: u32,
+        addr: usize,
+        new: Option<V>,
+        at: usize,
+    ) -> Result<(), Box<Stop>> {
+        let size = size_of::<V>();
+        let end = addr.saturating_add(size);
+        let access = if new.is_some() {
+            Access::Store
+        } else {
+            Access::Load
+        };
+        let hit =
+            |w: &Watchpoint| w.watch.matches(access) && addr < w.range.end && w.range.start < end;
+        if !self.watchpoints.iter().any(hit) {
+            return Ok(());
+        }
+
+        // Out of range access is trapped by the access itself
+        let old: V = match memory.load(addr, at) {
+            Ok(v) => v,
+            Err(_) => return Ok(()),
+        };
+        let access = MemoryAccess {
+            access,
+            funcidx,
+            offset: at,
+            addr,
+            size,
+            old: old.value(),
+            new: new.unwrap_or(old).value(),
+        };
+
+        let mut suspend = false;
+        for w in self.watchpoints.iter_mut().filter(|w| hit(w)) {
+            if w.hook.hit(&access) == WatchAction::Suspend {
+                suspend = true;
+            }
+        }
+        if suspend {
+            return Err(Stop::new(StopReason::Watchpoint(access), at));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::DefaultImporter;
+    use crate::machine::Machine;
+    use crate::stop::ExecError;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use wain_syntax_text::parse;
+
+    const SOURCE: &str = r#"
+(module
+  (memory 1)
+  (func $write (param $addr i32) (param $v i32)
+    local.get $addr
+    local.get $v
+    i32.store)
+  (func (export "_start")
+    i32.const 16
+    i32.const 42
+    call $write
+    i32.const 17
+    i32.const -1
+    i32.store8 ;; overwrites second byte of the value at 16
+    i32.const 16
+    i32.load
+    drop
+    i32.const 64
+    i64.const 7
+    i64.store))
+"#;
+
+    type Hits = Arc<Mutex<Vec<MemoryAccess>>>;
+
+    fn record(hits: &Hits, action: WatchAction) -> impl FnMut(&MemoryAccess) -> WatchAction {
+        let hits = hits.clone();
+        move |access: &MemoryAccess| {
+            hits.lock().unwrap().push(access.clone());
+            action
+        }
+    }
+
+    #[test]
+    fn watch_loads_and_stores() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine = Machine::instantiate(&root.module, importer).unwrap();
+
+        let stores = Hits::default();
+        let loads = Hits::default();
+        machine.add_watchpoint(16..18, Watch::Store, record(&stores, WatchAction::Continue));
+        machine.add_watchpoint(16..20, Watch::Load, record(&loads, WatchAction::Continue));
+        let unwatched = Hits::default();
+        let id = machine.add_watchpoint(
+            0..1024,
+            Watch::Any,
+            record(&unwatched, WatchAction::Continue),
+        );
+        assert!(machine.remove_watchpoint(id));
+        assert!(!machine.remove_watchpoint(id));
+
+        machine.execute().unwrap();
+
+        let stores = stores.lock().unwrap();
+        assert_eq!(stores.len(), 2);
+        assert_eq!(stores[0].access, Access::Store);
+        assert_eq!(stores[0].funcidx, 0);
+        assert_eq!(stores[0].addr, 16);
+        assert_eq!(stores[0].size, 4);
+        assert_eq!(stores[0].old, Value::I32(0));
+        assert_eq!(stores[0].new, Value::I32(42));
+        assert_eq!(stores[1].funcidx, 1);
+        assert_eq!(stores[1].addr, 17);
+        assert_eq!(stores[1].size, 1);
+        assert_eq!(stores[1].old, Value::I32(0));
+        assert_eq!(stores[1].new, Value::I32(-1));
+        assert!(stores[0].offset < stores[1].offset);
+
+        let loads = loads.lock().unwrap();
+        assert_eq!(loads.len(), 1);
+        assert_eq!(loads[0].access, Access::Load);
+        assert_eq!(loads[0].old, Value::I32(0xff2a));
+        assert_eq!(loads[0].new, Value::I32(0xff2a));
+
+        assert!(unwatched.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn suspend_at_watchpoint() {
+        let root = parse(SOURCE).unwrap_or_else(|e| panic!("{}", e));
+        let importer = DefaultImporter::with_stdio(io::empty(), io::sink());
+        let mut machine = Machine::instantiate(&root.module, importer).unwrap();
+
+        let hits = Hits::default();
+        machine.add_watchpoint(64..65, Watch::Store, record(&hits, WatchAction::Suspend));
+        let stop = match machine.execute() {
+            Err(ExecError::Stop(stop)) => stop,
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("execution was not suspended"),
+        };
+
+        let access = match &stop.reason {
+            StopReason::Watchpoint(access) => access,
+            r => panic!("unexpected stop reason: {:?}", r),
+        };
+        assert_eq!(access.addr, 64);
+        assert_eq!(access.new, Value::I64(7));
+        assert_eq!(stop.offset, access.offset);
+        assert_eq!(hits.lock().unwrap().len(), 1);
+        // Memory is not modified since execution was suspended before the store
+        assert_eq!(machine.memory().read_range(64..72), Some(vec![0; 8]));
+
+        let msg = stop.to_string();
+        assert!(msg.contains("store of 8 bytes at address 0x40"), "{}", msg);
+    }
+}
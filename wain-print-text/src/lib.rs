@@ -0,0 +1,16 @@
+#![forbid(unsafe_code)]
+
+mod printer;
+
+pub use printer::Printer;
+use wain_ast::Module;
+
+// Print the module in text format with flat instructions like 'block ... end'
+pub fn print(module: &Module<'_>) -> String {
+    Printer::new(module, false).print()
+}
+
+// Print the module in text format with folded instructions like '(i32.add (i32.const 1) (local.get 0))'
+pub fn print_folded(module: &Module<'_>) -> String {
+    Printer::new(module, true).print()
+}
@@ -0,0 +1,1189 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use wain_ast::*;
+
+// https://webassembly.github.io/spec/core/text/values.html#text-idchar
+fn is_idchar(c: char) -> bool {
+    matches!(
+        c,
+        '0'..='9'
+        | 'a'..='z'
+        | 'A'..='Z'
+        | '!'
+        | '#'
+        | '$'
+        | '%'
+        | '&'
+        | '\''
+        | '*'
+        | '+'
+        | '-'
+        | '.'
+        | '/'
+        | ':'
+        | '<'
+        | '='
+        | '>'
+        | '?'
+        | '@'
+        | '\\'
+        | '^'
+        | '_'
+        | '`'
+        | '|'
+        | '~'
+    )
+}
+
+// Identifiers of one index space. A name which is not a valid identifier or which is used more than
+// once in the space cannot be an identifier. Such name is printed with @name annotation instead.
+struct Ids {
+    ids: Vec<Option<String>>,
+}
+
+impl Ids {
+    fn new<'a, 's: 'a, I>(names: I, len: usize) -> Ids
+    where
+        I: IntoIterator<Item = (u32, &'a Name<'s>)>,
+    {
+        let names: Vec<_> = names
+            .into_iter()
+            .map(|(idx, name)| (idx as usize, name.0.as_ref()))
+            .filter(|(idx, name)| *idx < len && !name.is_empty() && name.chars().all(is_idchar))
+            .collect();
+        let mut seen = HashSet::new();
+        let mut dups = HashSet::new();
+        for (_, name) in names.iter() {
+            if !seen.insert(*name) {
+                dups.insert(*name);
+            }
+        }
+        let mut ids = vec![None; len];
+        for (idx, name) in names {
+            if !dups.contains(name) {
+                ids[idx] = Some(format!("${}", name));
+            }
+        }
+        Ids { ids }
+    }
+
+    fn from_map(map: &NameMap<'_>, len: usize) -> Ids {
+        Ids::new(map.iter().map(|(i, n)| (*i, n)), len)
+    }
+
+    fn get(&self, idx: u32) -> Option<&str> {
+        self.ids.get(idx as usize).and_then(|i| i.as_deref())
+    }
+
+    // Reference to the index in instructions or other definitions
+    fn refer(&self, idx: u32) -> String {
+        match self.get(idx) {
+            Some(id) => id.to_string(),
+            None => idx.to_string(),
+        }
+    }
+
+    // Identifier at definition. When it has no identifier, index is put as comment
+    fn define(&self, idx: u32) -> String {
+        match self.get(idx) {
+            Some(id) => format!(" {}", id),
+            None => format!(" (;{};)", idx),
+        }
+    }
+}
+
+// https://webassembly.github.io/spec/core/text/values.html#strings
+fn write_string(out: &mut String, bytes: &[u8]) {
+    out.push('"');
+    for b in bytes.iter() {
+        match *b {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\t' => out.push_str("\\t"),
+            b'\n' => out.push_str("\\n"),
+            b'\r' => out.push_str("\\r"),
+            0x20..=0x7e => out.push(*b as char),
+            b => write!(out, "\\{:02x}", b).unwrap(),
+        }
+    }
+    out.push('"');
+}
+
+fn string(bytes: &[u8]) -> String {
+    let mut s = String::new();
+    write_string(&mut s, bytes);
+    s
+}
+
+// https://webassembly.github.io/spec/core/text/values.html#floating-point
+macro_rules! float_text {
+    ($name:ident, $float:ty, $bits:ty, $sign:expr, $payload_mask:expr) => {
+        fn $name(f: $float) -> String {
+            let sign = if f.is_sign_negative() { "-" } else { "" };
+            if f.is_nan() {
+                let payload = f.to_bits() & $payload_mask;
+                let canonical: $bits = 1 << ($sign - 1);
+                if payload == canonical {
+                    format!("{}nan", sign)
+                } else {
+                    format!("{}nan:0x{:x}", sign, payload)
+                }
+            } else if f.is_infinite() {
+                format!("{}inf", sign)
+            } else {
+                // Debug format of float values is the shortest representation which can be parsed
+                // into the same value. It always contains '.' or exponent so '-0.0' keeps its sign.
+                format!("{:?}", f)
+            }
+        }
+    };
+}
+float_text!(f32_text, f32, u32, 23, 0x7f_ffff);
+float_text!(f64_text, f64, u64, 52, 0xf_ffff_ffff_ffff);
+
+fn result_text(ty: &Option<ValType>) -> String {
+    match ty {
+        Some(ty) => format!(" (result {})", ty),
+        None => "".to_string(),
+    }
+}
+
+fn limits_text(limits: &Limits) -> String {
+    match limits {
+        Limits::Range(min, max) => format!("{} {}", min, max),
+        Limits::From(min) => format!("{}", min),
+    }
+}
+
+// Natural alignment of memory access instruction in bytes
+fn natural_align(kind: &InsnKind) -> u32 {
+    use InsnKind::*;
+    match kind {
+        I32Load8S(_) | I32Load8U(_) | I64Load8S(_) | I64Load8U(_) | I32Store8(_) | I64Store8(_) => {
+            1
+        }
+        I32Load16S(_) | I32Load16U(_) | I64Load16S(_) | I64Load16U(_) | I32Store16(_)
+        | I64Store16(_) => 2,
+        I32Load(_) | F32Load(_) | I64Load32S(_) | I64Load32U(_) | I32Store(_) | F32Store(_)
+        | I64Store32(_) => 4,
+        _ => 8,
+    }
+}
+
+// Number of operands popped and number of values pushed by an instruction
+#[derive(Clone, Copy)]
+struct Arity {
+    pops: usize,
+    pushes: usize,
+}
+
+// Node of folded instructions
+enum Node {
+    Plain {
+        text: String,
+        operands: Vec<Node>,
+        pushes: usize,
+    },
+    Block {
+        head: String,
+        body: Vec<Node>,
+        pushes: usize,
+    },
+    If {
+        head: String,
+        cond: Vec<Node>,
+        then_body: Vec<Node>,
+        else_body: Vec<Node>,
+        pushes: usize,
+    },
+}
+
+impl Node {
+    fn pushes(&self) -> usize {
+        match self {
+            Node::Plain { pushes, .. } | Node::Block { pushes, .. } | Node::If { pushes, .. } => {
+                *pushes
+            }
+        }
+    }
+}
+
+// Take nodes at tail of the sequence as operands. Only nodes which push exactly one value can be
+// operands so that evaluation order of instructions is never changed by folding
+fn take_operands(nodes: &mut Vec<Node>, pops: usize) -> Vec<Node> {
+    let num = nodes
+        .iter()
+        .rev()
+        .take(pops)
+        .take_while(|n| n.pushes() == 1)
+        .count();
+    nodes.split_off(nodes.len() - num)
+}
+
+// Context of function body being printed
+struct FuncContext {
+    locals: Ids,
+    results: usize,
+    labels: Vec<usize>, // Arities of labels. The innermost label is the last element
+}
+
+impl FuncContext {
+    fn label_arity(&self, label: LabelIdx) -> usize {
+        let label = label as usize;
+        if label < self.labels.len() {
+            self.labels[self.labels.len() - 1 - label]
+        } else {
+            self.results // Label of function body
+        }
+    }
+}
+
+pub struct Printer<'m, 's> {
+    module: &'m Module<'s>,
+    out: String,
+    indent: usize,
+    folded: bool,
+    types: Ids,
+    funcs: Ids,
+    tables: Ids,
+    memories: Ids,
+    globals: Ids,
+}
+
+impl<'m, 's> Printer<'m, 's> {
+    pub fn new(module: &'m Module<'s>, folded: bool) -> Self {
+        let names = &module.names;
+        Printer {
+            module,
+            out: String::new(),
+            indent: 0,
+            folded,
+            types: Ids::from_map(&names.types, module.types.len()),
+            funcs: Ids::from_map(&names.funcs, module.funcs.len()),
+            tables: Ids::from_map(&names.tables, module.tables.len()),
+            memories: Ids::from_map(&names.memories, module.memories.len()),
+            globals: Ids::from_map(&names.globals, module.globals.len()),
+        }
+    }
+
+    pub fn print(mut self) -> String {
+        self.module();
+        self.out
+    }
+
+    fn newline(&mut self) {
+        self.out.push('\n');
+        for _ in 0..self.indent {
+            self.out.push_str("  ");
+        }
+    }
+
+    fn line(&mut self, s: &str) {
+        self.newline();
+        self.out.push_str(s);
+    }
+
+    fn name_annot(&mut self, has_id: bool, name: Option<&Name<'_>>) {
+        if has_id {
+            return;
+        }
+        if let Some(name) = name {
+            self.out.push_str(" (@name ");
+            write_string(&mut self.out, name.0.as_bytes());
+            self.out.push(')');
+        }
+    }
+
+    fn func_type(&self, idx: TypeIdx) -> Option<&'m FuncType> {
+        self.module.types.get(idx as usize)
+    }
+
+    fn module(&mut self) {
+        let module = self.module;
+        self.out.push_str("(module");
+        if let Some(name) = &module.names.module {
+            let name = name.0.as_ref();
+            if !name.is_empty() && name.chars().all(is_idchar) {
+                write!(self.out, " ${}", name).unwrap();
+            } else {
+                self.name_annot(false, module.names.module.as_ref());
+            }
+        }
+        self.indent += 1;
+
+        self.customs(CustomPlace::BeforeFirst);
+        self.customs(CustomPlace::Before(SectionId::Type));
+        self.types();
+        self.customs(CustomPlace::After(SectionId::Type));
+        self.customs(CustomPlace::Before(SectionId::Import));
+        self.imports();
+        self.customs(CustomPlace::After(SectionId::Import));
+        for sec in &[SectionId::Func, SectionId::Code] {
+            self.customs(CustomPlace::Before(*sec));
+        }
+        self.funcs();
+        for sec in &[SectionId::Func, SectionId::Code] {
+            self.customs(CustomPlace::After(*sec));
+        }
+        self.customs(CustomPlace::Before(SectionId::Table));
+        self.tables();
+        self.customs(CustomPlace::After(SectionId::Table));
+        self.customs(CustomPlace::Before(SectionId::Memory));
+        self.memories();
+        self.customs(CustomPlace::After(SectionId::Memory));
+        self.customs(CustomPlace::Before(SectionId::Global));
+        self.globals();
+        self.customs(CustomPlace::After(SectionId::Global));
+        self.customs(CustomPlace::Before(SectionId::Export));
+        self.exports();
+        self.customs(CustomPlace::After(SectionId::Export));
+        self.customs(CustomPlace::Before(SectionId::Start));
+        if let Some(start) = &module.entrypoint {
+            let f = self.funcs.refer(start.idx);
+            self.line(&format!("(start {})", f));
+        }
+        self.customs(CustomPlace::After(SectionId::Start));
+        self.customs(CustomPlace::Before(SectionId::Elem));
+        self.elems();
+        self.customs(CustomPlace::After(SectionId::Elem));
+        self.customs(CustomPlace::Before(SectionId::Data));
+        self.data();
+        self.customs(CustomPlace::After(SectionId::Data));
+        self.customs(CustomPlace::AfterLast);
+
+        self.indent -= 1;
+        self.out.push_str(")\n");
+    }
+
+    // https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md#custom-annotations
+    fn customs(&mut self, place: CustomPlace) {
+        for custom in self.module.customs.iter() {
+            // 'name' section is printed as identifiers and @name annotations
+            if custom.place != place || custom.name.0 == "name" {
+                continue;
+            }
+            let place = match custom.place {
+                CustomPlace::BeforeFirst => "(before first)".to_string(),
+                CustomPlace::Before(id) => format!("(before {})", id),
+                CustomPlace::After(id) => format!("(after {})", id),
+                CustomPlace::AfterLast => "(after last)".to_string(),
+            };
+            let text = format!(
+                "(@custom {} {} {})",
+                string(custom.name.0.as_bytes()),
+                place,
+                string(&custom.data),
+            );
+            self.line(&text);
+        }
+    }
+
+    fn params_results(&mut self, ty: &FuncType, locals: Option<&Ids>, funcidx: u32) {
+        let mut unnamed = vec![];
+        for (i, param) in ty.params.iter().enumerate() {
+            let i = i as u32;
+            let (id, name) = match locals {
+                Some(locals) => (locals.get(i), self.module.names.local(funcidx, i)),
+                None => (None, None),
+            };
+            if id.is_none() && name.is_none() {
+                unnamed.push(param.to_string());
+                continue;
+            }
+            if !unnamed.is_empty() {
+                write!(self.out, " (param {})", unnamed.join(" ")).unwrap();
+                unnamed.clear();
+            }
+            self.out.push_str(" (param");
+            if let Some(id) = id {
+                write!(self.out, " {}", id).unwrap();
+            }
+            self.name_annot(id.is_some(), name);
+            write!(self.out, " {})", param).unwrap();
+        }
+        if !unnamed.is_empty() {
+            write!(self.out, " (param {})", unnamed.join(" ")).unwrap();
+        }
+        if !ty.results.is_empty() {
+            let results: Vec<_> = ty.results.iter().map(ValType::to_string).collect();
+            write!(self.out, " (result {})", results.join(" ")).unwrap();
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#types
+    fn types(&mut self) {
+        for (i, ty) in self.module.types.iter().enumerate() {
+            let id = self.types.define(i as u32);
+            self.newline();
+            write!(self.out, "(type{} (func", id).unwrap();
+            self.params_results(ty, None, 0);
+            self.out.push_str("))");
+        }
+    }
+
+    fn import_names(&mut self, import: &Import<'_>) {
+        self.out.push_str("(import ");
+        write_string(&mut self.out, import.mod_name.0.as_bytes());
+        self.out.push(' ');
+        write_string(&mut self.out, import.name.0.as_bytes());
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#imports
+    fn imports(&mut self) {
+        let module = self.module;
+        let mut imports = vec![];
+        for (i, f) in module.funcs.iter().enumerate() {
+            if let FuncKind::Import(import) = &f.kind {
+                imports.push((f.start, SectionId::Func, i, import));
+            }
+        }
+        for (i, t) in module.tables.iter().enumerate() {
+            if let Some(import) = &t.import {
+                imports.push((t.start, SectionId::Table, i, import));
+            }
+        }
+        for (i, m) in module.memories.iter().enumerate() {
+            if let Some(import) = &m.import {
+                imports.push((m.start, SectionId::Memory, i, import));
+            }
+        }
+        for (i, g) in module.globals.iter().enumerate() {
+            if let GlobalKind::Import(import) = &g.kind {
+                imports.push((g.start, SectionId::Global, i, import));
+            }
+        }
+        // Keep the original order of imports
+        imports.sort_by_key(|(start, ..)| *start);
+
+        for (_, kind, idx, import) in imports {
+            self.newline();
+            self.import_names(import);
+            self.out.push(' ');
+            match kind {
+                SectionId::Func => {
+                    let func = &module.funcs[idx];
+                    self.func_head(idx as u32, func);
+                    self.out.push(')');
+                }
+                SectionId::Table => self.table_head(idx),
+                SectionId::Memory => self.memory_head(idx),
+                SectionId::Global => self.global_head(idx),
+                _ => unreachable!(),
+            }
+            self.out.push(')');
+        }
+    }
+
+    fn func_locals(&self, funcidx: u32, func: &Func<'_>) -> Ids {
+        let num_params = self
+            .func_type(func.idx)
+            .map(|t| t.params.len())
+            .unwrap_or(0);
+        let num_locals = match &func.kind {
+            FuncKind::Body { locals, .. } => locals.len(),
+            FuncKind::Import(_) => 0,
+        };
+        let names = &self.module.names.locals;
+        match names.binary_search_by_key(&funcidx, |(i, _)| *i) {
+            Ok(i) => Ids::from_map(&names[i].1, num_params + num_locals),
+            Err(_) => Ids::new(None, num_params + num_locals),
+        }
+    }
+
+    fn func_head(&mut self, idx: u32, func: &Func<'_>) {
+        let locals = self.func_locals(idx, func);
+        let id = self.funcs.define(idx);
+        write!(self.out, "(func{}", id).unwrap();
+        self.name_annot(self.funcs.get(idx).is_some(), self.module.names.func(idx));
+        let ty = self.types.refer(func.idx);
+        write!(self.out, " (type {})", ty).unwrap();
+        if let Some(ty) = self.func_type(func.idx) {
+            self.params_results(ty, Some(&locals), idx);
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#functions
+    fn funcs(&mut self) {
+        let module = self.module;
+        for (idx, func) in module.funcs.iter().enumerate() {
+            let (locals, expr) = match &func.kind {
+                FuncKind::Body { locals, expr } => (locals, expr),
+                FuncKind::Import(_) => continue,
+            };
+            let idx = idx as u32;
+            self.newline();
+            self.func_head(idx, func);
+
+            let (num_params, results) = match self.func_type(func.idx) {
+                Some(ty) => (ty.params.len(), ty.results.len()),
+                None => (0, 0),
+            };
+            let mut ctx = FuncContext {
+                locals: self.func_locals(idx, func),
+                results,
+                labels: vec![],
+            };
+
+            self.indent += 1;
+            if !locals.is_empty() {
+                self.newline();
+                let mut unnamed = vec![];
+                let mut first = true;
+                for (i, ty) in locals.iter().enumerate() {
+                    let localidx = (num_params + i) as u32;
+                    let id = ctx.locals.get(localidx);
+                    let name = module.names.local(idx, localidx);
+                    if id.is_none() && name.is_none() {
+                        unnamed.push(ty.to_string());
+                        continue;
+                    }
+                    if !unnamed.is_empty() {
+                        if !first {
+                            self.out.push(' ');
+                        }
+                        write!(self.out, "(local {})", unnamed.join(" ")).unwrap();
+                        unnamed.clear();
+                        first = false;
+                    }
+                    if !first {
+                        self.out.push(' ');
+                    }
+                    self.out.push_str("(local");
+                    if let Some(id) = id {
+                        write!(self.out, " {}", id).unwrap();
+                    }
+                    self.name_annot(id.is_some(), name);
+                    write!(self.out, " {})", ty).unwrap();
+                    first = false;
+                }
+                if !unnamed.is_empty() {
+                    if !first {
+                        self.out.push(' ');
+                    }
+                    write!(self.out, "(local {})", unnamed.join(" ")).unwrap();
+                }
+            }
+            if self.folded {
+                let nodes = self.fold(expr, &mut ctx);
+                for node in nodes.iter() {
+                    self.newline();
+                    self.node(node);
+                }
+            } else {
+                self.flat(expr, &mut ctx);
+            }
+            self.indent -= 1;
+            self.out.push(')');
+        }
+    }
+
+    fn table_head(&mut self, idx: usize) {
+        let table = &self.module.tables[idx];
+        let id = self.tables.define(idx as u32);
+        write!(
+            self.out,
+            "(table{} {} funcref)",
+            id,
+            limits_text(&table.ty.limit)
+        )
+        .unwrap();
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#tables
+    fn tables(&mut self) {
+        for (i, table) in self.module.tables.iter().enumerate() {
+            if table.import.is_none() {
+                self.newline();
+                self.table_head(i);
+            }
+        }
+    }
+
+    fn memory_head(&mut self, idx: usize) {
+        let memory = &self.module.memories[idx];
+        let id = self.memories.define(idx as u32);
+        write!(self.out, "(memory{} {})", id, limits_text(&memory.ty.limit)).unwrap();
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#memories
+    fn memories(&mut self) {
+        for (i, memory) in self.module.memories.iter().enumerate() {
+            if memory.import.is_none() {
+                self.newline();
+                self.memory_head(i);
+            }
+        }
+    }
+
+    fn global_head(&mut self, idx: usize) {
+        let global = &self.module.globals[idx];
+        let id = self.globals.define(idx as u32);
+        write!(self.out, "(global{} ", id).unwrap();
+        if global.mutable {
+            write!(self.out, "(mut {}))", global.ty).unwrap();
+        } else {
+            write!(self.out, "{})", global.ty).unwrap();
+        }
+    }
+
+    // Constant expression is always printed in folded form in one line
+    fn const_expr(&self, expr: &[Instruction]) -> String {
+        let ctx = FuncContext {
+            locals: Ids::new(None, 0),
+            results: 1,
+            labels: vec![],
+        };
+        let insns: Vec<_> = expr
+            .iter()
+            .map(|insn| format!("({})", self.insn_text(&insn.kind, &ctx)))
+            .collect();
+        insns.join(" ")
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#globals
+    fn globals(&mut self) {
+        let module = self.module;
+        for (i, global) in module.globals.iter().enumerate() {
+            if let GlobalKind::Init(expr) = &global.kind {
+                self.newline();
+                self.global_head(i);
+                self.out.pop(); // Remove ')'
+                let init = self.const_expr(expr);
+                write!(self.out, " {})", init).unwrap();
+            }
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#exports
+    fn exports(&mut self) {
+        for export in self.module.exports.iter() {
+            let desc = match export.kind {
+                ExportKind::Func(idx) => format!("(func {})", self.funcs.refer(idx)),
+                ExportKind::Table(idx) => format!("(table {})", self.tables.refer(idx)),
+                ExportKind::Memory(idx) => format!("(memory {})", self.memories.refer(idx)),
+                ExportKind::Global(idx) => format!("(global {})", self.globals.refer(idx)),
+            };
+            let text = format!("(export {} {})", string(export.name.0.as_bytes()), desc);
+            self.line(&text);
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#element-segments
+    fn elems(&mut self) {
+        for elem in self.module.elems.iter() {
+            let mut text = "(elem".to_string();
+            if elem.idx != 0 {
+                write!(text, " {}", self.tables.refer(elem.idx)).unwrap();
+            }
+            write!(text, " (offset {})", self.const_expr(&elem.offset)).unwrap();
+            for idx in elem.init.iter() {
+                write!(text, " {}", self.funcs.refer(*idx)).unwrap();
+            }
+            text.push(')');
+            self.line(&text);
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/text/modules.html#data-segments
+    fn data(&mut self) {
+        for data in self.module.data.iter() {
+            let mut text = "(data".to_string();
+            if data.idx != 0 {
+                write!(text, " {}", self.memories.refer(data.idx)).unwrap();
+            }
+            write!(text, " (offset {})", self.const_expr(&data.offset)).unwrap();
+            if !data.data.is_empty() {
+                text.push(' ');
+                write_string(&mut text, &data.data);
+            }
+            text.push(')');
+            self.line(&text);
+        }
+    }
+
+    fn arity(&self, kind: &InsnKind, ctx: &FuncContext) -> Arity {
+        use InsnKind::*;
+        let (pops, pushes) = match kind {
+            Block { .. } | Loop { .. } | If { .. } => unreachable!(),
+            Unreachable | Nop => (0, 0),
+            Br(label) => (ctx.label_arity(*label), 0),
+            BrIf(label) => {
+                let arity = ctx.label_arity(*label);
+                (arity + 1, arity)
+            }
+            BrTable { default_label, .. } => (ctx.label_arity(*default_label) + 1, 0),
+            Return => (ctx.results, 0),
+            Call(funcidx) => match self.module.funcs.get(*funcidx as usize) {
+                Some(func) => match self.func_type(func.idx) {
+                    Some(ty) => (ty.params.len(), ty.results.len()),
+                    None => (0, 0),
+                },
+                None => (0, 0),
+            },
+            CallIndirect(typeidx) => match self.func_type(*typeidx) {
+                Some(ty) => (ty.params.len() + 1, ty.results.len()),
+                None => (1, 0),
+            },
+            Drop => (1, 0),
+            Select => (3, 1),
+            LocalGet(_) | GlobalGet(_) => (0, 1),
+            LocalSet(_) | GlobalSet(_) => (1, 0),
+            LocalTee(_) => (1, 1),
+            I32Load(_) | I64Load(_) | F32Load(_) | F64Load(_) | I32Load8S(_) | I32Load8U(_)
+            | I32Load16S(_) | I32Load16U(_) | I64Load8S(_) | I64Load8U(_) | I64Load16S(_)
+            | I64Load16U(_) | I64Load32S(_) | I64Load32U(_) => (1, 1),
+            I32Store(_) | I64Store(_) | F32Store(_) | F64Store(_) | I32Store8(_)
+            | I32Store16(_) | I64Store8(_) | I64Store16(_) | I64Store32(_) => (2, 0),
+            MemorySize => (0, 1),
+            MemoryGrow => (1, 1),
+            I32Const(_) | I64Const(_) | F32Const(_) | F64Const(_) => (0, 1),
+            I32Clz | I32Ctz | I32Popcnt | I64Clz | I64Ctz | I64Popcnt | F32Abs | F32Neg
+            | F32Ceil | F32Floor | F32Trunc | F32Nearest | F32Sqrt | F64Abs | F64Neg | F64Ceil
+            | F64Floor | F64Trunc | F64Nearest | F64Sqrt | I32Eqz | I64Eqz | I32WrapI64
+            | I32TruncF32S | I32TruncF32U | I32TruncF64S | I32TruncF64U | I64ExtendI32S
+            | I64ExtendI32U | I64TruncF32S | I64TruncF32U | I64TruncF64S | I64TruncF64U
+            | F32ConvertI32S | F32ConvertI32U | F32ConvertI64S | F32ConvertI64U | F32DemoteF64
+            | F64ConvertI32S | F64ConvertI32U | F64ConvertI64S | F64ConvertI64U | F64PromoteF32
+            | I32ReinterpretF32 | I64ReinterpretF64 | F32ReinterpretI32 | F64ReinterpretI64 => {
+                (1, 1)
+            }
+            _ => (2, 1), // Binary operators and comparisons
+        };
+        Arity { pops, pushes }
+    }
+
+    // Mnemonic and immediates of non-structured instruction
+    fn insn_text(&self, kind: &InsnKind, ctx: &FuncContext) -> String {
+        use InsnKind::*;
+        let name = kind.name();
+        match kind {
+            Br(label) | BrIf(label) => format!("{} {}", name, label),
+            BrTable {
+                labels,
+                default_label,
+            } => {
+                let mut s = name.to_string();
+                for label in labels.iter().chain(Some(default_label)) {
+                    write!(s, " {}", label).unwrap();
+                }
+                s
+            }
+            Call(idx) => format!("{} {}", name, self.funcs.refer(*idx)),
+            CallIndirect(idx) => format!("{} (type {})", name, self.types.refer(*idx)),
+            LocalGet(idx) | LocalSet(idx) | LocalTee(idx) => {
+                format!("{} {}", name, ctx.locals.refer(*idx))
+            }
+            GlobalGet(idx) | GlobalSet(idx) => format!("{} {}", name, self.globals.refer(*idx)),
+            I32Load(mem) | I64Load(mem) | F32Load(mem) | F64Load(mem) | I32Load8S(mem)
+            | I32Load8U(mem) | I32Load16S(mem) | I32Load16U(mem) | I64Load8S(mem)
+            | I64Load8U(mem) | I64Load16S(mem) | I64Load16U(mem) | I64Load32S(mem)
+            | I64Load32U(mem) | I32Store(mem) | I64Store(mem) | F32Store(mem) | F64Store(mem)
+            | I32Store8(mem) | I32Store16(mem) | I64Store8(mem) | I64Store16(mem)
+            | I64Store32(mem) => {
+                let mut s = name.to_string();
+                match mem.offset {
+                    Some(offset) if offset != 0 => write!(s, " offset={}", offset).unwrap(),
+                    _ => {}
+                }
+                if let Some(align) = mem.align {
+                    let align = 1u64 << align;
+                    if align != natural_align(kind) as u64 {
+                        write!(s, " align={}", align).unwrap();
+                    }
+                }
+                s
+            }
+            I32Const(i) => format!("{} {}", name, i),
+            I64Const(i) => format!("{} {}", name, i),
+            F32Const(f) => format!("{} {}", name, f32_text(*f)),
+            F64Const(f) => format!("{} {}", name, f64_text(*f)),
+            _ => name.to_string(),
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/text/instructions.html#control-instructions
+    fn flat(&mut self, insns: &[Instruction], ctx: &mut FuncContext) {
+        use InsnKind::*;
+        for insn in insns.iter() {
+            match &insn.kind {
+                Block { ty, body } | Loop { ty, body } => {
+                    let is_loop = matches!(insn.kind, Loop { .. });
+                    let head = format!("{}{}", insn.kind.name(), result_text(ty));
+                    self.line(&head);
+                    let arity = if is_loop { 0 } else { ty.iter().count() };
+                    ctx.labels.push(arity);
+                    self.indent += 1;
+                    self.flat(body, ctx);
+                    self.indent -= 1;
+                    ctx.labels.pop();
+                    self.line("end");
+                }
+                If {
+                    ty,
+                    then_body,
+                    else_body,
+                } => {
+                    self.line(&format!("if{}", result_text(ty)));
+                    ctx.labels.push(ty.iter().count());
+                    self.indent += 1;
+                    self.flat(then_body, ctx);
+                    self.indent -= 1;
+                    if !else_body.is_empty() {
+                        self.line("else");
+                        self.indent += 1;
+                        self.flat(else_body, ctx);
+                        self.indent -= 1;
+                    }
+                    ctx.labels.pop();
+                    self.line("end");
+                }
+                kind => {
+                    let text = self.insn_text(kind, ctx);
+                    self.line(&text);
+                }
+            }
+        }
+    }
+
+    // https://webassembly.github.io/spec/core/text/instructions.html#folded-instructions
+    fn fold(&self, insns: &[Instruction], ctx: &mut FuncContext) -> Vec<Node> {
+        use InsnKind::*;
+        let mut nodes = vec![];
+        for insn in insns.iter() {
+            let node = match &insn.kind {
+                Block { ty, body } | Loop { ty, body } => {
+                    let is_loop = matches!(insn.kind, Loop { .. });
+                    let pushes = ty.iter().count();
+                    ctx.labels.push(if is_loop { 0 } else { pushes });
+                    let body = self.fold(body, ctx);
+                    ctx.labels.pop();
+                    Node::Block {
+                        head: format!("{}{}", insn.kind.name(), result_text(ty)),
+                        body,
+                        pushes,
+                    }
+                }
+                If {
+                    ty,
+                    then_body,
+                    else_body,
+                } => {
+                    let cond = take_operands(&mut nodes, 1);
+                    let pushes = ty.iter().count();
+                    ctx.labels.push(pushes);
+                    let then_body = self.fold(then_body, ctx);
+                    let else_body = self.fold(else_body, ctx);
+                    ctx.labels.pop();
+                    Node::If {
+                        head: format!("if{}", result_text(ty)),
+                        cond,
+                        then_body,
+                        else_body,
+                        pushes,
+                    }
+                }
+                kind => {
+                    let Arity { pops, pushes } = self.arity(kind, ctx);
+                    let operands = take_operands(&mut nodes, pops);
+                    Node::Plain {
+                        text: self.insn_text(kind, ctx),
+                        operands,
+                        pushes,
+                    }
+                }
+            };
+            nodes.push(node);
+        }
+        nodes
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) {
+        self.indent += 1;
+        for node in nodes.iter() {
+            self.newline();
+            self.node(node);
+        }
+        self.indent -= 1;
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::Plain { text, operands, .. } => {
+                write!(self.out, "({}", text).unwrap();
+                self.nodes(operands);
+                self.out.push(')');
+            }
+            Node::Block { head, body, .. } => {
+                write!(self.out, "({}", head).unwrap();
+                self.nodes(body);
+                self.out.push(')');
+            }
+            Node::If {
+                head,
+                cond,
+                then_body,
+                else_body,
+                ..
+            } => {
+                write!(self.out, "({}", head).unwrap();
+                self.nodes(cond);
+                self.indent += 1;
+                self.line("(then");
+                self.nodes(then_body);
+                self.out.push(')');
+                if !else_body.is_empty() {
+                    self.line("(else");
+                    self.nodes(else_body);
+                    self.out.push(')');
+                }
+                self.indent -= 1;
+                self.out.push(')');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use wain_encode_binary::encode;
+    use wain_syntax_binary::parse as parse_binary;
+    use wain_syntax_text::parse_with_id_names as parse_text;
+
+    fn example_files(ext: &str) -> Vec<PathBuf> {
+        let mut dir = env::current_dir().unwrap();
+        dir.pop();
+        dir.push("examples");
+        let mut files = vec![];
+        for dir in [dir.clone(), dir.join("hello")].iter() {
+            for entry in fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                if path.extension().map(|e| e == ext).unwrap_or(false) {
+                    files.push(path);
+                }
+            }
+        }
+        assert!(!files.is_empty());
+        files
+    }
+
+    fn print(module: &Module<'_>, folded: bool) -> String {
+        Printer::new(module, folded).print()
+    }
+
+    fn reparse(module: &Module<'_>, folded: bool, file: &PathBuf) -> Vec<u8> {
+        let text = print(module, folded);
+        let root = parse_text(&text).unwrap_or_else(|e| panic!("{:?}: {}\n{}", file, e, text));
+        encode(&root.module)
+    }
+
+    #[test]
+    fn roundtrip_binary() {
+        for file in example_files("wasm") {
+            let bin = fs::read(&file).unwrap();
+            let mut root = parse_binary(&bin).unwrap_or_else(|e| panic!("{:?}: {}", file, e));
+            for folded in [false, true].iter() {
+                let text = print(&root.module, *folded);
+                let reparsed =
+                    parse_text(&text).unwrap_or_else(|e| panic!("{:?}: {}\n{}", file, e, text));
+                assert_eq!(
+                    reparsed.module.names.funcs.len(),
+                    root.module.names.funcs.len(),
+                    "{:?}",
+                    file
+                );
+            }
+            // 'name' section is printed as identifiers
+            root.module.customs.retain(|c| c.name.0 != "name");
+            let expected = encode(&root.module);
+            assert_eq!(reparse(&root.module, false, &file), expected, "{:?}", file);
+            assert_eq!(reparse(&root.module, true, &file), expected, "{:?}", file);
+        }
+    }
+
+    #[test]
+    fn roundtrip_text() {
+        for file in example_files("wat") {
+            let source = fs::read_to_string(&file).unwrap();
+            let root = parse_text(&source).unwrap_or_else(|e| panic!("{:?}: {}", file, e));
+            let expected = encode(&root.module);
+            assert_eq!(reparse(&root.module, false, &file), expected, "{:?}", file);
+            assert_eq!(reparse(&root.module, true, &file), expected, "{:?}", file);
+        }
+    }
+
+    #[test]
+    fn flat_and_folded() {
+        let source = r#"
+            (module
+              (func $f (param $x i32) (result i32)
+                local.get $x
+                if (result i32)
+                  (i32.add (local.get $x) (i32.const 1))
+                else
+                  i32.const 0
+                end)
+              (func (call $f (i32.const 1)) drop))
+        "#;
+        let root = parse_text(source).unwrap_or_else(|e| panic!("{}", e));
+
+        let flat = print(&root.module, false);
+        assert_eq!(
+            flat,
+            r#"(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func))
+  (func $f (type 0) (param $x i32) (result i32)
+    local.get $x
+    if (result i32)
+      local.get $x
+      i32.const 1
+      i32.add
+    else
+      i32.const 0
+    end)
+  (func (;1;) (type 1)
+    i32.const 1
+    call $f
+    drop))
+"#
+        );
+
+        let folded = print(&root.module, true);
+        assert_eq!(
+            folded,
+            r#"(module
+  (type (;0;) (func (param i32) (result i32)))
+  (type (;1;) (func))
+  (func $f (type 0) (param $x i32) (result i32)
+    (if (result i32)
+      (local.get $x)
+      (then
+        (i32.add
+          (local.get $x)
+          (i32.const 1)))
+      (else
+        (i32.const 0))))
+  (func (;1;) (type 1)
+    (drop
+      (call $f
+        (i32.const 1)))))
+"#
+        );
+    }
+
+    #[test]
+    fn escape_strings() {
+        let source = r#"
+            (module
+              (import "a\"b" "c\\d" (func))
+              (memory 1)
+              (data (i32.const 0) "\00\01\t\n\7f\ff\e3\81\82 ok"))
+        "#;
+        let root = parse_text(source).unwrap_or_else(|e| panic!("{}", e));
+        let text = print(&root.module, false);
+        assert!(
+            text.contains(r#"(import "a\"b" "c\\d" (func (;0;) (type 0)))"#),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains(r#"(data (offset (i32.const 0)) "\00\01\t\n\7f\ff\e3\81\82 ok")"#),
+            "{}",
+            text
+        );
+    }
+
+    #[test]
+    fn float_values() {
+        let cases = [
+            (0.0f64, "0.0"),
+            (-0.0, "-0.0"),
+            (1.5, "1.5"),
+            (1e300, "1e300"),
+            (f64::INFINITY, "inf"),
+            (f64::NEG_INFINITY, "-inf"),
+            (f64::NAN, "nan"),
+            (f64::from_bits(0x7ff0_0000_0000_0001), "nan:0x1"),
+            (f64::from_bits(0xfff8_0000_0000_0000), "-nan"),
+        ];
+        for (f, expected) in cases.iter() {
+            assert_eq!(&f64_text(*f), expected);
+        }
+        assert_eq!(f32_text(f32::from_bits(0x7fa0_0000)), "nan:0x200000");
+        assert_eq!(f32_text(-0.25), "-0.25");
+
+        let source = r#"
+            (module (func
+              (drop (f32.const -0x0p+0)) (drop (f32.const nan:0x1234)) (drop (f32.const 0x1.fffffep+127))
+              (drop (f64.const -inf)) (drop (f64.const 0x1p-1074)) (drop (f64.const -nan))))
+        "#;
+        let root = parse_text(source).unwrap_or_else(|e| panic!("{}", e));
+        let expected = encode(&root.module);
+        let file = PathBuf::from("float_values");
+        assert_eq!(reparse(&root.module, false, &file), expected);
+    }
+
+    #[test]
+    fn names_which_are_not_ids() {
+        let source = r#"
+            (module
+              (func $dup)
+              (func $dup2 (@name "not id") (param (@name "a b") i32) (local $l i64))
+              (func $dup3)
+              (@custom "foo" (after func) "\01\02"))
+        "#;
+        let mut root = parse_text(source).unwrap_or_else(|e| panic!("{}", e));
+        root.module.names.funcs[0].1 = Name(Cow::Borrowed("same"));
+        root.module.names.funcs[2].1 = Name(Cow::Borrowed("same"));
+        let text = print(&root.module, false);
+        assert!(
+            text.contains(r#"(func (;0;) (@name "same") (type 0))"#),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains(r#"(func (;2;) (@name "same") (type 0))"#),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains(
+                r#"(func (;1;) (@name "not id") (type 1) (param (@name "a b") i32)
+    (local $l i64))"#
+            ),
+            "{}",
+            text
+        );
+        assert!(
+            text.contains(r#"(@custom "foo" (after func) "\01\02")"#),
+            "{}",
+            text
+        );
+
+        let reparsed = parse_text(&text).unwrap_or_else(|e| panic!("{}\n{}", e, text));
+        assert_eq!(reparsed.module.names.func(1).unwrap().0, "not id");
+        assert_eq!(reparsed.module.names.local(1, 0).unwrap().0, "a b");
+        assert_eq!(reparsed.module.names.local(1, 1).unwrap().0, "l");
+        assert_eq!(reparsed.module.names.func(2).unwrap().0, "same");
+    }
+
+    #[test]
+    fn memarg() {
+        let source = r#"
+            (module (memory 1) (func
+              (drop (i32.load offset=4 align=2 (i32.const 0)))
+              (drop (i64.load8_u align=1 (i32.const 0)))
+              (i32.store offset=0 align=4 (i32.const 0) (i32.const 0))))
+        "#;
+        let root = parse_text(source).unwrap_or_else(|e| panic!("{}", e));
+        let text = print(&root.module, false);
+        assert!(text.contains("i32.load offset=4 align=2\n"), "{}", text);
+        assert!(text.contains("i64.load8_u\n"), "{}", text);
+        assert!(text.contains("i32.store)"), "{}", text);
+    }
+}
@@ -7,11 +7,23 @@ mod parser;
 pub mod source;
 
 pub use error::{Error, ErrorKind, Result};
-pub use parser::Parser;
+pub use parser::{Parser, Section, SectionKind};
 use source::BinarySource;
-use wain_ast::Root;
+use wain_ast::{Root, TargetFeature};
 
 pub fn parse(input: &[u8]) -> Result<'_, Root<'_, BinarySource<'_>>> {
     let mut parser = Parser::new(input);
     parser.parse()
 }
+
+// List sections in the binary with their offsets and sizes
+pub fn sections(input: &[u8]) -> Result<'_, Vec<Section<'_>>> {
+    let mut parser = Parser::new(input);
+    parser.parse_sections()
+}
+
+// Decode features in 'target_features' custom section even if other sections are broken
+pub fn target_features(input: &[u8]) -> Result<'_, Vec<TargetFeature<'_>>> {
+    let mut parser = Parser::new(input);
+    parser.parse_target_features()
+}
@@ -26,6 +26,44 @@ fn section_name(id: u8) -> &'static str {
     }
 }
 
+fn section_id(id: u8) -> Option<SectionId> {
+    match id {
+        1 => Some(SectionId::Type),
+        2 => Some(SectionId::Import),
+        3 => Some(SectionId::Func),
+        4 => Some(SectionId::Table),
+        5 => Some(SectionId::Memory),
+        6 => Some(SectionId::Global),
+        7 => Some(SectionId::Export),
+        8 => Some(SectionId::Start),
+        9 => Some(SectionId::Elem),
+        10 => Some(SectionId::Code),
+        11 => Some(SectionId::Data),
+        _ => None,
+    }
+}
+
+pub enum SectionKind<'s> {
+    Standard(SectionId),
+    Custom(Name<'s>),
+}
+
+// Section found by Parser::parse_sections
+pub struct Section<'s> {
+    pub start: usize, // Offset of section ID
+    pub size: usize,  // Size of section contents
+    pub kind: SectionKind<'s>,
+}
+
+// Custom sections and information decoded from them while parsing module
+#[derive(Default)]
+struct Customs<'s> {
+    names: Names<'s>,
+    producers: Vec<ProducersField<'s>>,
+    target_features: Vec<TargetFeature<'s>>,
+    sections: Vec<Custom<'s>>,
+}
+
 // An iterator to iterate vec(P)
 // https://webassembly.github.io/spec/core/binary/conventions.html#binary-vec
 struct VecItems<'p, 's, P: Parse<'s>> {
@@ -72,6 +110,8 @@ pub struct Parser<'source> {
     rest_len: usize,
     // What is being parsed for better error message
     parsing: &'static str,
+    // The last standard section parsed so far to know where custom section is put
+    last_section: Option<SectionId>,
 }
 
 impl<'s> Parser<'s> {
@@ -81,6 +121,7 @@ impl<'s> Parser<'s> {
             input,
             rest_len: 0,
             parsing: "module",
+            last_section: None,
         }
     }
 
@@ -152,14 +193,19 @@ impl<'s> Parser<'s> {
         self.check_len(sub_len, what)?;
         Ok(Parser {
             source: self.source,
-            rest_len: self.input.len() - sub_len,
+            rest_len: self.rest_len + self.input.len() - sub_len,
             input: &self.input[..sub_len],
             parsing: what,
+            last_section: None,
         })
     }
 
     fn section_parser(&mut self) -> Result<'s, Parser<'s>> {
-        let section = section_name(self.input[0]);
+        let id = self.input[0];
+        let section = section_name(id);
+        if let Some(id) = section_id(id) {
+            self.last_section = Some(id);
+        }
         self.eat(1); // Eat section ID
         let size = self.parse_int::<u32>()? as usize;
         let parser = self.sub_parser(size as usize, section)?;
@@ -167,14 +213,48 @@ impl<'s> Parser<'s> {
         Ok(parser)
     }
 
-    // Note: Custom section is a pair of name and bytes payload. Currently custom section is simply
-    // ignored since it is not necessary to execute wasm binary.
-    fn ignore_custom_sections(&mut self) -> Result<'s, ()> {
+    // Note: Custom section is a pair of name and bytes payload. All custom sections are preserved with
+    // their places. Some well-known custom sections are also decoded.
+    fn parse_custom_sections(&mut self, customs: &mut Customs<'s>) -> Result<'s, ()> {
         while let [0, ..] = self.input {
-            self.eat(1); // Eat section ID
-            let size = self.parse_int::<u32>()? as usize;
-            self.check_len(size, "custom section")?;
-            self.eat(size);
+            let start = self.current_pos();
+            let place = match self.last_section {
+                Some(id) => CustomPlace::After(id),
+                None => CustomPlace::BeforeFirst,
+            };
+            let mut inner = self.section_parser()?;
+            let name: Name = inner.parse()?;
+            let data = inner.input;
+
+            // Malformed custom sections are not an error. They are simply not decoded.
+            match name.0.as_ref() {
+                // https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+                "name" => {
+                    if let Ok(names) = inner.parse() {
+                        customs.names = names;
+                    }
+                }
+                // https://github.com/WebAssembly/tool-conventions/blob/main/ProducersSection.md
+                "producers" => {
+                    if let Ok(fields) = inner.parse_vec().and_then(VecItems::into_vec) {
+                        customs.producers = fields;
+                    }
+                }
+                // https://github.com/WebAssembly/tool-conventions/blob/main/Linking.md#target-features-section
+                "target_features" => {
+                    if let Ok(features) = inner.parse_vec().and_then(VecItems::into_vec) {
+                        customs.target_features = features;
+                    }
+                }
+                _ => {}
+            }
+
+            customs.sections.push(Custom {
+                start,
+                name,
+                place,
+                data: Cow::Borrowed(data),
+            });
         }
         Ok(())
     }
@@ -188,6 +268,70 @@ impl<'s> Parser<'s> {
         }
     }
 
+    // Magic and version at the head of module
+    fn parse_preamble(&mut self) -> Result<'s, ()> {
+        match self.input {
+            [0x00, 0x61, 0x73, 0x6d, ..] => self.eat(4),
+            _ => return Err(self.error(ErrorKind::WasmMagicNotFound)),
+        }
+
+        match self.input {
+            [0x01, 0x00, 0x00, 0x00, ..] => {
+                self.eat(4);
+                Ok(())
+            }
+            _ => Err(self.error(ErrorKind::VersionMismatch(self.input.try_into().unwrap()))),
+        }
+    }
+
+    // List sections in the module without parsing their contents. Only names of custom sections are
+    // parsed.
+    pub fn parse_sections(&mut self) -> Result<'s, Vec<Section<'s>>> {
+        self.parse_preamble()?;
+
+        let mut sections = vec![];
+        while let [id, ..] = self.input {
+            let kind = match *id {
+                0 => None,
+                id => match section_id(id) {
+                    Some(id) => Some(SectionKind::Standard(id)),
+                    None => return Err(self.error(ErrorKind::ExpectedEof(id))),
+                },
+            };
+            let start = self.current_pos();
+            let mut inner = self.section_parser()?;
+            let size = inner.input.len();
+            let kind = match kind {
+                Some(kind) => kind,
+                None => SectionKind::Custom(inner.parse()?),
+            };
+            sections.push(Section { start, size, kind });
+        }
+        Ok(sections)
+    }
+
+    // Decode only 'target_features' custom section. Contents of other sections are not parsed so
+    // features used by the module are known even if parsing the module fails.
+    pub fn parse_target_features(&mut self) -> Result<'s, Vec<TargetFeature<'s>>> {
+        self.parse_preamble()?;
+
+        let mut features = vec![];
+        while let [id, ..] = self.input {
+            let id = *id;
+            if id != 0 && section_id(id).is_none() {
+                return Err(self.error(ErrorKind::ExpectedEof(id)));
+            }
+            let mut inner = self.section_parser()?;
+            if id == 0 {
+                let name: Name = inner.parse()?;
+                if name.0 == "target_features" {
+                    features = inner.parse_vec()?.into_vec()?;
+                }
+            }
+        }
+        Ok(features)
+    }
+
     // https://webassembly.github.io/spec/core/binary/conventions.html#binary-vec
     fn parse_vec<P: Parse<'s>>(&mut self) -> Result<'s, VecItems<'_, 's, P>> {
         let size: u32 = self.parse_int()?;
@@ -228,27 +372,16 @@ impl<'s> Parse<'s> for Module<'s> {
         }
 
         let start = parser.current_pos();
+        let mut customs = Customs::default();
 
-        match parser.input {
-            [0x00, 0x61, 0x73, 0x6d, ..] => parser.eat(4),
-            _ => return Err(parser.error(ErrorKind::WasmMagicNotFound)),
-        }
-
-        match parser.input {
-            [0x01, 0x00, 0x00, 0x00, ..] => parser.eat(4),
-            _ => {
-                return Err(
-                    parser.error(ErrorKind::VersionMismatch(parser.input.try_into().unwrap()))
-                )
-            }
-        }
+        parser.parse_preamble()?;
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         // Type section
         let types = parse_section(parser, 1)?;
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         let mut funcs = vec![];
         let mut tables = vec![];
@@ -268,7 +401,7 @@ impl<'s> Parse<'s> for Module<'s> {
             }
         }
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         // Function section
         // https://webassembly.github.io/spec/core/binary/modules.html#binary-funcsec
@@ -276,7 +409,7 @@ impl<'s> Parse<'s> for Module<'s> {
         let func_indices: Vec<u32> = parse_section(parser, 3)?;
         funcs.reserve(func_indices.len());
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         // Table section
         if let [0x04, ..] = parser.input {
@@ -288,7 +421,7 @@ impl<'s> Parse<'s> for Module<'s> {
             }
         }
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         // Memory section
         if let [0x05, ..] = parser.input {
@@ -300,7 +433,7 @@ impl<'s> Parse<'s> for Module<'s> {
             }
         }
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         // Global section
         if let [0x06, ..] = parser.input {
@@ -312,11 +445,11 @@ impl<'s> Parse<'s> for Module<'s> {
             }
         }
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         let exports = parse_section(parser, 7)?;
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         // Start function section
         let entrypoint = if let [8, ..] = parser.input {
@@ -326,12 +459,12 @@ impl<'s> Parse<'s> for Module<'s> {
             None
         };
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         // Element segments section
         let elems = parse_section(parser, 9)?;
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         // Code section
         if let [10, ..] = parser.input {
@@ -356,11 +489,11 @@ impl<'s> Parse<'s> for Module<'s> {
             }
         }
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         let data = parse_section(parser, 11)?;
 
-        parser.ignore_custom_sections()?;
+        parser.parse_custom_sections(&mut customs)?;
 
         if !parser.input.is_empty() {
             return Err(parser.error(ErrorKind::ExpectedEof(parser.input[0])));
@@ -378,6 +511,10 @@ impl<'s> Parse<'s> for Module<'s> {
             memories,
             globals,
             entrypoint,
+            names: customs.names,
+            producers: customs.producers,
+            target_features: customs.target_features,
+            customs: customs.sections,
         })
     }
 }
@@ -400,6 +537,124 @@ impl<'s> Parse<'s> for Name<'s> {
     }
 }
 
+// Indices in name map must be unique and sorted so that names can be looked up by binary search
+fn is_sorted<T>(map: &[(u32, T)]) -> bool {
+    map.windows(2).all(|w| w[0].0 < w[1].0)
+}
+
+// Malformed name map is empty
+fn name_map<'s>(parser: &mut Parser<'s>) -> NameMap<'s> {
+    match parser.parse_vec().and_then(VecItems::into_vec) {
+        Ok(map) if is_sorted(&map) => map,
+        _ => vec![],
+    }
+}
+
+fn indirect_name_map<'s>(parser: &mut Parser<'s>) -> IndirectNameMap<'s> {
+    let map: IndirectNameMap<'s> = match parser.parse_vec().and_then(VecItems::into_vec) {
+        Ok(map) => map,
+        Err(_) => return vec![],
+    };
+    if is_sorted(&map) && map.iter().all(|(_, m)| is_sorted(m)) {
+        map
+    } else {
+        vec![]
+    }
+}
+
+impl<'s> Parser<'s> {
+    // Returns ID and parser of the next name subsection
+    fn name_subsection(&mut self) -> Result<'s, (u8, Parser<'s>)> {
+        let id = self.consume("name subsection ID")?;
+        let size = self.parse_int::<u32>()? as usize;
+        let inner = self.sub_parser(size, "name subsection")?;
+        self.eat(size);
+        Ok((id, inner))
+    }
+}
+
+// https://webassembly.github.io/spec/core/appendix/custom.html#name-section
+// Malformed subsection is skipped so that names in other subsections are still available
+impl<'s> Parse<'s> for Names<'s> {
+    fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
+        let mut names = Names::default();
+        while !parser.input.is_empty() {
+            let (id, mut inner) = match parser.name_subsection() {
+                Ok(subsection) => subsection,
+                Err(_) => break, // Following subsections cannot be found
+            };
+            let inner = &mut inner;
+            match id {
+                0 => names.module = inner.parse().ok(),
+                1 => names.funcs = name_map(inner),
+                2 => names.locals = indirect_name_map(inner),
+                3 => names.labels = indirect_name_map(inner),
+                4 => names.types = name_map(inner),
+                5 => names.tables = name_map(inner),
+                6 => names.memories = name_map(inner),
+                7 => names.globals = name_map(inner),
+                8 => names.elems = name_map(inner),
+                9 => names.data = name_map(inner),
+                _ => { /* Unknown subsections are skipped */ }
+            }
+        }
+        Ok(names)
+    }
+}
+
+// https://webassembly.github.io/spec/core/appendix/custom.html#name-maps
+impl<'s> Parse<'s> for (u32, Name<'s>) {
+    fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
+        Ok((parser.parse()?, parser.parse()?))
+    }
+}
+
+// https://webassembly.github.io/spec/core/appendix/custom.html#binary-indirectnamemap
+impl<'s> Parse<'s> for (u32, NameMap<'s>) {
+    fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
+        Ok((parser.parse()?, parser.parse_vec()?.into_vec()?))
+    }
+}
+
+// https://github.com/WebAssembly/tool-conventions/blob/main/ProducersSection.md
+impl<'s> Parse<'s> for ProducersField<'s> {
+    fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
+        let start = parser.current_pos();
+        Ok(ProducersField {
+            start,
+            name: parser.parse()?,
+            values: parser.parse_vec()?.into_vec()?,
+        })
+    }
+}
+
+// Pair of program name and its version in producers section
+impl<'s> Parse<'s> for (Name<'s>, Name<'s>) {
+    fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
+        Ok((parser.parse()?, parser.parse()?))
+    }
+}
+
+// https://github.com/WebAssembly/tool-conventions/blob/main/Linking.md#target-features-section
+impl<'s> Parse<'s> for TargetFeature<'s> {
+    fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
+        let start = parser.current_pos();
+        let prefix = match parser.consume("target feature prefix")? {
+            b'+' => FeaturePrefix::Used,
+            b'=' => FeaturePrefix::Required,
+            b'-' => FeaturePrefix::Disallowed,
+            b => {
+                return Err(parser.unexpected_byte([b'+', b'=', b'-'], b, "target feature prefix"))
+            }
+        };
+        Ok(TargetFeature {
+            start,
+            prefix,
+            name: parser.parse()?,
+        })
+    }
+}
+
 // https://webassembly.github.io/spec/core/binary/types.html#function-types
 impl<'s> Parse<'s> for FuncType {
     fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
@@ -830,7 +1085,7 @@ impl<'s> Parse<'s> for Mem {
     fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
         let align: u32 = parser.parse_int()?;
         let offset = parser.parse_int()?;
-        let align = if align == 0 { None } else { Some(align as u8) };
+        let align = Some(align as u8);
         let offset = if offset == 0 { None } else { Some(offset) };
         Ok(Mem { align, offset })
     }
@@ -1110,6 +1365,24 @@ mod tests {
 
         assert!(root.module.entrypoint.is_none());
 
+        let c = &root.module.customs;
+        assert_eq!(c.len(), 2);
+        assert_eq!(c[0].name.0, "name");
+        assert_eq!(c[0].place, CustomPlace::After(SectionId::Data));
+        assert_eq!(c[1].name.0, "producers");
+        assert_eq!(c[1].place, CustomPlace::After(SectionId::Data));
+        assert_eq!(&bin[c[1].start..c[1].start + 2], &[0x00, 0x25]);
+
+        assert_eq!(root.module.names.func(0).unwrap().0, "putchar");
+
+        let p = &root.module.producers;
+        assert_eq!(p.len(), 1);
+        assert_eq!(p[0].name.0, "processed-by");
+        assert_eq!(p[0].values.len(), 1);
+        assert_eq!(p[0].values[0].0 .0, "clang");
+
+        assert!(root.module.target_features.is_empty());
+
         let bin = read_hello_file("hello_global.wasm");
         let mut parser = Parser::new(&bin);
         let _: Root<'_, _> = unwrap(parser.parse());
@@ -1122,4 +1395,217 @@ mod tests {
         let mut parser = Parser::new(&bin);
         let _: Root<'_, _> = unwrap(parser.parse());
     }
+
+    #[test]
+    fn insn_offset_followed_by_section() {
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        bin.extend_from_slice(b"\x01\x04\x01\x60\x00\x00"); // Type section
+        bin.extend_from_slice(b"\x03\x02\x01\x00"); // Function section
+        bin.extend_from_slice(b"\x0a\x05\x01\x03\x00\x00\x0b"); // Code section
+        bin.extend_from_slice(b"\x00\x04\x03foo"); // Custom section after code section
+
+        let mut parser = Parser::new(&bin);
+        let root: Root<'_, _> = unwrap(parser.parse());
+        match &root.module.funcs[0].kind {
+            FuncKind::Body { expr, .. } => {
+                assert!(matches!(expr[0].kind, InsnKind::Unreachable));
+                assert_eq!(expr[0].start, 23);
+            }
+            FuncKind::Import(_) => panic!("function must have body"),
+        }
+    }
+
+    #[test]
+    fn nested_parser_offsets() {
+        // Position in parser for function body must count both rest of code section and rest of
+        // the module
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        bin.extend_from_slice(b"\x01\x04\x01\x60\x00\x00"); // Type section
+        bin.extend_from_slice(b"\x03\x03\x02\x00\x00"); // Function section
+        bin.extend_from_slice(b"\x0a\x09\x02"); // Code section with 2 functions
+        bin.extend_from_slice(b"\x03\x00\x01\x0b"); // nop at offset 24
+        bin.extend_from_slice(b"\x03\x00\x00\x0b"); // unreachable at offset 28
+        bin.extend_from_slice(b"\x00\x04\x03foo"); // Custom section after code section
+
+        let mut parser = Parser::new(&bin);
+        let root: Root<'_, _> = unwrap(parser.parse());
+        let starts: Vec<_> = root
+            .module
+            .funcs
+            .iter()
+            .map(|f| match &f.kind {
+                FuncKind::Body { expr, .. } => expr[0].start,
+                FuncKind::Import(_) => panic!("function must have body"),
+            })
+            .collect();
+        assert_eq!(starts, [24, 28]);
+
+        // Invalid opcode in the first function body
+        bin[24] = 0xff;
+        let mut parser = Parser::new(&bin);
+        let err = parser.parse::<Root<'_, _>>().err().unwrap();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::UnexpectedByte { got: 0xff, .. }
+        ));
+        assert_eq!(err.pos, 24);
+    }
+
+    #[test]
+    fn name_section() {
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        let subsections: &[&[u8]] = &[
+            // Module name "m"
+            b"\x00\x02\x01m",
+            // Function names: 0 => "f", 1 => "g"
+            b"\x01\x07\x02\x00\x01f\x01\x01g",
+            // Local names: func 1 => (0 => "x")
+            b"\x02\x06\x01\x01\x01\x00\x01x",
+            // Unknown subsection is skipped
+            b"\x0f\x01\x00",
+            // Global names: 0 => "gv"
+            b"\x07\x05\x01\x00\x02gv",
+        ];
+        let payload: Vec<u8> = subsections.concat();
+        bin.push(0x00); // Custom section ID
+        bin.push((payload.len() + 5) as u8);
+        bin.extend_from_slice(b"\x04name");
+        bin.extend_from_slice(&payload);
+
+        let mut parser = Parser::new(&bin);
+        let root: Root<'_, _> = unwrap(parser.parse());
+        let names = &root.module.names;
+        assert_eq!(names.module.as_ref().unwrap().0, "m");
+        assert_eq!(names.funcs.len(), 2);
+        assert_eq!(names.func(0).unwrap().0, "f");
+        assert_eq!(names.func(1).unwrap().0, "g");
+        assert!(names.func(2).is_none());
+        assert_eq!(names.local(1, 0).unwrap().0, "x");
+        assert!(names.local(0, 0).is_none());
+        assert_eq!(names.global(0).unwrap().0, "gv");
+        assert!(names.labels.is_empty());
+    }
+
+    #[test]
+    fn malformed_name_section() {
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        let subsections: &[&[u8]] = &[
+            // Module name "m"
+            b"\x00\x02\x01m",
+            // Function names subsection whose name length exceeds the subsection
+            b"\x01\x04\x01\x00\x09f",
+            // Global names which are not sorted by index: 1 => "b", 0 => "a"
+            b"\x07\x07\x02\x01\x01b\x00\x01a",
+            // Local names whose inner map is not sorted: func 0 => (1 => "y", 0 => "x")
+            b"\x02\x09\x01\x00\x02\x01\x01y\x00\x01x",
+            // Type names: 0 => "t"
+            b"\x04\x04\x01\x00\x01t",
+            // Subsection whose size exceeds the section. Following bytes cannot be parsed
+            b"\x05\x10\x00",
+        ];
+        let payload: Vec<u8> = subsections.concat();
+        bin.push(0x00); // Custom section ID
+        bin.push((payload.len() + 5) as u8);
+        bin.extend_from_slice(b"\x04name");
+        bin.extend_from_slice(&payload);
+        // Other custom section after it is still parsed
+        bin.extend_from_slice(b"\x00\x04\x03foo");
+
+        let mut parser = Parser::new(&bin);
+        let root: Root<'_, _> = unwrap(parser.parse());
+        // Only malformed subsections are skipped
+        let names = &root.module.names;
+        assert_eq!(names.module.as_ref().unwrap().0, "m");
+        assert!(names.funcs.is_empty());
+        assert!(names.globals.is_empty());
+        assert!(names.locals.is_empty());
+        assert_eq!(names.types.len(), 1);
+        assert_eq!(names.types[0].1 .0, "t");
+        assert_eq!(root.module.customs.len(), 2);
+    }
+
+    // Alignment in binary format is always explicit. Zero must be kept since it is not the natural
+    // alignment of instructions like i32.load
+    #[test]
+    fn memarg_alignment() {
+        let mem: Mem = unwrap(Parser::new(&[0x00, 0x00]).parse());
+        assert_eq!(mem.align, Some(0));
+        assert_eq!(mem.offset, None);
+        let mem: Mem = unwrap(Parser::new(&[0x03, 0x08]).parse());
+        assert_eq!(mem.align, Some(3));
+        assert_eq!(mem.offset, Some(8));
+    }
+
+    #[test]
+    fn list_sections() {
+        let bin = read_hello_file("hello.wasm");
+        let sections = unwrap(Parser::new(&bin).parse_sections());
+        let ids: Vec<_> = sections
+            .iter()
+            .map(|s| match &s.kind {
+                SectionKind::Standard(id) => id.as_ref(),
+                SectionKind::Custom(name) => name.0.as_ref(),
+            })
+            .collect();
+        assert_eq!(
+            ids.join(" "),
+            "type import func table memory global export code data name producers",
+        );
+        // Sections are contiguous from the end of preamble to the end of binary. Each section
+        // consists of ID, size in LEB128 and contents
+        let mut start = 8;
+        for s in sections.iter() {
+            assert_eq!(s.start, start);
+            let (size, len) = u32::read_leb128(&bin[s.start + 1..]).unwrap();
+            assert_eq!(s.size, size as usize);
+            start = s.start + 1 + len + s.size;
+        }
+        assert_eq!(start, bin.len());
+        let last = sections.last().unwrap();
+        assert_eq!(&bin[last.start..last.start + 2], &[0x00, 0x25]);
+        assert_eq!(last.size, 0x25);
+
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        bin.extend_from_slice(&[0x0c, 0x00]);
+        match Parser::new(&bin).parse_sections() {
+            Err(e) => assert!(matches!(e.kind, ErrorKind::ExpectedEof(0x0c))),
+            Ok(_) => panic!("unknown section ID was accepted"),
+        }
+        assert!(Parser::new(b"\0asm\x02\0\0\0").parse_sections().is_err());
+    }
+
+    #[test]
+    fn custom_sections() {
+        let mut bin = b"\0asm\x01\0\0\0".to_vec();
+        // Custom section before type section
+        bin.extend_from_slice(b"\x00\x04\x03foo");
+        // Empty type section
+        bin.extend_from_slice(b"\x01\x01\x00");
+        // target_features: +mutable-globals, -simd128
+        bin.extend_from_slice(b"\x00\x2b\x0ftarget_features\x02");
+        bin.extend_from_slice(b"+\x0fmutable-globals-\x07simd128");
+        // Custom section with payload
+        bin.extend_from_slice(b"\x00\x06\x03bar\x01\x02");
+
+        let mut parser = Parser::new(&bin);
+        let root: Root<'_, _> = unwrap(parser.parse());
+        let c = &root.module.customs;
+        assert_eq!(c.len(), 3);
+        assert_eq!(c[0].name.0, "foo");
+        assert_eq!(c[0].start, 8);
+        assert_eq!(c[0].place, CustomPlace::BeforeFirst);
+        assert!(c[0].data.is_empty());
+        assert_eq!(c[1].name.0, "target_features");
+        assert_eq!(c[1].place, CustomPlace::After(SectionId::Type));
+        assert_eq!(c[2].name.0, "bar");
+        assert_eq!(c[2].place, CustomPlace::After(SectionId::Type));
+        assert_eq!(c[2].data.as_ref(), &[0x01, 0x02]);
+
+        let f = &root.module.target_features;
+        assert_eq!(f.len(), 2);
+        assert_eq!(f[0].prefix, FeaturePrefix::Used);
+        assert_eq!(f[0].name.0, "mutable-globals");
+        assert_eq!(f[1].prefix, FeaturePrefix::Disallowed);
+        assert_eq!(f[1].name.0, "simd128");
+    }
 }
@@ -1,6 +1,7 @@
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
+use wain_ast::CustomPlace;
 
 // TODO: Remove duplication between text format AST and binary format AST.
 // For example, wat::ValType and wasm::TableType has the same structure. So we can use
@@ -27,6 +28,7 @@ pub struct Parsed<'s> {
 pub struct Module<'s> {
     pub start: usize,
     pub id: Option<&'s str>,
+    pub name: Option<Name>,
     pub types: Vec<TypeDef<'s>>,
     pub exports: Vec<Export<'s>>,
     pub funcs: Vec<Func<'s>>,
@@ -36,6 +38,7 @@ pub struct Module<'s> {
     pub memories: Vec<Memory<'s>>,
     pub globals: Vec<Global<'s>>,
     pub entrypoint: Option<Start<'s>>,
+    pub customs: Vec<Custom<'s>>,
 }
 
 // https://webassembly.github.io/spec/core/text/modules.html#text-typedef
@@ -60,6 +63,7 @@ pub struct FuncType<'s> {
 pub struct Param<'s> {
     pub start: usize,
     pub id: Option<&'s str>,
+    pub name: Option<Name>,
     pub ty: ValType,
 }
 
@@ -94,6 +98,7 @@ pub struct Import {
 // In text format, special characters in string literal are escaped. Unescaped string must
 // be allocated in heap. In binary format, it is directly encoded as bytes so borrowing the
 // part of source is enough.
+#[derive(Clone)]
 #[cfg_attr(test, derive(Debug))]
 pub struct Name(pub String);
 
@@ -179,6 +184,7 @@ pub enum FuncKind<'s> {
 pub struct Func<'s> {
     pub start: usize,
     pub id: Option<&'s str>,
+    pub name: Option<Name>,
     pub ty: TypeUse<'s>,
     pub kind: FuncKind<'s>,
 }
@@ -188,6 +194,7 @@ pub struct Func<'s> {
 pub struct Local<'s> {
     pub start: usize,
     pub id: Option<&'s str>,
+    pub name: Option<Name>,
     pub ty: ValType,
 }
 
@@ -486,6 +493,15 @@ pub struct Start<'s> {
     pub idx: Index<'s>,
 }
 
+// https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md#custom-annotations
+#[cfg_attr(test, derive(Debug))]
+pub struct Custom<'s> {
+    pub start: usize,
+    pub name: Name,
+    pub place: CustomPlace,
+    pub data: Cow<'s, [u8]>,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -111,6 +111,22 @@ impl<'s> Composer<'s> {
         }
 
         // Compose module fields
+        let names = &mut self.target.names;
+        if names.module.is_none() {
+            names.module = composed.names.module.take();
+        }
+        names.funcs.append(&mut composed.names.funcs);
+        names.locals.append(&mut composed.names.locals);
+        names.labels.append(&mut composed.names.labels);
+        names.types.append(&mut composed.names.types);
+        names.tables.append(&mut composed.names.tables);
+        names.memories.append(&mut composed.names.memories);
+        names.globals.append(&mut composed.names.globals);
+        self.target.producers.append(&mut composed.producers);
+        self.target
+            .target_features
+            .append(&mut composed.target_features);
+        self.target.customs.append(&mut composed.customs);
         self.target.types.append(&mut composed.types);
         self.target.exports.append(&mut composed.exports);
         self.target.funcs.append(&mut composed.funcs);
@@ -178,6 +194,7 @@ impl<'s> Adjust<'s> for Module<'s> {
     fn adjust(&mut self, composer: &mut Composer) -> Result<'s, ()> {
         self.entrypoint.adjust(composer)?;
         self.exports.adjust(composer)?;
+        self.names.adjust(composer)?;
         self.funcs.adjust(composer)?;
         self.elems.adjust(composer)?;
         for table in self.tables.iter() {
@@ -190,6 +207,33 @@ impl<'s> Adjust<'s> for Module<'s> {
     }
 }
 
+impl<'s> Adjust<'s> for Names<'s> {
+    fn adjust(&mut self, composer: &mut Composer) -> Result<'s, ()> {
+        for (idx, _) in self.funcs.iter_mut() {
+            composer.adjust_func_idx(idx);
+        }
+        for (idx, _) in self.locals.iter_mut() {
+            composer.adjust_func_idx(idx);
+        }
+        for (idx, _) in self.labels.iter_mut() {
+            composer.adjust_func_idx(idx);
+        }
+        for (idx, _) in self.types.iter_mut() {
+            composer.adjust_type_idx(idx);
+        }
+        for (idx, _) in self.tables.iter_mut() {
+            composer.adjust_table_idx(idx);
+        }
+        for (idx, _) in self.memories.iter_mut() {
+            composer.adjust_mem_idx(idx);
+        }
+        for (idx, _) in self.globals.iter_mut() {
+            composer.adjust_global_idx(idx);
+        }
+        Ok(())
+    }
+}
+
 impl<'s> Adjust<'s> for StartFunction {
     fn adjust(&mut self, composer: &mut Composer) -> Result<'s, ()> {
         composer.adjust_func_idx(&mut self.idx);
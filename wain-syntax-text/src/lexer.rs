@@ -10,6 +10,7 @@ use std::str;
 pub enum LexErrorKind<'source> {
     UnterminatedBlockComment,
     UnterminatedString,
+    UnterminatedAnnotation,
     ReservedName(&'source str),
     UnexpectedCharacter(char),
 }
@@ -44,6 +45,7 @@ impl<'s> fmt::Display for LexError<'s> {
         match &self.kind {
             UnterminatedBlockComment => write!(f, "block comment is not terminated")?,
             UnterminatedString => write!(f, "string literal is not terminated",)?,
+            UnterminatedAnnotation => write!(f, "annotation is not terminated")?,
             ReservedName(name) => {
                 write!(f, "name '{}' is unavailable since it's reserved name", name)?
             }
@@ -127,6 +129,7 @@ pub enum Token<'source> {
     Float(Sign, Float<'source>),
     String(&'source str), // Should parse the literal into Vec<u8>?
     Ident(&'source str),
+    Annot(&'source str), // '(@' and annotation ID. '(' is included in this token
 }
 
 impl<'s> fmt::Display for Token<'s> {
@@ -171,10 +174,48 @@ impl<'s> fmt::Display for Token<'s> {
             ) => write!(f, "float number '{}{}{}", sign, base.prefix(), frac,),
             Token::String(s) => write!(f, "string literal \"{}\"", s),
             Token::Ident(ident) => write!(f, "identifier '{}'", ident),
+            Token::Annot(id) => write!(f, "annotation '(@{}'", id),
         }
     }
 }
 
+fn is_idchar(c: char) -> bool {
+    // https://webassembly.github.io/spec/core/text/values.html#text-idchar
+    match c {
+        '0'..='9'
+        | 'a'..='z'
+        | 'A'..='Z'
+        | '!'
+        | '#'
+        | '$'
+        | '%'
+        | '&'
+        | '\''
+        | '*'
+        | '+'
+        | '-'
+        | '.'
+        | '/'
+        | ':'
+        | '<'
+        | '='
+        | '>'
+        | '?'
+        | '@'
+        | '\\'
+        | '^'
+        | '_'
+        | '`'
+        | '|'
+        | '~' => true,
+        _ => false,
+    }
+}
+
+// Annotations which are handled by parser. Other annotations are skipped as if they were comments.
+// https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md
+const KNOWN_ANNOTATIONS: [&str; 2] = ["custom", "name"];
+
 type Lexed<'s> = Option<(Token<'s>, usize)>;
 type LexResult<'s> = Result<'s, Lexed<'s>>;
 
@@ -200,6 +241,9 @@ impl<'s> Lexer<'s> {
         while self.eat_whitespace()? {}
 
         // https://webassembly.github.io/spec/core/text/lexical.html#tokens
+        if let Some(lexed) = self.lex_annot() {
+            return Ok(Some(lexed));
+        }
         if let Some(lexed) = self.lex_paren() {
             return Ok(Some(lexed));
         }
@@ -229,6 +273,25 @@ impl<'s> Lexer<'s> {
         }
     }
 
+    fn peek_annot_id(&mut self) -> Option<&'s str> {
+        // annot ::= '(@' idchar+
+        let source = self.source;
+        let rest = source[self.offset()..].strip_prefix("(@")?;
+        let len = rest.find(|c| !is_idchar(c)).unwrap_or(rest.len());
+        if len == 0 {
+            None
+        } else {
+            Some(&rest[..len])
+        }
+    }
+
+    fn lex_annot(&mut self) -> Lexed<'s> {
+        let id = self.peek_annot_id()?;
+        let start = self.offset();
+        self.chars.nth(id.len() + 1); // Eat '(@' and ID. Note that idchar is always ASCII
+        Some((Token::Annot(id), start))
+    }
+
     fn lex_string(&mut self) -> LexResult<'s> {
         // https://webassembly.github.io/spec/core/text/values.html#strings
         let start = match self.eat_char('"') {
@@ -251,39 +314,6 @@ impl<'s> Lexer<'s> {
     }
 
     fn lex_idchars(&mut self) -> LexResult<'s> {
-        fn is_idchar(c: char) -> bool {
-            // https://webassembly.github.io/spec/core/text/values.html#text-idchar
-            match c {
-                '0'..='9'
-                | 'a'..='z'
-                | 'A'..='Z'
-                | '!'
-                | '#'
-                | '$'
-                | '%'
-                | '&'
-                | '\''
-                | '*'
-                | '+'
-                | '-'
-                | '.'
-                | '/'
-                | ':'
-                | '<'
-                | '='
-                | '>'
-                | '?'
-                | '@'
-                | '\\'
-                | '^'
-                | '_'
-                | '`'
-                | '|'
-                | '~' => true,
-                _ => false,
-            }
-        }
-
         let start = self.offset();
         let end = loop {
             match self.chars.peek() {
@@ -463,7 +493,39 @@ impl<'s> Lexer<'s> {
                 _ => false,
             }
         }
-        Ok(self.eat_char_by(is_ws_char) || self.eat_line_comment() || self.eat_block_comment()?)
+        Ok(self.eat_char_by(is_ws_char)
+            || self.eat_line_comment()
+            || self.eat_block_comment()?
+            || self.eat_unknown_annot()?)
+    }
+
+    fn eat_unknown_annot(&mut self) -> Result<'s, bool> {
+        // Unknown annotations are ignored. Tokens in annotation are not lexed so that tools can
+        // put reserved tokens in their annotations.
+        // https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md
+        match self.peek_annot_id() {
+            Some(id) if !KNOWN_ANNOTATIONS.contains(&id) => {}
+            _ => return Ok(false),
+        }
+        let start = self.offset();
+        self.lex_annot();
+
+        let mut depth = 1;
+        loop {
+            let skipped = self.eat_line_comment()
+                || self.eat_block_comment()?
+                || self.lex_string()?.is_some();
+            if skipped {
+                continue;
+            }
+            match self.chars.next() {
+                Some((_, '(')) => depth += 1,
+                Some((_, ')')) if depth == 1 => return Ok(true),
+                Some((_, ')')) => depth -= 1,
+                Some(_) => {}
+                None => return self.fail(LexErrorKind::UnterminatedAnnotation, start),
+            }
+        }
     }
 
     fn eat_line_comment(&mut self) -> bool {
@@ -625,6 +687,40 @@ mod tests {
         assert_lex_error!("(;(;;)", LexErrorKind::UnterminatedBlockComment);
     }
 
+    #[test]
+    fn annotations() {
+        assert_lex_one!("(@custom", Token::Annot("custom"));
+        assert_lex_one!("(@name", Token::Annot("name"));
+        // Unknown annotations are skipped
+        assert!(lex_all("(@foo)").unwrap().is_empty());
+        assert!(
+            lex_all("(@foo 1 $x \"str)\" (nested (@bar 0$reserved)) ;; )\n)")
+                .unwrap()
+                .is_empty()
+        );
+        assert!(lex_all("(@foo (; ) ;) )").unwrap().is_empty());
+        let tokens: Vec<_> = lex_all("(func (@foo) $f)")
+            .unwrap()
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::LParen,
+                Token::Keyword("func"),
+                Token::Ident("$f"),
+                Token::RParen,
+            ]
+        );
+        // '(@' without ID is not an annotation
+        assert_lex_error!("(@ foo)", LexErrorKind::ReservedName("@"));
+        // Errors
+        assert_lex_error!("(@foo", LexErrorKind::UnterminatedAnnotation);
+        assert_lex_error!("(@foo (bar)", LexErrorKind::UnterminatedAnnotation);
+        assert_lex_error!("(@foo \")\"", LexErrorKind::UnterminatedAnnotation);
+    }
+
     #[test]
     fn parens() {
         assert_lex_one!("(", Token::LParen);
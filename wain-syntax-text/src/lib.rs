@@ -14,7 +14,7 @@ use compose::{ComposeError, Composer};
 use parser::{ParseError, Parser};
 use source::TextSource;
 use std::fmt;
-use wat2wasm::{wat2wasm, TransformError};
+use wat2wasm::{wat2wasm, wat2wasm_with_id_names, TransformError};
 
 // TODO: Unify all error types into one error type
 pub enum Error<'source> {
@@ -61,14 +61,32 @@ from_errors! {
 }
 
 pub fn parse(source: &'_ str) -> Result<wain_ast::Root<'_, TextSource>, Error<'_>> {
+    parse_module(source, false)
+}
+
+// Parse the source and use identifiers as names of module, functions, locals and so on when no @name
+// annotation is given. The names are useful for debugging and profiling.
+pub fn parse_with_id_names(source: &'_ str) -> Result<wain_ast::Root<'_, TextSource>, Error<'_>> {
+    parse_module(source, true)
+}
+
+fn parse_module(
+    source: &'_ str,
+    id_names: bool,
+) -> Result<wain_ast::Root<'_, TextSource>, Error<'_>> {
+    let transform = if id_names {
+        wat2wasm_with_id_names
+    } else {
+        wat2wasm
+    };
     let mut parser = Parser::new(source);
     let parsed = parser.parse()?;
-    let mut tree = wat2wasm(parsed, source)?;
+    let mut tree = transform(parsed, source)?;
 
     // Compose multiple modules: https://webassembly.github.io/spec/core/text/modules.html#text-module
     while !parser.is_done() {
         let parsed = parser.parse()?;
-        let module = wat2wasm(parsed, source)?.module;
+        let module = transform(parsed, source)?.module;
         let composer = Composer::new(tree.module, source);
         tree.module = composer.compose(module)?;
     }
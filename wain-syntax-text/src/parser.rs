@@ -10,6 +10,7 @@ use std::fmt;
 use std::mem;
 use std::ops;
 use std::str::FromStr;
+use wain_ast::{CustomPlace, SectionId};
 
 #[cfg_attr(test, derive(Debug))]
 pub enum ParseErrorKind<'source> {
@@ -52,6 +53,7 @@ pub enum ParseErrorKind<'source> {
     ImportMustPrecedeOtherDefs {
         what: &'static str,
     },
+    InvalidAlignment(u8),
 }
 
 #[cfg_attr(test, derive(Debug))]
@@ -120,6 +122,7 @@ impl<'s> fmt::Display for ParseError<'s> {
             IdAlreadyDefined{id, prev_idx, what, scope} => write!(f, "identifier '{}' for {} is already defined for index {} in the {}", id, what, prev_idx, scope)?,
             ExpectEndOfFile{after, token} => write!(f, "expect EOF but got {} after parsing {}", token, after)?,
             ImportMustPrecedeOtherDefs{what} => write!(f, "import {} must be put before other {} definitions", what, what)?,
+            InvalidAlignment(align) => write!(f, "alignment must be a power of 2 but got {}", align)?,
         };
 
         describe_position(f, self.source, self.offset)
@@ -416,6 +419,18 @@ impl<'s> Parser<'s> {
         })
     }
 
+    // https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md#custom-annotations
+    fn maybe_name_annot(&mut self) -> Result<'s, Option<Name>> {
+        if let (Token::Annot("name"), _) = self.peek("name annotation")? {
+            self.eat_token(); // eat '(@name'
+            let name = self.parse()?;
+            self.closing_paren("name annotation")?;
+            Ok(Some(name))
+        } else {
+            Ok(None)
+        }
+    }
+
     fn missing_paren<T>(
         &mut self,
         paren: char,
@@ -687,6 +702,7 @@ enum ModuleField<'s> {
     Memory(MemoryAbbrev<'s>),
     Global(Global<'s>),
     Start(Start<'s>),
+    Custom(Custom<'s>),
 }
 
 // https://webassembly.github.io/spec/core/text/modules.html#text-module
@@ -706,6 +722,11 @@ impl<'s> Parse<'s> for Module<'s> {
         }
 
         let id = parser.maybe_ident("identifier for module")?;
+        let name = if abbreviated {
+            None
+        } else {
+            parser.maybe_name_annot()?
+        };
 
         let mut funcs = vec![];
         let mut elems = vec![];
@@ -714,6 +735,7 @@ impl<'s> Parse<'s> for Module<'s> {
         let mut memories = vec![];
         let mut globals = vec![];
         let mut entrypoint = None;
+        let mut customs = vec![];
 
         // Any import must be put before other definitions because indices of imports must precede
         // indices of other definitions
@@ -735,7 +757,7 @@ impl<'s> Parse<'s> for Module<'s> {
 
         loop {
             match parser.tokens.peek() {
-                Some(Ok((Token::LParen, _))) => {} // fallthrough
+                Some(Ok((Token::LParen, _))) | Some(Ok((Token::Annot(_), _))) => {} // fallthrough
                 Some(Ok(_)) => break,
                 Some(Err(err)) => return Err(err.clone().into()),
                 None if abbreviated => break, // with abbreviation, it may reach EOF
@@ -822,6 +844,7 @@ impl<'s> Parse<'s> for Module<'s> {
                         entrypoint = Some(start);
                     }
                 }
+                ModuleField::Custom(custom) => customs.push(custom),
             }
         }
 
@@ -842,6 +865,7 @@ impl<'s> Parse<'s> for Module<'s> {
         Ok(Module {
             start,
             id,
+            name,
             types: mem::replace(&mut parser.ctx.types, vec![]),
             exports: mem::replace(&mut parser.ctx.exports, vec![]),
             funcs,
@@ -851,6 +875,7 @@ impl<'s> Parse<'s> for Module<'s> {
             memories,
             globals,
             entrypoint,
+            customs,
         })
     }
 }
@@ -858,7 +883,10 @@ impl<'s> Parse<'s> for Module<'s> {
 // https://webassembly.github.io/spec/core/text/modules.html#text-modulefield
 impl<'s> Parse<'s> for ModuleField<'s> {
     fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
-        let expected = "one of 'type', 'import', 'export', 'func', 'elem', 'table', 'data', 'memory', 'global', 'start' sections in module";
+        let expected = "one of 'type', 'import', 'export', 'func', 'elem', 'table', 'data', 'memory', 'global', 'start' sections or '@custom' annotation in module";
+        if let (Token::Annot("custom"), _) = parser.peek(expected)? {
+            return Ok(ModuleField::Custom(parser.parse()?));
+        }
         match parser.peek_fold_start(expected)? {
             (Some(kw), offset) => match kw {
                 "type" => Ok(ModuleField::Type(parser.parse()?)),
@@ -922,11 +950,17 @@ impl<'s> Parse<'s> for Vec<Param<'s>> {
             parser.eat_token(); // eat 'param'
 
             let id = parser.maybe_ident("identifier for param")?;
-            if id.is_some() {
-                // ID is not available for abbreviation
+            let name = parser.maybe_name_annot()?;
+            if id.is_some() || name.is_some() {
+                // ID and name annotation are not available for abbreviation
                 let ty = parser.parse()?;
                 parser.closing_paren("parameter")?;
-                params.push(Param { start, id, ty });
+                params.push(Param {
+                    start,
+                    id,
+                    name,
+                    ty,
+                });
                 continue;
             }
 
@@ -940,7 +974,12 @@ impl<'s> Parse<'s> for Vec<Param<'s>> {
                     }
                     (_, start) => {
                         let ty = parser.parse()?;
-                        params.push(Param { start, id, ty });
+                        params.push(Param {
+                            start,
+                            id,
+                            name: None,
+                            ty,
+                        });
                     }
                 }
             }
@@ -1091,6 +1130,7 @@ impl<'s> Parse<'s> for ImportItem<'s> {
                 ImportItem::Func(Func {
                     start,
                     id,
+                    name: parser.maybe_name_annot()?,
                     ty: parser.parse()?,
                     kind: FuncKind::Import(import),
                 })
@@ -1353,6 +1393,7 @@ impl<'s> Parse<'s> for Func<'s> {
         // https://webassembly.github.io/spec/core/text/modules.html#text-func-abbrev
 
         let id = parser.maybe_ident("identifier for func field")?;
+        let name = parser.maybe_name_annot()?;
         let idx = parser.ctx.func_indices.new_idx(id, start)?;
         loop {
             match parser
@@ -1371,6 +1412,7 @@ impl<'s> Parse<'s> for Func<'s> {
                     return Ok(Func {
                         start,
                         id,
+                        name,
                         ty,
                         kind: FuncKind::Import(import),
                     });
@@ -1397,6 +1439,7 @@ impl<'s> Parse<'s> for Func<'s> {
                     return Ok(Func {
                         start,
                         id,
+                        name,
                         ty,
                         kind: FuncKind::Body { locals, body },
                     });
@@ -1417,11 +1460,14 @@ impl<'s> Parse<'s> for Vec<Local<'s>> {
             parser.eat_token(); // Eat '(' keyword
             parser.eat_token(); // Eat 'local' keyword
 
-            if let Some(id) = parser.maybe_ident("identifier for local")? {
+            let id = parser.maybe_ident("identifier for local")?;
+            let name = parser.maybe_name_annot()?;
+            if id.is_some() || name.is_some() {
                 let ty = parser.parse()?;
                 locals.push(Local {
                     start,
-                    id: Some(id),
+                    id,
+                    name,
                     ty,
                 });
             } else {
@@ -1433,6 +1479,7 @@ impl<'s> Parse<'s> for Vec<Local<'s>> {
                         _ => locals.push(Local {
                             start,
                             id: None,
+                            name: None,
                             ty: parser.parse()?,
                         }),
                     }
@@ -1469,6 +1516,10 @@ impl<'s> Parse<'s> for Mem {
             (Token::Keyword(kw), offset) if kw.starts_with("align=") => {
                 let (base, digits) = base_and_digits(&kw[6..]);
                 let u = parse_u8_str(parser, digits, base, offset)?;
+                // Alignment is encoded as exponent of 2 in binary format
+                if !u.is_power_of_two() {
+                    return parser.error(ParseErrorKind::InvalidAlignment(u), offset);
+                }
                 parser.eat_token(); // Eat 'align' keyword
                 Some(u)
             }
@@ -2530,6 +2581,71 @@ impl<'s> Parse<'s> for Start<'s> {
     }
 }
 
+// https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md#custom-annotations
+impl<'s> Parse<'s> for Custom<'s> {
+    fn parse(parser: &mut Parser<'s>) -> Result<'s, Self> {
+        let (_, start) = match_token!(parser, "'@custom' annotation", Token::Annot("custom"));
+        let name = parser.parse()?;
+
+        let place = match parser.peek_fold_start("placement of custom section")? {
+            (Some(kw @ "before"), _) | (Some(kw @ "after"), _) => {
+                parser.eat_token(); // Eat '('
+                parser.eat_token(); // Eat 'before' or 'after'
+                let (id, offset) = match_token!(parser, "section ID for placement of custom section", Token::Keyword(id) => id);
+                let sec = match id {
+                    "first" if kw == "before" => None,
+                    "last" if kw == "after" => None,
+                    "type" => Some(SectionId::Type),
+                    "import" => Some(SectionId::Import),
+                    "func" => Some(SectionId::Func),
+                    "table" => Some(SectionId::Table),
+                    "memory" => Some(SectionId::Memory),
+                    "global" => Some(SectionId::Global),
+                    "export" => Some(SectionId::Export),
+                    "start" => Some(SectionId::Start),
+                    "elem" => Some(SectionId::Elem),
+                    "code" => Some(SectionId::Code),
+                    "data" => Some(SectionId::Data),
+                    _ => return parser.error(ParseErrorKind::UnexpectedKeyword(id), offset),
+                };
+                parser.closing_paren("placement of custom section")?;
+                match (kw, sec) {
+                    ("before", Some(sec)) => CustomPlace::Before(sec),
+                    ("before", None) => CustomPlace::BeforeFirst,
+                    (_, Some(sec)) => CustomPlace::After(sec),
+                    (_, None) => CustomPlace::AfterLast,
+                }
+            }
+            _ => CustomPlace::AfterLast,
+        };
+
+        let mut data = vec![];
+        loop {
+            match parser.next_token("')' or string literal for custom section")? {
+                (Token::RParen, _) => {
+                    return Ok(Custom {
+                        start,
+                        name,
+                        place,
+                        data: Cow::Owned(data),
+                    });
+                }
+                (Token::String(s), offset) => {
+                    let mut decoded = parser.parse_bytes_encoded_in_string(s, offset)?;
+                    data.append(&mut decoded);
+                }
+                (tok, offset) => {
+                    return parser.unexpected_token(
+                        tok,
+                        "')' or string literal for custom section",
+                        offset,
+                    )
+                }
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -4181,6 +4297,20 @@ mod tests {
             Vec<Instruction<'_>>,
             CannotParseNum{ .. }
         );
+        assert_error!(
+            r#"i32.load align=0"#,
+            Vec<Instruction<'_>>,
+            InvalidAlignment(0)
+        );
+        assert_error!(
+            r#"i32.load offset=4 align=3"#,
+            Vec<Instruction<'_>>,
+            InvalidAlignment(3)
+        );
+        let err = Parser::new(r#"i32.load align=3"#)
+            .parse::<Vec<Instruction<'_>>>()
+            .unwrap_err();
+        assert_eq!(err.offset(), 9); // Offset of 'align=3' keyword
     }
 
     #[test]
@@ -5091,6 +5221,177 @@ mod tests {
         assert_parse!(r#"(start $f)"#, Start<'_>, Start{ idx: Index::Ident("$f"), .. });
     }
 
+    #[test]
+    fn custom_annotation() {
+        assert_parse!(
+            r#"(@custom "foo" "hello" "\00\01")"#,
+            Custom<'_>,
+            Custom {
+                name: Name(n),
+                place: CustomPlace::AfterLast,
+                data,
+                ..
+            } if n == "foo" && data.as_ref() == b"hello\x00\x01"
+        );
+        assert_parse!(
+            r#"(@custom "foo")"#,
+            Custom<'_>,
+            Custom { data, .. } if data.is_empty()
+        );
+        assert_parse!(
+            r#"(@custom "foo" (before first) "x")"#,
+            Custom<'_>,
+            Custom {
+                place: CustomPlace::BeforeFirst,
+                ..
+            }
+        );
+        assert_parse!(
+            r#"(@custom "foo" (after last) "x")"#,
+            Custom<'_>,
+            Custom {
+                place: CustomPlace::AfterLast,
+                ..
+            }
+        );
+        assert_parse!(
+            r#"(@custom "foo" (before func) "x")"#,
+            Custom<'_>,
+            Custom {
+                place: CustomPlace::Before(SectionId::Func),
+                ..
+            }
+        );
+        assert_parse!(
+            r#"(@custom "foo" (after data) "x")"#,
+            Custom<'_>,
+            Custom {
+                place: CustomPlace::After(SectionId::Data),
+                ..
+            }
+        );
+        assert_parse!(
+            r#"(module (type (func)) (@custom "a" "x") (func) (@custom "b" (before type)) (@unknown foo))"#,
+            Module<'_>,
+            Module { customs, types, funcs, .. } if customs.len() == 2 && types.len() == 1 && funcs.len() == 1
+        );
+        assert_parse!(
+            r#"(@custom "a" "x") (func)"#,
+            Module<'_>,
+            Module { customs, funcs, .. } if customs.len() == 1 && funcs.len() == 1
+        );
+
+        assert_error!(
+            r#"(@custom "foo" (before last))"#,
+            Custom<'_>,
+            UnexpectedKeyword("last")
+        );
+        assert_error!(
+            r#"(@custom "foo" (after first))"#,
+            Custom<'_>,
+            UnexpectedKeyword("first")
+        );
+        assert_error!(
+            r#"(@custom "foo" (after foo))"#,
+            Custom<'_>,
+            UnexpectedKeyword("foo")
+        );
+        assert_error!(r#"(@custom "foo" 1)"#, Custom<'_>, UnexpectedToken { .. });
+        assert_error!(r#"(@custom 1)"#, Custom<'_>, UnexpectedToken { .. });
+        assert_error!(r#"(@custom "foo""#, Custom<'_>, UnexpectedEndOfFile { .. });
+    }
+
+    #[test]
+    fn name_annotation() {
+        assert_parse!(
+            r#"(module $m (@name "my module"))"#,
+            Module<'_>,
+            Module { id: Some("$m"), name: Some(Name(n)), .. } if n == "my module"
+        );
+        assert_parse!(
+            r#"(module (@name "m"))"#,
+            Module<'_>,
+            Module {
+                id: None,
+                name: Some(_),
+                ..
+            }
+        );
+        assert_parse!(
+            r#"(func $f (@name "f") (param $p (@name "p") i32) (param i64) (local (@name "l") f32))"#,
+            Func<'_>,
+            Func {
+                name: Some(Name(f)),
+                ty: TypeUse { params, .. },
+                kind: FuncKind::Body { locals, .. },
+                ..
+            } if f == "f"
+                && matches!(params.as_slice(), [Param { name: Some(Name(p)), .. }, Param { name: None, .. }] if p == "p")
+                && matches!(locals.as_slice(), [Local { id: None, name: Some(Name(l)), .. }] if l == "l")
+        );
+        assert_parse!(
+            r#"(import "m" "n" (func $f (@name "f")))"#,
+            ImportItem<'_>,
+            ImportItem::Func(Func { name: Some(Name(f)), .. }) if f == "f"
+        );
+        assert_error!(r#"(func (@name 1))"#, Func<'_>, UnexpectedToken { .. });
+        assert_error!(
+            r#"(func (@name "f" "g"))"#,
+            Func<'_>,
+            MissingParen { paren: ')', .. }
+        );
+
+        let root = crate::parse(
+            r#"
+            (module (@name "m")
+              (import "m" "n" (func $imported (@name "imported")))
+              (func (param $p (@name "p") i32) (local $x i32) (local (@name "y") i32))
+              (func $f (@name "f"))
+            )
+            "#,
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
+        let names = root.module.names;
+        assert_eq!(names.module.unwrap().0, "m");
+        let funcs: Vec<_> = names
+            .funcs
+            .iter()
+            .map(|(i, n)| (*i, n.0.as_ref()))
+            .collect();
+        assert_eq!(funcs, vec![(0, "imported"), (2, "f")]);
+        assert_eq!(names.locals.len(), 1);
+        let (idx, locals) = &names.locals[0];
+        assert_eq!(*idx, 1);
+        let locals: Vec<_> = locals.iter().map(|(i, n)| (*i, n.0.as_ref())).collect();
+        assert_eq!(locals, vec![(0, "p"), (2, "y")]);
+    }
+
+    #[test]
+    fn name_annotation_with_type_use() {
+        let root = crate::parse(
+            r#"
+            (module
+              (type $t (func (param i32 i64)))
+              (func (type $t) (local (@name "xx") i32))
+              (func (type 0) (param i32) (param $p (@name "b") i64) (local (@name "l") f32)))
+            "#,
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
+        let names = root.module.names;
+        let locals: Vec<_> = names
+            .locals
+            .iter()
+            .map(|(f, m)| {
+                let m: Vec<_> = m.iter().map(|(i, n)| (*i, n.0.as_ref())).collect();
+                (*f, m)
+            })
+            .collect();
+        assert_eq!(
+            locals,
+            vec![(0, vec![(2, "xx")]), (1, vec![(1, "b"), (2, "l")])],
+        );
+    }
+
     #[test]
     fn hello_world() {
         assert_parse!(r#"
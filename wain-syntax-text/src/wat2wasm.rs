@@ -3,6 +3,7 @@ use crate::source::{describe_position, TextSource};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt;
+use std::iter;
 use wain_ast as wasm;
 
 #[cfg_attr(test, derive(Debug))]
@@ -169,6 +170,8 @@ struct Context<'s> {
     local_indices: Indices<'s>,
     next_local_idx: u32,
     label_stack: LabelStack<'s>,
+    // Use identifiers as names when no @name annotation is given
+    id_names: bool,
 }
 
 impl<'s> Context<'s> {
@@ -247,6 +250,23 @@ impl<'s> Context<'s> {
 pub fn wat2wasm<'s>(
     parsed: wat::Parsed<'s>,
     source: &'s str,
+) -> Result<'s, wasm::Root<'s, TextSource<'s>>> {
+    transform_module(parsed, source, false)
+}
+
+// Same as wat2wasm but identifiers are also used as names of module, functions, locals and so on
+// when no @name annotation is given, as wabt does with --debug-names
+pub fn wat2wasm_with_id_names<'s>(
+    parsed: wat::Parsed<'s>,
+    source: &'s str,
+) -> Result<'s, wasm::Root<'s, TextSource<'s>>> {
+    transform_module(parsed, source, true)
+}
+
+fn transform_module<'s>(
+    parsed: wat::Parsed<'s>,
+    source: &'s str,
+    id_names: bool,
 ) -> Result<'s, wasm::Root<'s, TextSource<'s>>> {
     let mut ctx = Context {
         source,
@@ -258,6 +278,7 @@ pub fn wat2wasm<'s>(
         local_indices: Indices::new(),
         next_local_idx: 0,
         label_stack: LabelStack::new(source),
+        id_names,
     };
     let module = parsed.module.transform(&mut ctx)?;
     Ok(wasm::Root {
@@ -285,9 +306,74 @@ impl<'s, T: Transform<'s>> Transform<'s> for Option<T> {
     }
 }
 
+// Collect names given by @name annotations
+// https://github.com/WebAssembly/annotations/blob/master/proposals/annotations/Overview.md#custom-annotations
+// When id_names is true and no annotation is specified, identifier is used instead as wabt does.
+fn collect_names<'s>(
+    module: &wat::Module<'s>,
+    type_indices: &Indices<'s>,
+    id_names: bool,
+) -> wasm::Names<'s> {
+    let to_name = |name: &Option<wat::Name>, id: Option<&str>| -> Option<wasm::Name<'s>> {
+        match (name, id.filter(|_| id_names)) {
+            (Some(name), _) => Some(wasm::Name(Cow::Owned(name.0.clone()))),
+            (None, Some(id)) => Some(wasm::Name(Cow::Owned(id[1..].to_string()))), // Omit '$'
+            (None, None) => None,
+        }
+    };
+
+    fn name_map<'s, I>(iter: I) -> wasm::NameMap<'s>
+    where
+        I: Iterator<Item = Option<wasm::Name<'s>>>,
+    {
+        iter.enumerate()
+            .filter_map(|(i, name)| name.map(|n| (i as u32, n)))
+            .collect()
+    }
+
+    let mut names = wasm::Names {
+        module: to_name(&module.name, module.id),
+        types: name_map(module.types.iter().map(|t| to_name(&None, t.id))),
+        funcs: name_map(module.funcs.iter().map(|f| to_name(&f.name, f.id))),
+        tables: name_map(module.tables.iter().map(|t| to_name(&None, t.id))),
+        memories: name_map(module.memories.iter().map(|m| to_name(&None, m.id))),
+        globals: name_map(module.globals.iter().map(|g| to_name(&None, g.id))),
+        ..Default::default()
+    };
+
+    for (idx, func) in module.funcs.iter().enumerate() {
+        if let wat::FuncKind::Body { locals, .. } = &func.kind {
+            // Local indices start after parameters. Inline parameters may be omitted when the
+            // function type is given by (type $t) so the number of parameters is taken from the type
+            let typeidx = match &func.ty.idx {
+                wat::Index::Num(i) => Some(*i),
+                wat::Index::Ident(id) => type_indices.get(id).copied(),
+            };
+            let num_params = typeidx
+                .and_then(|i| module.types.get(i as usize))
+                .map(|t| t.ty.params.len())
+                .unwrap_or_else(|| func.ty.params.len());
+            let params = func
+                .ty
+                .params
+                .iter()
+                .map(|p| to_name(&p.name, p.id))
+                .chain(iter::repeat_with(|| None))
+                .take(num_params);
+            let map = name_map(params.chain(locals.iter().map(|l| to_name(&l.name, l.id))));
+            if !map.is_empty() {
+                names.locals.push((idx as u32, map));
+            }
+        }
+    }
+
+    names
+}
+
 impl<'s> Transform<'s> for wat::Module<'s> {
     type Target = wasm::Module<'s>;
     fn transform(self, ctx: &mut Context<'s>) -> Result<'s, Self::Target> {
+        let names = collect_names(&self, &ctx.type_indices, ctx.id_names);
         Ok(wasm::Module {
             start: self.start,
             id: self.id,
@@ -300,6 +386,11 @@ impl<'s> Transform<'s> for wat::Module<'s> {
             memories: self.memories.transform(ctx)?,
             globals: self.globals.transform(ctx)?,
             entrypoint: self.entrypoint.transform(ctx)?,
+            names,
+            // Note: Custom sections in text format are not decoded
+            producers: vec![],
+            target_features: vec![],
+            customs: self.customs.transform(ctx)?,
         })
     }
 }
@@ -387,7 +478,8 @@ impl<'s> Transform<'s> for wat::Mem {
     type Target = wasm::Mem;
     fn transform(self, _ctx: &mut Context<'s>) -> Result<'s, Self::Target> {
         Ok(wasm::Mem {
-            align: self.align,
+            // Alignment is in bytes in text format, but it is exponent of 2 in abstract syntax
+            align: self.align.map(|a| a.trailing_zeros() as u8),
             offset: self.offset,
         })
     }
@@ -773,3 +865,15 @@ impl<'s> Transform<'s> for wat::Start<'s> {
         })
     }
 }
+
+impl<'s> Transform<'s> for wat::Custom<'s> {
+    type Target = wasm::Custom<'s>;
+    fn transform(self, ctx: &mut Context<'s>) -> Result<'s, Self::Target> {
+        Ok(wasm::Custom {
+            start: self.start,
+            name: self.name.transform(ctx)?,
+            place: self.place,
+            data: self.data,
+        })
+    }
+}
@@ -123,7 +123,7 @@ impl<S: Source> fmt::Display for Error<S> {
                  control frame is op_stack[{}]", op, frame_start, idx_in_op_stack)?,
             LabelStackEmpty { op } => write!(f, "label stack for control instructions is unexpectedly empty at '{}' instruction", op)?,
             SetImmutableGlobal{ ty, idx } => write!(f, "{} value cannot be set to immutable global variable {}", ty, idx)?,
-            TooLargeAlign { align, bits } => write!(f, "align {} must not be larger than {}bits / 8", align, bits)?,
+            TooLargeAlign { align, bits } => write!(f, "align 2^{} must not be larger than {}bits / 8", align, bits)?,
             InvalidLimitRange(min, max) => write!(f, "range for limits {}..{} is invalid", min, max)?,
             LimitsOutOfRange { value, min, max, what } => write!(f, "limit {} is out of range {}..{} at {}", value, min, max, what)?,
             NotConstantInstruction(op) => write!(f, "instruction '{}' is not valid for constant. only 'global.get' or '*.const' are valid in constant expressions", op)?,
@@ -186,9 +186,9 @@ impl<'outer, 'm, 's, S: Source> FuncBodyContext<'outer, 'm, 's, S> {
     fn validate_memarg(&self, mem: &Mem, bits: u8) -> Result<(), S> {
         self.outer
             .memory_from_idx(0, self.current_op, self.current_offset)?;
-        // The alignment must not be larger than the bit width of t divided by 8.
+        // The alignment 2^align must not be larger than the bit width of t divided by 8.
         if let Some(align) = mem.align {
-            if align > bits / 8 {
+            if align as u32 > (bits / 8).trailing_zeros() {
                 return self.error(ErrorKind::TooLargeAlign { align, bits });
             }
         }
@@ -425,4 +425,60 @@ mod tests {
         let err = validate(&root(m)).unwrap_err();
         assert!(matches!(err.kind(), ErrorKind::StackNotEmptyAfterFunc{..}));
     }
+
+    // From https://github.com/WebAssembly/spec/blob/main/test/core/align.wast
+    // Alignment in memarg is exponent of 2. For example 'i64.load align=16' in text format has align 4.
+    #[test]
+    fn alignment_must_not_be_larger_than_natural() {
+        type Load = fn(Mem) -> InsnKind;
+
+        fn validate_load(insn: Load, align: u8) -> Result<(), DummySource> {
+            let mut m = Module::default();
+            m.memories.push(memory(Limits::From(0)));
+            m.types.push(func_type(vec![], None));
+            let mem = Mem {
+                align: Some(align),
+                offset: None,
+            };
+            m.funcs
+                .push(func(0, vec![], vec![I32Const(0), insn(mem), Drop]));
+            validate(&root(m))
+        }
+
+        let valid: &[(Load, u8)] = &[
+            (I32Load8S, 0),
+            (I32Load16U, 1),
+            (I32Load, 0),
+            (I32Load, 2),
+            (I64Load8U, 0),
+            (I64Load16S, 1),
+            (I64Load32S, 2),
+            (I64Load, 3),
+            (F32Load, 2),
+            (F64Load, 3),
+        ];
+        for (i, (insn, align)) in valid.iter().enumerate() {
+            assert!(validate_load(*insn, *align).is_ok(), "case {}", i);
+        }
+
+        let invalid: &[(Load, u8)] = &[
+            (I32Load8S, 1),
+            (I32Load16U, 2),
+            (I32Load, 3),
+            (I64Load8U, 1),
+            (I64Load16S, 2),
+            (I64Load32S, 3),
+            (I64Load, 4),
+            (F32Load, 3),
+            (F64Load, 4),
+        ];
+        for (i, (insn, align)) in invalid.iter().enumerate() {
+            let err = validate_load(*insn, *align).unwrap_err();
+            assert!(
+                matches!(err.kind(), ErrorKind::TooLargeAlign { align: a, .. } if a == align),
+                "case {}",
+                i
+            );
+        }
+    }
 }